@@ -0,0 +1,89 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use std::path::Path;
+
+/// Larger images are refused before upload; every multimodal provider caps requests
+/// around this size
+const MAX_ATTACHMENT_BYTES: u64 = 20 * 1024 * 1024;
+
+/// An image sent along with the prompt (`--attach`), base64-encoded for the request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    /// MIME type, e.g. `image/png`
+    pub media_type: &'static str,
+    /// The file's contents in standard base64
+    pub data: String,
+}
+
+impl Attachment {
+    /// Read and encode the image at `path`; its type comes from the extension
+    pub fn load(path: &Path) -> Result<Self> {
+        let media_type = media_type(path).with_context(|| {
+            format!(
+                "Unsupported attachment {}: expected a .png, .jpg, .jpeg, .gif or .webp image",
+                path.display()
+            )
+        })?;
+        let size = std::fs::metadata(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .len();
+        if size > MAX_ATTACHMENT_BYTES {
+            anyhow::bail!(
+                "{} is too large to attach ({} MB, at most {} MB)",
+                path.display(),
+                size / (1024 * 1024),
+                MAX_ATTACHMENT_BYTES / (1024 * 1024)
+            );
+        }
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Self::new(media_type, &bytes))
+    }
+
+    pub fn new(media_type: &'static str, bytes: &[u8]) -> Self {
+        Self {
+            media_type,
+            data: base64::engine::general_purpose::STANDARD.encode(bytes),
+        }
+    }
+
+    /// The image as a `data:` URL, as OpenAI expects it
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+}
+
+fn media_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_encodes_images_by_extension() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let image = temp_dir.path().join("error.PNG");
+        std::fs::write(&image, b"\x89PNG").unwrap();
+        let attachment = Attachment::load(&image).unwrap();
+        assert_eq!(attachment.media_type, "image/png");
+        assert_eq!(attachment.data, "iVBORw==");
+        assert_eq!(attachment.data_url(), "data:image/png;base64,iVBORw==");
+
+        let notes = temp_dir.path().join("notes.txt");
+        std::fs::write(&notes, "hello").unwrap();
+        let err = Attachment::load(&notes).unwrap_err();
+        assert!(err.to_string().contains("Unsupported attachment"), "{err}");
+
+        let err = Attachment::load(&temp_dir.path().join("missing.jpg")).unwrap_err();
+        assert!(err.to_string().contains("Failed to read"), "{err}");
+    }
+}
@@ -0,0 +1,318 @@
+use crate::{
+    load_config,
+    orchestrator::{execute_plan, plan_execution_options, request_plan},
+    query_provider::QueryProvider,
+    risk,
+    script::ScriptRecorder,
+    timing::PhaseTimer,
+    ExecutionOptions,
+};
+use anyhow::{Context, Result};
+use futures_util::stream::{self, StreamExt};
+use std::path::{Path, PathBuf};
+
+/// Options controlling a `tai batch` run
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /// How many prompts are planned and run at the same time
+    pub jobs: usize,
+    /// Execute the plans; without it every prompt is only planned (a dry run)
+    pub yes: bool,
+    /// Suppress the `[Terminal AI]` banners regardless of the `branding` setting
+    pub no_branding: bool,
+    /// Send the queries to this endpoint instead of the active provider's configured URL
+    pub url: Option<String>,
+    /// Save a timestamped transcript of the executed commands and their output here
+    pub record: Option<PathBuf>,
+    /// Stop querying the provider once the estimated spend of the batch reaches this many USD
+    pub max_cost: Option<f64>,
+    /// Shell the commands run in (`--shell`), instead of the `shell` setting
+    pub shell: Option<String>,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            jobs: 1,
+            yes: false,
+            no_branding: false,
+            url: None,
+            record: None,
+            max_cost: None,
+            shell: None,
+        }
+    }
+}
+
+/// What happened to one prompt of a batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Planned but not run, because the batch is a dry run
+    Planned(Vec<String>),
+    Executed(Vec<String>),
+    NoCommands,
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptResult {
+    pub prompt: String,
+    pub outcome: Outcome,
+}
+
+/// Per-prompt results of a batch, in file order
+#[derive(Debug, Clone, Default)]
+pub struct BatchSummary {
+    pub results: Vec<PromptResult>,
+}
+
+impl BatchSummary {
+    pub fn failed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Failed(_)))
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failed() == 0
+    }
+
+    fn count(&self, predicate: impl Fn(&Outcome) -> bool) -> usize {
+        self.results
+            .iter()
+            .filter(|r| predicate(&r.outcome))
+            .count()
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = format!(
+            "📊 Batch summary: {} prompts, {} executed, {} planned only, {} without commands, {} failed\n",
+            self.results.len(),
+            self.count(|o| matches!(o, Outcome::Executed(_))),
+            self.count(|o| matches!(o, Outcome::Planned(_))),
+            self.count(|o| matches!(o, Outcome::NoCommands)),
+            self.failed()
+        );
+        for (i, result) in self.results.iter().enumerate() {
+            let line = match &result.outcome {
+                Outcome::Executed(commands) => {
+                    format!(
+                        "✅ {}. {} ({} commands)",
+                        i + 1,
+                        result.prompt,
+                        commands.len()
+                    )
+                }
+                Outcome::Planned(commands) => format!(
+                    "📝 {}. {} ({} commands, not run)",
+                    i + 1,
+                    result.prompt,
+                    commands.len()
+                ),
+                Outcome::NoCommands => format!("⚠️  {}. {} (no commands)", i + 1, result.prompt),
+                Outcome::Failed(error) => format!("❌ {}. {}: {error}", i + 1, result.prompt),
+            };
+            text.push_str(&format!("  {line}\n"));
+        }
+        text
+    }
+}
+
+/// Prompts in a batch file: one per line, skipping blank lines and `#` comments
+pub fn parse_batch_file(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Run every prompt in the batch file at `path` with the configured provider
+pub async fn run_batch_file(path: &Path, options: &BatchOptions) -> Result<BatchSummary> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read batch file {}", path.display()))?;
+    let prompts = parse_batch_file(&content);
+    if prompts.is_empty() {
+        anyhow::bail!("Batch file {} contains no prompts", path.display());
+    }
+
+    let mut config = load_config()?;
+    if let Some(url) = &options.url {
+        config.override_url(url)?;
+    }
+    crate::branding::configure(&config, options.no_branding);
+    crate::ui::configure(&config);
+    crate::configure_extraction(&config)?;
+    crate::shell::configure(&config, options.shell.as_deref())?;
+    let execution_options = plan_execution_options(&config, options.record.as_deref())?;
+    let mut provider = QueryProvider::new(config).context("Failed to create query provider")?;
+    if let Some(max_cost) = options.max_cost {
+        provider = provider.with_max_cost(max_cost)?;
+    }
+
+    if !options.yes {
+        println!("📝 Dry run: plans are shown but not executed (use --yes to run them)\n");
+    }
+    Ok(run_batch(&prompts, &provider, &execution_options, options).await)
+}
+
+/// Plan, and with `--yes` execute, each prompt through the one `provider`.
+///
+/// Up to `jobs` prompts are in flight at once; results keep the prompts' order.
+pub async fn run_batch(
+    prompts: &[String],
+    provider: &QueryProvider,
+    execution_options: &ExecutionOptions,
+    options: &BatchOptions,
+) -> BatchSummary {
+    let total = prompts.len();
+    let results = stream::iter(prompts.iter().enumerate())
+        .map(|(i, prompt)| async move {
+            println!("▶️  [{}/{total}] {prompt}", i + 1);
+            let outcome = match run_prompt(prompt, provider, execution_options, options.yes).await {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    eprintln!("❌ [{}/{total}] {e:#}", i + 1);
+                    Outcome::Failed(format!("{e:#}"))
+                }
+            };
+            PromptResult {
+                prompt: prompt.clone(),
+                outcome,
+            }
+        })
+        .buffered(options.jobs.max(1))
+        .collect()
+        .await;
+    BatchSummary { results }
+}
+
+async fn run_prompt(
+    prompt: &str,
+    provider: &QueryProvider,
+    execution_options: &ExecutionOptions,
+    execute: bool,
+) -> Result<Outcome> {
+    let plan = request_plan(
+        provider,
+        prompt,
+        false,
+        execution_options.dedup,
+        &mut PhaseTimer::new(),
+        false,
+    )
+    .await?;
+    if plan.commands.is_empty() {
+        return Ok(Outcome::NoCommands);
+    }
+
+    let messages = crate::ui::messages();
+    crate::ui::preview(
+        &messages.plan_commands,
+        &crate::category::label_commands(&plan.commands),
+    );
+    // Nobody is asked to confirm in a batch, so flagged plans never run
+    let findings = risk::assess_all(&plan.commands);
+    if !findings.is_empty() {
+        print!("{}", risk::render_findings(&findings));
+    }
+    if !execute {
+        return Ok(Outcome::Planned(plan.commands));
+    }
+    if !findings.is_empty() {
+        anyhow::bail!("Not running a plan flagged by the risk check");
+    }
+    crate::policy::check(&plan.commands)?;
+
+    execute_plan(
+        &plan.commands,
+        execution_options,
+        &mut ScriptRecorder::new(),
+    )
+    .await?;
+    Ok(Outcome::Executed(plan.commands))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockProvider;
+
+    /// Provider planning `echo <prompt>` for every prompt it receives
+    fn echo_planner() -> MockProvider {
+        MockProvider::from_fn(|user_prompt| match user_prompt {
+            "fail" => Err(anyhow::anyhow!("provider unavailable")),
+            "chat" => Ok("I can't help with that.".to_string()),
+            _ => Ok(format!("COMMAND: echo '{user_prompt}'")),
+        })
+    }
+
+    #[test]
+    fn test_parse_batch_file() {
+        let content = "# nightly chores\nlist the logs\n\n   disk usage of /var  \n# done\n";
+        assert_eq!(
+            parse_batch_file(content),
+            vec![
+                "list the logs".to_string(),
+                "disk usage of /var".to_string()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_dispatches_each_prompt() {
+        let prompts = parse_batch_file("list the logs\nfail\nchat\nshow the date\n");
+        let (provider, received) = echo_planner().into_query_provider();
+        let options = BatchOptions {
+            jobs: 2,
+            ..Default::default()
+        };
+
+        let summary = run_batch(&prompts, &provider, &ExecutionOptions::default(), &options).await;
+
+        let mut received = received.user_prompts();
+        received.sort();
+        let mut expected = prompts.clone();
+        expected.sort();
+        assert_eq!(received, expected);
+
+        let outcomes: Vec<&Outcome> = summary.results.iter().map(|r| &r.outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                &Outcome::Planned(vec!["echo 'list the logs'".to_string()]),
+                &Outcome::Failed(
+                    "Failed to get orchestration plan from AI: provider unavailable".to_string()
+                ),
+                &Outcome::NoCommands,
+                &Outcome::Planned(vec!["echo 'show the date'".to_string()]),
+            ]
+        );
+        assert_eq!(summary.failed(), 1);
+        assert!(summary
+            .render()
+            .starts_with("📊 Batch summary: 4 prompts, 0 executed, 2 planned only, 1 without commands, 1 failed\n"));
+    }
+
+    #[tokio::test]
+    async fn test_batch_with_yes_executes_plans() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let prompts = vec!["first".to_string(), "second".to_string()];
+        let (provider, _) = echo_planner().into_query_provider();
+        let execution_options = ExecutionOptions {
+            working_dir: Some(temp_dir.path().to_path_buf()),
+            quiet: true,
+            ..Default::default()
+        };
+        let options = BatchOptions {
+            yes: true,
+            ..Default::default()
+        };
+
+        let summary = run_batch(&prompts, &provider, &execution_options, &options).await;
+        assert!(summary.is_ok());
+        assert_eq!(
+            summary.results[1].outcome,
+            Outcome::Executed(vec!["echo 'second'".to_string()])
+        );
+    }
+}
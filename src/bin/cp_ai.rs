@@ -1,8 +1,12 @@
 use anyhow::{Context, Result};
 use clap::{Arg, Command};
+use std::io::IsTerminal;
+use std::path::Path;
+use std::time::Instant;
 use terminalai::{
-    command_parser, command_validator, extract_and_execute_command, load_config,
-    query_provider::QueryProvider,
+    command_parser, command_validator, copy_verify::VerifyMode, extract_and_execute_command_timed,
+    extract_commands_from_response, load_config, query_provider::QueryProvider, search_scope,
+    selection, timing::PhaseTimer, transcript::Transcript, ui, ExecutionOptions,
 };
 
 #[tokio::main]
@@ -11,39 +15,218 @@ async fn main() -> Result<()> {
         .version("0.1.0")
         .author("Terminal AI Contributors")
         .about("AI-powered copy operations")
+        .args(terminalai::cli::common_args())
         .arg(
             Arg::new("prompt")
                 .help("Natural language description of the copy operation")
                 .required(true)
                 .index(1),
         )
+        .arg(
+            Arg::new("show-reasoning")
+                .long("show-reasoning")
+                .help("Print the model's reasoning (<think> blocks) before the commands")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("git-summary")
+                .long("git-summary")
+                .help("Inside a git work tree, list the files the commands created, modified or deleted")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("record")
+                .long("record")
+                .value_name("FILE")
+                .help("Save a timestamped transcript of the executed commands and their output to FILE"),
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .help("Refuse commands the risk check flags (sudo, rm -rf, $(...), device writes, ...) instead of asking")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .help("Print how long config loading, the provider, extraction and execution took")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("verify")
+                .long("verify")
+                .help("After copying, check that every destination file exists with the source's size")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("verify-contents")
+                .long("verify-contents")
+                .help("Like --verify, but also compare file contents byte for byte")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("follow-symlinks")
+                .long("follow-symlinks")
+                .help("Make the generated commands copy the files links point to instead of the links themselves (-L)")
+                .conflicts_with("no-follow-symlinks")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-follow-symlinks")
+                .long("no-follow-symlinks")
+                .help("Make the generated commands act on symlinks themselves (-P, the default)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("stdin-paths")
+                .long("stdin-paths")
+                .help("Copy the paths piped on stdin, one per line (e.g. from find_ai --emit-paths); the prompt says where to")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("directory")
+                .short('C')
+                .long("directory")
+                .value_name("DIR")
+                .help("Run the generated commands in DIR instead of the current directory"),
+        )
+        .arg(
+            Arg::new("print")
+                .long("print")
+                .help("Print only the generated commands, one per line, without running them (e.g. CMD=$(cp_ai --print \"...\"))")
+                .conflicts_with_all(["show-reasoning", "profile"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Show the suggested commands with an explanation of each, without running them")
+                .conflicts_with("print")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(terminalai::cli::breakdown_arg().conflicts_with_all(["print", "dry-run"]))
         .get_matches();
+    let mut timer = PhaseTimer::new();
 
     let prompt = matches.get_one::<String>("prompt").unwrap();
+    let print = matches.get_flag("print");
+    if print {
+        ui::use_pipeline_mode();
+    }
+    let mut execution_options =
+        ExecutionOptions::for_directory(matches.get_one::<String>("directory"))?;
+    execution_options.git_summary = matches.get_flag("git-summary");
+    execution_options.strict = matches.get_flag("strict");
+    if let Some(path) = matches.get_one::<String>("record") {
+        execution_options.transcript = Some(Transcript::open_shared(Path::new(path))?);
+    }
+    if matches.get_flag("verify-contents") {
+        execution_options.verify_copies = Some(VerifyMode::Contents);
+    } else if matches.get_flag("verify") {
+        execution_options.verify_copies = Some(VerifyMode::Size);
+    }
+
+    // With --stdin-paths the paths say what to copy and the prompt only where to
+    let query = if matches.get_flag("stdin-paths") {
+        match stdin_paths_query(prompt) {
+            Ok(query) => query,
+            Err(e) => {
+                eprintln!("❌ {e:#}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        prompt.to_string()
+    };
 
     // Validate that this is a copy-related query
-    if let Err(e) = command_validator::validate_cp_query(prompt) {
+    let validated = if matches.get_flag("stdin-paths") {
+        format!("copy {prompt}")
+    } else {
+        prompt.to_string()
+    };
+    if let Err(e) = command_validator::validate_cp_query(&validated) {
         eprintln!("❌ {e}");
         std::process::exit(1);
     }
 
     // Load configuration
-    let config = load_config()?;
+    let config = timer.time("config load", load_config)?;
+    terminalai::cli::configure_from_matches(&config, &matches)?;
+    execution_options.error_signatures = config.error_signatures();
+    execution_options.dedup = config.command_dedup();
+    execution_options.history = config.history_origin("cp_ai", prompt);
+    execution_options.auto_execute_installs = config.auto_execute_installs();
+    execution_options.confirm_each = config.confirm_each_command();
 
     // Load command definition
     let (system_prompt, _args_section) = command_parser::load_command_definition("cp")?;
+    let system_prompt = command_parser::system_prompt_for(&config, system_prompt)?;
 
     // Create query provider
-    let provider = QueryProvider::new(config).context("Failed to create query provider")?;
+    let language = config.output_language();
+    let provider = timer
+        .time("provider setup", || QueryProvider::new(config))
+        .context("Failed to create query provider")?;
 
-    println!("🤖 Processing your copy request...\n");
+    ui::show("🤖 Processing your copy request...\n\n");
 
     // Send query to AI
-    match provider.send_query(&system_prompt, prompt).await {
+    let query_started = Instant::now();
+    let response = provider
+        .send_command_query(&system_prompt, &query, extract_commands_from_response)
+        .await;
+    timer.record("provider query", query_started);
+
+    match response {
         Ok(response) => {
-            // Extract and execute commands
-            if let Err(e) = extract_and_execute_command(&response) {
+            if matches.get_flag("show-reasoning") {
+                terminalai::print_reasoning(&response);
+            }
+            let response = search_scope::apply_symlinks_in_response(
+                &response,
+                search_scope::symlink_mode(matches.get_flag("follow-symlinks")),
+            );
+            // Extract and execute commands, or only print them
+            if print {
+                let printed = terminalai::print_commands(
+                    &response,
+                    execution_options.dedup,
+                    &mut std::io::stdout().lock(),
+                );
+                if let Err(e) = printed {
+                    eprintln!("❌ Error: {e:#}");
+                    std::process::exit(1);
+                }
+            } else if matches.get_flag("breakdown") {
+                let explained = terminalai::explain::explain_response(
+                    &response,
+                    execution_options.dedup,
+                    &provider,
+                    language.as_deref(),
+                )
+                .await;
+                if let Err(e) = explained {
+                    eprintln!("❌ Error: {e:#}");
+                    std::process::exit(1);
+                }
+            } else if matches.get_flag("dry-run") {
+                let dry_run = terminalai::explain::dry_run(
+                    &response,
+                    execution_options.dedup,
+                    &provider,
+                    language.as_deref(),
+                )
+                .await;
+                if let Err(e) = dry_run {
+                    eprintln!("❌ Error: {e:#}");
+                    std::process::exit(1);
+                }
+            } else if let Err(e) =
+                extract_and_execute_command_timed(&response, &execution_options, &mut timer)
+            {
                 eprintln!("❌ Error executing commands: {e}");
+                std::process::exit(1);
             }
         }
         Err(e) => {
@@ -54,5 +237,28 @@ async fn main() -> Result<()> {
         }
     }
 
+    if matches.get_flag("profile") {
+        println!("\n{}", timer.summary());
+    }
+
     Ok(())
 }
+
+/// The copy request for paths piped on stdin. Stdin is used up by the paths, so
+/// confirmations are asked on the terminal from here on.
+fn stdin_paths_query(prompt: &str) -> Result<String> {
+    if std::io::stdin().is_terminal() {
+        anyhow::bail!("--stdin-paths expects paths piped on stdin, e.g. find_ai --emit-paths \"...\" | cp_ai --stdin-paths \"{prompt}\"");
+    }
+    let paths = selection::read_paths(std::io::stdin().lock())?;
+    if paths.is_empty() {
+        anyhow::bail!("No paths were piped on stdin; nothing to copy");
+    }
+    ui::use_pipeline_mode();
+    ui::show(&format!("📥 Read {} paths from stdin\n", paths.len()));
+    Ok(selection::followup_prompt(
+        &format!("Copy these paths {prompt}"),
+        &paths,
+        None,
+    ))
+}
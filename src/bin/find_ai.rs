@@ -1,8 +1,16 @@
 use anyhow::{Context, Result};
 use clap::{Arg, Command};
+use std::path::Path;
+use std::time::Instant;
 use terminalai::{
-    command_parser, command_validator, extract_and_execute_command, load_config,
+    command_parser, command_validator, dedup_commands, extract_and_execute_command_timed,
+    extract_commands_from_response, load_config,
+    orchestrator::{self, OrchestrationOptions},
     query_provider::QueryProvider,
+    risk, search_scope, selection,
+    timing::PhaseTimer,
+    transcript::Transcript,
+    ui, ExecutionOptions,
 };
 
 #[tokio::main]
@@ -11,15 +19,120 @@ async fn main() -> Result<()> {
         .version("0.1.0")
         .author("Terminal AI Contributors")
         .about("AI-powered file and directory search operations")
+        .args(terminalai::cli::common_args())
         .arg(
             Arg::new("prompt")
                 .help("Natural language description of what to find")
                 .required(true)
                 .index(1),
         )
+        .arg(
+            Arg::new("show-reasoning")
+                .long("show-reasoning")
+                .help("Print the model's reasoning (<think> blocks) before the commands")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("git-summary")
+                .long("git-summary")
+                .help("Inside a git work tree, list the files the commands created, modified or deleted")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("record")
+                .long("record")
+                .value_name("FILE")
+                .help("Save a timestamped transcript of the executed commands and their output to FILE"),
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .help("Refuse commands the risk check flags (sudo, rm -rf, $(...), device writes, ...) instead of asking")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .help("Print how long config loading, the provider, extraction and execution took")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("directory")
+                .short('C')
+                .long("directory")
+                .value_name("DIR")
+                .help("Run the generated commands in DIR instead of the current directory"),
+        )
+        .arg(
+            Arg::new("path")
+                .long("path")
+                .value_name("DIR")
+                .help("Directory to use as the search root for the generated command"),
+        )
+        .arg(
+            Arg::new("allow-root-scan")
+                .long("allow-root-scan")
+                .help("Allow searching the whole filesystem from / without a depth limit")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("follow-symlinks")
+                .long("follow-symlinks")
+                .help("Make the generated commands descend into and report the files links point to (-L)")
+                .conflicts_with("no-follow-symlinks")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-follow-symlinks")
+                .long("no-follow-symlinks")
+                .help("Make the generated commands act on symlinks themselves (-P, the default)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("emit-paths")
+                .long("emit-paths")
+                .help("Print only the found paths, one per line, for piping into another tool (e.g. cp_ai --stdin-paths)")
+                .conflicts_with_all(["select", "show-reasoning", "profile"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("select")
+                .long("select")
+                .help("Pick from the found paths and describe what to do with them next")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("print")
+                .long("print")
+                .help("Print only the generated commands, one per line, without running them (e.g. CMD=$(find_ai --print \"...\"))")
+                .conflicts_with_all(["emit-paths", "select", "show-reasoning", "profile"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Show the suggested commands with an explanation of each, without running them")
+                .conflicts_with_all(["print", "emit-paths", "select"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(terminalai::cli::breakdown_arg().conflicts_with_all(["print", "emit-paths", "select", "dry-run"]))
         .get_matches();
+    let mut timer = PhaseTimer::new();
 
     let prompt = matches.get_one::<String>("prompt").unwrap();
+    let mut execution_options =
+        ExecutionOptions::for_directory(matches.get_one::<String>("directory"))?;
+    execution_options.git_summary = matches.get_flag("git-summary");
+    execution_options.strict = matches.get_flag("strict");
+    if let Some(path) = matches.get_one::<String>("record") {
+        execution_options.transcript = Some(Transcript::open_shared(Path::new(path))?);
+    }
+    let search_path = matches.get_one::<String>("path");
+    let emit_paths = matches.get_flag("emit-paths");
+    let print = matches.get_flag("print");
+    if emit_paths || print {
+        ui::use_pipeline_mode();
+    }
 
     // Keywords that indicate find/search operations
     let valid_keywords = [
@@ -113,22 +226,148 @@ async fn main() -> Result<()> {
     }
 
     // Load configuration
-    let config = load_config()?;
+    let config = timer.time("config load", load_config)?;
+    terminalai::cli::configure_from_matches(&config, &matches)?;
+    execution_options.error_signatures = config.error_signatures();
+    execution_options.dedup = config.command_dedup();
+    execution_options.history = config.history_origin("find_ai", prompt);
+    execution_options.auto_execute_installs = config.auto_execute_installs();
+    execution_options.confirm_each = config.confirm_each_command();
 
     // Load command definition
     let (system_prompt, _args_section) = command_parser::load_command_definition("find")?;
+    let system_prompt = command_parser::system_prompt_for(&config, system_prompt)?;
 
     // Create query provider
-    let provider = QueryProvider::new(config).context("Failed to create query provider")?;
+    let language = config.output_language();
+    let provider = timer
+        .time("provider setup", || QueryProvider::new(config))
+        .context("Failed to create query provider")?;
 
-    println!("🔍 Processing your search request...\n");
+    // Constrain the search to the requested directory, if any
+    let query = match search_path {
+        Some(path) => {
+            if let Err(e) =
+                search_scope::validate_search_path(path, execution_options.working_dir.as_deref())
+            {
+                eprintln!("❌ {e}");
+                std::process::exit(1);
+            }
+            search_scope::scope_prompt(prompt, path)
+        }
+        None => prompt.to_string(),
+    };
+    // The output becomes a list of paths, so it must be bare paths
+    let query = if matches.get_flag("select") || emit_paths {
+        format!("{query}\n\n{}", selection::PATHS_ONLY_INSTRUCTION)
+    } else {
+        query
+    };
+
+    ui::show("🔍 Processing your search request...\n\n");
 
     // Send query to AI
-    match provider.send_query(&system_prompt, prompt).await {
+    let query_started = Instant::now();
+    let response = provider
+        .send_command_query(&system_prompt, &query, extract_commands_from_response)
+        .await;
+    timer.record("provider query", query_started);
+
+    match response {
         Ok(response) => {
-            // Extract and execute commands
-            if let Err(e) = extract_and_execute_command(&response) {
+            if matches.get_flag("show-reasoning") {
+                terminalai::print_reasoning(&response);
+            }
+            let response = match search_path {
+                Some(path) => search_scope::scope_response_to_path(&response, path),
+                None => response,
+            };
+            let response = if matches.get_flag("allow-root-scan") {
+                response
+            } else {
+                let limited = search_scope::limit_root_scans_in_response(
+                    &response,
+                    search_scope::DEFAULT_ROOT_SCAN_MAXDEPTH,
+                );
+                if limited != response {
+                    ui::show(&format!(
+                        "⚠️  Limited search from / to -maxdepth {} (use --allow-root-scan to search everything)\n\n",
+                        search_scope::DEFAULT_ROOT_SCAN_MAXDEPTH
+                    ));
+                }
+                limited
+            };
+            let response = search_scope::apply_symlinks_in_response(
+                &response,
+                search_scope::symlink_mode(matches.get_flag("follow-symlinks")),
+            );
+
+            if print {
+                let printed = terminalai::print_commands(
+                    &response,
+                    execution_options.dedup,
+                    &mut std::io::stdout().lock(),
+                );
+                if let Err(e) = printed {
+                    eprintln!("❌ Error: {e:#}");
+                    std::process::exit(1);
+                }
+            } else if matches.get_flag("breakdown") {
+                let explained = terminalai::explain::explain_response(
+                    &response,
+                    execution_options.dedup,
+                    &provider,
+                    language.as_deref(),
+                )
+                .await;
+                if let Err(e) = explained {
+                    eprintln!("❌ Error: {e:#}");
+                    std::process::exit(1);
+                }
+            } else if matches.get_flag("dry-run") {
+                let dry_run = terminalai::explain::dry_run(
+                    &response,
+                    execution_options.dedup,
+                    &provider,
+                    language.as_deref(),
+                )
+                .await;
+                if let Err(e) = dry_run {
+                    eprintln!("❌ Error: {e:#}");
+                    std::process::exit(1);
+                }
+            } else if emit_paths {
+                let emitted = match confirm_and_collect(&response, &execution_options).await {
+                    Ok(Some(paths)) => {
+                        selection::write_paths(&paths, &mut std::io::stdout().lock())
+                            .context("Failed to write paths")
+                    }
+                    Ok(None) => Ok(()),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = emitted {
+                    eprintln!("❌ Error: {e:#}");
+                    std::process::exit(1);
+                }
+            } else if matches.get_flag("select") {
+                let followup = OrchestrationOptions {
+                    no_branding: matches.get_flag("no-branding"),
+                    git_summary: execution_options.git_summary,
+                    strict: execution_options.strict,
+                    no_context: matches.get_flag("no-context"),
+                    ..Default::default()
+                };
+                if let Err(e) = select_and_follow_up(&response, &execution_options, &followup).await
+                {
+                    eprintln!("❌ Error: {e:#}");
+                    std::process::exit(1);
+                }
+            } else if let Err(e) =
+                extract_and_execute_command_timed(&response, &execution_options, &mut timer)
+            {
+                // Extract and execute commands
                 eprintln!("❌ Error executing commands: {e}");
+                std::process::exit(1);
             }
         }
         Err(e) => {
@@ -139,5 +378,73 @@ async fn main() -> Result<()> {
         }
     }
 
+    if matches.get_flag("profile") {
+        println!("\n{}", timer.summary());
+    }
+
     Ok(())
 }
+
+/// Confirm the search commands, run them and return the paths they found;
+/// `None` when nothing ran
+async fn confirm_and_collect(
+    response: &str,
+    execution_options: &ExecutionOptions,
+) -> Result<Option<Vec<String>>> {
+    let commands = dedup_commands(
+        extract_commands_from_response(response),
+        execution_options.dedup,
+    );
+    if commands.is_empty() {
+        ui::show(&format!(
+            "⚠️  No executable commands found in AI response.\n💡 AI Response:\n{response}\n"
+        ));
+        return Ok(None);
+    }
+    terminalai::check_plan_size(&commands)?;
+
+    let messages = ui::messages();
+    ui::preview(&messages.suggested_commands, &commands);
+    if !risk::confirm_commands(
+        &commands,
+        execution_options.strict,
+        &messages.confirm_execute,
+    )? {
+        ui::show(&format!("{}\n", messages.not_executed));
+        return Ok(None);
+    }
+
+    selection::collect_paths(&commands, execution_options)
+        .await
+        .map(Some)
+}
+
+/// Run the search, let the user pick from what it found, then hand the chosen
+/// paths and a follow-up request to the orchestrator
+async fn select_and_follow_up(
+    response: &str,
+    execution_options: &ExecutionOptions,
+    followup: &OrchestrationOptions,
+) -> Result<()> {
+    let Some(paths) = confirm_and_collect(response, execution_options).await? else {
+        return Ok(());
+    };
+    if paths.is_empty() {
+        println!("🔍 Nothing found.");
+        return Ok(());
+    }
+    println!("\n🔍 Found {} paths\n", paths.len());
+
+    let selected = selection::choose(&paths)?;
+    if selected.is_empty() {
+        println!("Nothing selected.");
+        return Ok(());
+    }
+    let Some(action) = selection::ask_followup()? else {
+        return Ok(());
+    };
+
+    let prompt =
+        selection::followup_prompt(&action, &selected, execution_options.working_dir.as_deref());
+    orchestrator::orchestrate_query_with_options(&prompt, followup).await
+}
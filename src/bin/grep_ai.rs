@@ -1,8 +1,11 @@
 use anyhow::{Context, Result};
 use clap::{Arg, Command};
+use std::path::Path;
+use std::time::Instant;
 use terminalai::{
-    command_parser, command_validator, extract_and_execute_command, load_config,
-    query_provider::QueryProvider,
+    command_parser, command_validator, extract_and_execute_command_timed,
+    extract_commands_from_response, load_config, query_provider::QueryProvider, search_scope,
+    timing::PhaseTimer, transcript::Transcript, ui, ExecutionOptions,
 };
 
 #[tokio::main]
@@ -11,15 +14,95 @@ async fn main() -> Result<()> {
         .version("0.1.0")
         .author("Terminal AI Contributors")
         .about("AI-powered text search operations")
+        .args(terminalai::cli::common_args())
         .arg(
             Arg::new("prompt")
                 .help("Natural language description of the search operation")
                 .required(true)
                 .index(1),
         )
+        .arg(
+            Arg::new("show-reasoning")
+                .long("show-reasoning")
+                .help("Print the model's reasoning (<think> blocks) before the commands")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("git-summary")
+                .long("git-summary")
+                .help("Inside a git work tree, list the files the commands created, modified or deleted")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("record")
+                .long("record")
+                .value_name("FILE")
+                .help("Save a timestamped transcript of the executed commands and their output to FILE"),
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .help("Refuse commands the risk check flags (sudo, rm -rf, $(...), device writes, ...) instead of asking")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .help("Print how long config loading, the provider, extraction and execution took")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("directory")
+                .short('C')
+                .long("directory")
+                .value_name("DIR")
+                .help("Run the generated commands in DIR instead of the current directory"),
+        )
+        .arg(
+            Arg::new("path")
+                .long("path")
+                .value_name("DIR")
+                .help("Directory to use as the search root for the generated command"),
+        )
+        .arg(
+            Arg::new("context")
+                .long("context")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u32))
+                .help("Show N lines around each match (-C N in the generated command)"),
+        )
+        .arg(
+            Arg::new("print")
+                .long("print")
+                .help("Print only the generated commands, one per line, without running them (e.g. CMD=$(grep_ai --print \"...\"))")
+                .conflicts_with_all(["show-reasoning", "profile"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Show the suggested commands with an explanation of each, without running them")
+                .conflicts_with("print")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(terminalai::cli::breakdown_arg().conflicts_with_all(["print", "dry-run"]))
         .get_matches();
+    let mut timer = PhaseTimer::new();
 
     let prompt = matches.get_one::<String>("prompt").unwrap();
+    let print = matches.get_flag("print");
+    if print {
+        ui::use_pipeline_mode();
+    }
+    let mut execution_options =
+        ExecutionOptions::for_directory(matches.get_one::<String>("directory"))?;
+    execution_options.git_summary = matches.get_flag("git-summary");
+    execution_options.strict = matches.get_flag("strict");
+    if let Some(path) = matches.get_one::<String>("record") {
+        execution_options.transcript = Some(Transcript::open_shared(Path::new(path))?);
+    }
+    let search_path = matches.get_one::<String>("path");
+    let context_lines = matches.get_one::<u32>("context").copied();
 
     // Validate that this is a search-related query
     if let Err(e) = command_validator::validate_grep_query(prompt) {
@@ -28,22 +111,106 @@ async fn main() -> Result<()> {
     }
 
     // Load configuration
-    let config = load_config()?;
+    let config = timer.time("config load", load_config)?;
+    terminalai::cli::configure_from_matches(&config, &matches)?;
+    execution_options.error_signatures = config.error_signatures();
+    execution_options.dedup = config.command_dedup();
+    execution_options.history = config.history_origin("grep_ai", prompt);
+    execution_options.auto_execute_installs = config.auto_execute_installs();
+    execution_options.confirm_each = config.confirm_each_command();
 
     // Load command definition
     let (system_prompt, _args_section) = command_parser::load_command_definition("grep")?;
+    let system_prompt = command_parser::system_prompt_for(&config, system_prompt)?;
 
     // Create query provider
-    let provider = QueryProvider::new(config).context("Failed to create query provider")?;
+    let language = config.output_language();
+    let provider = timer
+        .time("provider setup", || QueryProvider::new(config))
+        .context("Failed to create query provider")?;
 
-    println!("🔍 Processing your search request...\n");
+    // Constrain the search to the requested directory, if any
+    let query = match search_path {
+        Some(path) => {
+            if let Err(e) =
+                search_scope::validate_search_path(path, execution_options.working_dir.as_deref())
+            {
+                eprintln!("❌ {e}");
+                std::process::exit(1);
+            }
+            search_scope::scope_prompt(prompt, path)
+        }
+        None => prompt.to_string(),
+    };
+    let query = if context_lines.is_some() || search_scope::wants_context(prompt) {
+        search_scope::context_prompt(&query, context_lines)
+    } else {
+        query
+    };
+
+    ui::show("🔍 Processing your search request...\n\n");
 
     // Send query to AI
-    match provider.send_query(&system_prompt, prompt).await {
+    let query_started = Instant::now();
+    let response = provider
+        .send_command_query(&system_prompt, &query, extract_commands_from_response)
+        .await;
+    timer.record("provider query", query_started);
+
+    match response {
         Ok(response) => {
-            // Extract and execute commands
-            if let Err(e) = extract_and_execute_command(&response) {
+            if matches.get_flag("show-reasoning") {
+                terminalai::print_reasoning(&response);
+            }
+            let response = match search_path {
+                Some(path) => search_scope::scope_response_to_path(&response, path),
+                None => response,
+            };
+            let response = match context_lines {
+                Some(lines) => search_scope::context_in_response(&response, lines),
+                None => response,
+            };
+
+            // Extract and execute commands, or only print them
+            if print {
+                let printed = terminalai::print_commands(
+                    &response,
+                    execution_options.dedup,
+                    &mut std::io::stdout().lock(),
+                );
+                if let Err(e) = printed {
+                    eprintln!("❌ Error: {e:#}");
+                    std::process::exit(1);
+                }
+            } else if matches.get_flag("breakdown") {
+                let explained = terminalai::explain::explain_response(
+                    &response,
+                    execution_options.dedup,
+                    &provider,
+                    language.as_deref(),
+                )
+                .await;
+                if let Err(e) = explained {
+                    eprintln!("❌ Error: {e:#}");
+                    std::process::exit(1);
+                }
+            } else if matches.get_flag("dry-run") {
+                let dry_run = terminalai::explain::dry_run(
+                    &response,
+                    execution_options.dedup,
+                    &provider,
+                    language.as_deref(),
+                )
+                .await;
+                if let Err(e) = dry_run {
+                    eprintln!("❌ Error: {e:#}");
+                    std::process::exit(1);
+                }
+            } else if let Err(e) =
+                extract_and_execute_command_timed(&response, &execution_options, &mut timer)
+            {
                 eprintln!("❌ Error executing commands: {e}");
+                std::process::exit(1);
             }
         }
         Err(e) => {
@@ -54,5 +221,9 @@ async fn main() -> Result<()> {
         }
     }
 
+    if matches.get_flag("profile") {
+        println!("\n{}", timer.summary());
+    }
+
     Ok(())
 }
@@ -2,8 +2,16 @@ use anyhow::{Context, Result};
 use clap::{Arg, Command};
 use colored::*;
 use std::path::Path;
-use std::process::Command as StdCommand;
-use terminalai::{command_parser, command_validator, load_config, query_provider::QueryProvider};
+use terminalai::{
+    command_parser, command_validator,
+    explain::{self, request_command_explanations},
+    load_config, localized_prompt,
+    query_provider::QueryProvider,
+    registry::{Existence, Registry, RegistryClient},
+    risk, runtime,
+    script::ScriptRecorder,
+    shell, ui,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -11,12 +19,13 @@ async fn main() -> Result<()> {
         .version("0.1.0")
         .author("Terminal AI Contributors")
         .about("AI-powered package dependency resolution")
+        .args(terminalai::cli::common_args())
         .arg(
             Arg::new("type")
                 .short('t')
                 .long("type")
-                .help("Package manager type (npm or python)")
-                .value_parser(["npm", "python"])
+                .help("Package manager type (npm or python), or 'runtime' to install a language runtime via mise/asdf")
+                .value_parser(["npm", "python", "runtime"])
                 .value_name("TYPE"),
         )
         .arg(
@@ -41,6 +50,77 @@ async fn main() -> Result<()> {
                 .value_parser(["venv", "conda"])
                 .value_name("ENV"),
         )
+        .arg(
+            Arg::new("python-client")
+                .long("python-client")
+                .help("Python installer to use: pip (default) or uv. uv is picked automatically for projects with a uv.lock")
+                .value_parser(["pip", "uv"])
+                .value_name("CLIENT"),
+        )
+        .arg(
+            Arg::new("emit-script")
+                .long("emit-script")
+                .help("Write the executed commands to a runnable shell script")
+                .value_name("PATH"),
+        )
+        .arg(
+            Arg::new("report")
+                .long("report")
+                .help("Write the packages installed during the run to a file")
+                .value_name("PATH"),
+        )
+        .arg(
+            Arg::new("verify-max-lines")
+                .long("verify-max-lines")
+                .help("Maximum lines of verification output to print")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("20"),
+        )
+        .arg(
+            Arg::new("check-registry")
+                .long("check-registry")
+                .help("Check that the package, and each package the AI installs, exists on PyPI or npm")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("explain")
+                .long("explain")
+                .help("Ask the AI for a one-line explanation of each command before confirming")
+                .conflicts_with("dry-run")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Show the suggested commands with an explanation of each, without running them")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("output-language")
+                .long("output-language")
+                .value_name("LANG")
+                .help("Write --explain explanations and the failure summary in LANG (e.g. German)"),
+        )
+        .arg(
+            Arg::new("max-cost")
+                .long("max-cost")
+                .value_name("USD")
+                .value_parser(clap::value_parser!(f64))
+                .help("Stop asking the AI for fixes once the estimated spend reaches USD"),
+        )
+        .arg(
+            Arg::new("post-success")
+                .long("post-success")
+                .value_name("COMMAND")
+                .help("Run COMMAND (e.g. 'npm test') once the installation is verified"),
+        )
+        .arg(
+            Arg::new("no-summary")
+                .long("no-summary")
+                .help("Skip the AI-generated summary when all resolution attempts fail")
+                .action(clap::ArgAction::SetTrue),
+        )
         .group(
             clap::ArgGroup::new("input_mode")
                 .args(["type", "package"])
@@ -55,11 +135,48 @@ async fn main() -> Result<()> {
         )
         .get_matches();
 
-    // Get environment preference (default to venv/pip)
+    // Get environment preference (default to venv/pip); uv replaces pip when requested
     let env_type = matches
         .get_one::<String>("env")
         .map(|s| s.as_str())
         .unwrap_or("venv");
+    let python_client = matches
+        .get_one::<String>("python-client")
+        .map(String::as_str);
+    if python_client == Some("uv") && env_type == "conda" {
+        eprintln!("❌ --python-client uv cannot be combined with -e conda");
+        std::process::exit(1);
+    }
+    let env_type = match (python_client, matches.get_one::<String>("file")) {
+        (Some("uv"), _) => "uv",
+        (None, Some(file_path)) if env_type == "venv" && uses_uv(file_path) => {
+            println!("🔧 Found uv.lock, using uv for Python packages");
+            "uv"
+        }
+        _ => env_type,
+    };
+
+    // Load configuration
+    let config = load_config()?;
+    terminalai::cli::configure_from_matches(&config, &matches)?;
+
+    let mut options = ResolveOptions {
+        env_type,
+        show_summary: !matches.get_flag("no-summary"),
+        explain: matches.get_flag("explain"),
+        dry_run: matches.get_flag("dry-run"),
+        output_language: matches
+            .get_one::<String>("output-language")
+            .cloned()
+            .or_else(|| config.output_language()),
+        verify_max_lines: *matches.get_one::<usize>("verify-max-lines").unwrap(),
+        post_success: matches.get_one::<String>("post-success").cloned(),
+        recorder: ScriptRecorder::new(),
+        pip: pip_invocation(detect_python_launcher(shell::is_available)),
+        auto_execute_installs: config.auto_execute_installs(),
+        installed: InstalledPackages::default(),
+        check_registry: matches.get_flag("check-registry"),
+    };
 
     // Handle different input modes
     let (package_type, package, is_file_mode) = if let Some(file_path) =
@@ -77,6 +194,48 @@ async fn main() -> Result<()> {
             .get_one::<String>("package")
             .ok_or_else(|| anyhow::anyhow!("Package is required when not using file mode"))?;
 
+        // Language runtimes are installed through a version manager, not a package manager
+        match runtime::parse_runtime_spec(package) {
+            Some(spec) => {
+                if let Some(manager) = runtime::detect_version_manager() {
+                    if options.dry_run {
+                        explain::show_dry_run(
+                            &ui::messages().suggested_commands,
+                            &runtime::runtime_install_commands(&spec, manager),
+                            &[],
+                        );
+                        return Ok(());
+                    }
+                    let result = install_runtime(
+                        &spec,
+                        manager,
+                        &mut options,
+                        config.history_origin("resolve_ai", package).as_ref(),
+                    );
+                    save_script(matches.get_one::<String>("emit-script"), &options.recorder)?;
+                    report_installed(matches.get_one::<String>("report"), &options.installed)?;
+                    if let Err(e) = result {
+                        eprintln!("❌ Error installing {} {}: {e}", spec.runtime, spec.version);
+                        std::process::exit(1);
+                    }
+                    return Ok(());
+                } else if package_type == "runtime" {
+                    eprintln!(
+                        "❌ No supported version manager found. Install mise (https://mise.jdx.dev) or asdf (https://asdf-vm.com) to manage {} versions.",
+                        spec.runtime
+                    );
+                    std::process::exit(1);
+                }
+            }
+            None if package_type == "runtime" => {
+                eprintln!(
+                    "❌ '{package}' is not a supported runtime. Use python==<version>, node@<version> or ruby@<version>"
+                );
+                std::process::exit(1);
+            }
+            None => {}
+        }
+
         // Validate that this is a package resolution query
         if let Err(e) = command_validator::validate_resolve_query(package_type, package) {
             eprintln!("❌ {e}");
@@ -99,17 +258,24 @@ async fn main() -> Result<()> {
                 package.clone()
             };
 
+        if let Some(registry) =
+            Registry::for_package_type(package_type).filter(|_| options.check_registry)
+        {
+            check_package_exists(registry, &final_package).await;
+        }
+
         (package_type.clone(), final_package, false)
     };
 
-    // Load configuration
-    let config = load_config()?;
-
     // Load command definition
     let (system_prompt, _args_section) = command_parser::load_command_definition("resolve")?;
+    let system_prompt = command_parser::system_prompt_for(&config, system_prompt)?;
 
     // Create query provider
-    let provider = QueryProvider::new(config).context("Failed to create query provider")?;
+    let mut provider = QueryProvider::new(config).context("Failed to create query provider")?;
+    if let Some(max_cost) = matches.get_one::<f64>("max-cost") {
+        provider = provider.with_max_cost(*max_cost)?;
+    }
 
     println!("🤖 Processing your package resolution request...\n");
 
@@ -124,10 +290,7 @@ async fn main() -> Result<()> {
     // Create a concise prompt for the AI - start with BASIC installation only
     let prompt = if is_file_mode {
         let package_manager = if package_type == "python" {
-            match env_type {
-                "conda" => "conda",
-                _ => "pip",
-            }
+            python_installer(env_type)
         } else {
             "npm"
         };
@@ -148,7 +311,8 @@ async fn main() -> Result<()> {
                 let pkg_name = extract_package_name(&package);
                 match env_type {
                     "conda" => format!("\n\nNOTE: Using conda environment as specified:\n- conda install {pkg_name}"),
-                    _ => format!("\n\nNOTE: Using pip (default) for Python packages:\n- pip install {pkg_name}")
+                    "uv" => format!("\n\nNOTE: Using uv as specified:\n- uv pip install {pkg_name}"),
+                    _ => format!("\n\nNOTE: Using pip (default) for Python packages:\n- {} install {pkg_name}", options.pip)
                 }
             }
         } else if package_type == "npm"
@@ -160,33 +324,42 @@ async fn main() -> Result<()> {
         };
 
         let package_manager = if package_type == "python" {
-            match env_type {
-                "conda" => "conda",
-                _ => "pip",
-            }
+            python_installer(env_type)
         } else {
             "npm"
         };
+        let example = install_example(package_manager, &package, &options.pip);
         format!(
-            "Generate the BASIC installation command for {package_type} package '{package}' using {package_manager}. Start with the standard installation command only (e.g., '{package_manager} install {package}'). Do NOT include cache clearing, purging, upgrade pip, or force reinstall commands - these will be used only if the basic installation fails. Provide ONLY the basic executable command.{upfront_detection}"
+            "Generate the BASIC installation command for {package_type} package '{package}' using {package_manager}. Start with the standard installation command only (e.g., '{example}'). Do NOT include cache clearing, purging, upgrade pip, or force reinstall commands - these will be used only if the basic installation fails. Provide ONLY the basic executable command.{upfront_detection}"
         )
     };
 
     // Send query to AI
-    match provider.send_query(&system_prompt, &prompt).await {
+    match provider
+        .send_command_query(
+            &system_prompt,
+            &prompt,
+            terminalai::extract_commands_from_response,
+        )
+        .await
+    {
         Ok(response) => {
             // Extract and execute commands with iterative approach
-            if let Err(e) = execute_resolution_commands(
+            let result = execute_resolution_commands(
                 &response,
                 &package_type,
                 &package,
                 is_file_mode,
-                env_type,
                 &provider,
                 &system_prompt,
+                &mut options,
             )
-            .await
-            {
+            .await;
+
+            save_script(matches.get_one::<String>("emit-script"), &options.recorder)?;
+            report_installed(matches.get_one::<String>("report"), &options.installed)?;
+
+            if let Err(e) = result {
                 eprintln!("❌ Error executing resolution commands: {e}");
                 std::process::exit(1);
             }
@@ -202,6 +375,85 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Print what the run installed and write it to the `--report` path, if one was given
+fn report_installed(report_path: Option<&String>, installed: &InstalledPackages) -> Result<()> {
+    let summary = installed.summary();
+    if let Some(summary) = &summary {
+        println!("📦 {summary}");
+    }
+    if let Some(report_path) = report_path {
+        let report = summary.unwrap_or_else(|| "Installed: nothing".to_string());
+        std::fs::write(report_path, format!("{report}\n"))
+            .with_context(|| format!("Failed to write report to {report_path}"))?;
+        println!("📝 Installation report saved to {report_path}");
+    }
+    Ok(())
+}
+
+/// Write the executed commands to the `--emit-script` path, if one was given
+fn save_script(script_path: Option<&String>, recorder: &ScriptRecorder) -> Result<()> {
+    if let Some(script_path) = script_path {
+        recorder.write_to(Path::new(script_path))?;
+        println!("📝 Executed commands saved to {script_path}");
+    }
+    Ok(())
+}
+
+/// Install a language runtime with the detected version manager. The commands that
+/// ran are recorded for `--emit-script` and the history, and the runtime for the
+/// installed summary.
+fn install_runtime(
+    spec: &runtime::RuntimeSpec,
+    manager: runtime::VersionManager,
+    options: &mut ResolveOptions,
+    history: Option<&terminalai::history::Origin>,
+) -> Result<()> {
+    println!(
+        "🧰 '{}' {} is a language runtime; installing it with {manager}",
+        spec.runtime, spec.version
+    );
+
+    let commands = runtime::runtime_install_commands(spec, manager);
+    terminalai::policy::check(&commands)?;
+    let messages = ui::messages();
+    ui::preview(&messages.suggested_commands, &commands);
+    if !ui::confirm(&messages.confirm_execute) {
+        println!("{}", messages.not_executed);
+        return Ok(());
+    }
+
+    let mut executed = Vec::new();
+    let result = (|| {
+        for cmd in &commands {
+            if !terminalai::confirm_install(cmd, options.auto_execute_installs) {
+                println!("{}", messages.not_executed);
+                return Ok(());
+            }
+            executed.push(cmd.clone());
+            if let Err(e) = terminalai::execute_command_with_live_output(cmd) {
+                options.recorder.record_failed(cmd);
+                return Err(e);
+            }
+            options.recorder.record(cmd);
+        }
+        options
+            .installed
+            .add(format!("{}@{}", spec.runtime, spec.version));
+        println!(
+            "🎉 {} {} installed with {manager}",
+            spec.runtime, spec.version
+        );
+        Ok(())
+    })();
+
+    if let Some(origin) = history.filter(|_| !executed.is_empty()) {
+        let entry = terminalai::history::HistoryEntry::new(origin, &executed, None, result.is_ok())
+            .with_exit_codes(&result);
+        terminalai::history::record(&entry);
+    }
+    result
+}
+
 /// Check for common invalid packages and provide immediate feedback
 fn check_for_common_invalid_packages(package_type: &str, package: &str) -> Option<String> {
     if package_type == "python" {
@@ -231,6 +483,37 @@ fn check_for_common_invalid_packages(package_type: &str, package: &str) -> Optio
     None
 }
 
+/// Look the package up in its registry and warn, with the closest match, when it does
+/// not exist. The install is still offered, so a private index or a registry outage
+/// never blocks it.
+async fn check_package_exists(registry: Registry, package: &str) {
+    let name = registry.package_name(package);
+    match RegistryClient::new().check(registry, package).await {
+        Ok(Existence::Exists) => println!("🔎 '{name}' exists on {registry}"),
+        Ok(Existence::Missing { suggestion }) => {
+            println!("⚠️  Package '{name}' was not found on {registry}.");
+            if let Some(suggestion) = suggestion {
+                println!("💡 Did you mean '{suggestion}'?");
+            }
+        }
+        Err(e) => println!("⚠️  Could not check {registry} for '{name}': {e}"),
+    }
+}
+
+/// The registry and spec of a command installing exactly one PyPI or npm package,
+/// e.g. `(PyPI, "requests==2.31.0")` for `pip install requests==2.31.0`
+fn single_registry_install(command: &str) -> Option<(Registry, String)> {
+    let registry = command.split_whitespace().find_map(|word| match word {
+        "pip" | "pip3" | "uv" | "poetry" | "pipenv" => Some(Registry::PyPI),
+        "npm" | "yarn" | "pnpm" => Some(Registry::Npm),
+        _ => None,
+    })?;
+    match installed_specs(command).as_slice() {
+        [spec] => Some((registry, spec.clone())),
+        _ => None,
+    }
+}
+
 /// Detect common package name typos and suggest corrections
 fn detect_common_typos(package: &str) -> Option<String> {
     let package_name = extract_package_name(package).to_lowercase();
@@ -330,6 +613,255 @@ fn deduplicate_commands(commands: Vec<String>) -> Vec<String> {
     deduplicated
 }
 
+/// Prep commands (anything but the install itself) that already succeeded, so later
+/// attempts don't run them again
+#[derive(Debug, Default)]
+struct CompletedCommands {
+    succeeded: std::collections::HashSet<String>,
+}
+
+impl CompletedCommands {
+    fn key(command: &str) -> String {
+        command
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    }
+
+    fn record_success(&mut self, command: &str) {
+        self.succeeded.insert(Self::key(command));
+    }
+
+    /// Drop prep commands that already succeeded in an earlier attempt. Install
+    /// commands always stay, since retrying them is the point of a new attempt.
+    fn filter_attempt(
+        &self,
+        commands: Vec<String>,
+        is_install: impl Fn(&str) -> bool,
+    ) -> (Vec<String>, Vec<String>) {
+        commands
+            .into_iter()
+            .partition(|cmd| is_install(cmd) || !self.succeeded.contains(&Self::key(cmd)))
+    }
+}
+
+/// Package managers whose install commands name the packages they install
+const PACKAGE_MANAGERS: [&str; 14] = [
+    "pip", "pip3", "uv", "npm", "yarn", "pnpm", "conda", "poetry", "pipenv", "brew", "apt-get",
+    "apt", "cargo", "gem",
+];
+
+/// Options of those install commands that take a value rather than a package
+const VALUE_OPTIONS: [&str; 22] = [
+    "-r",
+    "--requirement",
+    "-c",
+    "--constraint",
+    "-e",
+    "--editable",
+    "-i",
+    "--index-url",
+    "--extra-index-url",
+    "-t",
+    "--target",
+    "-n",
+    "--name",
+    "--prefix",
+    "--version",
+    "--git",
+    "--branch",
+    "--tag",
+    "--rev",
+    "--root",
+    "-F",
+    "--features",
+];
+
+/// Packages the successful install and upgrade commands of a run installed, for the
+/// final summary and `--report`
+#[derive(Debug, Default)]
+struct InstalledPackages {
+    /// Package specs as the commands named them, e.g. `react@18.2.0`, in the order
+    /// they were first installed
+    packages: Vec<String>,
+}
+
+impl InstalledPackages {
+    /// Record the packages a successful `command` installed; a later install of the
+    /// same package replaces the earlier spec
+    fn record(&mut self, command: &str) {
+        for spec in installed_specs(command) {
+            self.add(spec);
+        }
+    }
+
+    /// Record `spec`, replacing an earlier spec of the same package
+    fn add(&mut self, spec: String) {
+        let name = package_spec_name(&spec).to_lowercase();
+        match self
+            .packages
+            .iter_mut()
+            .find(|known| package_spec_name(known).to_lowercase() == name)
+        {
+            Some(known) => *known = spec,
+            None => self.packages.push(spec),
+        }
+    }
+
+    /// `Installed: react@18.2.0, lodash@4.17.21`; `None` when nothing was installed
+    fn summary(&self) -> Option<String> {
+        (!self.packages.is_empty()).then(|| format!("Installed: {}", self.packages.join(", ")))
+    }
+}
+
+/// The package specs `command` passes to an install, add or upgrade, across `&&`
+/// and `;` chains; requirement files, paths and options are skipped
+fn installed_specs(command: &str) -> Vec<String> {
+    let mut specs = Vec::new();
+    for segment in command.split("&&").flat_map(|part| part.split(';')) {
+        let words: Vec<&str> = segment
+            .split_whitespace()
+            .map(|word| word.trim_matches(|c| c == '"' || c == '\''))
+            .collect();
+        let Some(verb) = words.windows(2).position(|pair| {
+            PACKAGE_MANAGERS.contains(&pair[0])
+                && matches!(pair[1], "install" | "i" | "add" | "upgrade")
+        }) else {
+            continue;
+        };
+        // gem's -v is its version; elsewhere it means verbose
+        let takes_value =
+            |arg: &str| VALUE_OPTIONS.contains(&arg) || (words[verb] == "gem" && arg == "-v");
+        let mut args = words[verb + 2..].iter();
+        while let Some(&arg) = args.next() {
+            if ["|", ">", ">>", "2>&1", "||"].contains(&arg) {
+                break;
+            }
+            if takes_value(arg) {
+                args.next();
+                continue;
+            }
+            let is_path = arg == "." || (arg.contains('/') && !arg.starts_with('@'));
+            if !arg.is_empty() && !arg.starts_with('-') && !is_path {
+                specs.push(arg.to_string());
+            }
+        }
+    }
+    specs
+}
+
+/// The name in a package spec: `react` for `react@18.2.0`, `@types/node` for
+/// `@types/node@20`, `requests` for `requests>=2.31`
+fn package_spec_name(spec: &str) -> &str {
+    if let Some(scoped) = spec.strip_prefix('@') {
+        return match scoped.find('@') {
+            Some(at) => &spec[..at + 1],
+            None => spec,
+        };
+    }
+    let end = spec
+        .find(|c: char| "@=<>!~[;".contains(c))
+        .unwrap_or(spec.len());
+    &spec[..end]
+}
+
+/// What became of one set of suggested commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RoundOutcome {
+    Pending,
+    Declined,
+    Succeeded,
+    Failed,
+}
+
+/// The command sets of a resolution run: the initial suggestion (round 0), then each
+/// AI remediation set as its own numbered round, confirmed and run separately
+#[derive(Debug, Default)]
+struct RemediationRounds {
+    rounds: Vec<(Vec<String>, RoundOutcome)>,
+}
+
+impl RemediationRounds {
+    /// Start with the already confirmed initial commands
+    fn new(initial: Vec<String>) -> Self {
+        Self {
+            rounds: vec![(initial, RoundOutcome::Pending)],
+        }
+    }
+
+    /// Number `commands` as the next remediation round and queue it if `confirm`
+    /// accepts it; returns the round number either way
+    fn propose(
+        &mut self,
+        commands: Vec<String>,
+        confirm: impl FnOnce(usize, &[String]) -> Result<bool>,
+    ) -> Result<usize> {
+        let number = self.rounds.len();
+        let outcome = if confirm(number, &commands)? {
+            RoundOutcome::Pending
+        } else {
+            RoundOutcome::Declined
+        };
+        self.rounds.push((commands, outcome));
+        Ok(number)
+    }
+
+    /// The earliest confirmed round that hasn't run yet
+    fn next_pending(&self) -> Option<usize> {
+        self.rounds
+            .iter()
+            .position(|(_, outcome)| *outcome == RoundOutcome::Pending)
+    }
+
+    fn commands(&self, round: usize) -> &[String] {
+        &self.rounds[round].0
+    }
+
+    fn finish(&mut self, round: usize, failed: bool) {
+        self.rounds[round].1 = if failed {
+            RoundOutcome::Failed
+        } else {
+            RoundOutcome::Succeeded
+        };
+    }
+
+    /// Stop `round` part way because the user declined one of its commands
+    fn decline(&mut self, round: usize) {
+        self.rounds[round].1 = RoundOutcome::Declined;
+    }
+
+    fn label(round: usize) -> String {
+        match round {
+            0 => "Initial commands".to_string(),
+            n => format!("Remediation round {n}"),
+        }
+    }
+
+    /// Which rounds ran, were declined or never got to run; empty without remediation
+    fn log(&self) -> String {
+        if self.rounds.len() < 2 {
+            return String::new();
+        }
+        let mut log = "📋 Resolution rounds:\n".to_string();
+        for (round, (commands, outcome)) in self.rounds.iter().enumerate() {
+            let outcome = match outcome {
+                RoundOutcome::Pending => "not run",
+                RoundOutcome::Declined => "declined",
+                RoundOutcome::Succeeded => "succeeded",
+                RoundOutcome::Failed => "failed",
+            };
+            log.push_str(&format!(
+                "  {} ({} command{}): {outcome}\n",
+                Self::label(round),
+                commands.len(),
+                if commands.len() == 1 { "" } else { "s" }
+            ));
+        }
+        log
+    }
+}
+
 /// Normalize command pattern by replacing package names with a placeholder
 /// This helps detect commands that are the same except for the package name
 fn normalize_command_pattern(command: &str) -> String {
@@ -400,7 +932,8 @@ fn detect_package_manager_from_file(file_path: &str) -> Result<String> {
     // Detect package manager based on file name
     match file_name_lower.as_str() {
         "package.json" | "package-lock.json" | "yarn.lock" => Ok("npm".to_string()),
-        "requirements.txt" | "poetry.lock" | "pipfile" | "pipfile.lock" => Ok("python".to_string()),
+        "requirements.txt" | "poetry.lock" | "pipfile" | "pipfile.lock" | "uv.lock"
+        | "pyproject.toml" => Ok("python".to_string()),
         _ => {
             // Try to read file content for better detection
             let content = std::fs::read_to_string(path)
@@ -412,7 +945,7 @@ fn detect_package_manager_from_file(file_path: &str) -> Result<String> {
                 Ok("python".to_string())
             } else {
                 Err(anyhow::anyhow!(
-                    "Could not detect package manager type from file '{}'. Supported files: package.json, requirements.txt, yarn.lock, poetry.lock, Pipfile",
+                    "Could not detect package manager type from file '{}'. Supported files: package.json, requirements.txt, yarn.lock, poetry.lock, Pipfile, pyproject.toml, uv.lock",
                     file_path
                 ))
             }
@@ -420,21 +953,131 @@ fn detect_package_manager_from_file(file_path: &str) -> Result<String> {
     }
 }
 
+/// Whether a dependency file belongs to a uv project: it is a uv.lock or sits next to one
+fn uses_uv(file_path: &str) -> bool {
+    let path = Path::new(file_path);
+    let is_lock = path
+        .file_name()
+        .is_some_and(|name| name.eq_ignore_ascii_case("uv.lock"));
+    is_lock
+        || path
+            .parent()
+            .map(|dir| dir.join("uv.lock"))
+            .is_some_and(|lock| lock.is_file())
+}
+
+/// Installer for Python packages in the given environment type
+fn python_installer(env_type: &str) -> &'static str {
+    match env_type {
+        "conda" => "conda",
+        "uv" => "uv",
+        _ => "pip",
+    }
+}
+
+/// Example basic install command shown to the AI; `pip` is how pip is invoked
+fn install_example(package_manager: &str, package: &str, pip: &str) -> String {
+    match package_manager {
+        "uv" => format!("uv pip install {package}"),
+        "pip" => format!("{pip} install {package}"),
+        _ => format!("{package_manager} install {package}"),
+    }
+}
+
+/// Python launchers in the order they are tried; `py` is the Windows launcher
+const PYTHON_LAUNCHERS: [&str; 3] = ["python3", "python", "py"];
+
+/// The first of [`PYTHON_LAUNCHERS`] that `is_available`
+fn detect_python_launcher(is_available: impl Fn(&str) -> bool) -> Option<&'static str> {
+    PYTHON_LAUNCHERS
+        .into_iter()
+        .find(|launcher| is_available(launcher))
+}
+
+/// How pip is run: through the Python launcher, so it installs for that interpreter
+/// even where no bare `pip` is on PATH
+fn pip_invocation(launcher: Option<&str>) -> String {
+    match launcher {
+        Some(launcher) => format!("{launcher} -m pip"),
+        None => "pip".to_string(),
+    }
+}
+
+/// Ways the AI writes a pip call, longest first so `python3 -m pip` isn't read as `python`
+const PIP_SPELLINGS: [&str; 5] = [
+    "python3 -m pip ",
+    "python -m pip ",
+    "py -m pip ",
+    "pip3 ",
+    "pip ",
+];
+
+/// `commands` with a leading pip call rewritten to `pip`, so every install and
+/// verification uses the same interpreter
+fn use_pip_invocation(commands: Vec<String>, pip: &str) -> Vec<String> {
+    commands
+        .into_iter()
+        .map(|cmd| {
+            match PIP_SPELLINGS
+                .iter()
+                .find_map(|spelling| cmd.strip_prefix(spelling))
+            {
+                Some(rest) => format!("{pip} {rest}"),
+                None => cmd,
+            }
+        })
+        .collect()
+}
+
+/// Options controlling how a resolution run behaves
+struct ResolveOptions<'a> {
+    /// Python environment type (venv, conda, or uv when uv replaces pip)
+    env_type: &'a str,
+    /// Ask the AI for a summary once all attempts are exhausted
+    show_summary: bool,
+    /// Explain each suggested command before asking for confirmation
+    explain: bool,
+    /// Show the explained commands and stop before executing any (`--dry-run`)
+    dry_run: bool,
+    /// Language for explanations and the failure summary; English when unset
+    output_language: Option<String>,
+    /// Maximum lines of verification output to print
+    verify_max_lines: usize,
+    /// Command run once the installation is verified, e.g. the project's tests
+    post_success: Option<String>,
+    /// Commands executed so far, in order
+    recorder: ScriptRecorder,
+    /// How pip is run, e.g. `python3 -m pip` with the detected launcher
+    pip: String,
+    /// Run package commands without asking about each one again
+    /// (`auto_execute_installs`)
+    auto_execute_installs: bool,
+    /// Packages installed so far, across all attempts
+    installed: InstalledPackages,
+    /// Look each single-package install up on PyPI or npm before it runs
+    /// (`--check-registry`)
+    check_registry: bool,
+}
+
 /// Execute resolution commands with iterative approach
 async fn execute_resolution_commands(
     ai_response: &str,
     package_type: &str,
     package: &str,
     is_file_mode: bool,
-    env_type: &str,
     provider: &QueryProvider,
     system_prompt: &str,
+    options: &mut ResolveOptions<'_>,
 ) -> Result<()> {
-    let mut commands_to_execute =
-        deduplicate_commands(terminalai::extract_commands_from_response(ai_response));
+    let env_type = options.env_type;
+    let mut commands_to_execute = deduplicate_commands(use_pip_invocation(
+        terminalai::extract_commands_from_response(ai_response),
+        &options.pip,
+    ));
     let mut attempt_count = 0;
     const MAX_ATTEMPTS: u32 = 15; // Increased for more iterative attempts
     let mut error_history = Vec::new();
+    let mut completed = CompletedCommands::default();
 
     if commands_to_execute.is_empty() {
         println!("⚠️  No executable commands found in AI response.");
@@ -442,52 +1085,119 @@ async fn execute_resolution_commands(
         println!("{ai_response}");
         return Ok(());
     }
+    terminalai::check_plan_size(&commands_to_execute)?;
+    // Checked after the pip rewrite, so the policy sees the commands that will run
+    terminalai::policy::check(&commands_to_execute)?;
 
     // Show initial commands to user and ask for confirmation
-    println!("Terminal AI suggest following commands:");
-    for (i, cmd) in commands_to_execute.iter().enumerate() {
-        println!("  {}. {}", i + 1, cmd);
+    let messages = ui::messages();
+    if options.explain || options.dry_run {
+        let explanations = match request_command_explanations(
+            &commands_to_execute,
+            provider,
+            options.output_language.as_deref(),
+        )
+        .await
+        {
+            Ok(explanations) => explanations,
+            Err(e) => {
+                println!("⚠️  Could not get command explanations: {e}");
+                Vec::new()
+            }
+        };
+        if options.dry_run {
+            explain::show_dry_run(
+                &messages.suggested_commands,
+                &commands_to_execute,
+                &explanations,
+            );
+            return Ok(());
+        }
+        ui::preview_explained(
+            &messages.suggested_commands,
+            &commands_to_execute,
+            &explanations,
+        );
+    } else {
+        ui::preview(&messages.suggested_commands, &commands_to_execute);
     }
-
-    print!("\n❓ Execute these resolution commands? [Y/n]: ");
-    std::io::Write::flush(&mut std::io::stdout()).unwrap();
-
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input).unwrap();
-
-    if input.trim().to_lowercase() == "n" || input.trim().to_lowercase() == "no" {
-        println!("❌ Resolution commands not executed.");
+    if !risk::confirm_commands(&commands_to_execute, false, &messages.confirm_resolution)? {
+        println!("{}", messages.resolution_not_executed);
         return Ok(());
     }
 
-    // Execute commands with iterative error handling
-    while !commands_to_execute.is_empty() && attempt_count < MAX_ATTEMPTS {
+    // Execute commands with iterative error handling, one round at a time
+    let mut rounds = RemediationRounds::new(commands_to_execute);
+    while let Some(round) = rounds.next_pending() {
+        if attempt_count >= MAX_ATTEMPTS {
+            break;
+        }
+        let commands = rounds.commands(round).to_vec();
+        let (remaining, skipped) = completed.filter_attempt(commands, |cmd| {
+            is_installation_command(cmd, package_type, package, is_file_mode)
+        });
+        for cmd in &skipped {
+            println!("⏭️  Skipping '{cmd}': it already succeeded in an earlier attempt");
+        }
+        commands_to_execute = remaining;
+        if commands_to_execute.is_empty() {
+            rounds.finish(round, false);
+            continue;
+        }
+
         attempt_count += 1;
         println!(
-            "\n🔄 Attempt {}: Executing {} commands",
+            "\n🔄 Attempt {}, {}: Executing {} commands",
             attempt_count,
+            RemediationRounds::label(round),
             commands_to_execute.len()
         );
 
-        let mut new_commands = Vec::new();
         let mut has_failures = false;
 
         for (cmd_index, cmd) in commands_to_execute.iter().enumerate() {
             println!("\n📋 Command {}: {}", cmd_index + 1, cmd);
+            if let Some((registry, spec)) =
+                single_registry_install(cmd).filter(|_| options.check_registry)
+            {
+                check_package_exists(registry, &spec).await;
+            }
+            if !terminalai::confirm_install(cmd, options.auto_execute_installs) {
+                println!("{}", messages.resolution_not_executed);
+                rounds.decline(round);
+                print!("{}", rounds.log());
+                return Ok(());
+            }
 
             // Execute the command
             let output = execute_single_command(cmd)?;
 
             // Check if the command was successful
             if output.status.success() {
+                options.recorder.record(cmd);
                 println!("✅ Command completed successfully");
+                completed.record_success(cmd);
+                options.installed.record(cmd);
                 if !output.stdout.is_empty() {
                     println!("Output: {}", String::from_utf8_lossy(&output.stdout));
                 }
 
                 // If this was an installation command and it succeeded, verify the installation
                 if is_installation_command(cmd, package_type, package, is_file_mode) {
-                    if verify_package_installation(package_type, package, is_file_mode, env_type)? {
+                    let verified = verify_package_installation(
+                        package_type,
+                        package,
+                        is_file_mode,
+                        env_type,
+                        &options.pip,
+                        options.verify_max_lines,
+                    )?;
+                    let hook_passed = run_post_success(
+                        verified,
+                        options.post_success.as_deref(),
+                        &mut options.recorder,
+                    )?;
+                    if verified {
                         if is_file_mode {
                             println!(
                                 "🎉 Dependencies from '{package}' successfully installed and verified!"
@@ -495,6 +1205,11 @@ async fn execute_resolution_commands(
                         } else {
                             println!("🎉 Package '{package}' successfully installed and verified!");
                         }
+                        rounds.finish(round, false);
+                        print!("{}", rounds.log());
+                        if let (Some(hook), Some(false)) = (&options.post_success, hook_passed) {
+                            anyhow::bail!("Post-success command '{hook}' failed");
+                        }
                         return Ok(());
                     } else {
                         println!("⚠️  Installation command succeeded but verification failed");
@@ -502,6 +1217,7 @@ async fn execute_resolution_commands(
                     }
                 }
             } else {
+                options.recorder.record_failed(cmd);
                 has_failures = true;
                 let exit_code = output.status.code().unwrap_or(-1);
                 let stderr_output = String::from_utf8_lossy(&output.stderr);
@@ -532,30 +1248,34 @@ async fn execute_resolution_commands(
                     .await
                     {
                         Ok(additional_commands) => {
-                            let deduplicated_additional = deduplicate_commands(additional_commands);
+                            let deduplicated_additional = deduplicate_commands(use_pip_invocation(
+                                additional_commands,
+                                &options.pip,
+                            ));
                             if !deduplicated_additional.is_empty() {
-                                println!(
-                                    "🆕 AI generated {} new resolution commands:",
-                                    deduplicated_additional.len()
-                                );
-                                for (i, new_cmd) in deduplicated_additional.iter().enumerate() {
-                                    println!("  {}. {}", i + 1, new_cmd);
-                                }
-
-                                // Ask user for confirmation of new commands
-                                print!("\n❓ Execute these new resolution commands? [Y/n]: ");
-                                std::io::Write::flush(&mut std::io::stdout()).unwrap();
-
-                                let mut confirm_input = String::new();
-                                std::io::stdin().read_line(&mut confirm_input).unwrap();
-
-                                if confirm_input.trim().to_lowercase() == "n"
-                                    || confirm_input.trim().to_lowercase() == "no"
-                                {
-                                    println!("❌ New resolution commands not executed.");
-                                } else {
-                                    new_commands.extend(deduplicated_additional);
-                                }
+                                rounds.propose(deduplicated_additional, |number, commands| {
+                                    println!(
+                                        "\n🩹 Remediation round {number}: AI suggested {} new resolution commands:",
+                                        commands.len()
+                                    );
+                                    for (i, new_cmd) in commands.iter().enumerate() {
+                                        println!("  {}. {}", i + 1, new_cmd);
+                                    }
+                                    if let Err(e) = terminalai::policy::check(commands) {
+                                        println!("⚠️  {e:#}");
+                                        println!("{}", messages.new_resolution_not_executed);
+                                        return Ok(false);
+                                    }
+                                    let accepted = risk::confirm_commands(
+                                        commands,
+                                        false,
+                                        &messages.confirm_new_resolution,
+                                    )?;
+                                    if !accepted {
+                                        println!("{}", messages.new_resolution_not_executed);
+                                    }
+                                    Ok(accepted)
+                                })?;
                             }
                         }
                         Err(e) => {
@@ -566,16 +1286,16 @@ async fn execute_resolution_commands(
             }
         }
 
-        // Update commands for next iteration
-        commands_to_execute = new_commands;
+        rounds.finish(round, has_failures);
 
         // If no failures occurred and no new commands were generated, we're done
-        if !has_failures && commands_to_execute.is_empty() {
+        if !has_failures && rounds.next_pending().is_none() {
             break;
         }
 
         // If we've reached max attempts and still have failures
         if attempt_count >= MAX_ATTEMPTS && has_failures {
+            print!("{}", rounds.log());
             println!(
                 "🛑 Maximum resolution attempts ({MAX_ATTEMPTS}) reached. Installation failed."
             );
@@ -583,6 +1303,27 @@ async fn execute_resolution_commands(
             for (i, error) in error_history.iter().enumerate() {
                 println!("  {}. {}", i + 1, error);
             }
+
+            if options.show_summary {
+                println!("\n🤖 Summarizing what went wrong...");
+                match request_failure_summary(
+                    package_type,
+                    package,
+                    is_file_mode,
+                    &error_history,
+                    provider,
+                    options.output_language.as_deref(),
+                )
+                .await
+                {
+                    Ok(summary) => {
+                        println!("💡 Summary and next steps:");
+                        println!("{}", summary.trim());
+                    }
+                    Err(e) => println!("⚠️  Failed to get failure summary from AI: {e}"),
+                }
+            }
+
             return Err(anyhow::anyhow!(
                 "Failed to install {} after {} attempts",
                 if is_file_mode {
@@ -595,6 +1336,7 @@ async fn execute_resolution_commands(
         }
     }
 
+    print!("{}", rounds.log());
     println!("✅ All resolution commands completed");
     Ok(())
 }
@@ -628,12 +1370,14 @@ async fn request_error_resolution(
             let pkg_name = extract_package_name(package);
             match env_type {
                 "conda" => format!("\n\nSUGGESTION: Try conda alternatives:\n- conda install {pkg_name}\n- conda install -c conda-forge {pkg_name}"),
+                "uv" => format!("\n\nSUGGESTION: Try uv alternatives:\n- uv pip install {pkg_name}\n- uv pip install --no-cache {pkg_name}"),
                 _ => format!("\n\nSUGGESTION: Try pip alternatives:\n- pip install {pkg_name}\n- pip install --no-cache-dir {pkg_name}")
             }
         } else {
             let pkg_name = extract_package_name(package);
             match env_type {
                 "conda" => format!("\n\nSUGGESTION: Try conda alternatives:\n- conda install {pkg_name}"),
+                "uv" => format!("\n\nSUGGESTION: Try uv alternatives:\n- uv pip install {pkg_name}\n- uv pip install --no-cache {pkg_name}"),
                 _ => format!("\n\nSUGGESTION: Try pip alternatives:\n- pip install {pkg_name}\n- pip install --no-cache-dir {pkg_name}")
             }
         }
@@ -649,10 +1393,7 @@ async fn request_error_resolution(
 
     let prompt = if is_file_mode {
         let package_manager = if package_type == "python" {
-            match env_type {
-                "conda" => "conda",
-                _ => "pip",
-            }
+            python_installer(env_type)
         } else {
             "npm"
         };
@@ -663,6 +1404,9 @@ async fn request_error_resolution(
         let env_note = if package_type == "python" {
             match env_type {
                 "conda" => "\nUsing conda environment as specified by user.",
+                "uv" => {
+                    "\nUsing uv as specified by user ('uv pip install', 'uv add' or 'uv sync')."
+                }
                 _ => "\nUsing pip environment as specified by user (default).",
             }
         } else {
@@ -670,10 +1414,7 @@ async fn request_error_resolution(
         };
 
         let package_manager = if package_type == "python" {
-            match env_type {
-                "conda" => "conda",
-                _ => "pip",
-            }
+            python_installer(env_type)
         } else {
             "npm"
         };
@@ -682,7 +1423,14 @@ async fn request_error_resolution(
         )
     };
 
-    match provider.send_query(system_prompt, &prompt).await {
+    match provider
+        .send_command_query(
+            system_prompt,
+            &prompt,
+            terminalai::extract_commands_from_response,
+        )
+        .await
+    {
         Ok(response) => {
             let new_commands = terminalai::extract_commands_from_response(&response);
             Ok(new_commands)
@@ -693,11 +1441,92 @@ async fn request_error_resolution(
     }
 }
 
+/// System prompt used for the final failure summary once all attempts are exhausted
+const FAILURE_SUMMARY_SYSTEM_PROMPT: &str = "You are an assistant that explains package installation failures to developers. Summarize the root cause of the errors in plain language and list concrete next steps (for example, missing system libraries, compilers, or incompatible versions). Keep the answer short: at most 3 sentences followed by a bulleted list of next steps.";
+
+/// Build the prompt asking the AI to summarize an exhausted resolution run
+fn build_failure_summary_prompt(
+    package_type: &str,
+    package: &str,
+    is_file_mode: bool,
+    error_history: &[String],
+) -> String {
+    let target = if is_file_mode {
+        format!("dependencies from '{package}'")
+    } else {
+        format!("package '{package}'")
+    };
+
+    let errors = error_history
+        .iter()
+        .enumerate()
+        .map(|(i, error)| format!("{}. {}", i + 1, error))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        "All automatic attempts to install {target} ({package_type}) failed. Here is the full error history:\n\n{errors}\n\nExplain what went wrong and what the user should do next."
+    )
+}
+
+/// Request a human-readable summary of the failures from the AI
+async fn request_failure_summary(
+    package_type: &str,
+    package: &str,
+    is_file_mode: bool,
+    error_history: &[String],
+    provider: &QueryProvider,
+    language: Option<&str>,
+) -> Result<String> {
+    let prompt = build_failure_summary_prompt(package_type, package, is_file_mode, error_history);
+    provider
+        .send_query(
+            &localized_prompt(FAILURE_SUMMARY_SYSTEM_PROMPT, language),
+            &prompt,
+        )
+        .await
+}
+
+/// Run the `--post-success` command once verification passed, through the executor
+/// like any plan step; `None` when it didn't run, otherwise whether it succeeded
+fn run_post_success(
+    verified: bool,
+    hook: Option<&str>,
+    recorder: &mut ScriptRecorder,
+) -> Result<Option<bool>> {
+    let Some(hook) = hook.filter(|_| verified) else {
+        return Ok(None);
+    };
+    println!("\n🧪 Running post-success command: {hook}");
+    let options = terminalai::ExecutionOptions {
+        quiet: true,
+        ..Default::default()
+    };
+    match terminalai::executor::execute_command_blocking(hook, &options) {
+        Ok(()) => {
+            recorder.record(hook);
+            println!("✅ Post-success command passed");
+            Ok(Some(true))
+        }
+        Err(e) => {
+            let Some(failed) = e.downcast_ref::<terminalai::executor::CommandFailed>() else {
+                return Err(e);
+            };
+            recorder.record_failed(hook);
+            println!(
+                "❌ Post-success command failed with exit code {}",
+                failed.exit_code
+            );
+            Ok(Some(false))
+        }
+    }
+}
+
 /// Execute a single command with live output and return the output
 fn execute_single_command(cmd: &str) -> Result<std::process::Output> {
-    let is_install_cmd = terminalai::is_install_update_remove_command(cmd);
+    let branded = terminalai::branding::applies_to(cmd);
 
-    if is_install_cmd {
+    if branded {
         println!(
             "{}",
             "[Terminal AI] - Executing package management command"
@@ -710,9 +1539,7 @@ fn execute_single_command(cmd: &str) -> Result<std::process::Output> {
 
     // For error analysis, we need to capture stderr while still showing live output
     // We'll use a hybrid approach: capture stderr for analysis, but show stdout live
-    let mut command = StdCommand::new("sh");
-    command.arg("-c");
-    command.arg(cmd);
+    let mut command = shell::shell()?.command(cmd);
     command.stdin(std::process::Stdio::piped());
     command.stdout(std::process::Stdio::inherit());
     command.stderr(std::process::Stdio::piped()); // Capture stderr for error analysis
@@ -727,7 +1554,7 @@ fn execute_single_command(cmd: &str) -> Result<std::process::Output> {
         eprint!("{stderr_str}");
     }
 
-    if is_install_cmd {
+    if branded {
         if output.status.success() {
             println!(
                 "{}",
@@ -768,10 +1595,17 @@ fn is_installation_command(
                         || !cmd_lower.contains(" "))
             }
             "python" => {
-                (cmd_lower.contains("pip install") || cmd_lower.contains("python -m pip install"))
-                    && (cmd_lower.contains("requirements.txt")
-                        || cmd_lower.contains("poetry.lock")
-                        || cmd_lower.contains("pipfile"))
+                // `uv sync` installs the whole project without naming the file
+                let file_lower = package.to_lowercase();
+                let syncs_uv_project = cmd_lower.contains("uv sync")
+                    && (file_lower.ends_with("uv.lock") || file_lower.ends_with("pyproject.toml"));
+                syncs_uv_project
+                    || (cmd_lower.contains("pip install")
+                        || cmd_lower.contains("python -m pip install"))
+                        && (cmd_lower.contains("requirements.txt")
+                            || cmd_lower.contains("poetry.lock")
+                            || cmd_lower.contains("pipfile")
+                            || cmd_lower.contains("pyproject.toml"))
             }
             _ => false,
         }
@@ -785,7 +1619,10 @@ fn is_installation_command(
                     && (cmd_lower.contains(&package_name) || cmd_lower.contains("package.json"))
             }
             "python" => {
-                (cmd_lower.contains("pip install") || cmd_lower.contains("python -m pip install"))
+                // `uv pip install` is covered by the pip check
+                (cmd_lower.contains("pip install")
+                    || cmd_lower.contains("python -m pip install")
+                    || cmd_lower.contains("uv add"))
                     && (cmd_lower.contains(&package_name) || cmd_lower.contains("requirements.txt"))
             }
             _ => false,
@@ -808,16 +1645,16 @@ fn verify_package_installation(
     package: &str,
     is_file_mode: bool,
     env_type: &str,
+    pip: &str,
+    max_lines: usize,
 ) -> Result<bool> {
     if is_file_mode {
-        // For file mode, verify that dependencies are installed
-        let verification_cmd = match package_type {
-            "npm" => "npm list".to_string(),
-            "python" => match env_type {
-                "conda" => "conda list".to_string(),
-                _ => "pip list".to_string(),
-            },
-            _ => return Ok(false),
+        // For file mode, verify only the dependencies listed in the file
+        let packages = dependency_names_from_file(package);
+        let Some(verification_cmd) =
+            build_file_verification_command(package_type, env_type, pip, &packages)
+        else {
+            return Ok(false);
         };
 
         println!("🔍 Verifying dependencies installation: {verification_cmd}");
@@ -829,14 +1666,17 @@ fn verify_package_installation(
             if !output.stdout.is_empty() {
                 println!(
                     "Installed packages: {}",
-                    String::from_utf8_lossy(&output.stdout)
+                    cap_output_lines(&String::from_utf8_lossy(&output.stdout), max_lines)
                 );
             }
             Ok(true)
         } else {
             println!("❌ Dependencies verification failed");
             if !output.stderr.is_empty() {
-                println!("Error: {}", String::from_utf8_lossy(&output.stderr));
+                println!(
+                    "Error: {}",
+                    cap_output_lines(&String::from_utf8_lossy(&output.stderr), max_lines)
+                );
             }
             Ok(false)
         }
@@ -844,13 +1684,10 @@ fn verify_package_installation(
         // For single package mode, verify specific package
         let package_name = extract_package_name(package);
 
-        let verification_cmd = match package_type {
-            "npm" => format!("npm list {package_name}"),
-            "python" => match env_type {
-                "conda" => format!("conda list {package_name}"),
-                _ => format!("pip show {package_name}"),
-            },
-            _ => return Ok(false),
+        let Some(verification_cmd) =
+            build_package_verification_command(package_type, env_type, pip, &package_name)
+        else {
+            return Ok(false);
         };
 
         println!("🔍 Verifying installation: {verification_cmd}");
@@ -860,15 +1697,612 @@ fn verify_package_installation(
         if output.status.success() {
             println!("✅ Package verification successful");
             if !output.stdout.is_empty() {
-                println!("Package info: {}", String::from_utf8_lossy(&output.stdout));
+                println!(
+                    "Package info: {}",
+                    cap_output_lines(&String::from_utf8_lossy(&output.stdout), max_lines)
+                );
             }
             Ok(true)
         } else {
             println!("❌ Package verification failed");
             if !output.stderr.is_empty() {
-                println!("Error: {}", String::from_utf8_lossy(&output.stderr));
+                println!(
+                    "Error: {}",
+                    cap_output_lines(&String::from_utf8_lossy(&output.stderr), max_lines)
+                );
             }
             Ok(false)
         }
     }
 }
+
+/// Package names declared in a dependency file (package.json, pyproject.toml or
+/// requirements-style files)
+fn dependency_names_from_file(file_path: &str) -> Vec<String> {
+    let Ok(content) = std::fs::read_to_string(file_path) else {
+        return Vec::new();
+    };
+
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&content) {
+        return ["dependencies", "devDependencies"]
+            .iter()
+            .filter_map(|section| json.get(section).and_then(|deps| deps.as_object()))
+            .flat_map(|deps| deps.keys().cloned())
+            .collect();
+    }
+
+    let file_name = file_path.to_lowercase();
+    if file_name.ends_with(".toml") {
+        return pyproject_dependency_names(&content);
+    }
+    // Lock files and Pipfiles use their own formats; verify those unfiltered
+    if file_name.ends_with(".lock") || file_name.ends_with("pipfile") {
+        return Vec::new();
+    }
+
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('-'))
+        .filter_map(requirement_name)
+        .collect()
+}
+
+/// The packages `project.dependencies` and `tool.poetry.dependencies` of a
+/// pyproject.toml list; none when it does not parse
+fn pyproject_dependency_names(content: &str) -> Vec<String> {
+    let Ok(pyproject) = content.parse::<toml::Table>() else {
+        return Vec::new();
+    };
+    let section = |path: &[&str]| {
+        path.iter()
+            .try_fold(&pyproject, |table, key| table.get(*key)?.as_table())
+    };
+
+    let mut names: Vec<String> = section(&["project"])
+        .and_then(|project| project.get("dependencies")?.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|requirement| requirement_name(requirement.as_str()?))
+        .collect();
+    // Poetry lists the Python version among the dependencies
+    names.extend(
+        section(&["tool", "poetry", "dependencies"])
+            .into_iter()
+            .flat_map(|deps| deps.keys())
+            .filter(|name| !name.eq_ignore_ascii_case("python"))
+            .cloned(),
+    );
+    names
+}
+
+/// The package name of a requirement such as `uvicorn[standard]~=0.23`
+fn requirement_name(requirement: &str) -> Option<String> {
+    let name = requirement
+        .split(|c: char| "=<>!~[;@ ".contains(c))
+        .next()
+        .unwrap_or_default()
+        .trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Verification command for a single installed package
+fn build_package_verification_command(
+    package_type: &str,
+    env_type: &str,
+    pip: &str,
+    package_name: &str,
+) -> Option<String> {
+    let cmd = match (package_type, env_type) {
+        ("npm", _) => format!("npm list {package_name}"),
+        ("python", "conda") => format!("conda list {package_name}"),
+        ("python", "uv") => format!("uv pip show {package_name}"),
+        ("python", _) => format!("{pip} show {package_name}"),
+        _ => return None,
+    };
+    Some(cmd)
+}
+
+/// Verification command for file mode, filtered to the given packages when known
+fn build_file_verification_command(
+    package_type: &str,
+    env_type: &str,
+    pip: &str,
+    packages: &[String],
+) -> Option<String> {
+    let pattern = packages
+        .iter()
+        .map(|p| p.replace('.', "\\."))
+        .collect::<Vec<_>>()
+        .join("|");
+
+    let cmd = match (package_type, env_type, packages.is_empty()) {
+        ("npm", _, true) => "npm list --depth=0".to_string(),
+        ("npm", _, false) => format!("npm list --depth=0 {}", packages.join(" ")),
+        ("python", "conda", true) => "conda list".to_string(),
+        ("python", "conda", false) => format!("conda list '^({pattern})$'"),
+        ("python", "uv", true) => "uv pip list".to_string(),
+        ("python", "uv", false) => format!("uv pip list | grep -i -E '^({pattern}) '"),
+        ("python", _, true) => format!("{pip} list"),
+        ("python", _, false) => format!("{pip} list | grep -i -E '^({pattern}) '"),
+        _ => return None,
+    };
+    Some(cmd)
+}
+
+/// Keep at most `max_lines` lines of command output, noting how many were dropped
+fn cap_output_lines(output: &str, max_lines: usize) -> String {
+    let lines: Vec<&str> = output.lines().collect();
+    if lines.len() <= max_lines {
+        return output.to_string();
+    }
+    format!(
+        "{}\n... ({} more lines)",
+        lines[..max_lines].join("\n"),
+        lines.len() - max_lines
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_dependency_names_from_requirements_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("requirements.txt");
+        std::fs::write(
+            &path,
+            "# deps\nrequests==2.31.0\nDjango>=4.2\n-r other.txt\nuvicorn[standard]~=0.23\n\n",
+        )
+        .unwrap();
+        assert_eq!(
+            dependency_names_from_file(path.to_str().unwrap()),
+            vec!["requests", "Django", "uvicorn"]
+        );
+    }
+
+    #[test]
+    fn test_dependency_names_from_pyproject() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("pyproject.toml");
+        std::fs::write(
+            &path,
+            "[project]\nname = \"app\"\nrequires-python = \">=3.10\"\ndependencies = [\"requests>=2.31\", \"uvicorn[standard]\"]\n\n[tool.poetry.dependencies]\npython = \"^3.10\"\nfastapi = \"^0.110\"\n\n[build-system]\nrequires = [\"hatchling\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            dependency_names_from_file(path.to_str().unwrap()),
+            vec!["requests", "uvicorn", "fastapi"]
+        );
+
+        std::fs::write(&path, "[tool.black]\nline-length = 100\n").unwrap();
+        assert!(dependency_names_from_file(path.to_str().unwrap()).is_empty());
+        std::fs::write(&path, "not = [toml").unwrap();
+        assert!(dependency_names_from_file(path.to_str().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_dependency_names_from_package_json() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("package.json");
+        std::fs::write(
+            &path,
+            r#"{"name": "app", "dependencies": {"react": "^18.2.0"}, "devDependencies": {"jest": "^29.0.0"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            dependency_names_from_file(path.to_str().unwrap()),
+            vec!["react", "jest"]
+        );
+    }
+
+    #[test]
+    fn test_file_verification_command_is_filtered() {
+        let packages = vec!["requests".to_string(), "zope.interface".to_string()];
+        assert_eq!(
+            build_file_verification_command("python", "venv", "pip", &packages).unwrap(),
+            "pip list | grep -i -E '^(requests|zope\\.interface) '"
+        );
+        assert_eq!(
+            build_file_verification_command("python", "conda", "pip", &packages).unwrap(),
+            "conda list '^(requests|zope\\.interface)$'"
+        );
+        assert_eq!(
+            build_file_verification_command("npm", "venv", "pip", &["react".to_string()]).unwrap(),
+            "npm list --depth=0 react"
+        );
+        assert_eq!(
+            build_file_verification_command("npm", "venv", "pip", &[]).unwrap(),
+            "npm list --depth=0"
+        );
+        assert!(build_file_verification_command("cargo", "venv", "pip", &packages).is_none());
+    }
+
+    #[test]
+    fn test_uv_detection() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let lock = temp_dir.path().join("uv.lock");
+        let pyproject = temp_dir.path().join("pyproject.toml");
+        std::fs::write(&pyproject, "[project]\nname = \"demo\"\n").unwrap();
+
+        let pyproject_path = pyproject.to_str().unwrap();
+        assert_eq!(
+            detect_package_manager_from_file(pyproject_path).unwrap(),
+            "python"
+        );
+        assert!(!uses_uv(pyproject_path));
+
+        std::fs::write(&lock, "version = 1\n").unwrap();
+        assert!(uses_uv(pyproject_path));
+        assert!(uses_uv(lock.to_str().unwrap()));
+        assert_eq!(
+            detect_package_manager_from_file(lock.to_str().unwrap()).unwrap(),
+            "python"
+        );
+        assert!(!uses_uv("requirements.txt"));
+    }
+
+    #[test]
+    fn test_uv_command_generation() {
+        assert_eq!(python_installer("uv"), "uv");
+        assert_eq!(python_installer("venv"), "pip");
+        assert_eq!(
+            install_example("uv", "requests==2.31.0", "pip"),
+            "uv pip install requests==2.31.0"
+        );
+        assert_eq!(
+            install_example("pip", "requests", "pip"),
+            "pip install requests"
+        );
+
+        assert!(is_installation_command(
+            "uv pip install requests==2.31.0",
+            "python",
+            "requests==2.31.0",
+            false
+        ));
+        assert!(is_installation_command(
+            "uv add requests",
+            "python",
+            "requests",
+            false
+        ));
+        assert!(is_installation_command(
+            "uv sync --locked",
+            "python",
+            "uv.lock",
+            true
+        ));
+        assert!(!is_installation_command(
+            "uv sync",
+            "python",
+            "requirements.txt",
+            true
+        ));
+        assert!(is_installation_command(
+            "uv pip install -r requirements.txt",
+            "python",
+            "requirements.txt",
+            true
+        ));
+
+        assert_eq!(
+            build_package_verification_command("python", "uv", "pip", "requests").unwrap(),
+            "uv pip show requests"
+        );
+        assert_eq!(
+            build_package_verification_command("python", "venv", "pip", "requests").unwrap(),
+            "pip show requests"
+        );
+        assert_eq!(
+            build_file_verification_command("python", "uv", "pip", &["requests".to_string()])
+                .unwrap(),
+            "uv pip list | grep -i -E '^(requests) '"
+        );
+    }
+
+    #[test]
+    fn test_cap_output_lines() {
+        let output = (1..=30)
+            .map(|i| format!("pkg{i}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let capped = cap_output_lines(&output, 20);
+        assert_eq!(capped.lines().count(), 21);
+        assert!(capped.ends_with("... (10 more lines)"));
+        assert_eq!(cap_output_lines("a\nb", 20), "a\nb");
+    }
+
+    #[test]
+    fn test_build_failure_summary_prompt() {
+        let errors = vec![
+            "Command 'pip install lxml==5.0.0' failed with exit code 1: gcc not found".to_string(),
+            "Command 'pip install --no-cache-dir lxml' failed with exit code 1: libxml2 missing"
+                .to_string(),
+        ];
+
+        let prompt = build_failure_summary_prompt("python", "lxml==5.0.0", false, &errors);
+        assert!(prompt.contains("package 'lxml==5.0.0'"));
+        assert!(prompt.contains("1. Command 'pip install lxml==5.0.0'"));
+        assert!(prompt.contains("2. Command 'pip install --no-cache-dir lxml'"));
+
+        let file_prompt = build_failure_summary_prompt("npm", "package.json", true, &errors);
+        assert!(file_prompt.contains("dependencies from 'package.json'"));
+    }
+
+    #[tokio::test]
+    async fn test_request_failure_summary_uses_error_history() {
+        // Bins can't reach the library's test provider, so this one answers over HTTP
+        let mut server = mockito::Server::new_async().await;
+        let system_prompt = serde_json::to_string(FAILURE_SUMMARY_SYSTEM_PROMPT).unwrap();
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::Regex(regex::escape(&system_prompt)),
+                mockito::Matcher::Regex("gcc not found".to_string()),
+            ]))
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"This package requires a C compiler."}}]}"#,
+            )
+            .expect(1)
+            .create_async()
+            .await;
+        let mut openai = terminalai::providers::ProviderConfig::new_openai(
+            "sk-test".to_string(),
+            "gpt-4o".to_string(),
+            30,
+        );
+        openai.settings.insert("base_url".to_string(), server.url());
+        let provider =
+            QueryProvider::from_provider(terminalai::providers::create_provider(&openai).unwrap());
+        let errors =
+            vec!["Command 'pip install lxml' failed with exit code 1: gcc not found".to_string()];
+
+        let summary =
+            request_failure_summary("python", "lxml==5.0.0", false, &errors, &provider, None)
+                .await
+                .unwrap();
+        assert_eq!(summary, "This package requires a C compiler.");
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_remediation_rounds_are_numbered_and_confirmed_separately() {
+        let mut rounds = RemediationRounds::new(vec!["pip install lxml".to_string()]);
+        assert_eq!(rounds.next_pending(), Some(0));
+        assert_eq!(rounds.log(), "");
+        rounds.finish(0, true);
+
+        let mut asked = Vec::new();
+        let first = rounds
+            .propose(
+                vec![
+                    "apt-get install -y libxml2-dev".to_string(),
+                    "pip install lxml".to_string(),
+                ],
+                |number, commands| {
+                    asked.push((number, commands.len()));
+                    Ok(true)
+                },
+            )
+            .unwrap();
+        let second = rounds
+            .propose(
+                vec!["pip install lxml==4.9.4".to_string()],
+                |number, commands| {
+                    asked.push((number, commands.len()));
+                    Ok(false)
+                },
+            )
+            .unwrap();
+        assert_eq!((first, second), (1, 2));
+        assert_eq!(asked, vec![(1, 2), (2, 1)]);
+
+        // Only the accepted round runs
+        assert_eq!(rounds.next_pending(), Some(1));
+        assert_eq!(rounds.commands(1)[0], "apt-get install -y libxml2-dev");
+        rounds.finish(1, false);
+        assert_eq!(rounds.next_pending(), None);
+
+        assert_eq!(
+            rounds.log(),
+            "📋 Resolution rounds:\n  Initial commands (1 command): failed\n  Remediation round 1 (2 commands): succeeded\n  Remediation round 2 (1 command): declined\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_post_success_runs_only_after_verification() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let marker = temp_dir.path().join("tests-ran");
+        let hook = format!("touch '{}'", marker.display());
+        let mut recorder = ScriptRecorder::new();
+
+        assert_eq!(
+            run_post_success(false, Some(&hook), &mut recorder).unwrap(),
+            None
+        );
+        assert!(!marker.exists());
+        assert_eq!(run_post_success(true, None, &mut recorder).unwrap(), None);
+
+        assert_eq!(
+            run_post_success(true, Some(&hook), &mut recorder).unwrap(),
+            Some(true)
+        );
+        assert!(marker.exists());
+        assert_eq!(
+            run_post_success(true, Some("exit 3"), &mut recorder).unwrap(),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn test_succeeded_prep_command_is_not_rerun_in_later_attempt() {
+        let is_install = |cmd: &str| is_installation_command(cmd, "python", "lxml==5.0.0", false);
+        let mut completed = CompletedCommands::default();
+
+        // Attempt 1: the pip upgrade succeeds, the install fails
+        let (first, skipped) = completed.filter_attempt(
+            vec![
+                "pip install --upgrade pip".to_string(),
+                "pip install lxml==5.0.0".to_string(),
+            ],
+            is_install,
+        );
+        assert_eq!(first.len(), 2);
+        assert!(skipped.is_empty());
+        completed.record_success("pip install --upgrade pip");
+
+        // Attempt 2: the AI suggests the same prep again alongside the install retry
+        let (second, skipped) = completed.filter_attempt(
+            vec![
+                "pip  install --upgrade PIP".to_string(),
+                "sudo apt-get install -y libxml2-dev".to_string(),
+                "pip install lxml==5.0.0".to_string(),
+            ],
+            is_install,
+        );
+        assert_eq!(skipped, vec!["pip  install --upgrade PIP".to_string()]);
+        assert_eq!(
+            second,
+            vec![
+                "sudo apt-get install -y libxml2-dev".to_string(),
+                "pip install lxml==5.0.0".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_installed_packages_are_tracked_across_attempts() {
+        let mut installed = InstalledPackages::default();
+        assert_eq!(installed.summary(), None);
+
+        // Attempt 1: prep and a first install
+        installed.record("python3 -m pip install --upgrade pip");
+        installed.record("npm install react@18.2.0 --save");
+        // Attempt 2: a fix installs another package and upgrades the first one
+        installed.record("npm cache clean --force");
+        installed.record("sudo apt-get install -y libxml2-dev && npm i lodash@4.17.21");
+        installed.record("npm install --legacy-peer-deps react@18.3.1 @types/react@18.2.0");
+        installed.record("pip install -r requirements.txt -e ./local 'requests>=2.31' 2>&1");
+        // Attempt 3: system, Rust and Ruby packages
+        installed.record("sudo apt install -y pkg-config");
+        installed.record("cargo install ripgrep --version 14.1.0 --features pcre2");
+        installed.record("gem install rails -v 7.1.3 --no-document");
+        installed.add("python@3.13".to_string());
+
+        assert_eq!(
+            installed.summary().unwrap(),
+            "Installed: pip, react@18.3.1, libxml2-dev, lodash@4.17.21, @types/react@18.2.0, requests>=2.31, pkg-config, ripgrep, rails, python@3.13"
+        );
+    }
+
+    #[test]
+    fn test_single_registry_install() {
+        assert_eq!(
+            single_registry_install("python3 -m pip install reqeusts==2.31.0"),
+            Some((Registry::PyPI, "reqeusts==2.31.0".to_string()))
+        );
+        assert_eq!(
+            single_registry_install("npm install --save-dev @types/node"),
+            Some((Registry::Npm, "@types/node".to_string()))
+        );
+        assert_eq!(single_registry_install("pip install requests flask"), None);
+        assert_eq!(
+            single_registry_install("pip install -r requirements.txt"),
+            None
+        );
+        assert_eq!(single_registry_install("sudo apt-get install -y jq"), None);
+    }
+
+    #[test]
+    fn test_report_lists_installed_packages() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let report = temp_dir.path().join("installed.txt");
+        let report_path = report.to_string_lossy().to_string();
+
+        report_installed(Some(&report_path), &InstalledPackages::default()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&report).unwrap(),
+            "Installed: nothing\n"
+        );
+
+        let mut installed = InstalledPackages::default();
+        installed.record("uv pip install numpy==1.26.4");
+        report_installed(Some(&report_path), &installed).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&report).unwrap(),
+            "Installed: numpy==1.26.4\n"
+        );
+    }
+
+    #[test]
+    fn test_install_retry_runs_even_after_success() {
+        let mut completed = CompletedCommands::default();
+        completed.record_success("npm install react@18.2.0");
+        completed.record_success("npm cache clean --force");
+
+        let (commands, skipped) = completed.filter_attempt(
+            vec![
+                "npm cache clean --force".to_string(),
+                "npm install react@18.2.0".to_string(),
+            ],
+            |cmd| is_installation_command(cmd, "npm", "react@18.2.0", false),
+        );
+        assert_eq!(commands, vec!["npm install react@18.2.0".to_string()]);
+        assert_eq!(skipped, vec!["npm cache clean --force".to_string()]);
+    }
+
+    #[test]
+    fn test_python_launcher_detection_order() {
+        let only = |available: &'static [&'static str]| {
+            detect_python_launcher(move |program| available.contains(&program))
+        };
+        assert_eq!(only(&["py", "python", "python3"]), Some("python3"));
+        assert_eq!(only(&["py", "python"]), Some("python"));
+        assert_eq!(only(&["py"]), Some("py"));
+        assert_eq!(only(&[]), None);
+
+        assert_eq!(pip_invocation(Some("python3")), "python3 -m pip");
+        assert_eq!(pip_invocation(Some("py")), "py -m pip");
+        assert_eq!(pip_invocation(None), "pip");
+    }
+
+    #[test]
+    fn test_pip_commands_use_the_launcher() {
+        let commands = vec![
+            "pip install requests".to_string(),
+            "pip3 install --no-cache-dir numpy".to_string(),
+            "python -m pip install -U pip".to_string(),
+            "python3 -m pip uninstall -y urllib3".to_string(),
+            "uv pip install httpx".to_string(),
+            "sudo apt-get install -y libffi-dev".to_string(),
+        ];
+        assert_eq!(
+            use_pip_invocation(commands, "py -m pip"),
+            vec![
+                "py -m pip install requests",
+                "py -m pip install --no-cache-dir numpy",
+                "py -m pip install -U pip",
+                "py -m pip uninstall -y urllib3",
+                "uv pip install httpx",
+                "sudo apt-get install -y libffi-dev",
+            ]
+        );
+
+        assert_eq!(
+            install_example("pip", "requests", "python3 -m pip"),
+            "python3 -m pip install requests"
+        );
+        assert_eq!(
+            build_package_verification_command("python", "venv", "python3 -m pip", "requests")
+                .unwrap(),
+            "python3 -m pip show requests"
+        );
+        assert_eq!(
+            build_file_verification_command("python", "venv", "py -m pip", &[]).unwrap(),
+            "py -m pip list"
+        );
+    }
+}
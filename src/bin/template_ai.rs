@@ -1,8 +1,11 @@
 use anyhow::{Context, Result};
 use clap::{Arg, Command};
+use std::path::Path;
+use std::time::Instant;
 use terminalai::{
-    command_parser, command_validator, extract_and_execute_command, load_config,
-    query_provider::QueryProvider,
+    command_parser, command_validator, extract_and_execute_command_timed,
+    extract_commands_from_response, load_config, query_provider::QueryProvider, timing::PhaseTimer,
+    transcript::Transcript, ExecutionOptions,
 };
 
 #[tokio::main]
@@ -11,15 +14,61 @@ async fn main() -> Result<()> {
         .version("0.1.0")
         .author("Terminal AI Contributors")
         .about("AI-powered [COMMAND_DESCRIPTION] operations")
+        .args(terminalai::cli::common_args())
         .arg(
             Arg::new("prompt")
                 .help("Natural language description of the [COMMAND_TYPE] operation")
                 .required(true)
                 .index(1),
         )
+        .arg(
+            Arg::new("show-reasoning")
+                .long("show-reasoning")
+                .help("Print the model's reasoning (<think> blocks) before the commands")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("git-summary")
+                .long("git-summary")
+                .help("Inside a git work tree, list the files the commands created, modified or deleted")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("record")
+                .long("record")
+                .value_name("FILE")
+                .help("Save a timestamped transcript of the executed commands and their output to FILE"),
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .help("Refuse commands the risk check flags (sudo, rm -rf, $(...), device writes, ...) instead of asking")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .help("Print how long config loading, the provider, extraction and execution took")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("directory")
+                .short('C')
+                .long("directory")
+                .value_name("DIR")
+                .help("Run the generated commands in DIR instead of the current directory"),
+        )
         .get_matches();
+    let mut timer = PhaseTimer::new();
 
     let prompt = matches.get_one::<String>("prompt").unwrap();
+    let mut execution_options =
+        ExecutionOptions::for_directory(matches.get_one::<String>("directory"))?;
+    execution_options.git_summary = matches.get_flag("git-summary");
+    execution_options.strict = matches.get_flag("strict");
+    if let Some(path) = matches.get_one::<String>("record") {
+        execution_options.transcript = Some(Transcript::open_shared(Path::new(path))?);
+    }
 
     // TODO: Replace with your specific validation keywords
     let valid_keywords = [
@@ -49,24 +98,45 @@ async fn main() -> Result<()> {
     }
 
     // Load configuration
-    let config = load_config()?;
+    let config = timer.time("config load", load_config)?;
+    terminalai::cli::configure_from_matches(&config, &matches)?;
+    execution_options.error_signatures = config.error_signatures();
+    execution_options.dedup = config.command_dedup();
+    execution_options.history = config.history_origin("template_ai", prompt);
+    execution_options.auto_execute_installs = config.auto_execute_installs();
+    execution_options.confirm_each = config.confirm_each_command();
 
     // Load command definition
     // Replace "template" with your command name (should match cmd/[command].md filename)
     let (system_prompt, _args_section) = command_parser::load_command_definition("template")?;
+    let system_prompt = command_parser::system_prompt_for(&config, system_prompt)?;
 
     // Create query provider
-    let provider = QueryProvider::new(config).context("Failed to create query provider")?;
+    let provider = timer
+        .time("provider setup", || QueryProvider::new(config))
+        .context("Failed to create query provider")?;
 
     // Replace emoji and message with appropriate ones for your command
     println!("🤖 Processing your [COMMAND_TYPE] request...\n");
 
     // Send query to AI
-    match provider.send_query(&system_prompt, prompt).await {
+    let query_started = Instant::now();
+    let response = provider
+        .send_command_query(&system_prompt, prompt, extract_commands_from_response)
+        .await;
+    timer.record("provider query", query_started);
+
+    match response {
         Ok(response) => {
+            if matches.get_flag("show-reasoning") {
+                terminalai::print_reasoning(&response);
+            }
             // Extract and execute commands
-            if let Err(e) = extract_and_execute_command(&response) {
+            if let Err(e) =
+                extract_and_execute_command_timed(&response, &execution_options, &mut timer)
+            {
                 eprintln!("❌ Error executing commands: {e}");
+                std::process::exit(1);
             }
         }
         Err(e) => {
@@ -77,6 +147,10 @@ async fn main() -> Result<()> {
         }
     }
 
+    if matches.get_flag("profile") {
+        println!("\n{}", timer.summary());
+    }
+
     Ok(())
 }
 
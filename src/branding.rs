@@ -0,0 +1,24 @@
+use crate::{is_install_update_remove_command, TerminalAIConfig};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set once at startup from the `branding` setting and `--no-branding`
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Apply the `branding` config setting, which `--no-branding` overrides
+pub fn configure(config: &TerminalAIConfig, no_branding: bool) {
+    set_enabled(config.branding_enabled() && !no_branding);
+}
+
+/// Whether `cmd` gets the `[Terminal AI]` banners: package management commands while
+/// branding is on
+pub fn applies_to(cmd: &str) -> bool {
+    is_enabled() && is_install_update_remove_command(cmd)
+}
@@ -0,0 +1,255 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How long answers are kept (`cache_ttl_seconds`); 0, the default, turns the
+/// cache off
+pub const CACHE_TTL_SETTING: &str = "cache_ttl_seconds";
+
+// Set once at startup from `--no-cache`
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Ask the provider every time, neither reading nor saving cached answers
+pub fn set_disabled(disabled: bool) {
+    DISABLED.store(disabled, Ordering::Relaxed);
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".terminalai").join("cache"))
+}
+
+/// A saved answer, one file per request
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    /// Seconds since the Unix epoch when the answer was received
+    created: u64,
+    response: String,
+}
+
+/// Provider answers saved on disk, so asking the same model the same thing again
+/// within `ttl` seconds skips the round-trip
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    /// The active provider, its endpoint and model, part of every key
+    scope: String,
+    ttl: u64,
+}
+
+impl ResponseCache {
+    pub fn new(dir: PathBuf, provider: &str, endpoint: &str, model: &str, ttl: u64) -> Self {
+        Self {
+            dir,
+            scope: format!("{provider}\0{endpoint}\0{model}"),
+            ttl,
+        }
+    }
+
+    /// The cache for the config's active provider, endpoint and model, `None` when
+    /// `cache_ttl_seconds` is not set or `--no-cache` was given
+    pub fn for_config(config: &crate::TerminalAIConfig) -> Option<Self> {
+        if DISABLED.load(Ordering::Relaxed) {
+            return None;
+        }
+        let ttl = config
+            .get_setting(CACHE_TTL_SETTING)
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .filter(|&ttl| ttl > 0)?;
+        let provider = config.get_active_provider();
+        let model = provider
+            .and_then(|provider| provider.get_setting("model"))
+            .map_or("", String::as_str);
+        // Two servers can offer the same model name, so `base_url`/`url` (and
+        // `--url`, which sets it) keep their answers apart
+        let endpoint = provider.map_or_else(String::new, |provider| match provider.provider_type {
+            crate::providers::ProviderType::Ollama => provider.ollama_url(),
+            _ => provider
+                .url_key()
+                .and_then(|key| provider.get_setting(key))
+                .map_or_else(String::new, |url| url.trim().to_string()),
+        });
+        Some(Self::new(
+            cache_dir()?,
+            &config.active_provider,
+            &endpoint,
+            model,
+            ttl,
+        ))
+    }
+
+    fn path(&self, system_prompt: &str, user_prompt: &str) -> PathBuf {
+        let user_hash = Sha256::digest(user_prompt.as_bytes());
+        let mut key = Sha256::new();
+        key.update(self.scope.as_bytes());
+        key.update([0]);
+        key.update(system_prompt.as_bytes());
+        key.update([0]);
+        key.update(user_hash);
+        let name: String = key
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        self.dir.join(format!("{name}.json"))
+    }
+
+    /// The saved answer to this request, unless there is none or it expired
+    pub fn get(&self, system_prompt: &str, user_prompt: &str) -> Option<String> {
+        self.get_at(system_prompt, user_prompt, now())
+    }
+
+    fn get_at(&self, system_prompt: &str, user_prompt: &str, now: u64) -> Option<String> {
+        let path = self.path(system_prompt, user_prompt);
+        let content = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str::<Entry>(&content) {
+            Ok(entry) if now.saturating_sub(entry.created) < self.ttl => Some(entry.response),
+            _ => {
+                // Expired or unreadable, so it is only taking up space
+                let _ = std::fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    /// Save `response` as the answer to this request; failures are reported on
+    /// stderr rather than failing the query
+    pub fn put(&self, system_prompt: &str, user_prompt: &str, response: &str) {
+        if let Err(e) = self.put_at(system_prompt, user_prompt, response, now()) {
+            eprintln!("⚠️  Failed to cache the answer: {e:#}");
+        }
+    }
+
+    fn put_at(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        response: &str,
+        now: u64,
+    ) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            std::fs::set_permissions(&self.dir, std::fs::Permissions::from_mode(0o700))
+                .with_context(|| format!("Failed to restrict {}", self.dir.display()))?;
+            options.mode(0o600);
+        }
+
+        let entry = Entry {
+            created: now,
+            response: response.to_string(),
+        };
+        let path = self.path(system_prompt, user_prompt);
+        let mut file = options
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        std::io::Write::write_all(&mut file, serde_json::to_string(&entry)?.as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_answers_are_kept_until_they_expire() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::new(
+            dir.path().to_path_buf(),
+            "openai",
+            "https://api.openai.com/v1",
+            "gpt-4o",
+            60,
+        );
+        assert_eq!(cache.get_at("system", "find all TODOs", 1_000), None);
+
+        cache
+            .put_at(
+                "system",
+                "find all TODOs",
+                "COMMAND: grep -rn TODO .",
+                1_000,
+            )
+            .unwrap();
+        assert_eq!(
+            cache.get_at("system", "find all TODOs", 1_059).as_deref(),
+            Some("COMMAND: grep -rn TODO .")
+        );
+        assert_eq!(cache.get_at("system", "find all TODOs", 1_060), None);
+        // The expired entry is removed
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_key_covers_provider_endpoint_model_and_both_prompts() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::new(
+            dir.path().to_path_buf(),
+            "openai",
+            "https://api.openai.com/v1",
+            "gpt-4o",
+            60,
+        );
+        cache.put_at("system", "user", "answer", 0).unwrap();
+        assert_eq!(cache.get_at("system", "user", 0).as_deref(), Some("answer"));
+        assert_eq!(cache.get_at("other system", "user", 0), None);
+        assert_eq!(cache.get_at("system", "other user", 0), None);
+
+        let other_model = ResponseCache::new(
+            dir.path().to_path_buf(),
+            "openai",
+            "https://api.openai.com/v1",
+            "gpt-4o-mini",
+            60,
+        );
+        assert_eq!(other_model.get_at("system", "user", 0), None);
+        let other_provider = ResponseCache::new(
+            dir.path().to_path_buf(),
+            "ollama",
+            "https://api.openai.com/v1",
+            "gpt-4o",
+            60,
+        );
+        assert_eq!(other_provider.get_at("system", "user", 0), None);
+        let other_endpoint = ResponseCache::new(
+            dir.path().to_path_buf(),
+            "openai",
+            "http://localhost:8000/v1",
+            "gpt-4o",
+            60,
+        );
+        assert_eq!(other_endpoint.get_at("system", "user", 0), None);
+    }
+
+    #[test]
+    fn test_answers_are_private_to_the_user() {
+        let home = tempfile::tempdir().unwrap();
+        let dir = home.path().join("cache");
+        let cache = ResponseCache::new(dir.clone(), "openai", "", "gpt-4o", 60);
+        cache.put_at("system", "user", "answer", 0).unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &std::path::Path| {
+                std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+            };
+            assert_eq!(mode(&dir), 0o700);
+            assert_eq!(mode(&cache.path("system", "user")), 0o600);
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use serde::Serialize;
+
+/// What kind of work a command does, judged by its executable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Category {
+    FileOp,
+    Search,
+    Archive,
+    Network,
+    Package,
+    System,
+    Other,
+}
+
+impl std::fmt::Display for Category {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Category::FileOp => "file-op",
+            Category::Search => "search",
+            Category::Archive => "archive",
+            Category::Network => "network",
+            Category::Package => "package",
+            Category::System => "system",
+            Category::Other => "other",
+        };
+        f.write_str(name)
+    }
+}
+
+const FILE_OPS: &[&str] = &[
+    "cp", "mv", "rm", "mkdir", "rmdir", "touch", "ln", "chmod", "chown", "chgrp", "ls", "cat",
+    "head", "tail", "stat", "du", "rsync", "tee", "dd", "truncate", "shred", "sed", "awk", "sort",
+    "uniq", "wc", "cut", "tr", "diff", "echo", "printf", "cd", "pwd", "realpath", "basename",
+    "dirname",
+];
+const SEARCHES: &[&str] = &[
+    "find", "grep", "egrep", "fgrep", "rg", "ag", "fd", "locate", "which", "whereis", "xargs",
+];
+const ARCHIVES: &[&str] = &[
+    "tar", "gzip", "gunzip", "zip", "unzip", "bzip2", "bunzip2", "xz", "unxz", "7z", "zstd", "zcat",
+];
+const NETWORK: &[&str] = &[
+    "curl",
+    "wget",
+    "ssh",
+    "scp",
+    "sftp",
+    "ping",
+    "nc",
+    "netcat",
+    "dig",
+    "nslookup",
+    "host",
+    "traceroute",
+    "telnet",
+    "ftp",
+    "ip",
+    "ifconfig",
+    "netstat",
+    "ss",
+];
+const PACKAGES: &[&str] = &[
+    "apt", "apt-get", "dpkg", "yum", "dnf", "rpm", "pacman", "zypper", "apk", "brew", "port",
+    "snap", "flatpak", "pip", "pip3", "pipx", "npm", "yarn", "pnpm", "conda", "poetry", "pipenv",
+    "uv", "gem", "composer", "nvm", "pyenv",
+];
+const SYSTEM: &[&str] = &[
+    "ps",
+    "kill",
+    "pkill",
+    "killall",
+    "top",
+    "htop",
+    "df",
+    "free",
+    "uptime",
+    "whoami",
+    "id",
+    "uname",
+    "systemctl",
+    "service",
+    "journalctl",
+    "mount",
+    "umount",
+    "lsof",
+    "hostname",
+    "date",
+    "crontab",
+    "shutdown",
+    "reboot",
+    "useradd",
+    "usermod",
+    "renice",
+    "export",
+];
+
+/// Subcommands that make `cargo`/`go` manage packages rather than build code
+const PACKAGE_SUBCOMMANDS: &[&str] = &["install", "add", "remove", "uninstall", "get", "update"];
+
+/// Category of `cmd`'s executable; `sudo`, `env` and `VAR=value` prefixes are skipped
+pub fn categorize_command(cmd: &str) -> Category {
+    let mut words = cmd
+        .split_whitespace()
+        .skip_while(|word| {
+            matches!(
+                *word,
+                "sudo" | "env" | "nohup" | "time" | "command" | "exec"
+            ) || (word.contains('=') && !word.starts_with('-'))
+        })
+        .map(|word| word.trim_start_matches(['(', '{']));
+    let Some(program) = words.next() else {
+        return Category::Other;
+    };
+    let program = program.rsplit('/').next().unwrap_or(program);
+    let next = words.next().unwrap_or_default();
+
+    let listed = |list: &[&str]| list.contains(&program);
+    if listed(PACKAGES)
+        || (matches!(program, "cargo" | "go") && PACKAGE_SUBCOMMANDS.contains(&next))
+        || (program.starts_with("python") && next == "-m" && words.next() == Some("pip"))
+    {
+        Category::Package
+    } else if listed(SEARCHES) {
+        Category::Search
+    } else if listed(ARCHIVES) {
+        Category::Archive
+    } else if listed(NETWORK) {
+        Category::Network
+    } else if listed(FILE_OPS) {
+        Category::FileOp
+    } else if listed(SYSTEM) {
+        Category::System
+    } else {
+        Category::Other
+    }
+}
+
+/// `commands` prefixed with their category, e.g. `[archive] tar czf logs.tgz logs/`,
+/// for plan previews
+pub fn label_commands(commands: &[String]) -> Vec<String> {
+    commands
+        .iter()
+        .map(|cmd| format!("[{}] {cmd}", categorize_command(cmd)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_representative_commands() {
+        for (cmd, category) in [
+            ("mkdir -p backup", Category::FileOp),
+            ("cp -r src/ backup/", Category::FileOp),
+            ("sudo chown -R www-data /var/www", Category::FileOp),
+            ("find . -name '*.log' -mtime +7", Category::Search),
+            ("grep -rn TODO src | wc -l", Category::Search),
+            ("tar czf logs.tgz logs/", Category::Archive),
+            ("/usr/bin/unzip release.zip", Category::Archive),
+            (
+                "curl -fsSL https://example.com/data.json -o data.json",
+                Category::Network,
+            ),
+            ("ssh deploy@host 'uptime'", Category::Network),
+            ("sudo apt-get install -y jq", Category::Package),
+            ("PIP_NO_CACHE_DIR=1 pip install requests", Category::Package),
+            ("python3 -m pip install --upgrade pip", Category::Package),
+            ("cargo install ripgrep", Category::Package),
+            ("ps aux --sort=-%mem", Category::System),
+            ("systemctl restart nginx", Category::System),
+            ("cargo build --release", Category::Other),
+            ("./deploy.sh", Category::Other),
+            ("", Category::Other),
+        ] {
+            assert_eq!(categorize_command(cmd), category, "{cmd}");
+        }
+    }
+
+    #[test]
+    fn test_labels_and_json_names() {
+        assert_eq!(
+            label_commands(&["tar xzf a.tgz".to_string(), "ls".to_string()]),
+            vec!["[archive] tar xzf a.tgz", "[file-op] ls"]
+        );
+        assert_eq!(
+            serde_json::to_string(&Category::FileOp).unwrap(),
+            "\"file-op\""
+        );
+    }
+}
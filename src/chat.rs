@@ -0,0 +1,259 @@
+use crate::{
+    history::{self, HistoryEntry},
+    load_config,
+    orchestrator::{confirmed_plan, execute_plan, plan_execution_options, OrchestrationOptions},
+    query_provider::QueryProvider,
+    script::ScriptRecorder,
+    timing::PhaseTimer,
+    transcript::Transcript,
+};
+use anyhow::{Context, Result};
+use std::io::{BufRead, Write};
+
+/// Earlier turns included in the prompt; older ones are left out
+const MAX_TURNS_IN_PROMPT: usize = 10;
+
+/// Transcript lines kept per turn; the last ones are kept, since they usually hold
+/// the result and any error
+const MAX_OUTPUT_LINES_PER_TURN: usize = 40;
+
+/// Options controlling a `tai chat` session
+#[derive(Debug, Clone, Default)]
+pub struct ChatOptions {
+    /// Send the queries to this endpoint instead of the active provider's configured URL
+    pub url: Option<String>,
+    /// Stop querying the provider once the estimated spend of the session reaches this many USD
+    pub max_cost: Option<f64>,
+    /// Shell the commands run in (`--shell`), instead of the `shell` setting
+    pub shell: Option<String>,
+}
+
+/// One request of the session with the commands it ran and what they printed
+#[derive(Debug, Clone, PartialEq)]
+struct Turn {
+    request: String,
+    /// Transcript lines: `$ command`, output, `! stderr` and `= exit N`
+    transcript: Vec<String>,
+    /// Why nothing ran, e.g. the plan was declined
+    not_run: Option<String>,
+}
+
+/// What the model is told about the session so far
+#[derive(Debug, Default)]
+pub struct Conversation {
+    turns: Vec<Turn>,
+}
+
+impl Conversation {
+    pub fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+
+    /// Remember `request` and the transcript of the commands it ran
+    pub fn push(&mut self, request: &str, transcript: Vec<String>) {
+        self.turns.push(Turn {
+            request: request.to_string(),
+            transcript,
+            not_run: None,
+        });
+    }
+
+    /// Remember `request`, for which nothing ran because of `reason`
+    pub fn push_not_run(&mut self, request: &str, reason: &str) {
+        self.turns.push(Turn {
+            request: request.to_string(),
+            transcript: Vec::new(),
+            not_run: Some(reason.to_string()),
+        });
+    }
+
+    /// The prompt for `request`: the request alone at the start of the session,
+    /// afterwards preceded by the recent turns so follow-ups like "now also
+    /// compress that folder" can refer to them
+    pub fn prompt_for(&self, request: &str) -> String {
+        if self.turns.is_empty() {
+            return request.to_string();
+        }
+
+        let skip = self.turns.len().saturating_sub(MAX_TURNS_IN_PROMPT);
+        let mut prompt = String::from("Earlier in this session (oldest first):\n");
+        for turn in &self.turns[skip..] {
+            prompt.push_str(&format!("\nRequest: {}\n", turn.request));
+            if turn.transcript.is_empty() {
+                match &turn.not_run {
+                    Some(reason) => prompt.push_str(&format!("(no commands were run: {reason})\n")),
+                    None => prompt.push_str("(no commands were run)\n"),
+                }
+                continue;
+            }
+            let omitted = turn
+                .transcript
+                .len()
+                .saturating_sub(MAX_OUTPUT_LINES_PER_TURN);
+            if omitted > 0 {
+                prompt.push_str(&format!("... ({omitted} earlier lines omitted)\n"));
+            }
+            for line in &turn.transcript[omitted..] {
+                prompt.push_str(line);
+                prompt.push('\n');
+            }
+        }
+        prompt.push_str(&format!(
+            "\nCurrent request: {request}\n\nThe commands above already ran; don't repeat them unless the current request asks to. Use their output when the current request refers to earlier results."
+        ));
+        prompt
+    }
+}
+
+/// Whether `line` ends the session
+fn is_exit(line: &str) -> bool {
+    matches!(line, "exit" | "quit" | "/exit" | "/quit")
+}
+
+/// `tai chat`: read requests until `exit` or end of input, planning and running
+/// each with the earlier requests, commands and output as context
+pub async fn run_chat(options: &ChatOptions) -> Result<()> {
+    let mut config = load_config()?;
+    if let Some(url) = &options.url {
+        config.override_url(url)?;
+    }
+    crate::branding::configure(&config, false);
+    crate::ui::configure(&config);
+    crate::configure_extraction(&config)?;
+    crate::shell::configure(&config, options.shell.as_deref())?;
+    let mut execution_options = plan_execution_options(&config, None)?;
+    let transcript = Transcript::in_memory_shared();
+    execution_options.transcript = Some(transcript.clone());
+    let history_config = config.clone();
+    let mut provider = QueryProvider::new(config).context("Failed to create query provider")?;
+    if let Some(max_cost) = options.max_cost {
+        provider = provider.with_max_cost(max_cost)?;
+    }
+
+    println!("💬 Describe a task; follow-ups can refer to what already ran. Type 'exit' to quit.");
+    let plan_options = OrchestrationOptions::default();
+    let mut conversation = Conversation::default();
+    let mut timer = PhaseTimer::new();
+    loop {
+        print!("\n💬 > ");
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().lock().read_line(&mut line)? == 0 {
+            println!();
+            break;
+        }
+        let request = line.trim();
+        if request.is_empty() {
+            continue;
+        }
+        if is_exit(request) {
+            break;
+        }
+
+        let query = conversation.prompt_for(request);
+        let plan = match confirmed_plan(
+            &provider,
+            &query,
+            None,
+            &plan_options,
+            &execution_options,
+            &mut timer,
+        )
+        .await
+        {
+            Ok(Some(plan)) => plan,
+            Ok(None) => {
+                conversation.push_not_run(request, "no plan was confirmed");
+                continue;
+            }
+            Err(e) => {
+                eprintln!("❌ Error: {e:#}");
+                conversation.push_not_run(request, &format!("planning failed: {e:#}"));
+                continue;
+            }
+        };
+
+        let mut recorder = ScriptRecorder::new();
+        let result = execute_plan(&plan.commands, &execution_options, &mut recorder).await;
+        if let Some(origin) = history_config.history_origin("tai chat", request) {
+            history::record(
+                &HistoryEntry::new(&origin, &plan.commands, None, result.is_ok())
+                    .with_rationales(plan.rationales)
+                    .with_exit_codes(&result)
+                    .with_response(&origin, &plan.response),
+            );
+        }
+        match &result {
+            Ok(()) => println!("\n✅ Done."),
+            Err(e) => eprintln!("\n❌ Error: {e:#}"),
+        }
+        let lines = transcript
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take_lines();
+        conversation.push(request, lines);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_request_is_sent_as_is() {
+        let conversation = Conversation::default();
+        assert!(conversation.is_empty());
+        assert_eq!(
+            conversation.prompt_for("back up the photos folder"),
+            "back up the photos folder"
+        );
+    }
+
+    #[test]
+    fn test_follow_up_includes_earlier_commands_and_output() {
+        let mut conversation = Conversation::default();
+        conversation.push(
+            "back up the photos folder",
+            vec![
+                "$ cp -r photos photos-backup".to_string(),
+                "= exit 0".to_string(),
+                "$ du -sh photos-backup".to_string(),
+                "  1.2G\tphotos-backup".to_string(),
+                "= exit 0".to_string(),
+            ],
+        );
+        conversation.push_not_run("delete everything", "no plan was confirmed");
+        conversation.push("list the photos", Vec::new());
+
+        let prompt = conversation.prompt_for("now also compress that folder");
+        assert!(prompt.starts_with(
+            "Earlier in this session (oldest first):\n\nRequest: back up the photos folder\n$ cp -r photos photos-backup\n= exit 0\n$ du -sh photos-backup\n  1.2G\tphotos-backup\n= exit 0\n\nRequest: delete everything\n(no commands were run: no plan was confirmed)\n\nRequest: list the photos\n(no commands were run)\n\nCurrent request: now also compress that folder\n"
+        ));
+    }
+
+    #[test]
+    fn test_prompt_keeps_recent_turns_and_last_output_lines() {
+        let mut conversation = Conversation::default();
+        for i in 0..MAX_TURNS_IN_PROMPT + 2 {
+            conversation.push(&format!("request {i}"), vec![format!("$ echo {i}")]);
+        }
+        let output: Vec<String> = (0..MAX_OUTPUT_LINES_PER_TURN + 5)
+            .map(|i| format!("  line {i}"))
+            .collect();
+        conversation.push("print many lines", output);
+
+        let prompt = conversation.prompt_for("count them");
+        assert!(!prompt.contains("Request: request 2\n"));
+        assert!(prompt.contains("Request: request 3\n"));
+        assert!(prompt.contains("... (5 earlier lines omitted)\n  line 5\n"));
+        assert!(!prompt.contains("  line 4\n"));
+    }
+
+    #[test]
+    fn test_exit_words() {
+        assert!(is_exit("exit"));
+        assert!(is_exit("/quit"));
+        assert!(!is_exit("exit the vim session"));
+    }
+}
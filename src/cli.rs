@@ -0,0 +1,96 @@
+use crate::TerminalAIConfig;
+use anyhow::Result;
+use clap::{Arg, ArgAction, ArgMatches};
+
+/// Flags every command line tool takes: `--shell`, `--no-context`, `--no-cache`,
+/// `--trace-http`, `--simulate-error`, `--no-branding` and `--yes`
+pub fn common_args() -> Vec<Arg> {
+    vec![
+        Arg::new("shell")
+            .long("shell")
+            .value_name("SHELL")
+            .help("Shell to run the commands with: sh, bash, zsh, dash, fish, pwsh, powershell or cmd (overrides the execution.shell setting)"),
+        Arg::new("no-context")
+            .long("no-context")
+            .help("Don't tell the model the OS, shell, working directory listing and git branch (prompt_context)")
+            .action(ArgAction::SetTrue),
+        Arg::new("no-cache")
+            .long("no-cache")
+            .help("Ask the provider even if the answer is cached (cache_ttl_seconds)")
+            .action(ArgAction::SetTrue),
+        Arg::new("trace-http")
+            .long("trace-http")
+            .help("Print each provider request and response body, with credentials masked")
+            .action(ArgAction::SetTrue),
+        Arg::new("simulate-error")
+            .long("simulate-error")
+            .value_name("TYPE")
+            .value_parser(crate::providers::SIMULATED_ERRORS)
+            .help("Make the next provider call fail with a timeout, 429, 500 or DNS error")
+            .hide(true),
+        Arg::new("no-branding")
+            .long("no-branding")
+            .help("Don't print the [Terminal AI] banners around package management commands")
+            .action(ArgAction::SetTrue),
+        Arg::new("yes")
+            .short('y')
+            .long("yes")
+            .help("Run the commands without asking (also TERMINALAI_ASSUME_YES=1); commands the risk check flags are refused")
+            .action(ArgAction::SetTrue),
+    ]
+}
+
+/// `--breakdown`, breaking the suggested commands down instead of running them.
+/// Not `--explain`, which in `resolve_ai` explains the commands and then runs them.
+pub fn breakdown_arg() -> Arg {
+    Arg::new("breakdown")
+        .long("breakdown")
+        .help("Break each suggested command down flag by flag, with the files it would touch, instead of running it")
+        .action(ArgAction::SetTrue)
+}
+
+/// Apply `--no-cache`, `--trace-http` and `--simulate-error` to every later provider call
+pub fn configure_requests(matches: &ArgMatches) {
+    crate::cache::set_disabled(matches.get_flag("no-cache"));
+    crate::providers::set_http_trace(matches.get_flag("trace-http"));
+    crate::providers::simulate_error(
+        matches
+            .get_one::<String>("simulate-error")
+            .and_then(|name| crate::providers::SimulatedError::from_name(name)),
+    );
+}
+
+/// Apply the config and the [`common_args`] a tool was started with: branding, prompts,
+/// extraction limits, provider requests, the shell and the prompt context
+pub fn configure_from_matches(config: &TerminalAIConfig, matches: &ArgMatches) -> Result<()> {
+    crate::branding::configure(config, matches.get_flag("no-branding"));
+    crate::ui::configure(config);
+    crate::ui::configure_assume_yes(matches.get_flag("yes"));
+    crate::configure_extraction(config)?;
+    configure_requests(matches);
+    crate::shell::configure(
+        config,
+        matches.get_one::<String>("shell").map(String::as_str),
+    )?;
+    crate::context::configure(config, matches.get_flag("no-context"));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_common_args_parse_in_any_tool() {
+        let matches = clap::Command::new("tool")
+            .args(common_args())
+            .arg(breakdown_arg())
+            .try_get_matches_from(["tool", "-y", "--no-cache", "--shell", "bash", "--breakdown"])
+            .unwrap();
+        assert!(matches.get_flag("yes"));
+        assert!(matches.get_flag("no-cache"));
+        assert!(matches.get_flag("breakdown"));
+        assert!(!matches.get_flag("no-context"));
+        assert_eq!(matches.get_one::<String>("shell").unwrap(), "bash");
+    }
+}
@@ -24,6 +24,25 @@ pub fn load_command_definition(command_name: &str) -> Result<(String, String)> {
     Ok((system_prompt, args_section))
 }
 
+/// `definition_prompt`, a command definition's system prompt, or the config's
+/// `system_prompt_overrides` entry for the active model in its place. Only command
+/// definitions are overridden; the prompts asking for plans, explanations and
+/// summaries keep the format their answers are parsed in.
+pub fn system_prompt_for(
+    config: &crate::TerminalAIConfig,
+    definition_prompt: String,
+) -> Result<String> {
+    Ok(config
+        .system_prompt_override()?
+        .unwrap_or(definition_prompt))
+}
+
+/// Parse a command definition that is not built in, e.g. one embedded by a
+/// scaffolded binary with `include_str!`
+pub fn parse_command_definition(content: &str) -> Result<(String, String)> {
+    parse_command_conf(content)
+}
+
 fn parse_command_conf(content: &str) -> Result<(String, String)> {
     let mut system_prompt = String::new();
     let mut args_section = String::new();
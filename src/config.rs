@@ -1,20 +1,86 @@
 use crate::providers::{ProviderConfig, ProviderType};
-use crate::save_config;
+use crate::{save_config, save_config_to_conf, TerminalAIConfig};
 use anyhow::{Context, Result};
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Local provider setting marking an install postponed by `--defer-setup`
+pub const LOCAL_SETUP_SETTING: &str = "setup";
+pub const LOCAL_SETUP_DEFERRED: &str = "deferred";
+
+/// Options controlling `tai init`
+#[derive(Debug, Clone, Default)]
+pub struct InitOptions {
+    /// Save the Local provider without installing llama.cpp or downloading its model
+    pub defer_setup: bool,
+    /// Save to `terminalai.conf` next to the executable instead of the user config,
+    /// so the configuration travels with the binary
+    pub portable: bool,
+}
 
-async fn trigger_local_setup(config: &ProviderConfig) -> Result<()> {
-    // Create a temporary LocalProvider to trigger the setup
-    let provider = crate::providers::LocalProvider::new(config.clone())?;
+/// The downloads that make the Local provider usable
+#[async_trait::async_trait]
+trait LocalSetup {
+    fn install_llama_cpp(&self, config: &ProviderConfig) -> Result<()>;
+    async fn download_model(&self, config: &ProviderConfig) -> Result<()>;
+}
 
-    // Install llama.cpp and download the model during setup
-    let _ = provider.ensure_llama_cpp_installed()?;
-    let _ = provider.ensure_model_downloaded().await?;
+/// Installs llama.cpp and downloads the model into ~/.terminalai
+struct LlamaCppSetup;
 
-    Ok(())
+#[async_trait::async_trait]
+impl LocalSetup for LlamaCppSetup {
+    fn install_llama_cpp(&self, config: &ProviderConfig) -> Result<()> {
+        crate::providers::LocalProvider::new(config.clone())?.ensure_llama_cpp_installed()?;
+        Ok(())
+    }
+
+    async fn download_model(&self, config: &ProviderConfig) -> Result<()> {
+        crate::providers::LocalProvider::new(config.clone())?
+            .ensure_model_downloaded()
+            .await?;
+        Ok(())
+    }
+}
+
+/// Run the Local provider's setup now, or with `defer_setup` mark it for first use
+async fn setup_local_provider(
+    setup: &dyn LocalSetup,
+    config: &mut ProviderConfig,
+    defer_setup: bool,
+) {
+    if defer_setup {
+        config.settings.insert(
+            LOCAL_SETUP_SETTING.to_string(),
+            LOCAL_SETUP_DEFERRED.to_string(),
+        );
+        println!("\n⏭️  Skipping the llama.cpp install and model download (--defer-setup).");
+        println!("   They run the first time a command uses the local provider.");
+        return;
+    }
+
+    println!("\n🚀 Starting local provider setup...");
+    let result = match setup.install_llama_cpp(config) {
+        Ok(()) => setup.download_model(config).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => {
+            println!("✅ Local provider setup completed successfully!");
+            println!("📋 Both llama.cpp and model are now ready to use.");
+        }
+        Err(e) => {
+            println!("⚠️  Warning: Failed to complete local setup: {e}");
+            println!("   You can retry by running any command with the local provider.");
+        }
+    }
 }
 
 pub async fn init_config() -> Result<()> {
+    init_config_with_options(&InitOptions::default()).await
+}
+
+pub async fn init_config_with_options(options: &InitOptions) -> Result<()> {
     println!("🚀 Initializing Terminal AI configuration...\n");
 
     // Load existing config or create default
@@ -34,33 +100,34 @@ pub async fn init_config() -> Result<()> {
             let timeout = get_timeout()?;
 
             // Configure provider-specific settings
-            let provider_config = match provider_type {
+            let mut provider_config = match provider_type {
                 ProviderType::Ollama => configure_ollama(timeout)?,
                 ProviderType::OpenAI => configure_openai(timeout)?,
+                ProviderType::OpenAICompatible => configure_openai_compatible(timeout)?,
                 ProviderType::Claude => configure_claude(timeout)?,
                 ProviderType::Gemini => configure_gemini(timeout)?,
-                ProviderType::Local => configure_local(timeout)?,
+                ProviderType::Local => configure_local(timeout, options.defer_setup)?,
+                ProviderType::Custom(name) => {
+                    return Err(anyhow::anyhow!(
+                        "Provider '{name}' is registered by code and cannot be configured interactively"
+                    ))
+                }
             };
 
             // Determine provider name
-            let provider_name = match provider_type {
+            let provider_name = match &provider_type {
                 ProviderType::Ollama => "ollama",
                 ProviderType::OpenAI => "openai",
+                ProviderType::OpenAICompatible => "openai_compatible",
                 ProviderType::Claude => "claude",
                 ProviderType::Gemini => "gemini",
                 ProviderType::Local => "local",
+                ProviderType::Custom(name) => name.as_str(),
             };
 
-            // For local provider, trigger immediate setup (llama.cpp only)
             if provider_type == ProviderType::Local {
-                println!("\n🚀 Starting local provider setup...");
-                if let Err(e) = trigger_local_setup(&provider_config).await {
-                    println!("⚠️  Warning: Failed to complete local setup: {e}");
-                    println!("   You can retry by running any command with the local provider.");
-                } else {
-                    println!("✅ Local provider setup completed successfully!");
-                    println!("📋 Both llama.cpp and model are now ready to use.");
-                }
+                setup_local_provider(&LlamaCppSetup, &mut provider_config, options.defer_setup)
+                    .await;
             }
 
             // Update provider in config
@@ -83,14 +150,37 @@ pub async fn init_config() -> Result<()> {
         }
     }
 
-    save_config(&config).context("Failed to save configuration")?;
+    let local_config_path = crate::get_local_config_path()?;
+    let config_path = save_init_config(&config, options.portable, &local_config_path)
+        .context("Failed to save configuration")?;
 
     println!("🎯 Active provider: {}", config.active_provider);
-    println!("📁 Config file location: {:?}", crate::get_config_path()?);
+    println!("📁 Config file location: {config_path:?}");
+    if !options.portable && local_config_path.exists() {
+        println!(
+            "⚠️  {local_config_path:?} takes precedence over this file; run 'tai init --portable' to change it"
+        );
+    }
 
     Ok(())
 }
 
+/// Save what `tai init` configured and return where it went: the portable `.conf`
+/// at `local_config_path` or the user's JSON config
+fn save_init_config(
+    config: &TerminalAIConfig,
+    portable: bool,
+    local_config_path: &Path,
+) -> Result<PathBuf> {
+    if portable {
+        let path = local_config_path.to_path_buf();
+        save_config_to_conf(config, &path)?;
+        return Ok(path);
+    }
+    save_config(config)?;
+    crate::get_config_path()
+}
+
 #[derive(Debug)]
 enum ConfigAction {
     ConfigureProvider,
@@ -161,9 +251,10 @@ fn select_provider() -> Result<ProviderType> {
     println!("3. Claude (Anthropic)");
     println!("4. Gemini (Google)");
     println!("5. Local (llamacpp)");
+    println!("6. OpenAI-compatible server (LM Studio, vLLM, LiteLLM, OpenRouter, ...)");
 
     loop {
-        print!("\nEnter your choice [1-5]: ");
+        print!("\nEnter your choice [1-6]: ");
         io::stdout().flush()?;
 
         let mut input = String::new();
@@ -175,7 +266,8 @@ fn select_provider() -> Result<ProviderType> {
             "3" => return Ok(ProviderType::Claude),
             "4" => return Ok(ProviderType::Gemini),
             "5" => return Ok(ProviderType::Local),
-            _ => println!("❌ Invalid choice. Please enter 1, 2, 3, 4, or 5."),
+            "6" => return Ok(ProviderType::OpenAICompatible),
+            _ => println!("❌ Invalid choice. Please enter 1, 2, 3, 4, 5, or 6."),
         }
     }
 }
@@ -249,6 +341,40 @@ fn configure_openai(timeout: u64) -> Result<ProviderConfig> {
     Ok(ProviderConfig::new_openai(api_key, model, timeout))
 }
 
+fn configure_openai_compatible(timeout: u64) -> Result<ProviderConfig> {
+    println!("\n🔌 Configuring an OpenAI-compatible server...");
+
+    print!("Base URL (e.g. http://localhost:1234/v1): ");
+    io::stdout().flush()?;
+    let mut base_url = String::new();
+    io::stdin().read_line(&mut base_url)?;
+    let base_url = base_url.trim().to_string();
+
+    if base_url.is_empty() {
+        return Err(anyhow::anyhow!("Base URL is required"));
+    }
+
+    print!("API Key (leave empty if the server needs none): ");
+    io::stdout().flush()?;
+    let mut api_key = String::new();
+    io::stdin().read_line(&mut api_key)?;
+    let api_key = Some(api_key.trim().to_string()).filter(|key| !key.is_empty());
+
+    print!("Model: ");
+    io::stdout().flush()?;
+    let mut model = String::new();
+    io::stdin().read_line(&mut model)?;
+    let model = model.trim().to_string();
+
+    if model.is_empty() {
+        return Err(anyhow::anyhow!("Model is required"));
+    }
+
+    Ok(ProviderConfig::new_openai_compatible(
+        base_url, api_key, model, timeout,
+    ))
+}
+
 fn configure_claude(timeout: u64) -> Result<ProviderConfig> {
     println!("\n🧠 Configuring Claude...");
 
@@ -301,9 +427,13 @@ fn configure_gemini(timeout: u64) -> Result<ProviderConfig> {
     Ok(ProviderConfig::new_gemini(api_key, model, timeout))
 }
 
-fn configure_local(timeout: u64) -> Result<ProviderConfig> {
+fn configure_local(timeout: u64, defer_setup: bool) -> Result<ProviderConfig> {
     println!("\n🏠 Configuring Local AI Provider...");
-    println!("This will automatically install llama.cpp and download the specified model.");
+    if defer_setup {
+        println!("llama.cpp and the model will be installed the first time they are needed.");
+    } else {
+        println!("This will automatically install llama.cpp and download the specified model.");
+    }
     println!("The installation will be stored in ~/.terminalai/");
 
     print!("Press Enter to continue or Ctrl+C to cancel: ");
@@ -327,6 +457,371 @@ fn configure_local(timeout: u64) -> Result<ProviderConfig> {
     Ok(config)
 }
 
+/// Problems found by `tai config validate`; warnings don't fail validation
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ValidationReport {
+    pub problems: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    pub fn print(&self) {
+        for warning in &self.warnings {
+            println!("⚠️  {warning}");
+        }
+        for problem in &self.problems {
+            println!("❌ {problem}");
+        }
+        if self.is_valid() {
+            println!("✅ Configuration is valid");
+        } else {
+            println!(
+                "\n{} problem{} found",
+                self.problems.len(),
+                if self.problems.len() == 1 { "" } else { "s" }
+            );
+        }
+    }
+}
+
+/// The file `load_config` reads, or `None` when it falls back to the defaults
+pub fn config_source() -> Option<std::path::PathBuf> {
+    crate::get_local_config_path()
+        .ok()
+        .filter(|path| path.exists())
+        .or_else(|| crate::get_config_path().ok().filter(|path| path.exists()))
+}
+
+/// Check the config file at `path`: syntax first, then the settings it produces
+pub fn validate_config_file(path: &std::path::Path) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            report
+                .problems
+                .push(format!("Failed to read {}: {e}", path.display()));
+            return report;
+        }
+    };
+
+    if path.extension().is_some_and(|ext| ext == "json") {
+        return validate_config_json(&content);
+    }
+    report.problems.extend(lint_conf(&content));
+    if let Ok(config) = crate::load_config_from_conf(&path.to_path_buf()) {
+        validate_config(&config, &mut report);
+    }
+    report
+}
+
+/// Check a JSON config, e.g. the value of `TERMINALAI_CONFIG`
+pub fn validate_config_json(content: &str) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    if let Some(config) = parse_json_config(content, &mut report) {
+        for name in duplicate_provider_keys(content) {
+            report.warnings.push(format!(
+                "providers.{name} is defined more than once; only the last entry is used"
+            ));
+        }
+        validate_config(&config, &mut report);
+    }
+    report
+}
+
+/// Provider names that appear more than once in a JSON config's `providers` object,
+/// which serde would otherwise resolve silently by keeping the last
+fn duplicate_provider_keys(content: &str) -> Vec<String> {
+    /// The keys of a JSON object in document order, repeats included
+    #[derive(Default)]
+    struct Keys(Vec<String>);
+
+    impl<'de> serde::Deserialize<'de> for Keys {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct KeysVisitor;
+
+            impl<'de> serde::de::Visitor<'de> for KeysVisitor {
+                type Value = Keys;
+
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("an object")
+                }
+
+                fn visit_map<A: serde::de::MapAccess<'de>>(
+                    self,
+                    mut map: A,
+                ) -> Result<Keys, A::Error> {
+                    let mut keys = Vec::new();
+                    while let Some((key, _)) = map.next_entry::<String, serde::de::IgnoredAny>()? {
+                        keys.push(key);
+                    }
+                    Ok(Keys(keys))
+                }
+            }
+
+            deserializer.deserialize_map(KeysVisitor)
+        }
+    }
+
+    #[derive(serde::Deserialize)]
+    struct ProviderKeys {
+        #[serde(default)]
+        providers: Keys,
+    }
+
+    let Ok(ProviderKeys { providers }) = serde_json::from_str::<ProviderKeys>(content) else {
+        return Vec::new();
+    };
+    let mut duplicates = Vec::new();
+    for (i, key) in providers.0.iter().enumerate() {
+        if providers.0[..i].contains(key) && !duplicates.contains(key) {
+            duplicates.push(key.clone());
+        }
+    }
+    duplicates
+}
+
+fn parse_json_config(content: &str, report: &mut ValidationReport) -> Option<TerminalAIConfig> {
+    match serde_json::from_str::<TerminalAIConfig>(content) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            // The pre-multi-provider format still loads (and is migrated) by load_config
+            if content.contains("\"provider\"") && !content.contains("\"providers\"") {
+                report
+                    .warnings
+                    .push("Legacy single-provider format; run 'tai init' to migrate".to_string());
+                return None;
+            }
+            report
+                .problems
+                .push(format!("line {}, column {}: {}", e.line(), e.column(), e));
+            None
+        }
+    }
+}
+
+/// Syntax problems in a `terminalai.conf`, with line numbers. The loader skips
+/// these lines silently, so they would otherwise go unnoticed.
+fn lint_conf(content: &str) -> Vec<String> {
+    let mut known_sections = TerminalAIConfig::default().get_provider_names();
+    known_sections.push(crate::SYSTEM_PROMPT_OVERRIDES_SECTION.to_string());
+    known_sections.push(crate::policy::POLICY_SECTION.to_string());
+    let mut problems = Vec::new();
+    let mut section = String::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            let header = line.strip_prefix('[').and_then(|l| l.strip_suffix(']'));
+            section = header.unwrap_or_default().to_string();
+            match header {
+                Some(section) if known_sections.iter().any(|known| known == section) => {}
+                Some(section) => problems.push(format!(
+                    "line {number}: unknown provider section [{section}]; its settings are ignored"
+                )),
+                None => problems.push(format!(
+                    "line {number}: unterminated section header '{line}'"
+                )),
+            }
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            problems.push(format!(
+                "line {number}: expected 'key = value', found '{line}'"
+            ));
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if key.is_empty() {
+            problems.push(format!("line {number}: missing key before '='"));
+        }
+        if value.starts_with('"') != (value.len() > 1 && value.ends_with('"')) {
+            problems.push(format!("line {number}: unterminated quote in '{value}'"));
+        }
+        if key == "timeout_seconds" && value.trim_matches('"').parse::<u64>().is_err() {
+            problems.push(format!(
+                "line {number}: timeout_seconds must be a whole number of seconds, found '{value}'"
+            ));
+        }
+        if section == crate::policy::POLICY_SECTION {
+            let pattern = value.trim_matches('"');
+            let mut policy = crate::policy::PolicyConfig::default();
+            if let Err(e) = policy.insert(key, pattern) {
+                problems.push(format!("line {number}: {e}"));
+            } else if let Err(e) = regex::Regex::new(pattern) {
+                problems.push(format!("line {number}: invalid pattern '{pattern}': {e}"));
+            }
+        }
+    }
+    problems
+}
+
+/// Settings a provider can't work with when empty; missing ones are reported by the
+/// provider itself
+fn required_settings(provider_type: &ProviderType) -> &'static [&'static str] {
+    match provider_type {
+        // An empty Ollama url falls back to OLLAMA_HOST or the default
+        ProviderType::Ollama
+        | ProviderType::OpenAI
+        | ProviderType::Claude
+        | ProviderType::Gemini
+        | ProviderType::Local => &["model"],
+        ProviderType::OpenAICompatible => &["base_url", "model"],
+        ProviderType::Custom(_) => &[],
+    }
+}
+
+/// Check the active provider exists and every provider has its required settings.
+/// Only the active provider's problems fail validation.
+pub fn validate_config(config: &TerminalAIConfig, report: &mut ValidationReport) {
+    if config.get_active_provider().is_none() {
+        report.problems.push(format!(
+            "active_provider '{}' is not configured (available: {})",
+            config.active_provider,
+            sorted(config.get_provider_names()).join(", ")
+        ));
+    }
+
+    for name in sorted(config.get_provider_names()) {
+        let provider = &config.providers[&name];
+        let is_active = name == config.active_provider;
+        if provider.timeout_seconds == 0 {
+            report
+                .problems
+                .push(format!("[{name}] timeout_seconds must be greater than 0"));
+            continue;
+        }
+        // Providers accept an empty api_key and only fail once a request is sent
+        let blank = |key: &str| {
+            provider
+                .get_setting(key)
+                .is_none_or(|v| v.trim().is_empty())
+        };
+        let missing_key = matches!(
+            provider.provider_type,
+            ProviderType::OpenAI | ProviderType::Claude | ProviderType::Gemini
+        ) && blank("api_key")
+            && blank("api_key_command")
+            && blank("token_command");
+        let empty_setting = required_settings(&provider.provider_type)
+            .iter()
+            .find(|key| {
+                provider
+                    .get_setting(key)
+                    .is_some_and(|v| v.trim().is_empty())
+            });
+        let error = match (crate::providers::create_provider(provider), empty_setting) {
+            (Err(e), _) => e.to_string(),
+            (Ok(_), _) if missing_key => {
+                "api_key is empty (set it, api_key_command or token_command)".to_string()
+            }
+            (Ok(_), Some(key)) => format!("{key} is empty"),
+            (Ok(_), None) => continue,
+        };
+        if is_active {
+            report.problems.push(format!("[{name}] {error}"));
+        } else {
+            report
+                .warnings
+                .push(format!("[{name}] {error} (not the active provider)"));
+        }
+    }
+
+    let names = sorted(config.get_provider_names());
+    for (i, first) in names.iter().enumerate() {
+        for second in &names[i + 1..] {
+            let (a, b) = (&config.providers[first], &config.providers[second]);
+            if a.provider_type == b.provider_type
+                && !a.settings.is_empty()
+                && a.settings == b.settings
+            {
+                report.warnings.push(format!(
+                    "[{first}] and [{second}] have identical settings; one is probably an accidental duplicate"
+                ));
+            }
+        }
+    }
+
+    if let Err(e) = config.system_prompt_override() {
+        report.problems.push(format!("{e:#}"));
+    }
+    report.problems.extend(config.setting_problems());
+}
+
+/// Settings holding credentials, or commands that print them. `token` has to be a
+/// whole word, so token counts like `max_tokens` stay readable, and durations like
+/// `token_ttl_seconds` only name the secret they are about.
+fn is_secret_setting(key: &str) -> bool {
+    let key = key.to_lowercase();
+    if key.ends_with("_seconds") {
+        return false;
+    }
+    ["key", "secret", "password", "auth", "bearer", "credential"]
+        .iter()
+        .any(|part| key.contains(part))
+        || key.split(['_', '-', '.']).any(|word| word == "token")
+}
+
+/// `config` with credentials replaced by `***`, for `tai config show`
+pub fn sanitized_config(config: &TerminalAIConfig) -> TerminalAIConfig {
+    let mut config = config.clone();
+    let sections = config
+        .providers
+        .values_mut()
+        .map(|provider| &mut provider.settings)
+        .chain(std::iter::once(&mut config.settings));
+    for settings in sections {
+        for (key, value) in settings.iter_mut() {
+            if is_secret_setting(key) && !value.is_empty() {
+                *value = "***".to_string();
+            }
+        }
+    }
+    config
+}
+
+/// `config` in the `.conf` layout with sections and keys sorted
+pub fn render_config(config: &TerminalAIConfig) -> String {
+    let mut content = format!("active_provider = \"{}\"\n", config.active_provider);
+    for key in sorted(config.settings.keys().cloned().collect()) {
+        content.push_str(&format!("{key} = \"{}\"\n", config.settings[&key]));
+    }
+    for name in sorted(config.get_provider_names()) {
+        let provider = &config.providers[&name];
+        content.push_str(&format!("\n[{name}]\n"));
+        for key in sorted(provider.settings.keys().cloned().collect()) {
+            content.push_str(&format!("{key} = \"{}\"\n", provider.settings[&key]));
+        }
+        content.push_str(&format!("timeout_seconds = {}\n", provider.timeout_seconds));
+    }
+    if !config.system_prompt_overrides.is_empty() {
+        content.push_str(&format!("\n[{}]\n", crate::SYSTEM_PROMPT_OVERRIDES_SECTION));
+        for model in sorted(config.system_prompt_overrides.keys().cloned().collect()) {
+            content.push_str(&format!(
+                "\"{model}\" = \"{}\"\n",
+                config.system_prompt_overrides[&model].replace('\n', "\\n")
+            ));
+        }
+    }
+    content.push_str(&crate::render_policy_section(&config.policy));
+    content
+}
+
+fn sorted(mut names: Vec<String>) -> Vec<String> {
+    names.sort();
+    names
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -560,4 +1055,295 @@ mod tests {
         assert!(openai_provider.get_setting("api_key").is_some());
         assert!(openai_provider.get_setting("url").is_none());
     }
+
+    #[test]
+    fn test_lint_conf_checks_policy_patterns() {
+        assert_eq!(
+            lint_conf("[policy]\nallow = \"^git \"\ndeny = \"(unclosed\"\nblock = \"rm\"\n"),
+            vec![
+                "line 3: invalid pattern '(unclosed': regex parse error:\n    (unclosed\n    ^\nerror: unclosed group".to_string(),
+                "line 4: unknown policy key 'block' (use allow or deny)".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_conf_reports_line_numbers() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("terminalai.conf");
+        std::fs::write(
+            &path,
+            "active_provider = \"openai\"\n\n[openai\nmodel = \"gpt-4\n[ollama]\ntimeout_seconds = soon\njust some words\n[olama]\n",
+        )
+        .unwrap();
+
+        let report = validate_config_file(&path);
+        assert!(!report.is_valid());
+        assert_eq!(
+            report.problems[..5],
+            [
+                "line 3: unterminated section header '[openai'".to_string(),
+                "line 4: unterminated quote in '\"gpt-4'".to_string(),
+                "line 6: timeout_seconds must be a whole number of seconds, found 'soon'"
+                    .to_string(),
+                "line 7: expected 'key = value', found 'just some words'".to_string(),
+                "line 8: unknown provider section [olama]; its settings are ignored".to_string(),
+            ]
+        );
+        // The default openai section has no API key and it is the active provider
+        assert_eq!(
+            report.problems[5],
+            "[openai] api_key is empty (set it, api_key_command or token_command)"
+        );
+    }
+
+    #[test]
+    fn test_validate_config_checks_active_provider() {
+        let mut report = ValidationReport::default();
+        validate_config(&TerminalAIConfig::default(), &mut report);
+        assert!(report.is_valid(), "{:?}", report.problems);
+        // Unused providers without credentials are only warnings
+        assert!(report
+            .warnings
+            .iter()
+            .any(|w| w.starts_with("[claude]") && w.ends_with("(not the active provider)")));
+
+        let config = TerminalAIConfig {
+            active_provider: "gpt".to_string(),
+            ..Default::default()
+        };
+        let mut report = ValidationReport::default();
+        validate_config(&config, &mut report);
+        assert_eq!(
+            report.problems,
+            vec![
+                "active_provider 'gpt' is not configured (available: claude, gemini, local, ollama, openai, openai_compatible)"
+                    .to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_json_reports_parse_position() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.json");
+        std::fs::write(
+            &path,
+            "{\n  \"active_provider\": \"ollama\",\n  \"providers\": {,\n}",
+        )
+        .unwrap();
+
+        let report = validate_config_file(&path);
+        assert_eq!(report.problems.len(), 1);
+        assert!(report.problems[0].starts_with("line 3, column 17:"));
+    }
+
+    /// Records which setup steps ran instead of downloading anything
+    #[derive(Default)]
+    struct RecordingSetup {
+        calls: std::sync::Mutex<Vec<&'static str>>,
+    }
+
+    #[async_trait::async_trait]
+    impl LocalSetup for RecordingSetup {
+        fn install_llama_cpp(&self, _config: &ProviderConfig) -> Result<()> {
+            self.calls.lock().unwrap().push("install_llama_cpp");
+            Ok(())
+        }
+
+        async fn download_model(&self, _config: &ProviderConfig) -> Result<()> {
+            self.calls.lock().unwrap().push("download_model");
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_defer_setup_skips_downloads() {
+        let setup = RecordingSetup::default();
+        let mut config = ProviderConfig::new_local(30);
+        setup_local_provider(&setup, &mut config, true).await;
+
+        assert!(setup.calls.lock().unwrap().is_empty());
+        assert_eq!(
+            config.get_setting(LOCAL_SETUP_SETTING).map(String::as_str),
+            Some(LOCAL_SETUP_DEFERRED)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_local_setup_runs_without_defer() {
+        let setup = RecordingSetup::default();
+        let mut config = ProviderConfig::new_local(30);
+        setup_local_provider(&setup, &mut config, false).await;
+
+        assert_eq!(
+            *setup.calls.lock().unwrap(),
+            vec!["install_llama_cpp", "download_model"]
+        );
+        assert!(config.get_setting(LOCAL_SETUP_SETTING).is_none());
+    }
+
+    #[test]
+    fn test_portable_init_writes_conf_that_load_config_prefers() {
+        let temp_dir = TempDir::new().unwrap();
+        let local_config_path = temp_dir.path().join("terminalai.conf");
+        let user_config_path = temp_dir.path().join("config.json");
+        std::fs::write(
+            &user_config_path,
+            serde_json::to_string(&crate::TerminalAIConfig::default()).unwrap(),
+        )
+        .unwrap();
+
+        let mut config = crate::TerminalAIConfig::default();
+        config.set_active_provider("gemini").unwrap();
+        let saved = save_init_config(&config, true, &local_config_path).unwrap();
+        assert_eq!(saved, local_config_path);
+        assert!(std::fs::read_to_string(&local_config_path)
+            .unwrap()
+            .contains("active_provider = \"gemini\""));
+
+        let loaded = crate::load_config_from(Some(&local_config_path), &user_config_path).unwrap();
+        assert_eq!(loaded.active_provider, "gemini");
+
+        std::fs::remove_file(&local_config_path).unwrap();
+        let loaded = crate::load_config_from(Some(&local_config_path), &user_config_path).unwrap();
+        assert_eq!(loaded.active_provider, "ollama");
+    }
+
+    #[test]
+    fn test_validate_rejects_unknown_history_prompt() {
+        let mut config = TerminalAIConfig::default();
+        config
+            .settings
+            .insert("history_prompt".to_string(), "hashed".to_string());
+        assert_eq!(config.history_prompt(), crate::history::PromptStorage::Full);
+        let mut report = ValidationReport::default();
+        validate_config(&config, &mut report);
+        assert_eq!(
+            report.problems,
+            vec!["history_prompt must be \"full\", \"hash\" or \"summary\", not \"hashed\"; prompts are saved in full"]
+        );
+
+        config
+            .settings
+            .insert("history_prompt".to_string(), " summary ".to_string());
+        assert_eq!(
+            config.history_prompt(),
+            crate::history::PromptStorage::Summary
+        );
+        assert!(config.setting_problems().is_empty());
+    }
+
+    #[test]
+    fn test_validate_warns_about_duplicate_providers() {
+        let mut config = TerminalAIConfig::default();
+        let work = config.providers["openai"].clone();
+        config.providers.insert("openai-work".to_string(), work);
+        let mut report = ValidationReport::default();
+        validate_config(&config, &mut report);
+        assert!(report.warnings.contains(
+            &"[openai] and [openai-work] have identical settings; one is probably an accidental duplicate"
+                .to_string()
+        ));
+
+        let report = validate_config_json(
+            r#"{"active_provider": "ollama", "providers": {
+                "ollama": {"provider_type": "Ollama", "settings": {"url": "http://a:11434", "model": "llama3"}, "timeout_seconds": 30},
+                "ollama": {"provider_type": "Ollama", "settings": {"url": "http://b:11434", "model": "llama3"}, "timeout_seconds": 30}
+            }}"#,
+        );
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert_eq!(
+            report.warnings,
+            vec!["providers.ollama is defined more than once; only the last entry is used"]
+        );
+    }
+
+    #[test]
+    fn test_validate_reports_empty_required_field_of_active_provider() {
+        let mut config = TerminalAIConfig::default();
+        config
+            .providers
+            .get_mut("ollama")
+            .unwrap()
+            .settings
+            .insert("model".to_string(), " ".to_string());
+        let mut report = ValidationReport::default();
+        validate_config(&config, &mut report);
+        assert_eq!(report.problems, vec!["[ollama] model is empty"]);
+
+        config.active_provider = "local".to_string();
+        let mut report = ValidationReport::default();
+        validate_config(&config, &mut report);
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert!(report
+            .warnings
+            .contains(&"[ollama] model is empty (not the active provider)".to_string()));
+    }
+
+    #[test]
+    fn test_sanitized_config_masks_credentials() {
+        let mut config = create_test_openai_config("sk-live-123", "gpt-4", 30);
+        let openai = config.providers.get_mut("openai").unwrap();
+        openai
+            .settings
+            .insert("token_command".to_string(), "vault read llm".to_string());
+        openai
+            .settings
+            .insert("max_tokens".to_string(), "auto".to_string());
+
+        let sanitized = sanitized_config(&config);
+        let json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&sanitized).unwrap()).unwrap();
+        let settings = &json["providers"]["openai"]["settings"];
+        assert_eq!(settings["api_key"], "***");
+        assert_eq!(settings["token_command"], "***");
+        assert_eq!(settings["model"], "gpt-4");
+        assert_eq!(settings["max_tokens"], "auto");
+        assert_eq!(json["active_provider"], "openai");
+        assert!(!render_config(&sanitized).contains("sk-live-123"));
+    }
+
+    #[test]
+    fn test_secret_settings_are_recognized() {
+        for key in [
+            "api_key",
+            "API_KEY",
+            "client_secret",
+            "password",
+            "token",
+            "access_token",
+            "token_command",
+            "auth_header",
+            "Authorization",
+            "bearer",
+            "bearer_token",
+            "credentials",
+            "credential_command",
+        ] {
+            assert!(is_secret_setting(key), "{key}");
+        }
+        for key in [
+            "model",
+            "max_tokens",
+            "token_ttl_seconds",
+            "timeout_seconds",
+            "base_url",
+        ] {
+            assert!(!is_secret_setting(key), "{key}");
+        }
+    }
+
+    #[test]
+    fn test_render_config_is_sorted_conf() {
+        let mut config = create_test_ollama_config("http://localhost:11434", "llama3", 45);
+        config.providers.retain(|name, _| name == "ollama");
+        config
+            .settings
+            .insert("branding".to_string(), "false".to_string());
+        assert_eq!(
+            render_config(&config),
+            "active_provider = \"ollama\"\nbranding = \"false\"\n\n[ollama]\nmodel = \"llama3\"\nurl = \"http://localhost:11434\"\ntimeout_seconds = 45\n"
+        );
+    }
 }
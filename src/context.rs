@@ -0,0 +1,173 @@
+use crate::TerminalAIConfig;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether system prompts start with a [`PromptContext`] (`prompt_context`)
+pub const CONTEXT_SETTING: &str = "prompt_context";
+
+/// Directory entries listed before the rest are only counted
+const MAX_LISTED_ENTRIES: usize = 50;
+
+// Set once at startup from `prompt_context` and `--no-context`
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Tell the model about the machine and directory in every later query when
+/// `prompt_context = true`, unless `--no-context` (`no_context`). Off by default,
+/// since it sends the directory's file names to the provider.
+pub fn configure(config: &TerminalAIConfig, no_context: bool) {
+    ENABLED.store(
+        !no_context && config.get_bool_setting(CONTEXT_SETTING, false),
+        Ordering::Relaxed,
+    );
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// What the model is told about where its commands run, so it suggests paths and
+/// flags that exist there
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PromptContext {
+    pub os: String,
+    pub arch: String,
+    pub shell: Option<String>,
+    pub directory: PathBuf,
+    /// Names in `directory`, sorted, with a `/` after directories
+    pub entries: Vec<String>,
+    pub git_branch: Option<String>,
+    /// Files `git status` reports as changed or untracked
+    pub git_changes: Option<usize>,
+}
+
+impl PromptContext {
+    /// Look around `dir`, the directory the commands will run in
+    pub fn gather(dir: &Path) -> Self {
+        let directory = std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+        let root = crate::git_summary::work_tree_root(Some(&directory));
+        Self {
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            shell: crate::shell::shell()
+                .ok()
+                .map(|shell| shell.program.to_string()),
+            entries: list_entries(&directory),
+            git_branch: root
+                .as_ref()
+                .and_then(|_| crate::git_summary::branch(Some(&directory))),
+            git_changes: root
+                .and_then(|root| crate::git_summary::snapshot(&root).ok())
+                .map(|snapshot| snapshot.files.len()),
+            directory,
+        }
+    }
+
+    /// The context as a block to put before the system prompt
+    pub fn render(&self) -> String {
+        let mut lines = vec![
+            "Where the commands run:".to_string(),
+            format!("- Operating system: {} ({})", self.os, self.arch),
+        ];
+        if let Some(shell) = &self.shell {
+            lines.push(format!("- Shell: {shell}"));
+        }
+        lines.push(format!("- Working directory: {}", self.directory.display()));
+        match (&self.git_branch, self.git_changes) {
+            (Some(branch), Some(changes)) => lines.push(format!(
+                "- Git: on branch {branch}, {changes} changed file{}",
+                if changes == 1 { "" } else { "s" }
+            )),
+            (Some(branch), None) => lines.push(format!("- Git: on branch {branch}")),
+            (None, Some(changes)) => lines.push(format!(
+                "- Git: detached HEAD, {changes} changed file{}",
+                if changes == 1 { "" } else { "s" }
+            )),
+            (None, None) => {}
+        }
+        if self.entries.is_empty() {
+            lines.push("- The working directory is empty".to_string());
+        } else {
+            let listed = &self.entries[..self.entries.len().min(MAX_LISTED_ENTRIES)];
+            let mut listing = listed.join(", ");
+            if self.entries.len() > listed.len() {
+                listing.push_str(&format!(" and {} more", self.entries.len() - listed.len()));
+            }
+            lines.push(format!("- Its contents: {listing}"));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Sorted names in `dir`, directories marked with a trailing `/`
+fn list_entries(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.path().is_dir() {
+                format!("{name}/")
+            } else {
+                name
+            }
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// The rendered context for `dir` when it is enabled
+pub fn for_directory(dir: &Path) -> Option<String> {
+    enabled().then(|| PromptContext::gather(dir).render())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_lists_machine_git_and_directory() {
+        let context = PromptContext {
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            shell: Some("bash".to_string()),
+            directory: PathBuf::from("/home/me/project"),
+            entries: vec!["Cargo.toml".to_string(), "src/".to_string()],
+            git_branch: Some("main".to_string()),
+            git_changes: Some(1),
+        };
+        assert_eq!(
+            context.render(),
+            "Where the commands run:\n\
+             - Operating system: linux (x86_64)\n\
+             - Shell: bash\n\
+             - Working directory: /home/me/project\n\
+             - Git: on branch main, 1 changed file\n\
+             - Its contents: Cargo.toml, src/"
+        );
+
+        let outside_git = PromptContext {
+            entries: (0..MAX_LISTED_ENTRIES + 3)
+                .map(|i| format!("f{i:02}"))
+                .collect(),
+            git_branch: None,
+            git_changes: None,
+            ..context
+        };
+        let rendered = outside_git.render();
+        assert!(!rendered.contains("Git:"));
+        assert!(rendered.ends_with(", f49 and 3 more"), "{rendered}");
+    }
+
+    #[test]
+    fn test_gather_marks_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("README.md"), "").unwrap();
+        let context = PromptContext::gather(dir.path());
+        assert_eq!(context.entries, vec!["README.md", "src/"]);
+        assert_eq!(context.os, std::env::consts::OS);
+    }
+}
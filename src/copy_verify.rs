@@ -0,0 +1,470 @@
+use anyhow::{Context, Result};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// How thoroughly copied files are checked against their sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyMode {
+    /// The destination exists and has the same size as the source
+    Size,
+    /// Additionally compare file contents byte for byte
+    Contents,
+}
+
+/// Sources and destination of a `cp` command, resolved before it runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyPlan {
+    pub sources: Vec<PathBuf>,
+    pub destination: PathBuf,
+    /// Sources are copied into `destination` rather than onto it
+    pub into_directory: bool,
+}
+
+/// Outcome of verifying one or more copies
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CopyReport {
+    pub copied: usize,
+    pub verified: usize,
+    pub problems: Vec<String>,
+}
+
+impl CopyReport {
+    pub fn merge(&mut self, other: CopyReport) {
+        self.copied += other.copied;
+        self.verified += other.verified;
+        self.problems.extend(other.problems);
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// One-line summary, e.g. "copied 12 files, verified 12"
+    pub fn summary(&self) -> String {
+        let noun = if self.copied == 1 { "file" } else { "files" };
+        format!(
+            "copied {} {}, verified {}",
+            self.copied, noun, self.verified
+        )
+    }
+
+    pub fn print(&self) {
+        if self.is_ok() {
+            println!("✅ Copy verification: {}", self.summary());
+        } else {
+            println!("⚠️  Copy verification: {}", self.summary());
+            for problem in &self.problems {
+                println!("   - {problem}");
+            }
+        }
+    }
+}
+
+/// Work out what a `cp` command will copy, before it runs.
+///
+/// Returns `None` for anything that is not a plain `cp` invocation. Sources and
+/// destination are expanded by the shell so globs, quotes and `~` behave exactly
+/// as they will when the command runs.
+pub fn plan_copy(cmd: &str, working_dir: Option<&Path>) -> Result<Option<CopyPlan>> {
+    Ok(plan_transfer(cmd, working_dir, &["cp"])?.map(|(plan, _)| plan))
+}
+
+/// Like [`plan_copy`] for any of `programs` (`cp` and `mv` share their operand
+/// syntax), also returning the options given
+fn plan_transfer(
+    cmd: &str,
+    working_dir: Option<&Path>,
+    programs: &[&str],
+) -> Result<Option<(CopyPlan, Vec<String>)>> {
+    let (tokens, _) = crate::search_scope::split_leading_command(cmd);
+    if !tokens
+        .first()
+        .is_some_and(|program| programs.contains(&program.as_str()))
+    {
+        return Ok(None);
+    }
+
+    let mut options = Vec::new();
+    let mut operands = Vec::new();
+    let mut target_directory = None;
+    let mut no_target_directory = false;
+    let mut args = tokens[1..].iter();
+    let mut options_done = false;
+    while let Some(arg) = args.next() {
+        if options_done || !arg.starts_with('-') || arg == "-" {
+            operands.push(arg.clone());
+        } else if arg == "--" {
+            options_done = true;
+        } else if arg == "-t" || arg == "--target-directory" {
+            target_directory = args.next().cloned();
+        } else if let Some(dir) = arg.strip_prefix("--target-directory=") {
+            target_directory = Some(dir.to_string());
+        } else if arg == "-T" || arg == "--no-target-directory" {
+            no_target_directory = true;
+        } else {
+            options.push(arg.clone());
+        }
+    }
+
+    // Expansion runs the words through the shell, so never evaluate command substitutions twice
+    if operands
+        .iter()
+        .chain(target_directory.iter())
+        .any(|word| word.contains('`') || word.contains("$("))
+    {
+        return Ok(None);
+    }
+
+    let (source_words, destination_word) = match target_directory {
+        Some(dir) => (operands, dir),
+        None => match operands.split_last() {
+            Some((destination, sources)) if !sources.is_empty() => {
+                (sources.to_vec(), destination.clone())
+            }
+            _ => return Ok(None),
+        },
+    };
+
+    let sources = expand_words(&source_words, working_dir)?;
+    let destination =
+        match expand_words(std::slice::from_ref(&destination_word), working_dir)?.as_slice() {
+            [destination] => destination.clone(),
+            _ => return Ok(None),
+        };
+
+    let into_directory = !no_target_directory
+        && (destination_word.ends_with('/')
+            || sources.len() > 1
+            || destination_word == "."
+            || destination.is_dir());
+
+    Ok(Some((
+        CopyPlan {
+            sources,
+            destination,
+            into_directory,
+        },
+        options,
+    )))
+}
+
+/// Existing files a `cp` or `mv` command would replace, before it runs.
+///
+/// Empty for other commands and when an option keeps existing files: `-n`, `-i`,
+/// `-b` (or their long forms) and `--update=none`.
+pub fn overwritten_destinations(cmd: &str, working_dir: Option<&Path>) -> Result<Vec<PathBuf>> {
+    let Some((plan, options)) = plan_transfer(cmd, working_dir, &["cp", "mv"])? else {
+        return Ok(Vec::new());
+    };
+    if options.iter().any(|option| keeps_existing(option)) {
+        return Ok(Vec::new());
+    }
+
+    let mut destinations = Vec::new();
+    for source in &plan.sources {
+        let target = target_of(&plan, source);
+        let same_file = match (source.canonicalize(), target.canonicalize()) {
+            (Ok(source), Ok(target)) => source == target,
+            _ => false,
+        };
+        if target.symlink_metadata().is_ok() && !same_file && !destinations.contains(&target) {
+            destinations.push(target);
+        }
+    }
+    Ok(destinations)
+}
+
+fn keeps_existing(option: &str) -> bool {
+    match option.strip_prefix("--") {
+        Some(long) => {
+            matches!(long, "no-clobber" | "interactive" | "update=none")
+                || long.starts_with("backup")
+        }
+        None => option.chars().skip(1).any(|c| matches!(c, 'n' | 'i' | 'b')),
+    }
+}
+
+/// Ask before `cmd` replaces existing files; `true` when it may run
+pub fn confirm_overwrites(cmd: &str, working_dir: Option<&Path>) -> Result<bool> {
+    let destinations = overwritten_destinations(cmd, working_dir)?;
+    if destinations.is_empty() {
+        return Ok(true);
+    }
+    let mut text = format!("\n⚠️  '{cmd}' would overwrite:\n");
+    for destination in &destinations {
+        text.push_str(&format!("   - {}\n", destination.display()));
+    }
+    crate::ui::show(&text);
+    Ok(crate::ui::confirm_risky(
+        &crate::ui::messages().confirm_overwrite,
+    ))
+}
+
+/// Where `source` ends up under `plan`
+fn target_of(plan: &CopyPlan, source: &Path) -> PathBuf {
+    if plan.into_directory {
+        match source.file_name() {
+            Some(name) => plan.destination.join(name),
+            None => plan.destination.clone(),
+        }
+    } else {
+        plan.destination.clone()
+    }
+}
+
+/// Check that every file covered by `plan` exists at its destination
+pub fn verify_copy(plan: &CopyPlan, mode: VerifyMode) -> CopyReport {
+    let mut report = CopyReport::default();
+    for source in &plan.sources {
+        let target = target_of(plan, source);
+
+        if source.is_dir() {
+            for relative in files_under(source) {
+                verify_file(
+                    &source.join(&relative),
+                    &target.join(&relative),
+                    mode,
+                    &mut report,
+                );
+            }
+        } else {
+            verify_file(source, &target, mode, &mut report);
+        }
+    }
+    report
+}
+
+fn verify_file(source: &Path, target: &Path, mode: VerifyMode, report: &mut CopyReport) {
+    report.copied += 1;
+    match compare_files(source, target, mode) {
+        Ok(None) => report.verified += 1,
+        Ok(Some(problem)) => report.problems.push(problem),
+        Err(e) => report.problems.push(format!("{}: {e}", target.display())),
+    }
+}
+
+/// `None` when `target` matches `source`, otherwise a description of the mismatch
+fn compare_files(source: &Path, target: &Path, mode: VerifyMode) -> Result<Option<String>> {
+    if !target.is_file() {
+        return Ok(Some(format!("{} is missing", target.display())));
+    }
+
+    let source_len = std::fs::metadata(source)
+        .with_context(|| format!("Failed to read {}", source.display()))?
+        .len();
+    let target_len = std::fs::metadata(target)
+        .with_context(|| format!("Failed to read {}", target.display()))?
+        .len();
+    if source_len != target_len {
+        return Ok(Some(format!(
+            "{} is {} bytes but {} is {} bytes",
+            target.display(),
+            target_len,
+            source.display(),
+            source_len
+        )));
+    }
+
+    if mode == VerifyMode::Contents && !same_contents(source, target)? {
+        return Ok(Some(format!(
+            "{} differs from {}",
+            target.display(),
+            source.display()
+        )));
+    }
+    Ok(None)
+}
+
+fn same_contents(a: &Path, b: &Path) -> Result<bool> {
+    let open = |path: &Path| {
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))
+    };
+    let (mut a, mut b) = (open(a)?, open(b)?);
+    let mut buf_a = [0u8; 8192];
+    let mut buf_b = [0u8; 8192];
+    loop {
+        let read = a.read(&mut buf_a)?;
+        if read == 0 {
+            return Ok(b.read(&mut buf_b)? == 0);
+        }
+        b.read_exact(&mut buf_b[..read])?;
+        if buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Regular files under `dir`, relative to it; symlinks are not followed
+fn files_under(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(dir.join(&relative)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = relative.join(entry.file_name());
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Expand shell words (globs, quotes, `~`, variables) the way `sh` will. Other
+/// shells only get quotes stripped, since `printf` is not available there.
+fn expand_words(words: &[String], working_dir: Option<&Path>) -> Result<Vec<PathBuf>> {
+    let shell = crate::shell::shell()?;
+    let base = working_dir.map(Path::to_path_buf).unwrap_or_default();
+    if !shell.is_posix() {
+        return Ok(words
+            .iter()
+            .map(|word| base.join(word.trim_matches(['"', '\''])))
+            .collect());
+    }
+
+    let mut command = shell.command(&format!("printf '%s\\0' {}", words.join(" ")));
+    if let Some(dir) = working_dir {
+        command.current_dir(dir);
+    }
+    let output = command.output().context("Failed to expand cp arguments")?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|word| !word.is_empty())
+        .map(|word| base.join(word))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn setup() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "alpha").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "bravo").unwrap();
+        std::fs::create_dir_all(dir.path().join("project/src")).unwrap();
+        std::fs::write(dir.path().join("project/src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(dir.path().join("project/README"), "readme").unwrap();
+        std::fs::create_dir(dir.path().join("backup")).unwrap();
+        dir
+    }
+
+    fn run(cmd: &str, dir: &Path) {
+        let status = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn test_plan_copy_parses_sources_and_destination() {
+        let dir = setup();
+        let plan = plan_copy("cp *.txt backup/", Some(dir.path()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            plan.sources,
+            vec![dir.path().join("a.txt"), dir.path().join("b.txt")]
+        );
+        assert_eq!(plan.destination, dir.path().join("backup/"));
+        assert!(plan.into_directory);
+
+        let plan = plan_copy("cp -t backup 'a.txt'", Some(dir.path()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(plan.sources, vec![dir.path().join("a.txt")]);
+        assert!(plan.into_directory);
+
+        let plan = plan_copy("cp a.txt a_copy.txt", Some(dir.path()))
+            .unwrap()
+            .unwrap();
+        assert!(!plan.into_directory);
+
+        assert!(plan_copy("mkdir -p backup", Some(dir.path()))
+            .unwrap()
+            .is_none());
+        assert!(plan_copy("cp a.txt", Some(dir.path())).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_overwritten_destinations() {
+        let dir = setup();
+        std::fs::write(dir.path().join("backup/a.txt"), "old").unwrap();
+        let overwritten = |cmd: &str| overwritten_destinations(cmd, Some(dir.path())).unwrap();
+
+        assert_eq!(
+            overwritten("cp a.txt b.txt"),
+            vec![dir.path().join("b.txt")]
+        );
+        assert_eq!(
+            overwritten("mv *.txt backup/"),
+            vec![dir.path().join("backup/").join("a.txt")]
+        );
+        assert_eq!(overwritten("cp -rv project backup"), Vec::<PathBuf>::new());
+        for cmd in [
+            "cp a.txt new.txt",
+            "cp -n a.txt b.txt",
+            "cp -vi a.txt b.txt",
+            "mv --no-clobber a.txt b.txt",
+            "cp --backup=numbered a.txt b.txt",
+            "cp a.txt ./a.txt",
+            "ls a.txt b.txt",
+        ] {
+            assert_eq!(overwritten(cmd), Vec::<PathBuf>::new(), "{cmd}");
+        }
+    }
+
+    #[test]
+    fn test_verify_files_copied_into_directory() {
+        let dir = setup();
+        let cmd = "cp *.txt backup/";
+        let plan = plan_copy(cmd, Some(dir.path())).unwrap().unwrap();
+        run(cmd, dir.path());
+
+        let report = verify_copy(&plan, VerifyMode::Contents);
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert_eq!(report.summary(), "copied 2 files, verified 2");
+    }
+
+    #[test]
+    fn test_verify_recursive_copy_to_new_directory() {
+        let dir = setup();
+        let cmd = "cp -r project project_copy";
+        let plan = plan_copy(cmd, Some(dir.path())).unwrap().unwrap();
+        assert!(!plan.into_directory);
+        run(cmd, dir.path());
+
+        let report = verify_copy(&plan, VerifyMode::Size);
+        assert_eq!(report.summary(), "copied 2 files, verified 2");
+    }
+
+    #[test]
+    fn test_verify_reports_missing_and_changed_files() {
+        let dir = setup();
+        let cmd = "cp a.txt b.txt backup";
+        let plan = plan_copy(cmd, Some(dir.path())).unwrap().unwrap();
+        run("cp a.txt backup/", dir.path());
+        std::fs::write(dir.path().join("backup/a.txt"), "ALPHA").unwrap();
+
+        let size_report = verify_copy(&plan, VerifyMode::Size);
+        assert_eq!(size_report.summary(), "copied 2 files, verified 1");
+        assert!(size_report.problems[0].ends_with("b.txt is missing"));
+
+        let contents_report = verify_copy(&plan, VerifyMode::Contents);
+        assert_eq!(contents_report.verified, 0);
+        assert!(contents_report.problems[0].contains("differs from"));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::providers::{ProviderConfig, ProviderType, Usage};
+use anyhow::Result;
+use std::sync::Mutex;
+
+/// Provider settings overriding the built-in prices, in USD per million tokens
+pub const INPUT_COST_SETTING: &str = "input_cost_per_million";
+pub const OUTPUT_COST_SETTING: &str = "output_cost_per_million";
+
+/// List prices in USD per million input and output tokens, matched by model name
+/// prefix; more specific names come first
+const MODEL_PRICES: [(&str, f64, f64); 16] = [
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4-turbo", 10.00, 30.00),
+    ("gpt-4", 30.00, 60.00),
+    ("gpt-3.5-turbo", 0.50, 1.50),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("claude-3-opus", 15.00, 75.00),
+    ("claude-opus", 15.00, 75.00),
+    ("claude-3", 3.00, 15.00),
+    ("claude-sonnet", 3.00, 15.00),
+    ("gemini-1.5-flash", 0.075, 0.30),
+    ("gemini-2.0-flash", 0.10, 0.40),
+    ("gemini-1.5-pro", 1.25, 5.00),
+    ("gemini-pro", 0.50, 1.50),
+    ("gemini-1.0-pro", 0.50, 1.50),
+];
+
+/// What a provider charges, in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pricing {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+impl Pricing {
+    /// Local providers cost nothing per token
+    pub const FREE: Pricing = Pricing {
+        input_per_million: 0.0,
+        output_per_million: 0.0,
+    };
+
+    /// Prices for `config`: its cost settings, else the built-in list price of its
+    /// model; `None` for a cloud model with no known price
+    pub fn for_provider(config: &ProviderConfig) -> Option<Pricing> {
+        let setting = |key: &str| {
+            config
+                .get_setting(key)
+                .and_then(|value| value.trim().parse::<f64>().ok())
+        };
+        if let (Some(input), Some(output)) =
+            (setting(INPUT_COST_SETTING), setting(OUTPUT_COST_SETTING))
+        {
+            return Some(Pricing {
+                input_per_million: input,
+                output_per_million: output,
+            });
+        }
+
+        match config.provider_type {
+            ProviderType::Ollama | ProviderType::Local => Some(Pricing::FREE),
+            _ => {
+                let model = config.get_setting("model")?;
+                MODEL_PRICES
+                    .iter()
+                    .find(|(prefix, _, _)| model.starts_with(prefix))
+                    .map(|&(_, input, output)| Pricing {
+                        input_per_million: input,
+                        output_per_million: output,
+                    })
+            }
+        }
+    }
+
+    /// Cost of `usage` in USD
+    pub fn cost(&self, usage: Usage) -> f64 {
+        (usage.input_tokens as f64 * self.input_per_million
+            + usage.output_tokens as f64 * self.output_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// Estimated spend across the provider calls of one session, capped by `--max-cost`
+#[derive(Debug)]
+pub struct Budget {
+    max_usd: f64,
+    spent_usd: Mutex<f64>,
+}
+
+impl Budget {
+    pub fn new(max_usd: f64) -> Result<Self> {
+        if !(max_usd.is_finite() && max_usd > 0.0) {
+            anyhow::bail!("--max-cost must be a positive amount in USD, got {max_usd}");
+        }
+        Ok(Self {
+            max_usd,
+            spent_usd: Mutex::new(0.0),
+        })
+    }
+
+    pub fn spent(&self) -> f64 {
+        *self.lock()
+    }
+
+    /// Fail once the budget is used up, before another call is made
+    pub fn check(&self) -> Result<()> {
+        let spent = self.spent();
+        if spent >= self.max_usd {
+            anyhow::bail!(
+                "Budget of ${:.2} reached (about ${spent:.4} spent); not sending further queries (--max-cost)",
+                self.max_usd
+            );
+        }
+        Ok(())
+    }
+
+    pub fn charge(&self, usd: f64) {
+        *self.lock() += usd;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, f64> {
+        self.spent_usd
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pricing_for_provider() {
+        let gpt = ProviderConfig::new_openai("key".to_string(), "gpt-4o-mini".to_string(), 30);
+        assert_eq!(
+            Pricing::for_provider(&gpt),
+            Some(Pricing {
+                input_per_million: 0.15,
+                output_per_million: 0.60
+            })
+        );
+
+        let mut custom =
+            ProviderConfig::new_claude("key".to_string(), "house-model".to_string(), 30);
+        assert_eq!(Pricing::for_provider(&custom), None);
+        custom
+            .settings
+            .insert(INPUT_COST_SETTING.to_string(), "1".to_string());
+        custom
+            .settings
+            .insert(OUTPUT_COST_SETTING.to_string(), "4".to_string());
+        let pricing = Pricing::for_provider(&custom).unwrap();
+        let usage = Usage {
+            input_tokens: 2_000,
+            output_tokens: 500,
+        };
+        assert!((pricing.cost(usage) - 0.004).abs() < 1e-12);
+
+        let ollama = ProviderConfig::default();
+        assert_eq!(Pricing::for_provider(&ollama), Some(Pricing::FREE));
+    }
+
+    #[test]
+    fn test_budget_stops_at_threshold() {
+        let budget = Budget::new(0.05).unwrap();
+        for _ in 0..2 {
+            budget.check().unwrap();
+            budget.charge(0.02);
+        }
+        budget.check().unwrap();
+        budget.charge(0.02);
+        let err = budget.check().unwrap_err().to_string();
+        assert!(
+            err.starts_with("Budget of $0.05 reached (about $0.0600 spent)"),
+            "{err}"
+        );
+
+        assert!(Budget::new(0.0).is_err());
+        assert!(Budget::new(f64::NAN).is_err());
+    }
+}
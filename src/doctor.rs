@@ -0,0 +1,185 @@
+use crate::config::{self, ValidationReport};
+use crate::TerminalAIConfig;
+use anyhow::Result;
+use serde::Serialize;
+
+/// One thing `tai doctor` looked at
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, result: Result<String>) -> Self {
+        match result {
+            Ok(detail) => Self {
+                name,
+                ok: true,
+                detail,
+            },
+            Err(e) => Self {
+                name,
+                ok: false,
+                detail: format!("{e:#}"),
+            },
+        }
+    }
+}
+
+/// Everything `tai doctor` checked, serialized as-is for `--json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DoctorReport {
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    pub fn is_ok(&self) -> bool {
+        self.checks.iter().all(|check| check.ok)
+    }
+
+    pub fn render(&self) -> String {
+        self.checks
+            .iter()
+            .map(|check| {
+                let status = if check.ok { "✅" } else { "❌" };
+                format!("{status} {}: {}\n", check.name, check.detail)
+            })
+            .collect()
+    }
+}
+
+/// Check the setup commands run with. `loaded` is what `load_config` returned and
+/// `source` where it came from; the provider and history checks need a config and
+/// are skipped when it failed to load. `shell` is the `--shell` flag, if given.
+pub fn diagnose(
+    loaded: &Result<TerminalAIConfig>,
+    source: &str,
+    shell: Option<&str>,
+) -> DoctorReport {
+    let mut checks = vec![Check::new(
+        "config",
+        match loaded {
+            Ok(_) => Ok(format!("loaded from {source}")),
+            Err(e) => Err(anyhow::anyhow!("{e:#}")),
+        },
+    )];
+    if let Ok(config) = loaded {
+        checks.push(Check::new("validation", validation(config)));
+        checks.push(Check::new("provider", provider(config)));
+    }
+    let shell = match loaded {
+        Ok(config) => crate::shell::from_config(config, shell),
+        Err(_) => crate::shell::shell().copied(),
+    };
+    checks.push(Check::new("shell", shell.map(|shell| shell.to_string())));
+    if let Ok(config) = loaded {
+        checks.push(Check::new("history", history(config)));
+    }
+    DoctorReport { checks }
+}
+
+fn validation(config: &TerminalAIConfig) -> Result<String> {
+    let mut report = ValidationReport::default();
+    config::validate_config(config, &mut report);
+    if !report.is_valid() {
+        anyhow::bail!("{}", report.problems.join("; "));
+    }
+    Ok(match report.warnings.len() {
+        0 => "no problems".to_string(),
+        1 => "1 warning (see tai config validate)".to_string(),
+        n => format!("{n} warnings (see tai config validate)"),
+    })
+}
+
+fn provider(config: &TerminalAIConfig) -> Result<String> {
+    let provider_config = config.get_active_provider().ok_or_else(|| {
+        anyhow::anyhow!(
+            "active_provider '{}' is not configured",
+            config.active_provider
+        )
+    })?;
+    let provider = crate::providers::create_provider(provider_config)?;
+    let model = provider_config
+        .get_setting("model")
+        .map(String::as_str)
+        .unwrap_or_default();
+    Ok(format!(
+        "{} ({}, model {model})",
+        config.active_provider,
+        provider.provider_name()
+    ))
+}
+
+fn history(config: &TerminalAIConfig) -> Result<String> {
+    if !config.history_enabled() {
+        return Ok("disabled".to_string());
+    }
+    let path = crate::history::history_path()
+        .ok_or_else(|| anyhow::anyhow!("Failed to find home directory"))?;
+    Ok(path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_json_has_checks() {
+        let report = diagnose(&Ok(TerminalAIConfig::default()), "built-in defaults", None);
+        let json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+        let checks = json["checks"].as_array().unwrap();
+        let names: Vec<&str> = checks
+            .iter()
+            .map(|check| check["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["config", "validation", "provider", "shell", "history"]
+        );
+        for check in checks {
+            assert!(check["ok"].is_boolean());
+            assert!(check["detail"].is_string());
+        }
+        assert_eq!(checks[0]["detail"], "loaded from built-in defaults");
+    }
+
+    #[test]
+    fn test_failed_config_skips_dependent_checks() {
+        let report = diagnose(&Err(anyhow::anyhow!("bad JSON")), "config.json", None);
+        assert!(!report.is_ok());
+        let names: Vec<&str> = report.checks.iter().map(|check| check.name).collect();
+        assert_eq!(names, vec!["config", "shell"]);
+        assert!(report.render().starts_with("❌ config: bad JSON\n"));
+    }
+
+    #[test]
+    fn test_invalid_config_fails_validation() {
+        let config = TerminalAIConfig {
+            active_provider: "missing".to_string(),
+            ..Default::default()
+        };
+        let report = diagnose(&Ok(config), "terminalai.conf", None);
+        let validation = &report.checks[1];
+        assert!(!validation.ok);
+        assert!(validation
+            .detail
+            .contains("active_provider 'missing' is not configured"));
+        assert!(!report.checks[2].ok);
+    }
+
+    #[test]
+    fn test_unsupported_shell_fails_the_shell_check() {
+        let report = diagnose(
+            &Ok(TerminalAIConfig::default()),
+            "terminalai.conf",
+            Some("tcsh"),
+        );
+        let shell = &report.checks[3];
+        assert_eq!(shell.name, "shell");
+        assert!(!shell.ok);
+        assert!(shell.detail.starts_with("Unsupported shell 'tcsh'"));
+    }
+}
@@ -0,0 +1,507 @@
+use crate::transcript::{self, Event, SharedTranscript};
+use crate::{branding, ExecutionOptions};
+use anyhow::{Context, Result};
+use colored::*;
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::Command;
+
+/// Execute a shell command with live output.
+///
+/// Applies the shell's fixups (the find `-exec ... +` fix for POSIX shells), prints Terminal AI branding for
+/// install/update/remove commands and fails on any non-zero exit code. With
+/// `error_signatures` set, output is scanned and matching lines are reported
+/// as a warning when the command still exits 0. With a `transcript`, the
+/// command and its output are recorded as well.
+pub async fn execute_command(cmd: &str, options: &ExecutionOptions) -> Result<()> {
+    let branded = branding::applies_to(cmd);
+    let report = |progress| print_progress(progress_lines(cmd, progress, branded, options.quiet));
+
+    report(Progress::Started);
+
+    // Use shell execution for proper handling of pipes, redirects, etc.
+    let shell = crate::shell::shell()?;
+    let fixed_cmd = shell.fix_command(cmd);
+    if fixed_cmd != cmd {
+        report(Progress::Adjusted(&fixed_cmd));
+    }
+
+    let mut command = shell.async_command(&fixed_cmd);
+    command.stdin(Stdio::piped());
+    command.stdout(Stdio::inherit());
+    command.stderr(Stdio::inherit());
+    if let Some(dir) = &options.working_dir {
+        command.current_dir(dir);
+    }
+
+    let transcript = options.transcript.as_ref();
+    transcript::record(transcript, Event::Command(&fixed_cmd));
+    let (status, error_lines) = if options.error_signatures.is_empty() && transcript.is_none() {
+        (command.status().await, Vec::new())
+    } else {
+        match run_piped_output(&mut command, &options.error_signatures, transcript).await {
+            Ok((status, error_lines)) => (Ok(status), error_lines),
+            Err(e) => (Err(e), Vec::new()),
+        }
+    };
+    let status = status.with_context(|| format!("Failed to execute command '{cmd}'"))?;
+    transcript::record(transcript, Event::Exit(status.code().unwrap_or(-1)));
+
+    if status.success() {
+        report(Progress::Succeeded);
+        warn_error_output(&error_lines);
+        return Ok(());
+    }
+
+    let exit_code = status.code().unwrap_or(-1);
+    report(Progress::Failed(exit_code));
+    Err(CommandFailed {
+        command: cmd.to_string(),
+        exit_code,
+    }
+    .into())
+}
+
+/// A command that ran and exited non-zero, as returned by [`execute_command`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandFailed {
+    pub command: String,
+    /// -1 when the command was killed by a signal
+    pub exit_code: i32,
+}
+
+impl std::fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Command '{}' failed with exit code: {}",
+            self.command, self.exit_code
+        )
+    }
+}
+
+impl std::error::Error for CommandFailed {}
+
+/// Run `command` while echoing (and recording) its output and collecting the lines
+/// that contain one of `signatures`
+async fn run_piped_output(
+    command: &mut Command,
+    signatures: &[String],
+    transcript: Option<&SharedTranscript>,
+) -> std::io::Result<(std::process::ExitStatus, Vec<String>)> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+
+    let (mut matches, stderr_matches, status) = tokio::join!(
+        echo_and_scan(stdout, false, signatures, transcript),
+        echo_and_scan(stderr, true, signatures, transcript),
+        child.wait()
+    );
+    matches.extend(stderr_matches);
+    Ok((status?, matches))
+}
+
+/// Echo each line of `reader`, returning those that contain an error signature
+async fn echo_and_scan(
+    reader: Option<impl tokio::io::AsyncRead + Unpin>,
+    to_stderr: bool,
+    signatures: &[String],
+    transcript: Option<&SharedTranscript>,
+) -> Vec<String> {
+    let mut matches = Vec::new();
+    let Some(reader) = reader else {
+        return matches;
+    };
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if to_stderr {
+            eprintln!("{line}");
+            transcript::record(transcript, Event::Stderr(&line));
+        } else {
+            println!("{line}");
+            transcript::record(transcript, Event::Stdout(&line));
+        }
+        if contains_error_signature(&line, signatures) {
+            matches.push(line);
+        }
+    }
+    matches
+}
+
+fn contains_error_signature(line: &str, signatures: &[String]) -> bool {
+    signatures
+        .iter()
+        .any(|signature| line.contains(signature.as_str()))
+}
+
+/// Warn about error output from a command that still exited 0
+fn warn_error_output(error_lines: &[String]) {
+    const SHOWN: usize = 5;
+    if error_lines.is_empty() {
+        return;
+    }
+    println!(
+        "{}",
+        "⚠️  The command exited successfully but printed errors:".yellow()
+    );
+    for line in error_lines.iter().take(SHOWN) {
+        println!("   {}", line.trim());
+    }
+    if error_lines.len() > SHOWN {
+        println!("   ... and {} more", error_lines.len() - SHOWN);
+    }
+}
+
+/// Progress reported while a command runs
+#[derive(Debug, Clone, Copy)]
+enum Progress<'a> {
+    Started,
+    Adjusted(&'a str),
+    Succeeded,
+    Failed(i32),
+}
+
+/// A line of progress output; errors go to stderr
+#[derive(Debug, Clone, PartialEq)]
+enum ProgressLine {
+    Out(String),
+    Err(String),
+}
+
+/// The lines printed for `progress`.
+///
+/// Branded commands get the `[Terminal AI]` banners; everything else gets the
+/// plain lines, which `quiet` suppresses (the compatibility notice is always shown).
+fn progress_lines(cmd: &str, progress: Progress, branded: bool, quiet: bool) -> Vec<ProgressLine> {
+    use ProgressLine::{Err, Out};
+
+    if branded {
+        return match progress {
+            Progress::Started => vec![
+                Out("[Terminal AI] - Executing package management command"
+                    .green()
+                    .bold()
+                    .to_string()),
+                Out(format!("[Terminal AI] - Command: {cmd}")
+                    .green()
+                    .to_string()),
+                Out("[Terminal AI] - Live output:".green().to_string()),
+            ],
+            Progress::Adjusted(fixed) => {
+                vec![Out(format!("[Terminal AI] - Adjusted command: {fixed}")
+                    .green()
+                    .to_string())]
+            }
+            Progress::Succeeded => vec![Out("[Terminal AI] - Command completed successfully"
+                .green()
+                .bold()
+                .to_string())],
+            Progress::Failed(code) => vec![Err(format!(
+                "[Terminal AI] - Command failed with exit code: {code}"
+            )
+            .red()
+            .bold()
+            .to_string())],
+        };
+    }
+
+    match progress {
+        Progress::Adjusted(fixed) => vec![Out(format!(
+            "🔧 Adjusted command for compatibility: {fixed}"
+        ))],
+        _ if quiet => Vec::new(),
+        Progress::Started => vec![Out(format!("\n🔄 Executing: {cmd}"))],
+        Progress::Succeeded => vec![Out("✅ Command completed successfully".to_string())],
+        Progress::Failed(code) => vec![Err(format!("❌ Command failed with exit code: {code}"))],
+    }
+}
+
+fn print_progress(lines: Vec<ProgressLine>) {
+    for line in lines {
+        match line {
+            ProgressLine::Out(text) => println!("{text}"),
+            ProgressLine::Err(text) => eprintln!("{text}"),
+        }
+    }
+}
+
+/// Whether `err` means the program could not be started because its file is gone,
+/// e.g. [`stream_command_output`] for a binary that was deleted
+pub fn is_missing_program(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+    })
+}
+
+/// Run `command`, handing each stdout line to `on_line` as soon as it is produced.
+///
+/// Returns the full stdout once the process exits successfully. Stderr is
+/// collected and included in the error when the process fails.
+pub async fn stream_command_output(
+    command: &mut Command,
+    mut on_line: impl FnMut(&str),
+) -> Result<String> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to start process")?;
+
+    let stdout = child.stdout.take().context("Failed to capture stdout")?;
+    let mut stderr = child.stderr.take().context("Failed to capture stderr")?;
+
+    // Drain stderr concurrently so a chatty process cannot block on a full pipe
+    let stderr_task = tokio::spawn(async move {
+        let mut buffer = String::new();
+        let _ = stderr.read_to_string(&mut buffer).await;
+        buffer
+    });
+
+    let mut output = String::new();
+    let mut lines = BufReader::new(stdout).lines();
+    while let Some(line) = lines
+        .next_line()
+        .await
+        .context("Failed to read process output")?
+    {
+        on_line(&line);
+        output.push_str(&line);
+        output.push('\n');
+    }
+
+    let status = child.wait().await.context("Failed to wait for process")?;
+    let stderr = stderr_task.await.unwrap_or_default();
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "process exited with code {}: {}",
+            status.code().unwrap_or(-1),
+            stderr.trim()
+        ));
+    }
+    Ok(output)
+}
+
+/// Blocking wrapper around [`execute_command`] for synchronous callers.
+///
+/// Runs on a fresh current-thread runtime. When called from inside an existing
+/// runtime (the `*_ai` binaries are `#[tokio::main]`) the fresh runtime is
+/// driven on a scoped thread so the two do not nest.
+pub fn execute_command_blocking(cmd: &str, options: &ExecutionOptions) -> Result<()> {
+    let run = || -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Failed to create async runtime")?
+            .block_on(execute_command(cmd, options))
+    };
+
+    if tokio::runtime::Handle::try_current().is_ok() {
+        std::thread::scope(|scope| {
+            scope
+                .spawn(run)
+                .join()
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("Command execution thread panicked")))
+        })
+    } else {
+        run()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const PARITY_COMMANDS: [&str; 4] = [
+        "true",
+        "exit 3",
+        "echo parity > /dev/null",
+        "find . -maxdepth 0 -exec echo {} +",
+    ];
+
+    #[test]
+    fn test_blocking_and_async_results_match() {
+        let options = ExecutionOptions::default();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        for cmd in PARITY_COMMANDS {
+            let blocking = execute_command_blocking(cmd, &options);
+            let asynchronous = runtime.block_on(execute_command(cmd, &options));
+
+            assert_eq!(blocking.is_ok(), asynchronous.is_ok(), "command: {cmd}");
+            if let (Err(a), Err(b)) = (&blocking, &asynchronous) {
+                assert_eq!(a.to_string(), b.to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn test_exit_code_reported_consistently() {
+        let options = ExecutionOptions::default();
+        let err = execute_command_blocking("exit 3", &options).unwrap_err();
+        assert_eq!(err.to_string(), "Command 'exit 3' failed with exit code: 3");
+        assert!(crate::execute_command_with_live_output("exit 3")
+            .unwrap_err()
+            .to_string()
+            .contains("exit code: 3"));
+    }
+
+    #[test]
+    fn test_branding_off_prints_plain_progress() {
+        let cmd = "npm install react";
+        let all_lines = |branded: bool| {
+            [
+                Progress::Started,
+                Progress::Adjusted(cmd),
+                Progress::Succeeded,
+                Progress::Failed(1),
+            ]
+            .into_iter()
+            .flat_map(|progress| progress_lines(cmd, progress, branded, false))
+            .map(|line| match line {
+                ProgressLine::Out(text) | ProgressLine::Err(text) => text,
+            })
+            .collect::<Vec<_>>()
+        };
+
+        let branded = all_lines(true);
+        assert!(branded
+            .iter()
+            .any(|l| l.contains("[Terminal AI] - Executing")));
+
+        let plain = all_lines(false);
+        assert!(plain.iter().all(|l| !l.contains("[Terminal AI]")));
+        assert_eq!(
+            plain,
+            vec![
+                "\n🔄 Executing: npm install react",
+                "🔧 Adjusted command for compatibility: npm install react",
+                "✅ Command completed successfully",
+                "❌ Command failed with exit code: 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quiet_keeps_only_compatibility_notice() {
+        let lines = [
+            Progress::Started,
+            Progress::Adjusted("ls"),
+            Progress::Succeeded,
+        ]
+        .into_iter()
+        .flat_map(|progress| progress_lines("ls", progress, false, true))
+        .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![ProgressLine::Out(
+                "🔧 Adjusted command for compatibility: ls".to_string()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_command_output_delivers_lines_incrementally() {
+        let mut fake = Command::new("sh");
+        fake.arg("-c")
+            .arg("echo first; sleep 0.3; echo second; echo noise >&2");
+
+        let started = std::time::Instant::now();
+        let mut arrivals = Vec::new();
+        let output = stream_command_output(&mut fake, |line| {
+            arrivals.push((line.to_string(), started.elapsed()));
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(output, "first\nsecond\n");
+        let lines: Vec<&str> = arrivals.iter().map(|(line, _)| line.as_str()).collect();
+        assert_eq!(lines, vec!["first", "second"]);
+        // The first line is delivered while the process is still sleeping
+        assert!(arrivals[1].1 - arrivals[0].1 >= std::time::Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_stream_command_output_reports_failure() {
+        let mut fake = Command::new("sh");
+        fake.arg("-c")
+            .arg("echo partial; echo model not found >&2; exit 2");
+
+        let mut seen = Vec::new();
+        let err = stream_command_output(&mut fake, |line| seen.push(line.to_string()))
+            .await
+            .unwrap_err();
+        assert_eq!(seen, vec!["partial"]);
+        assert_eq!(
+            err.to_string(),
+            "process exited with code 2: model not found"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blocking_wrapper_inside_runtime() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = ExecutionOptions {
+            working_dir: Some(temp_dir.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        execute_command_blocking("touch marker", &options).unwrap();
+        assert!(temp_dir.path().join("marker").exists());
+
+        execute_command("touch async_marker", &options)
+            .await
+            .unwrap();
+        assert!(temp_dir.path().join("async_marker").exists());
+    }
+
+    #[tokio::test]
+    async fn test_error_signatures_detected_on_zero_exit() {
+        let signatures = vec!["npm ERR!".to_string(), "fatal:".to_string()];
+        let mut command = crate::shell::shell().unwrap().async_command(
+            "echo 'added 1 package'; echo 'npm ERR! code E404' >&2; echo 'fatal: not a repo'; exit 0",
+        );
+
+        let (status, error_lines) = run_piped_output(&mut command, &signatures, None)
+            .await
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(
+            error_lines,
+            vec![
+                "fatal: not a repo".to_string(),
+                "npm ERR! code E404".to_string()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_signatures_only_warn() {
+        let clean = vec!["ERR!".to_string()];
+        let mut command = crate::shell::shell()
+            .unwrap()
+            .async_command("echo 'no errors: 0'");
+        let (_, error_lines) = run_piped_output(&mut command, &clean, None).await.unwrap();
+        assert!(error_lines.is_empty());
+
+        // A matching line on exit 0 is reported as a warning, not a failure
+        let options = ExecutionOptions {
+            error_signatures: clean,
+            ..Default::default()
+        };
+        execute_command("echo 'npm ERR! peer dep'", &options)
+            .await
+            .unwrap();
+        let err = execute_command("echo 'npm ERR! peer dep'; exit 2", &options)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("exit code: 2"));
+    }
+}
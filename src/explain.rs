@@ -0,0 +1,451 @@
+use crate::{
+    dedup_commands, extract_commands_from_response, localized_prompt,
+    query_provider::QueryProvider, risk, ui, Dedup,
+};
+use anyhow::{Context, Result};
+
+/// System prompt used by `--explain` and `--dry-run`
+pub const EXPLAIN_SYSTEM_PROMPT: &str = "You are an assistant that explains shell commands to beginners. For each numbered command, reply with one line in the form 'N. explanation', using the same number. Keep each explanation under 20 words and do not repeat the command itself.";
+
+/// Build the prompt asking the AI to explain each command on one line
+fn build_explanation_prompt(commands: &[String]) -> String {
+    let numbered = commands
+        .iter()
+        .enumerate()
+        .map(|(i, cmd)| format!("{}. {}", i + 1, cmd))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("Explain what each of these commands does:\n\n{numbered}")
+}
+
+/// Match the numbered lines of an explanation response to `commands`.
+///
+/// Lines are paired by their number (`1.`, `1)` or `1:`); a response without
+/// numbers is paired line by line when it has exactly one line per command.
+pub fn pair_explanations(commands: &[String], response: &str) -> Vec<Option<String>> {
+    let lines: Vec<&str> = response
+        .lines()
+        .map(|line| line.trim().trim_start_matches(['-', '*']).trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let mut explanations = vec![None; commands.len()];
+    let mut numbered = false;
+    for line in &lines {
+        let digits = line.chars().take_while(char::is_ascii_digit).count();
+        let Ok(number) = line[..digits].parse::<usize>() else {
+            continue;
+        };
+        let Some(text) = line[digits..].strip_prefix(['.', ')', ':']) else {
+            continue;
+        };
+        numbered = true;
+        let text = text.trim();
+        if let Some(slot) = number.checked_sub(1).and_then(|i| explanations.get_mut(i)) {
+            if !text.is_empty() {
+                *slot = Some(text.to_string());
+            }
+        }
+    }
+
+    if !numbered && lines.len() == commands.len() {
+        return lines.iter().map(|line| Some(line.to_string())).collect();
+    }
+    explanations
+}
+
+/// Ask the AI for a one-line explanation of each command
+pub async fn request_command_explanations(
+    commands: &[String],
+    provider: &QueryProvider,
+    language: Option<&str>,
+) -> Result<Vec<Option<String>>> {
+    let response = provider
+        .send_query(
+            &localized_prompt(EXPLAIN_SYSTEM_PROMPT, language),
+            &build_explanation_prompt(commands),
+        )
+        .await?;
+    Ok(pair_explanations(commands, &response))
+}
+
+/// Show `commands` with their explanations and any risk findings, and say
+/// that nothing ran
+pub fn show_dry_run(heading: &str, commands: &[String], explanations: &[Option<String>]) {
+    ui::preview_explained(heading, commands, explanations);
+    let findings = risk::assess_all(commands);
+    if !findings.is_empty() {
+        ui::show(&risk::render_findings(&findings));
+    }
+    ui::show(&format!("\n{}\n", ui::messages().dry_run));
+}
+
+/// `--dry-run`: explain the commands in `ai_response` without executing any of them.
+///
+/// A failed explanation request is reported and the commands are shown without
+/// explanations.
+pub async fn dry_run(
+    ai_response: &str,
+    dedup: Dedup,
+    provider: &QueryProvider,
+    language: Option<&str>,
+) -> Result<()> {
+    let commands = dedup_commands(extract_commands_from_response(ai_response), dedup);
+    if commands.is_empty() {
+        anyhow::bail!("No executable commands found in AI response");
+    }
+    crate::check_plan_size(&commands)?;
+    let explanations = match request_command_explanations(&commands, provider, language).await {
+        Ok(explanations) => explanations,
+        Err(e) => {
+            eprintln!("⚠️  Could not get command explanations: {e}");
+            Vec::new()
+        }
+    };
+    show_dry_run(&ui::messages().suggested_commands, &commands, &explanations);
+    Ok(())
+}
+
+/// System prompt used by `tai explain` and `--breakdown`
+pub const BREAKDOWN_SYSTEM_PROMPT: &str = "You are an assistant that explains shell commands before anyone runs them. Break the user's command down. Reply with one line 'SUMMARY: what the whole command does', then one line 'PART: part => what it does' for the program, each flag and each argument, in order, writing the part exactly as it appears in the command (a flag together with its value). Then add one line 'FILE: path or pattern => read, created, modified or deleted' for every file or directory it would touch, and none when it touches none. Do not run or change the command, and write nothing else.";
+
+/// Parts wider than this don't push the other explanations further right
+const MAX_PART_WIDTH: usize = 24;
+
+/// What the model said a command does, part by part
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Breakdown {
+    pub command: String,
+    pub summary: Option<String>,
+    /// Each program, flag and argument with what it does
+    pub parts: Vec<(String, String)>,
+    /// Each file or pattern the command would touch and how
+    pub files: Vec<(String, String)>,
+    /// The answer as it was, shown when it has none of the lines asked for
+    pub response: String,
+}
+
+impl Breakdown {
+    /// Read the `SUMMARY:`, `PART:` and `FILE:` lines of `response`
+    pub fn parse(command: &str, response: &str) -> Self {
+        let mut breakdown = Self {
+            command: command.to_string(),
+            response: response.trim().to_string(),
+            ..Default::default()
+        };
+        let split = |line: &str| {
+            let (left, right) = line.split_once("=>")?;
+            let left = left.trim().trim_matches('`').trim();
+            (!left.is_empty()).then(|| (left.to_string(), right.trim().to_string()))
+        };
+        for line in response.lines().map(str::trim) {
+            if let Some(summary) = line.strip_prefix("SUMMARY:") {
+                breakdown.summary = Some(summary.trim().to_string());
+            } else if let Some(part) = line.strip_prefix("PART:").and_then(split) {
+                breakdown.parts.push(part);
+            } else if let Some(file) = line.strip_prefix("FILE:").and_then(split) {
+                breakdown.files.push(file);
+            }
+        }
+        breakdown
+    }
+
+    /// Whether the answer had any of the lines asked for
+    fn is_structured(&self) -> bool {
+        self.summary.is_some() || !self.parts.is_empty()
+    }
+
+    /// The command with its summary, each part's explanation beside it and the
+    /// files it would touch
+    pub fn render(&self) -> String {
+        let mut lines = vec![format!("📖 {}", self.command)];
+        if !self.is_structured() {
+            lines.extend(self.response.lines().map(|line| format!("   {line}")));
+            return lines.join("\n");
+        }
+        if let Some(summary) = &self.summary {
+            lines.push(format!("   {summary}"));
+        }
+        let width = self
+            .parts
+            .iter()
+            .map(|(part, _)| part.chars().count())
+            .filter(|&width| width <= MAX_PART_WIDTH)
+            .max()
+            .unwrap_or(0);
+        if !self.parts.is_empty() {
+            lines.push(String::new());
+        }
+        for (part, meaning) in &self.parts {
+            lines.push(format!("   {part:<width$}  {meaning}"));
+        }
+        lines.push(String::new());
+        if self.files.is_empty() {
+            lines.push("   📂 Touches no files".to_string());
+        } else {
+            lines.push("   📂 Files it would touch:".to_string());
+            for (file, how) in &self.files {
+                lines.push(format!("      {file}: {how}"));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// Ask the AI to break `command` down flag by flag
+pub async fn request_breakdown(
+    command: &str,
+    provider: &QueryProvider,
+    language: Option<&str>,
+) -> Result<Breakdown> {
+    let response = provider
+        .send_query(
+            &localized_prompt(BREAKDOWN_SYSTEM_PROMPT, language),
+            &format!("Explain this command:\n\n{command}"),
+        )
+        .await?;
+    Ok(Breakdown::parse(
+        command,
+        &crate::strip_reasoning(&response),
+    ))
+}
+
+/// Show `command`'s breakdown and what the risk check says about it
+pub fn show_breakdown(breakdown: &Breakdown) {
+    ui::show(&format!("\n{}\n", breakdown.render()));
+    let findings = risk::assess_all(std::slice::from_ref(&breakdown.command));
+    if !findings.is_empty() {
+        ui::show(&risk::render_findings(&findings));
+    }
+}
+
+/// `--breakdown`: break each command in `ai_response` down instead of running them
+pub async fn explain_response(
+    ai_response: &str,
+    dedup: Dedup,
+    provider: &QueryProvider,
+    language: Option<&str>,
+) -> Result<()> {
+    let commands = dedup_commands(extract_commands_from_response(ai_response), dedup);
+    if commands.is_empty() {
+        anyhow::bail!("No executable commands found in AI response");
+    }
+    crate::check_plan_size(&commands)?;
+    for command in &commands {
+        show_breakdown(&request_breakdown(command, provider, language).await?);
+    }
+    ui::show(&format!("\n{}\n", ui::messages().not_executed));
+    Ok(())
+}
+
+/// Options controlling `tai explain`
+#[derive(Debug, Clone, Default)]
+pub struct ExplainOptions {
+    /// Send the query to this endpoint instead of the active provider's configured URL
+    pub url: Option<String>,
+    /// Leave the OS, shell, directory listing and git branch out of the prompt
+    pub no_context: bool,
+    /// Shell the command would run in (`--shell`), instead of the `shell` setting
+    pub shell: Option<String>,
+}
+
+/// `tai explain`: break `command` down without running it
+pub async fn explain_command(command: &str, options: &ExplainOptions) -> Result<()> {
+    let command = command.trim();
+    if command.is_empty() {
+        anyhow::bail!("Give the command to explain, e.g. tai explain \"tar czf out.tgz src\"");
+    }
+    let mut config = crate::load_config()?;
+    if let Some(url) = &options.url {
+        config.override_url(url)?;
+    }
+    ui::configure(&config);
+    crate::shell::configure(&config, options.shell.as_deref())?;
+    crate::context::configure(&config, options.no_context);
+    let language = config.output_language();
+    let provider = QueryProvider::new(config).context("Failed to create query provider")?;
+    let breakdown = {
+        let _spinner = ui::Spinner::start("Explaining the command...");
+        request_breakdown(command, &provider, language.as_deref()).await?
+    };
+    show_breakdown(&breakdown);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockProvider, PromptLog};
+
+    fn mock_provider(response: Option<&str>) -> (QueryProvider, PromptLog) {
+        match response {
+            Some(response) => MockProvider::repeating(response),
+            None => MockProvider::failing(),
+        }
+        .into_query_provider()
+    }
+
+    #[tokio::test]
+    async fn test_explanations_are_paired_with_commands() {
+        let commands = vec![
+            "python -m venv .venv".to_string(),
+            "pip install requests==2.31.0".to_string(),
+            "pip show requests".to_string(),
+        ];
+        let (provider, prompts) = mock_provider(Some(
+            "Here you go:\n1. Creates an isolated Python environment\n3) Shows the installed version\n2: Installs requests 2.31.0\n",
+        ));
+
+        let explanations = request_command_explanations(&commands, &provider, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            explanations,
+            vec![
+                Some("Creates an isolated Python environment".to_string()),
+                Some("Installs requests 2.31.0".to_string()),
+                Some("Shows the installed version".to_string()),
+            ]
+        );
+        let prompts = prompts.all();
+        assert_eq!(prompts[0].0, EXPLAIN_SYSTEM_PROMPT);
+        assert!(prompts[0].1.contains("2. pip install requests==2.31.0"));
+    }
+
+    #[tokio::test]
+    async fn test_explanations_requested_in_output_language() {
+        let commands = vec!["pip install requests".to_string()];
+        let (provider, prompts) = mock_provider(Some("1. Installiert requests"));
+
+        request_command_explanations(&commands, &provider, Some("German"))
+            .await
+            .unwrap();
+        let prompts = prompts.all();
+        assert!(prompts[0].0.starts_with(EXPLAIN_SYSTEM_PROMPT));
+        assert!(prompts[0].0.ends_with(
+            "Respond in German, but keep commands, file names and flags exactly as they are."
+        ));
+    }
+
+    #[test]
+    fn test_pair_explanations_handles_gaps_and_unnumbered_lines() {
+        let commands = vec!["npm ci".to_string(), "npm test".to_string()];
+        assert_eq!(
+            pair_explanations(&commands, "2. Runs the test suite\n7. Out of range"),
+            vec![None, Some("Runs the test suite".to_string())]
+        );
+        assert_eq!(
+            pair_explanations(&commands, "- Installs from the lockfile\n- Runs the tests"),
+            vec![
+                Some("Installs from the lockfile".to_string()),
+                Some("Runs the tests".to_string()),
+            ]
+        );
+        assert_eq!(pair_explanations(&commands, "No idea"), vec![None, None]);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_asks_for_explanations_only() {
+        let response = "COMMAND: rm -rf build\nCOMMAND: rm -rf build";
+        let (provider, prompts) = mock_provider(Some("1. Deletes the build folder"));
+        dry_run(response, Dedup::All, &provider, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            prompts.all(),
+            vec![(
+                EXPLAIN_SYSTEM_PROMPT.to_string(),
+                build_explanation_prompt(&["rm -rf build".to_string()])
+            )]
+        );
+
+        // A provider that cannot explain still gets the commands shown
+        let (failing, attempts) = mock_provider(None);
+        dry_run(response, Dedup::All, &failing, None).await.unwrap();
+        assert!(!attempts.is_empty());
+
+        let err = dry_run("I cannot help with that.", Dedup::All, &provider, None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("No executable commands"), "{err}");
+    }
+
+    #[test]
+    fn test_breakdown_is_annotated_part_by_part() {
+        let breakdown = Breakdown::parse(
+            "tar czf backup.tgz -C src .",
+            "SUMMARY: Packs the contents of src into a gzip archive.\n\
+             PART: tar => The archiving tool\n\
+             PART: `czf` => Create an archive, gzip it, write it to the next argument\n\
+             PART: backup.tgz => The archive to write\n\
+             PART: -C src => Change into src first\n\
+             PART: . => Everything in it\n\
+             FILE: backup.tgz => created or overwritten\n\
+             FILE: src/ => read",
+        );
+        assert_eq!(breakdown.parts[1].0, "czf");
+        assert_eq!(
+            breakdown.render(),
+            "📖 tar czf backup.tgz -C src .\n   \
+             Packs the contents of src into a gzip archive.\n\n   \
+             tar         The archiving tool\n   \
+             czf         Create an archive, gzip it, write it to the next argument\n   \
+             backup.tgz  The archive to write\n   \
+             -C src      Change into src first\n   \
+             .           Everything in it\n\n   \
+             📂 Files it would touch:\n      \
+             backup.tgz: created or overwritten\n      \
+             src/: read"
+        );
+
+        let pwd = Breakdown::parse(
+            "pwd",
+            "SUMMARY: Prints the current directory.\nPART: pwd => The program",
+        );
+        assert!(pwd.render().ends_with("\n\n   📂 Touches no files"));
+
+        // An answer in another shape is shown as it is
+        let free_form = Breakdown::parse("ls -la", "Lists every file.\nIncluding hidden ones.");
+        assert_eq!(
+            free_form.render(),
+            "📖 ls -la\n   Lists every file.\n   Including hidden ones."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_explain_response_breaks_down_each_command() {
+        let (provider, prompts) = mock_provider(Some(
+            "<think>flags</think>SUMMARY: Deletes the build folder.\nPART: rm -rf => Remove recursively, without asking\nFILE: build/ => deleted",
+        ));
+        explain_response(
+            "COMMAND: rm -rf build\nCOMMAND: ls",
+            Dedup::All,
+            &provider,
+            None,
+        )
+        .await
+        .unwrap();
+        {
+            let prompts = prompts.all();
+            assert_eq!(prompts.len(), 2);
+            assert_eq!(prompts[0].0, BREAKDOWN_SYSTEM_PROMPT);
+            assert_eq!(prompts[0].1, "Explain this command:\n\nrm -rf build");
+            assert_eq!(prompts[1].1, "Explain this command:\n\nls");
+        }
+
+        let breakdown = request_breakdown("rm -rf build", &provider, Some("German"))
+            .await
+            .unwrap();
+        assert_eq!(
+            breakdown.summary.as_deref(),
+            Some("Deletes the build folder.")
+        );
+        assert_eq!(
+            breakdown.files,
+            vec![("build/".to_string(), "deleted".to_string())]
+        );
+        assert!(prompts.all()[2].0.ends_with(
+            "Respond in German, but keep commands, file names and flags exactly as they are."
+        ));
+    }
+}
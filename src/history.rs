@@ -0,0 +1,507 @@
+use crate::executor::CommandFailed;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Which tool ran and what it was asked, saved with the commands it executed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    pub tool: &'static str,
+    pub prompt: String,
+    /// Whether the model's response is saved too; not when the prompt is redacted,
+    /// as the response often repeats it
+    pub keep_response: bool,
+}
+
+impl Origin {
+    pub fn new(tool: &'static str, prompt: &str) -> Self {
+        Self {
+            tool,
+            prompt: prompt.to_string(),
+            keep_response: true,
+        }
+    }
+
+    /// The same origin, saving the commands without the model's response
+    pub fn without_response(mut self) -> Self {
+        self.keep_response = false;
+        self
+    }
+}
+
+/// How prompts are saved in the history (the `history_prompt` setting)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PromptStorage {
+    /// The prompt as typed
+    #[default]
+    Full,
+    /// Its SHA-256, so a known prompt can still be matched against the history
+    Hash,
+    /// Only how long it was
+    Summary,
+}
+
+impl PromptStorage {
+    /// The storage a `history_prompt` value names, `None` for anything else
+    pub fn from_setting(value: &str) -> Option<Self> {
+        match value.trim() {
+            "full" => Some(PromptStorage::Full),
+            "hash" => Some(PromptStorage::Hash),
+            "summary" => Some(PromptStorage::Summary),
+            _ => None,
+        }
+    }
+
+    /// `prompt` as it is written to the history. One taken from the history or a
+    /// saved plan that is already hashed or summarized is kept as it is.
+    pub fn store(self, prompt: &str) -> String {
+        if is_redacted(prompt) {
+            return prompt.to_string();
+        }
+        match self {
+            PromptStorage::Full => prompt.to_string(),
+            PromptStorage::Hash => format!("sha256:{}", prompt_hash(prompt)),
+            PromptStorage::Summary => {
+                let words = prompt.split_whitespace().count();
+                format!(
+                    "[redacted prompt, {words} word{}]",
+                    if words == 1 { "" } else { "s" }
+                )
+            }
+        }
+    }
+}
+
+/// Whether `prompt` is what [`PromptStorage::Hash`] or [`PromptStorage::Summary`]
+/// wrote
+fn is_redacted(prompt: &str) -> bool {
+    let hashed = prompt
+        .strip_prefix("sha256:")
+        .is_some_and(|hash| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()));
+    let summarized = prompt.starts_with("[redacted prompt, ")
+        && (prompt.ends_with(" words]") || prompt.ends_with(" word]"));
+    hashed || summarized
+}
+
+/// Hex SHA-256 of `prompt`
+pub fn prompt_hash(prompt: &str) -> String {
+    Sha256::digest(prompt.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// One executed request, a line of `~/.terminalai/history.jsonl`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Unix time the commands finished
+    pub timestamp: u64,
+    /// Absolute directory the commands ran in
+    pub workdir: PathBuf,
+    pub tool: String,
+    pub prompt: String,
+    pub commands: Vec<String>,
+    /// The model's one-line reason for each command, in the same order; empty when
+    /// the tool asked for none
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rationales: Vec<Option<String>>,
+    /// Each command's exit code, in the same order; `None` for a command that never
+    /// ran. Empty when not known
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exit_codes: Vec<Option<i32>>,
+    /// The model's answer the commands were taken from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    pub succeeded: bool,
+}
+
+impl HistoryEntry {
+    /// An entry for commands that just ran in `working_dir` (the current directory
+    /// when `None`)
+    pub fn new(
+        origin: &Origin,
+        commands: &[String],
+        working_dir: Option<&Path>,
+        succeeded: bool,
+    ) -> Self {
+        let current = std::env::current_dir().unwrap_or_default();
+        let workdir = match working_dir {
+            Some(dir) => current.join(dir),
+            None => current,
+        };
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            workdir: workdir.canonicalize().unwrap_or(workdir),
+            tool: origin.tool.to_string(),
+            prompt: origin.prompt.clone(),
+            commands: commands.to_vec(),
+            rationales: Vec::new(),
+            exit_codes: Vec::new(),
+            response: None,
+            succeeded,
+        }
+    }
+
+    /// Save the model's `response` with the commands, unless `origin` says not to
+    pub fn with_response(mut self, origin: &Origin, response: &str) -> Self {
+        if origin.keep_response {
+            self.response = Some(response.to_string());
+        }
+        self
+    }
+
+    /// Save each command's exit code from the `result` of running them in order:
+    /// all 0 on success, and on a failed command its code, with the commands after
+    /// it never run. Other failures leave the exit codes unknown.
+    pub fn with_exit_codes(mut self, result: &Result<()>) -> Self {
+        let failed_at = match result {
+            Ok(()) => None,
+            Err(e) => {
+                let Some(failed) = e.downcast_ref::<CommandFailed>() else {
+                    return self;
+                };
+                let Some(at) = self.commands.iter().position(|cmd| *cmd == failed.command) else {
+                    return self;
+                };
+                Some((at, failed.exit_code))
+            }
+        };
+        self.exit_codes = (0..self.commands.len())
+            .map(|i| match failed_at {
+                Some((at, _)) if i > at => None,
+                Some((at, code)) if i == at => Some(code),
+                _ => Some(0),
+            })
+            .collect();
+        self
+    }
+
+    /// The exit code recorded for the command at `index`, if it ran
+    pub fn exit_code(&self, index: usize) -> Option<i32> {
+        self.exit_codes.get(index).copied().flatten()
+    }
+
+    /// Save `rationales` next to the commands; a list without any is dropped
+    pub fn with_rationales(mut self, rationales: Vec<Option<String>>) -> Self {
+        if rationales.iter().any(Option::is_some) {
+            self.rationales = rationales;
+        }
+        self
+    }
+
+    /// The rationale recorded for the command at `index`, if any
+    pub fn rationale(&self, index: usize) -> Option<&str> {
+        self.rationales.get(index)?.as_deref()
+    }
+}
+
+pub fn history_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".terminalai").join("history.jsonl"))
+}
+
+/// Append `entry` to the history; failures are reported on stderr rather than
+/// failing the run that produced it
+pub fn record(entry: &HistoryEntry) {
+    let Some(path) = history_path() else {
+        return;
+    };
+    if let Err(e) = append_to(&path, entry) {
+        eprintln!("⚠️  Failed to save history: {e:#}");
+    }
+}
+
+pub fn append_to(path: &Path, entry: &HistoryEntry) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Every entry in the history at `path`, oldest first; a missing file is an empty
+/// history and unreadable lines are skipped
+pub fn read_from(path: &Path) -> Result<Vec<HistoryEntry>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Every entry with its id, its 1-based position in the history
+pub fn numbered(entries: &[HistoryEntry]) -> Vec<(usize, &HistoryEntry)> {
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, e)| (i + 1, e))
+        .collect()
+}
+
+/// The entry with `id`, as shown by `tai history`
+pub fn find(entries: &[HistoryEntry], id: usize) -> Option<&HistoryEntry> {
+    entries.get(id.checked_sub(1)?)
+}
+
+/// The entries recorded in `dir` or a directory below it, with their ids
+pub fn recorded_under<'a>(
+    entries: &'a [HistoryEntry],
+    dir: &Path,
+) -> Vec<(usize, &'a HistoryEntry)> {
+    numbered(entries)
+        .into_iter()
+        .filter(|(_, entry)| entry.workdir.starts_with(dir))
+        .collect()
+}
+
+/// `entry` for `tai history`: its id, age, tool, prompt and commands
+pub fn render_entry(id: usize, entry: &HistoryEntry, now: u64) -> String {
+    let status = if entry.succeeded { "✅" } else { "❌" };
+    let mut text = format!(
+        "#{id} {status} {} · {} · {}\n   {}\n",
+        render_age(now.saturating_sub(entry.timestamp)),
+        entry.tool,
+        entry.workdir.display(),
+        entry.prompt
+    );
+    for (i, cmd) in entry.commands.iter().enumerate() {
+        text.push_str(&format!("     $ {cmd}\n"));
+        if let Some(why) = entry.rationale(i) {
+            text.push_str(&format!("       ↳ {why}\n"));
+        }
+    }
+    text
+}
+
+/// `entry` for `tai history show`: like [`render_entry`], with each command's exit
+/// code and the model's response
+pub fn render_details(id: usize, entry: &HistoryEntry, now: u64) -> String {
+    let status = if entry.succeeded { "✅" } else { "❌" };
+    let mut text = format!(
+        "#{id} {status} {} · {} · {}\n   {}\n",
+        render_age(now.saturating_sub(entry.timestamp)),
+        entry.tool,
+        entry.workdir.display(),
+        entry.prompt
+    );
+    for (i, cmd) in entry.commands.iter().enumerate() {
+        text.push_str(&format!("     $ {cmd}\n"));
+        if let Some(why) = entry.rationale(i) {
+            text.push_str(&format!("       ↳ {why}\n"));
+        }
+        match entry.exit_code(i) {
+            Some(code) => text.push_str(&format!("       exit code {code}\n")),
+            None if !entry.exit_codes.is_empty() => text.push_str("       not run\n"),
+            None => {}
+        }
+    }
+    if let Some(response) = &entry.response {
+        text.push_str("\n   Model response:\n");
+        for line in response.lines() {
+            text.push_str(&format!("     {line}\n"));
+        }
+    }
+    text
+}
+
+fn render_age(seconds: u64) -> String {
+    match seconds {
+        0..60 => "just now".to_string(),
+        60..3_600 => format!("{}m ago", seconds / 60),
+        3_600..86_400 => format!("{}h ago", seconds / 3_600),
+        _ => format!("{}d ago", seconds / 86_400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(workdir: &str, prompt: &str) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: 1_000,
+            workdir: PathBuf::from(workdir),
+            tool: "cp_ai".to_string(),
+            prompt: prompt.to_string(),
+            commands: vec!["cp a b".to_string()],
+            rationales: Vec::new(),
+            exit_codes: Vec::new(),
+            response: None,
+            succeeded: true,
+        }
+    }
+
+    #[test]
+    fn test_entries_record_working_directory() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let project = temp_dir.path().join("project");
+        std::fs::create_dir(&project).unwrap();
+
+        let origin = Origin::new("find_ai", "find logs");
+        let entry = HistoryEntry::new(&origin, &["ls".to_string()], Some(&project), false);
+        assert_eq!(entry.workdir, project.canonicalize().unwrap());
+        assert_eq!(entry.tool, "find_ai");
+        assert!(!entry.succeeded);
+
+        let path = temp_dir.path().join("history.jsonl");
+        append_to(&path, &entry).unwrap();
+        std::fs::write(
+            &path,
+            std::fs::read_to_string(&path).unwrap() + "not json\n",
+        )
+        .unwrap();
+        append_to(&path, &entry).unwrap();
+        assert_eq!(read_from(&path).unwrap(), vec![entry.clone(), entry]);
+        assert!(read_from(&temp_dir.path().join("missing.jsonl"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_redacted_prompts_are_not_stored() {
+        let prompt = "copy /home/dev/secrets/token.txt to the backup server";
+        let origin = Origin::new("cp_ai", &PromptStorage::Hash.store(prompt));
+        let entry = HistoryEntry::new(&origin, &["cp a b".to_string()], None, true);
+        assert_eq!(
+            entry.prompt,
+            "sha256:b58a0c00c86859b3be11c54006b2884e829910b6b9204b824833c682f382be3d"
+        );
+        assert_eq!(entry.commands, vec!["cp a b"]);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("history.jsonl");
+        append_to(&path, &entry).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains("secrets"));
+        assert!(saved.contains(&prompt_hash(prompt)));
+
+        assert_eq!(
+            PromptStorage::Summary.store(prompt),
+            "[redacted prompt, 6 words]"
+        );
+        assert_eq!(PromptStorage::Full.store(prompt), prompt);
+
+        // Prompts replayed from the history are not hashed again
+        let hashed = PromptStorage::Hash.store(prompt);
+        assert_eq!(PromptStorage::Hash.store(&hashed), hashed);
+        assert_eq!(PromptStorage::Summary.store(&hashed), hashed);
+        let summary = PromptStorage::Summary.store(prompt);
+        assert_eq!(PromptStorage::Hash.store(&summary), summary);
+        assert_ne!(PromptStorage::Hash.store("sha256:abc"), "sha256:abc");
+    }
+
+    #[test]
+    fn test_recorded_under_filters_by_directory_tree() {
+        let entries = vec![
+            entry("/home/dev/api", "copy configs"),
+            entry("/home/dev/api/src", "find handlers"),
+            entry("/home/dev/api-old", "copy backups"),
+            entry("/home/dev/web", "grep TODOs"),
+        ];
+        let found: Vec<(usize, &str)> = recorded_under(&entries, Path::new("/home/dev/api"))
+            .iter()
+            .map(|(id, entry)| (*id, entry.prompt.as_str()))
+            .collect();
+        assert_eq!(found, vec![(1, "copy configs"), (2, "find handlers")]);
+        assert_eq!(find(&entries, 4).unwrap().prompt, "grep TODOs");
+        assert!(find(&entries, 0).is_none());
+        assert!(find(&entries, 5).is_none());
+    }
+
+    #[test]
+    fn test_render_entry() {
+        assert_eq!(
+            render_entry(3, &entry("/home/dev/api", "copy configs"), 1_000 + 7_200),
+            "#3 ✅ 2h ago · cp_ai · /home/dev/api\n   copy configs\n     $ cp a b\n"
+        );
+    }
+
+    #[test]
+    fn test_entries_keep_rationale_per_command() {
+        let origin = Origin::new("tai", "back up the configs");
+        let commands = vec![
+            "mkdir -p backup".to_string(),
+            "cp *.conf backup/".to_string(),
+        ];
+        let entry = HistoryEntry::new(&origin, &commands, None, true)
+            .with_rationales(vec![Some("Create the backup folder".to_string()), None]);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("history.jsonl");
+        append_to(&path, &entry).unwrap();
+        let saved = read_from(&path).unwrap();
+        assert_eq!(saved, vec![entry.clone()]);
+        assert_eq!(saved[0].rationale(0), Some("Create the backup folder"));
+        assert_eq!(saved[0].rationale(1), None);
+        assert!(render_entry(1, &saved[0], saved[0].timestamp).contains(
+            "     $ mkdir -p backup\n       ↳ Create the backup folder\n     $ cp *.conf backup/\n"
+        ));
+
+        // Without any rationale nothing is written, and older entries still load
+        let bare =
+            HistoryEntry::new(&origin, &commands, None, true).with_rationales(vec![None, None]);
+        assert!(!serde_json::to_string(&bare).unwrap().contains("rationales"));
+        let old: HistoryEntry = serde_json::from_str(
+            r#"{"timestamp":1,"workdir":"/tmp","tool":"tai","prompt":"p","commands":["ls"],"succeeded":true}"#,
+        )
+        .unwrap();
+        assert!(old.rationales.is_empty());
+        assert_eq!(old.rationale(0), None);
+    }
+
+    #[test]
+    fn test_entries_keep_exit_codes_and_response() {
+        let origin = Origin::new("tai", "rebuild");
+        let commands = vec![
+            "make clean".to_string(),
+            "make".to_string(),
+            "make install".to_string(),
+        ];
+        let failed: Result<()> = Err(anyhow::Error::new(CommandFailed {
+            command: "make".to_string(),
+            exit_code: 2,
+        })
+        .context("Step 2 failed"));
+        let entry = HistoryEntry::new(&origin, &commands, None, false)
+            .with_exit_codes(&failed)
+            .with_response(
+                &origin,
+                "COMMAND: make clean\nCOMMAND: make\nCOMMAND: make install",
+            );
+        assert_eq!(entry.exit_codes, vec![Some(0), Some(2), None]);
+        assert_eq!(
+            render_details(7, &entry, entry.timestamp),
+            format!(
+                "#7 ❌ just now · tai · {}\n   rebuild\n     $ make clean\n       exit code 0\n     $ make\n       exit code 2\n     $ make install\n       not run\n\n   Model response:\n     COMMAND: make clean\n     COMMAND: make\n     COMMAND: make install\n",
+                entry.workdir.display()
+            )
+        );
+
+        let ok = HistoryEntry::new(&origin, &commands, None, true).with_exit_codes(&Ok(()));
+        assert_eq!(ok.exit_codes, vec![Some(0); 3]);
+        let declined = HistoryEntry::new(&origin, &commands, None, false)
+            .with_exit_codes(&Err(anyhow::anyhow!("Package command not confirmed: make")));
+        assert!(declined.exit_codes.is_empty());
+
+        // A redacted prompt's response is not kept
+        let redacted = Origin::new("tai", "sha256:abc").without_response();
+        let entry =
+            HistoryEntry::new(&redacted, &commands, None, true).with_response(&redacted, "make");
+        assert_eq!(entry.response, None);
+    }
+}
@@ -1,22 +1,72 @@
 use anyhow::{Context, Result};
-use colored::*;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
-
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub mod attachment;
+pub mod batch;
+pub mod branding;
+pub mod cache;
+pub mod category;
+pub mod chat;
+pub mod cli;
 pub mod command_parser;
 pub mod command_validator;
 pub mod config;
+pub mod context;
+pub mod copy_verify;
+pub mod cost;
+pub mod doctor;
+pub mod executor;
+pub mod explain;
+pub mod extractor;
+pub mod git_summary;
+pub mod history;
+pub mod injection;
 pub mod orchestrator;
+pub mod plans;
+pub mod policy;
 pub mod providers;
 pub mod query_provider;
+pub mod registry;
+pub mod risk;
+pub mod runtime;
+pub mod scaffold;
+pub mod script;
+pub mod search_scope;
+pub mod selection;
+pub mod shell;
+#[cfg(test)]
+mod test_support;
+pub mod timing;
+pub mod transcript;
+pub mod ui;
+pub mod variables;
+
+pub use extractor::set_allowed_command_prefixes;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TerminalAIConfig {
     pub active_provider: String,
     pub providers: std::collections::HashMap<String, providers::ProviderConfig>,
+    /// Settings outside any provider section, e.g. `branding = false`
+    #[serde(default)]
+    pub settings: std::collections::HashMap<String, String>,
+    /// System prompts replacing the commands' own for particular models, keyed by
+    /// model name; a value starting with `@` names a file holding the prompt
+    #[serde(default)]
+    pub system_prompt_overrides: std::collections::HashMap<String, String>,
+    /// Allow and deny patterns for commands, merged with `~/.terminalai/policy.toml`
+    #[serde(default)]
+    pub policy: policy::PolicyConfig,
 }
 
+/// `.conf` section holding [`TerminalAIConfig::system_prompt_overrides`]
+pub const SYSTEM_PROMPT_OVERRIDES_SECTION: &str = "system_prompt_overrides";
+
+/// `.conf` section whose keys are read as `execution.<key>` settings, e.g. `execution.shell`
+pub const EXECUTION_SECTION: &str = "execution";
+
 impl Default for TerminalAIConfig {
     fn default() -> Self {
         let mut providers = std::collections::HashMap::new();
@@ -36,6 +86,16 @@ impl Default for TerminalAIConfig {
             providers::ProviderConfig::new_openai("".to_string(), "gpt-3.5-turbo".to_string(), 30),
         );
 
+        providers.insert(
+            "openai_compatible".to_string(),
+            providers::ProviderConfig::new_openai_compatible(
+                "".to_string(),
+                None,
+                "".to_string(),
+                30,
+            ),
+        );
+
         providers.insert(
             "claude".to_string(),
             providers::ProviderConfig::new_claude(
@@ -58,6 +118,9 @@ impl Default for TerminalAIConfig {
         Self {
             active_provider: "ollama".to_string(),
             providers,
+            settings: std::collections::HashMap::new(),
+            system_prompt_overrides: std::collections::HashMap::new(),
+            policy: policy::PolicyConfig::default(),
         }
     }
 }
@@ -83,11 +146,239 @@ impl TerminalAIConfig {
     pub fn get_provider_names(&self) -> Vec<String> {
         self.providers.keys().cloned().collect()
     }
+
+    pub fn get_setting(&self, key: &str) -> Option<&String> {
+        self.settings.get(key)
+    }
+
+    /// Read a boolean setting, accepting true/false, yes/no, on/off and 1/0
+    pub fn get_bool_setting(&self, key: &str, default: bool) -> bool {
+        match self.get_setting(key).map(|v| v.trim().to_lowercase()) {
+            Some(v) if matches!(v.as_str(), "true" | "yes" | "on" | "1") => true,
+            Some(v) if matches!(v.as_str(), "false" | "no" | "off" | "0") => false,
+            _ => default,
+        }
+    }
+
+    /// Output that signals a failure even on exit code 0, from the comma-separated
+    /// `error_signatures` setting; empty (detection off) unless configured
+    pub fn error_signatures(&self) -> Vec<String> {
+        self.get_setting("error_signatures")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Point the active provider at `url` for this run, e.g. from `--url`
+    pub fn override_url(&mut self, url: &str) -> Result<()> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(anyhow::anyhow!(
+                "Invalid URL '{url}': expected http:// or https://"
+            ));
+        }
+        let active = self.active_provider.clone();
+        let provider = self
+            .providers
+            .get_mut(&active)
+            .with_context(|| format!("Active provider '{active}' is not configured"))?;
+        let key = provider.url_key().with_context(|| {
+            format!("Provider '{active}' runs locally and has no URL to override")
+        })?;
+        provider
+            .settings
+            .insert(key.to_string(), url.trim_end_matches('/').to_string());
+        Ok(())
+    }
+
+    /// The system prompt configured for the active provider's model, if any,
+    /// reading it from the file when the override is `@path`
+    pub fn system_prompt_override(&self) -> Result<Option<String>> {
+        let Some(model) = self
+            .get_active_provider()
+            .and_then(|provider| provider.get_setting("model"))
+        else {
+            return Ok(None);
+        };
+        let Some(prompt) = self.system_prompt_overrides.get(model) else {
+            return Ok(None);
+        };
+        match prompt.strip_prefix('@') {
+            Some(path) => std::fs::read_to_string(path.trim())
+                .map(Some)
+                .with_context(|| {
+                    format!("Failed to read the system prompt override for '{model}' from {path}")
+                }),
+            None => Ok(Some(prompt.replace("\\n", "\n"))),
+        }
+    }
+
+    /// `dedup_commands = "all"` also drops repeats that are not back to back
+    pub fn command_dedup(&self) -> Dedup {
+        match self.get_setting("dedup_commands").map(|v| v.trim()) {
+            Some("all") => Dedup::All,
+            _ => Dedup::Consecutive,
+        }
+    }
+
+    /// Whether package management commands get the `[Terminal AI]` banners (`branding`)
+    pub fn branding_enabled(&self) -> bool {
+        self.get_bool_setting("branding", true)
+    }
+
+    /// Language explanations are written in (`output_language`); English when unset
+    pub fn output_language(&self) -> Option<String> {
+        self.get_setting("output_language")
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Seconds a confirmation waits before resolving to `confirm_default`
+    /// (`confirm_timeout_seconds`); `None` waits forever
+    pub fn confirm_timeout_seconds(&self) -> Option<u64> {
+        self.get_setting("confirm_timeout_seconds")
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .filter(|&seconds| seconds > 0)
+    }
+
+    /// Answer assumed when a confirmation times out (`confirm_default`); no unless set
+    pub fn confirm_default(&self) -> bool {
+        self.get_bool_setting("confirm_default", false)
+    }
+
+    /// Whether executed commands are saved for `tai history` (`history`)
+    pub fn history_enabled(&self) -> bool {
+        self.get_bool_setting("history", true)
+    }
+
+    /// How prompts are saved in the history (`history_prompt`): `full` unless set to
+    /// `hash` or `summary`. Other values are reported by [`Self::setting_problems`].
+    pub fn history_prompt(&self) -> history::PromptStorage {
+        self.get_setting("history_prompt")
+            .and_then(|value| history::PromptStorage::from_setting(value))
+            .unwrap_or_default()
+    }
+
+    /// Top-level settings whose values are not understood, e.g. a misspelled
+    /// `history_prompt` that would otherwise save prompts in full without a word
+    pub fn setting_problems(&self) -> Vec<String> {
+        self.get_setting("history_prompt")
+            .filter(|value| history::PromptStorage::from_setting(value).is_none())
+            .map(|value| {
+                format!(
+                    "history_prompt must be \"full\", \"hash\" or \"summary\", not \"{value}\"; prompts are saved in full"
+                )
+            })
+            .into_iter()
+            .collect()
+    }
+
+    /// What `tool` saves to the history for `prompt`, `None` when history is off
+    pub fn history_origin(&self, tool: &'static str, prompt: &str) -> Option<history::Origin> {
+        let storage = self.history_prompt();
+        self.history_enabled().then(|| {
+            let origin = history::Origin::new(tool, &storage.store(prompt));
+            if storage == history::PromptStorage::Full {
+                origin
+            } else {
+                origin.without_response()
+            }
+        })
+    }
+
+    /// Whether package installs, updates and removals run without a second, per-command
+    /// confirmation (`auto_execute_installs`)
+    pub fn auto_execute_installs(&self) -> bool {
+        self.get_bool_setting("auto_execute_installs", false)
+    }
+
+    /// Whether each suggested command is confirmed on its own instead of all at once
+    /// (`confirm_each_command`)
+    pub fn confirm_each_command(&self) -> bool {
+        self.get_bool_setting("confirm_each_command", false)
+    }
+
+    /// Whether `tai -p` runs its plan in a directory the prompt names (`infer_directory`)
+    pub fn infer_directory(&self) -> bool {
+        self.get_bool_setting("infer_directory", true)
+    }
+
+    /// How often `tai -p` asks the model to replace a failed step before it stops
+    /// (`replan_attempts`); 0 stops at the first failure
+    pub fn replan_attempts(&self) -> u32 {
+        self.get_setting("replan_attempts")
+            .and_then(|value| value.trim().parse::<u32>().ok())
+            .unwrap_or(DEFAULT_REPLAN_ATTEMPTS)
+    }
+
+    /// Comma-separated `allowed_command_prefixes`: when set, the only commands
+    /// extraction accepts, replacing the built-in prefix list, and the only ones
+    /// any path runs
+    pub fn allowed_command_prefixes(&self) -> Option<Vec<String>> {
+        let prefixes: Vec<String> = self
+            .get_setting("allowed_command_prefixes")?
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect();
+        (!prefixes.is_empty()).then_some(prefixes)
+    }
+
+    /// Most commands a single plan may have (`max_commands_per_plan`); 0 lifts the limit
+    pub fn max_commands_per_plan(&self) -> usize {
+        self.get_setting("max_commands_per_plan")
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_COMMANDS_PER_PLAN)
+    }
+
+    /// Whether dangerous commands are refused instead of asked about
+    /// (`dangerous_commands = "block"`; the default is `confirm`)
+    pub fn block_dangerous_commands(&self) -> bool {
+        self.get_setting("dangerous_commands")
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("block"))
+    }
 }
 
 pub fn get_config_path() -> Result<PathBuf> {
     let home_dir = dirs::home_dir().context("Failed to find home directory")?;
-    Ok(home_dir.join(".terminalai").join("config.json"))
+
+    // Linux and other Unix systems follow the XDG base directory spec;
+    // macOS and Windows use their native per-user config directories
+    let config_dir = if cfg!(all(unix, not(target_os = "macos"))) {
+        xdg_config_dir(
+            std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from),
+            &home_dir,
+        )
+    } else {
+        dirs::config_dir().unwrap_or_else(|| home_dir.join(".config"))
+    };
+
+    Ok(resolve_config_path(&config_dir, &home_dir))
+}
+
+/// Base config directory per the XDG spec: `$XDG_CONFIG_HOME` when it is set to an
+/// absolute path, otherwise `~/.config`
+fn xdg_config_dir(xdg_config_home: Option<PathBuf>, home_dir: &Path) -> PathBuf {
+    match xdg_config_home {
+        Some(dir) if dir.is_absolute() => dir,
+        _ => home_dir.join(".config"),
+    }
+}
+
+/// Pick the config file inside `config_dir`, unless a legacy `~/.terminalai/config.json`
+/// already exists, in which case keep using it for backward compatibility
+fn resolve_config_path(config_dir: &Path, home_dir: &Path) -> PathBuf {
+    let legacy_path = home_dir.join(".terminalai").join("config.json");
+    if legacy_path.exists() {
+        return legacy_path;
+    }
+    config_dir.join("terminalai").join("config.json")
 }
 
 pub fn get_local_config_path() -> Result<PathBuf> {
@@ -135,7 +426,23 @@ pub fn load_config_from_conf(path: &PathBuf) -> Result<TerminalAIConfig> {
             let key = line[..eq_pos].trim();
             let value = line[eq_pos + 1..].trim().trim_matches('"');
 
-            if !current_section.is_empty() {
+            if current_section.is_empty() {
+                // Top-level settings apply to Terminal AI as a whole
+                config.settings.insert(key.to_string(), value.to_string());
+            } else if current_section == EXECUTION_SECTION {
+                // `[execution]` keys are saved back as top-level `execution.<key>` settings
+                config
+                    .settings
+                    .insert(format!("{EXECUTION_SECTION}.{key}"), value.to_string());
+            } else if current_section == SYSTEM_PROMPT_OVERRIDES_SECTION {
+                // Model names such as "llama3.2:1b" are usually quoted
+                config
+                    .system_prompt_overrides
+                    .insert(key.trim_matches('"').to_string(), value.to_string());
+            } else if current_section == policy::POLICY_SECTION {
+                // Unknown keys are reported by `tai config validate`
+                let _ = config.policy.insert(key, value);
+            } else {
                 // Update the provider config for this section
                 if let Some(provider_config) = config.providers.get_mut(&current_section) {
                     match key {
@@ -163,49 +470,103 @@ pub fn load_config_from_conf(path: &PathBuf) -> Result<TerminalAIConfig> {
     Ok(config)
 }
 
+/// Environment variable holding a complete JSON config, used ahead of any config
+/// file, e.g. injected from a CI secret
+pub const CONFIG_ENV_VAR: &str = "TERMINALAI_CONFIG";
+
 pub fn load_config() -> Result<TerminalAIConfig> {
+    let local_config_path = get_local_config_path().ok();
+    let config = load_layered_config(
+        std::env::var(CONFIG_ENV_VAR).ok().as_deref(),
+        local_config_path.as_deref(),
+        &get_config_path()?,
+    )?;
+    // Some commands load the config more than once; one warning is enough
+    static WARNED: std::sync::Once = std::sync::Once::new();
+    WARNED.call_once(|| {
+        for problem in config.setting_problems() {
+            eprintln!("⚠️  {problem}");
+        }
+    });
+    Ok(config)
+}
+
+/// The config from `env_value` (the [`CONFIG_ENV_VAR`] value) unless it is unset or
+/// blank, else from the files as [`load_config_from`] finds them
+fn load_layered_config(
+    env_value: Option<&str>,
+    local_config_path: Option<&Path>,
+    config_path: &Path,
+) -> Result<TerminalAIConfig> {
+    match env_value.filter(|value| !value.trim().is_empty()) {
+        Some(value) => config_from_env(value),
+        None => load_config_from(local_config_path, config_path),
+    }
+}
+
+/// Load the `.conf` at `local_config_path` when it exists, else the JSON at `config_path`
+pub fn load_config_from(
+    local_config_path: Option<&Path>,
+    config_path: &Path,
+) -> Result<TerminalAIConfig> {
     // First, try to load from local .conf file (next to executable)
-    if let Ok(local_config_path) = get_local_config_path() {
+    if let Some(local_config_path) = local_config_path {
         if local_config_path.exists() {
-            return load_config_from_conf(&local_config_path);
+            return load_config_from_conf(&local_config_path.to_path_buf());
         }
     }
 
     // Fallback to JSON config in user config directory
-    let config_path = get_config_path()?;
     if config_path.exists() {
         let config_content =
-            std::fs::read_to_string(&config_path).context("Failed to read config file")?;
-
-        // Try to parse as new multi-provider format first
-        if let Ok(config) = serde_json::from_str::<TerminalAIConfig>(&config_content) {
+            std::fs::read_to_string(config_path).context("Failed to read config file")?;
+        if let Some(config) = parse_json_config(&config_content) {
             return Ok(config);
         }
+    }
 
-        // If that fails, try to parse as old single-provider format and migrate
-        if let Ok(old_config) = serde_json::from_str::<OldTerminalAIConfig>(&config_content) {
-            let mut new_config = TerminalAIConfig::default();
-
-            // Determine provider name based on type
-            let provider_name = match old_config.provider.provider_type {
-                providers::ProviderType::Ollama => "ollama",
-                providers::ProviderType::OpenAI => "openai",
-                providers::ProviderType::Claude => "claude",
-                providers::ProviderType::Gemini => "gemini",
-                providers::ProviderType::Local => "local",
-            };
+    // Return default if no config exists
+    Ok(TerminalAIConfig::default())
+}
 
-            new_config.active_provider = provider_name.to_string();
-            new_config
-                .providers
-                .insert(provider_name.to_string(), old_config.provider);
+/// The config in the value of [`CONFIG_ENV_VAR`]
+pub fn config_from_env(value: &str) -> Result<TerminalAIConfig> {
+    // The value is often a secret, so it is not echoed in the error
+    parse_json_config(value)
+        .with_context(|| format!("{CONFIG_ENV_VAR} does not contain a valid JSON config"))
+}
 
-            return Ok(new_config);
-        }
+/// A JSON config in the multi-provider format, or migrated from the old single-provider one
+fn parse_json_config(content: &str) -> Option<TerminalAIConfig> {
+    // Try to parse as new multi-provider format first
+    if let Ok(config) = serde_json::from_str::<TerminalAIConfig>(content) {
+        return Some(config);
     }
 
-    // Return default if no config exists
-    Ok(TerminalAIConfig::default())
+    // If that fails, try to parse as old single-provider format and migrate
+    if let Ok(old_config) = serde_json::from_str::<OldTerminalAIConfig>(content) {
+        let mut new_config = TerminalAIConfig::default();
+
+        // Determine provider name based on type
+        let provider_name = match &old_config.provider.provider_type {
+            providers::ProviderType::Ollama => "ollama",
+            providers::ProviderType::OpenAI => "openai",
+            providers::ProviderType::OpenAICompatible => "openai_compatible",
+            providers::ProviderType::Claude => "claude",
+            providers::ProviderType::Gemini => "gemini",
+            providers::ProviderType::Local => "local",
+            providers::ProviderType::Custom(name) => name.as_str(),
+        }
+        .to_string();
+
+        new_config.active_provider = provider_name.clone();
+        new_config
+            .providers
+            .insert(provider_name, old_config.provider);
+
+        return Some(new_config);
+    }
+    None
 }
 
 // Old config format for migration
@@ -236,9 +597,13 @@ pub fn save_config_to_conf(config: &TerminalAIConfig, path: &PathBuf) -> Result<
 
     // Write active provider
     content.push_str(&format!(
-        "active_provider = \"{}\"\n\n",
+        "active_provider = \"{}\"\n",
         config.active_provider
     ));
+    for (key, value) in &config.settings {
+        content.push_str(&format!("{key} = \"{value}\"\n"));
+    }
+    content.push('\n');
 
     // Write each provider section
     for (provider_name, provider_config) in &config.providers {
@@ -260,13 +625,39 @@ pub fn save_config_to_conf(config: &TerminalAIConfig, path: &PathBuf) -> Result<
         ));
     }
 
+    if !config.system_prompt_overrides.is_empty() {
+        content.push_str(&format!("[{SYSTEM_PROMPT_OVERRIDES_SECTION}]\n"));
+        for (model, prompt) in &config.system_prompt_overrides {
+            content.push_str(&format!(
+                "\"{model}\" = \"{}\"\n",
+                prompt.replace('\n', "\\n")
+            ));
+        }
+    }
+    content.push_str(&render_policy_section(&config.policy));
+
     std::fs::write(path, content).context("Failed to write config file")?;
     Ok(())
 }
 
+/// `policy` as a `[policy]` section with one line per pattern, or nothing when empty
+pub fn render_policy_section(policy: &policy::PolicyConfig) -> String {
+    if policy.is_empty() {
+        return String::new();
+    }
+    let mut content = format!("\n[{}]\n", policy::POLICY_SECTION);
+    for pattern in &policy.allow {
+        content.push_str(&format!("allow = \"{pattern}\"\n"));
+    }
+    for pattern in &policy.deny {
+        content.push_str(&format!("deny = \"{pattern}\"\n"));
+    }
+    content
+}
+
 /// Fix find commands that use -exec with + terminator
 /// The + terminator doesn't work well when passed through sh -c, so we convert it to ;
-fn fix_find_exec_command(cmd: &str) -> String {
+pub(crate) fn fix_find_exec_command(cmd: &str) -> String {
     // Check if this is a find command with -exec that ends with +
     if cmd.trim_start().starts_with("find ")
         && cmd.contains("-exec")
@@ -286,49 +677,252 @@ fn fix_find_exec_command(cmd: &str) -> String {
     cmd.to_string()
 }
 
-pub fn extract_commands_from_response(ai_response: &str) -> Vec<String> {
-    // Look for command patterns in the AI response
-    let lines: Vec<&str> = ai_response.lines().collect();
-    let mut commands_to_execute = Vec::new();
+/// Tags reasoning models use to wrap their chain of thought
+pub(crate) const REASONING_TAGS: [(&str, &str); 2] =
+    [("<think>", "</think>"), ("<thinking>", "</thinking>")];
 
-    for line in lines {
-        let trimmed = line.trim();
+/// Split a response into the answer and any `<think>...</think>` reasoning it contains.
+///
+/// An unterminated opening tag is treated as reasoning up to the end of the response.
+pub fn split_reasoning(ai_response: &str) -> (String, Option<String>) {
+    let mut answer = String::new();
+    let mut reasoning = Vec::new();
+    let mut rest = ai_response;
 
-        // Look for code blocks or command patterns
-        if trimmed.starts_with("```bash") || trimmed.starts_with("```") {
-            continue;
-        }
-        if trimmed == "```" {
-            continue;
+    loop {
+        let next_tag = REASONING_TAGS
+            .iter()
+            .filter_map(|(open, close)| rest.find(open).map(|pos| (pos, *open, *close)))
+            .min_by_key(|(pos, _, _)| *pos);
+
+        let Some((start, open, close)) = next_tag else {
+            answer.push_str(rest);
+            break;
+        };
+
+        answer.push_str(&rest[..start]);
+        let inner = &rest[start + open.len()..];
+        match inner.find(close) {
+            Some(end) => {
+                reasoning.push(inner[..end].trim().to_string());
+                rest = &inner[end + close.len()..];
+            }
+            None => {
+                reasoning.push(inner.trim().to_string());
+                break;
+            }
         }
+    }
+
+    let reasoning = reasoning
+        .into_iter()
+        .filter(|r| !r.is_empty())
+        .collect::<Vec<_>>();
+    let reasoning = (!reasoning.is_empty()).then(|| reasoning.join("\n\n"));
+    (answer.trim().to_string(), reasoning)
+}
+
+/// `system_prompt` for an explanation-type query, asking for the answer in `language`
+/// while commands stay shell syntax
+pub fn localized_prompt(system_prompt: &str, language: Option<&str>) -> String {
+    match language {
+        Some(language) => format!(
+            "{system_prompt} Respond in {language}, but keep commands, file names and flags exactly as they are."
+        ),
+        None => system_prompt.to_string(),
+    }
+}
+
+/// The response with any reasoning blocks removed
+pub fn strip_reasoning(ai_response: &str) -> String {
+    split_reasoning(ai_response).0
+}
+
+/// Print the model's reasoning, if the response contains any
+pub fn print_reasoning(ai_response: &str) {
+    if let (_, Some(reasoning)) = split_reasoning(ai_response) {
+        println!("🧠 Model reasoning:\n{reasoning}\n");
+    }
+}
+
+/// Failed steps `tai -p` asks the model to replace when `replan_attempts` is not set
+pub const DEFAULT_REPLAN_ATTEMPTS: u32 = 3;
+
+/// Plans longer than this are refused unless `max_commands_per_plan` says otherwise
+pub const DEFAULT_MAX_COMMANDS_PER_PLAN: usize = 50;
+
+static MAX_COMMANDS_PER_PLAN: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_COMMANDS_PER_PLAN);
 
-        // Look for actual commands (starting with common command prefixes)
-        if trimmed.starts_with("cp ")
-            || trimmed.starts_with("grep ")
-            || trimmed.starts_with("find ")
-            || trimmed.starts_with("ps ")
-            || trimmed.starts_with("mkdir ")
-            || trimmed.starts_with("npm ")
-            || trimmed.starts_with("pip ")
-            || trimmed.starts_with("python -m pip ")
-            || trimmed.starts_with("conda ")
-            || trimmed.starts_with("pyenv ")
-            || trimmed.starts_with("nvm ")
-            || trimmed.starts_with("brew ")
-            || trimmed.starts_with("rm -rf ")
-            || trimmed.starts_with("yarn ")
-            || trimmed.starts_with("poetry ")
-            || trimmed.starts_with("pipenv ")
-        {
-            commands_to_execute.push(trimmed.to_string());
+/// Apply the config's `allowed_command_prefixes` and `max_commands_per_plan` to every
+/// later extraction, `dangerous_commands` to every later confirmation and the
+/// command policy to both
+pub fn configure_extraction(config: &TerminalAIConfig) -> Result<()> {
+    set_allowed_command_prefixes(config.allowed_command_prefixes());
+    set_max_commands_per_plan(config.max_commands_per_plan());
+    risk::set_block_dangerous(config.block_dangerous_commands());
+    policy::configure(config)
+}
+
+/// Refuse plans with more than `max` commands; 0 lifts the limit
+pub fn set_max_commands_per_plan(max: usize) {
+    MAX_COMMANDS_PER_PLAN.store(max, Ordering::Relaxed);
+}
+
+/// Fail when `commands` is more than the configured `max_commands_per_plan`, so a
+/// runaway answer is refused instead of shown as an unmanageable list
+pub fn check_plan_size(commands: &[String]) -> Result<()> {
+    check_command_count(
+        commands.len(),
+        MAX_COMMANDS_PER_PLAN.load(Ordering::Relaxed),
+    )
+}
+
+fn check_command_count(count: usize, max: usize) -> Result<()> {
+    if max > 0 && count > max {
+        anyhow::bail!(
+            "The AI suggested {count} commands, more than max_commands_per_plan ({max}); \
+             narrow the request and try again"
+        );
+    }
+    Ok(())
+}
+
+/// The commands in a model's answer, see [`extractor::extract`]; limited to the
+/// configured `allowed_command_prefixes` and command policy
+pub fn extract_commands_from_response(ai_response: &str) -> Vec<String> {
+    extractor::extract_commands(ai_response)
+        .into_iter()
+        .map(|extracted| extracted.command)
+        .collect()
+}
+
+/// [`extract_commands_from_response`] accepting only commands that start with one of
+/// `allowed`, when given; rejected commands are reported on stderr
+pub fn extract_commands_allowed(ai_response: &str, allowed: Option<&[String]>) -> Vec<String> {
+    extractor::extract(ai_response, allowed)
+        .into_iter()
+        .map(|extracted| extracted.command)
+        .collect()
+}
+
+/// Which repeated commands to drop from a model's answer (the `dedup_commands` setting)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dedup {
+    /// Only a command identical to the one right before it
+    #[default]
+    Consecutive,
+    /// Every repeat of an earlier command
+    All,
+}
+
+/// Drop repeated commands per `mode`, keeping the first occurrence
+pub fn dedup_commands(commands: Vec<String>, mode: Dedup) -> Vec<String> {
+    let mut kept: Vec<String> = Vec::with_capacity(commands.len());
+    for command in commands {
+        let repeated = match mode {
+            Dedup::Consecutive => kept.last() == Some(&command),
+            Dedup::All => kept.contains(&command),
+        };
+        if !repeated {
+            kept.push(command);
         }
     }
+    kept
+}
+
+/// Options controlling how extracted commands are executed
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionOptions {
+    /// Working directory for spawned commands; the process CWD when unset
+    pub working_dir: Option<PathBuf>,
+    /// Skip the per-command progress lines, for callers that print their own
+    pub quiet: bool,
+    /// Check that `cp` commands actually produced their destination files
+    pub verify_copies: Option<copy_verify::VerifyMode>,
+    /// Report the files created, modified and deleted in the git work tree
+    pub git_summary: bool,
+    /// Warn when a command exits 0 but prints one of these (the `error_signatures` setting)
+    pub error_signatures: Vec<String>,
+    /// Record each command and its output here (`--record`)
+    pub transcript: Option<transcript::SharedTranscript>,
+    /// Refuse commands flagged by the risk check instead of asking (`--strict`)
+    pub strict: bool,
+    /// Repeated commands to drop after extraction; consecutive ones always are
+    pub dedup: Dedup,
+    /// Save the executed commands to the history under this tool and prompt
+    pub history: Option<history::Origin>,
+    /// Run package installs, updates and removals without asking about each one
+    /// again (`auto_execute_installs`)
+    pub auto_execute_installs: bool,
+    /// Ask before each command, which may be skipped, instead of once for all of
+    /// them (`confirm_each_command`)
+    pub confirm_each: bool,
+}
 
-    commands_to_execute
+impl ExecutionOptions {
+    /// Options running commands in `dir` (the `-C/--directory` flag), which must exist
+    pub fn for_directory(dir: Option<&String>) -> Result<Self> {
+        let working_dir = match dir {
+            Some(dir) => {
+                let path = PathBuf::from(dir);
+                if !path.is_dir() {
+                    return Err(anyhow::anyhow!(
+                        "Directory '{}' does not exist or is not a directory",
+                        dir
+                    ));
+                }
+                Some(path)
+            }
+            None => None,
+        };
+        Ok(Self {
+            working_dir,
+            ..Default::default()
+        })
+    }
 }
 
 pub fn extract_and_execute_command(ai_response: &str) -> Result<()> {
-    let commands_to_execute = extract_commands_from_response(ai_response);
+    extract_and_execute_command_with_options(ai_response, &ExecutionOptions::default())
+}
+
+pub fn extract_and_execute_command_with_options(
+    ai_response: &str,
+    options: &ExecutionOptions,
+) -> Result<()> {
+    extract_and_execute_command_timed(ai_response, options, &mut timing::PhaseTimer::new())
+}
+
+/// Write the commands in `ai_response` to `output`, one per line, without running
+/// them (`--print`). Fails when there are none, so a script capturing the output
+/// doesn't go on with an empty command.
+pub fn print_commands(
+    ai_response: &str,
+    dedup: Dedup,
+    output: &mut impl std::io::Write,
+) -> Result<()> {
+    let commands = dedup_commands(extract_commands_from_response(ai_response), dedup);
+    if commands.is_empty() {
+        anyhow::bail!("No executable commands found in AI response");
+    }
+    check_plan_size(&commands)?;
+    for cmd in &commands {
+        writeln!(output, "{cmd}")?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// Like [`extract_and_execute_command_with_options`], recording the extraction and
+/// execution phases in `timer` for `--profile`
+pub fn extract_and_execute_command_timed(
+    ai_response: &str,
+    options: &ExecutionOptions,
+    timer: &mut timing::PhaseTimer,
+) -> Result<()> {
+    let commands_to_execute = timer.time("extraction", || {
+        dedup_commands(extract_commands_from_response(ai_response), options.dedup)
+    });
 
     if commands_to_execute.is_empty() {
         println!("⚠️  No executable commands found in AI response.");
@@ -336,33 +930,197 @@ pub fn extract_and_execute_command(ai_response: &str) -> Result<()> {
         println!("{ai_response}");
         return Ok(());
     }
+    confirm_and_execute_commands(commands_to_execute, Some(ai_response), options, timer)
+}
 
-    // Show commands to user and ask for confirmation
-    println!("Terminal AI suggest following commands:");
-    for (i, cmd) in commands_to_execute.iter().enumerate() {
-        println!("  {}. {}", i + 1, cmd);
+/// Preview `commands_to_execute`, ask whether to run them and run them. They are
+/// saved to the history with the model's `ai_response` they came from, if any.
+pub fn confirm_and_execute_commands(
+    mut commands_to_execute: Vec<String>,
+    ai_response: Option<&str>,
+    options: &ExecutionOptions,
+    timer: &mut timing::PhaseTimer,
+) -> Result<()> {
+    check_plan_size(&commands_to_execute)?;
+    policy::check(&commands_to_execute)?;
+
+    // Show commands to user and ask for confirmation, or to edit them first; with
+    // confirm_each only flagged commands are asked about up front
+    let messages = ui::messages();
+    ui::preview(&messages.suggested_commands, &commands_to_execute);
+    if options.confirm_each {
+        if !risk::confirm_flagged(&commands_to_execute, options.strict)? {
+            println!("{}", messages.not_executed);
+            return Ok(());
+        }
+    } else {
+        match confirm_or_edit_commands(
+            commands_to_execute,
+            options.strict,
+            &messages.confirm_execute,
+        )? {
+            Some(commands) => commands_to_execute = commands,
+            None => {
+                println!("{}", messages.not_executed);
+                return Ok(());
+            }
+        }
     }
 
-    print!("\n❓ Execute these commands? [Y/n]: ");
-    std::io::Write::flush(&mut std::io::stdout()).unwrap();
+    let git_tracker = if options.git_summary {
+        git_summary::GitTracker::start(options.working_dir.as_deref())
+    } else {
+        None
+    };
 
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input).unwrap();
+    // Execute commands with live output
+    let mut executed = Vec::new();
+    let result = timer.time("execution", || {
+        let mut copy_report = copy_verify::CopyReport::default();
+        let mut confirm_each = options.confirm_each;
+        for (i, cmd) in commands_to_execute.iter().enumerate() {
+            if confirm_each {
+                match ui::confirm_step(i + 1, cmd) {
+                    ui::Step::Run => {}
+                    ui::Step::Skip => {
+                        println!("⏭️  Skipped: {cmd}");
+                        continue;
+                    }
+                    ui::Step::All => confirm_each = false,
+                    ui::Step::Quit => {
+                        println!("🛑 Stopping; the remaining commands were not executed.");
+                        break;
+                    }
+                }
+            }
 
-    if input.trim().to_lowercase() == "n" || input.trim().to_lowercase() == "no" {
-        println!("❌ Commands not executed.");
-        return Ok(());
+            // Resolve what a cp will copy before it runs; globs may match differently afterwards
+            let copy_plan = match options.verify_copies {
+                Some(_) => copy_verify::plan_copy(cmd, options.working_dir.as_deref())?,
+                None => None,
+            };
+
+            if !copy_verify::confirm_overwrites(cmd, options.working_dir.as_deref())? {
+                println!("🛑 Stopping execution before overwriting existing files.");
+                return Err(anyhow::anyhow!("Not overwriting existing files: {cmd}"));
+            }
+            if !confirm_install(cmd, options.auto_execute_installs) {
+                println!("🛑 Stopping execution before the package command.");
+                return Err(anyhow::anyhow!("Package command not confirmed: {cmd}"));
+            }
+
+            executed.push(cmd.clone());
+            if let Err(e) = execute_command_with_options(cmd, options) {
+                println!("🛑 Stopping execution due to command failure.");
+                return Err(e);
+            }
+
+            if let (Some(mode), Some(plan)) = (options.verify_copies, copy_plan) {
+                copy_report.merge(copy_verify::verify_copy(&plan, mode));
+            }
+        }
+
+        if options.verify_copies.is_some() {
+            copy_report.print();
+            if !copy_report.is_ok() {
+                return Err(anyhow::anyhow!(
+                    "Copy verification failed: {}",
+                    copy_report.summary()
+                ));
+            }
+        }
+        Ok(())
+    });
+
+    // Report what changed even when a command failed part way through
+    if let Some(tracker) = git_tracker {
+        tracker.finish();
+    }
+    if let Some(origin) = &options.history {
+        let entry = history::HistoryEntry::new(
+            origin,
+            &executed,
+            options.working_dir.as_deref(),
+            result.is_ok(),
+        )
+        .with_exit_codes(&result);
+        history::record(&match ai_response {
+            Some(response) => entry.with_response(origin, response),
+            None => entry,
+        });
+    }
+    result
+}
+
+/// Ask whether to run the previewed `commands`, where an edit answer opens them in
+/// `$EDITOR` first. Returns the commands to run, or `None` when the user declined.
+fn confirm_or_edit_commands(
+    commands: Vec<String>,
+    strict: bool,
+    question: &str,
+) -> Result<Option<Vec<String>>> {
+    if !risk::assess_all(&commands).is_empty() {
+        return Ok(risk::confirm_flagged(&commands, strict)?.then_some(commands));
     }
+    commands_after_choice(
+        commands,
+        ui::confirm_or_edit(question),
+        strict,
+        orchestrator::edit_plan,
+    )
+}
 
-    // Execute commands with live output
-    for cmd in &commands_to_execute {
-        if let Err(e) = execute_command_with_live_output(cmd) {
-            println!("🛑 Stopping execution due to command failure.");
-            return Err(e);
+/// The commands to run once the user answered `choice`. Edited commands go through
+/// the same plan size, policy and risk checks as the AI's before they run; an empty
+/// edit runs nothing.
+fn commands_after_choice(
+    commands: Vec<String>,
+    choice: ui::Choice,
+    strict: bool,
+    edit: impl FnOnce(&[String]) -> Result<Vec<String>>,
+) -> Result<Option<Vec<String>>> {
+    match choice {
+        ui::Choice::Accept => Ok(Some(commands)),
+        ui::Choice::Decline => Ok(None),
+        ui::Choice::Edit => {
+            let edited = edit(&commands)?;
+            if edited.is_empty() {
+                return Ok(None);
+            }
+            check_plan_size(&edited)?;
+            policy::check(&edited)?;
+            ui::preview(&ui::messages().suggested_commands, &edited);
+            Ok(risk::confirm_flagged(&edited, strict)?.then_some(edited))
         }
     }
+}
 
-    Ok(())
+/// Ask once more before `cmd` installs, updates or removes packages, unless
+/// `auto_execute_installs`; `true` when it may run. With `--yes` nobody is there to
+/// answer, so such commands are refused, as flagged ones are.
+pub fn confirm_install(cmd: &str, auto_execute_installs: bool) -> bool {
+    confirm_install_with(cmd, auto_execute_installs, |question| {
+        if ui::assume_yes() {
+            ui::show("🚫 Refusing a package command with --yes; set auto_execute_installs = true to run them unattended.\n");
+            return false;
+        }
+        ui::confirm_risky(question)
+    })
+}
+
+/// Like [`confirm_install`], with `ask` putting the question to the user
+pub fn confirm_install_with(
+    cmd: &str,
+    auto_execute_installs: bool,
+    ask: impl FnOnce(&str) -> bool,
+) -> bool {
+    if auto_execute_installs || !is_install_update_remove_command(cmd) {
+        return true;
+    }
+    ui::show(&format!(
+        "\n📦 '{cmd}' installs, updates or removes packages.\n"
+    ));
+    ask(&ui::messages().confirm_install)
 }
 
 /// Check if a command is an installation, update, or remove command
@@ -377,6 +1135,8 @@ pub fn is_install_update_remove_command(cmd: &str) -> bool {
         "pip install",
         "python -m pip install",
         "pip3 install",
+        "uv add",
+        "uv sync",
         "apt install",
         "apt-get install",
         "yum install",
@@ -441,6 +1201,7 @@ pub fn is_install_update_remove_command(cmd: &str) -> bool {
         "pip uninstall",
         "python -m pip uninstall",
         "pip3 uninstall",
+        "uv remove",
         "apt remove",
         "apt-get remove",
         "yum remove",
@@ -478,83 +1239,125 @@ pub fn is_install_update_remove_command(cmd: &str) -> bool {
 
 /// Execute a command with live output and Terminal AI branding for install/update/remove commands
 pub fn execute_command_with_live_output(cmd: &str) -> Result<()> {
-    let is_install_cmd = is_install_update_remove_command(cmd);
+    execute_command_with_options(cmd, &ExecutionOptions::default())
+}
+
+/// Execute a command with live output, honoring the given execution options
+pub fn execute_command_with_options(cmd: &str, options: &ExecutionOptions) -> Result<()> {
+    executor::execute_command_blocking(cmd, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_split_reasoning() {
+        let response = "<think>\nMaybe rm -rf /tmp/x first?\n</think>\ncp a.txt b.txt";
+        let (answer, reasoning) = split_reasoning(response);
+        assert_eq!(answer, "cp a.txt b.txt");
+        assert_eq!(reasoning.unwrap(), "Maybe rm -rf /tmp/x first?");
+
+        let (answer, reasoning) = split_reasoning("find . -name '*.rs'");
+        assert_eq!(answer, "find . -name '*.rs'");
+        assert!(reasoning.is_none());
+
+        let (answer, reasoning) =
+            split_reasoning("<thinking>cp x y</thinking>ls\n<think>still going");
+        assert_eq!(answer, "ls");
+        assert_eq!(reasoning.unwrap(), "cp x y\n\nstill going");
+    }
 
-    if is_install_cmd {
-        println!(
-            "{}",
-            "[Terminal AI] - Executing package management command"
-                .green()
-                .bold()
+    #[test]
+    fn test_allowed_command_prefixes_setting() {
+        let mut config = TerminalAIConfig::default();
+        assert_eq!(config.allowed_command_prefixes(), None);
+        config.settings.insert(
+            "allowed_command_prefixes".to_string(),
+            " cp, git status ,".to_string(),
+        );
+        assert_eq!(
+            config.allowed_command_prefixes(),
+            Some(vec!["cp".to_string(), "git status".to_string()])
         );
-        println!("{}", format!("[Terminal AI] - Command: {cmd}").green());
-        println!("{}", "[Terminal AI] - Live output:".green());
-    } else {
-        println!("\n🔄 Executing: {cmd}");
     }
 
-    // Fix find commands with -exec that end with + which don't work well with sh -c
-    let fixed_cmd = fix_find_exec_command(cmd);
-    if fixed_cmd != cmd {
-        if is_install_cmd {
-            println!(
-                "{}",
-                format!("[Terminal AI] - Adjusted command: {fixed_cmd}").green()
-            );
-        } else {
-            println!("🔧 Adjusted command for compatibility: {fixed_cmd}");
-        }
+    #[test]
+    fn test_plans_over_max_commands_are_refused() {
+        let err = check_command_count(51, 50).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The AI suggested 51 commands, more than max_commands_per_plan (50); \
+             narrow the request and try again"
+        );
+        assert!(check_command_count(50, 50).is_ok());
+        assert!(check_command_count(500, 0).is_ok());
+
+        // The default limit applies to what would be printed or run
+        let long_plan: String = (0..=DEFAULT_MAX_COMMANDS_PER_PLAN)
+            .map(|i| format!("COMMAND: mkdir dir{i}\n"))
+            .collect();
+        let mut output = Vec::new();
+        let err = print_commands(&long_plan, Dedup::All, &mut output).unwrap_err();
+        assert!(err.to_string().contains("narrow the request"), "{err}");
+        assert!(output.is_empty());
+        print_commands(
+            "COMMAND: mkdir dir0\nCOMMAND: mkdir dir1",
+            Dedup::All,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "mkdir dir0\nmkdir dir1\n"
+        );
+
+        let mut config = TerminalAIConfig::default();
+        assert_eq!(
+            config.max_commands_per_plan(),
+            DEFAULT_MAX_COMMANDS_PER_PLAN
+        );
+        config
+            .settings
+            .insert("max_commands_per_plan".to_string(), " 0 ".to_string());
+        assert_eq!(config.max_commands_per_plan(), 0);
     }
 
-    // Use shell execution with live output
-    let mut command = Command::new("sh");
-    command.arg("-c");
-    command.arg(&fixed_cmd);
-    command.stdin(Stdio::piped());
-    command.stdout(Stdio::inherit());
-    command.stderr(Stdio::inherit());
-
-    let status = command
-        .status()
-        .map_err(|e| anyhow::anyhow!("Failed to execute command '{}': {}", cmd, e))?;
-
-    if status.success() {
-        if is_install_cmd {
-            println!(
-                "{}",
-                "[Terminal AI] - Command completed successfully"
-                    .green()
-                    .bold()
-            );
-        } else {
-            println!("✅ Command completed successfully");
-        }
-    } else {
-        let exit_code = status.code().unwrap_or(-1);
-        if is_install_cmd {
-            eprintln!(
-                "{}",
-                format!("[Terminal AI] - Command failed with exit code: {exit_code}")
-                    .red()
-                    .bold()
-            );
-        } else {
-            eprintln!("❌ Command failed with exit code: {exit_code:?}");
-        }
-        return Err(anyhow::anyhow!(
-            "Command '{}' failed with exit code: {}",
-            cmd,
-            exit_code
-        ));
+    #[test]
+    fn test_extract_commands_ignores_reasoning() {
+        let response = "<think>\nI could run:\nrm -rf ./build\ngrep -r foo .\n</think>\n```bash\nfind . -name \"*.log\"\n```";
+        let commands = extract_commands_from_response(response);
+        assert_eq!(commands, vec!["find . -name \"*.log\"".to_string()]);
+
+        let only_reasoning = "<think>cp important.txt /tmp/</think>";
+        assert!(extract_commands_from_response(only_reasoning).is_empty());
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_execute_command_with_working_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let options =
+            ExecutionOptions::for_directory(Some(&temp_dir.path().display().to_string())).unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
+        execute_command_with_options("pwd > cwd.txt", &options).unwrap();
+
+        let cwd = std::fs::read_to_string(temp_dir.path().join("cwd.txt")).unwrap();
+        assert_eq!(
+            std::fs::canonicalize(cwd.trim()).unwrap(),
+            std::fs::canonicalize(temp_dir.path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_execution_options_rejects_missing_directory() {
+        let missing = "/definitely/not/a/real/dir".to_string();
+        assert!(ExecutionOptions::for_directory(Some(&missing)).is_err());
+        assert!(ExecutionOptions::for_directory(None)
+            .unwrap()
+            .working_dir
+            .is_none());
+    }
 
     #[test]
     fn test_terminalai_config_default() {
@@ -576,6 +1379,7 @@ mod tests {
         // Check that all expected providers exist
         assert!(config.providers.contains_key("ollama"));
         assert!(config.providers.contains_key("openai"));
+        assert!(config.providers.contains_key("openai_compatible"));
         assert!(config.providers.contains_key("claude"));
         assert!(config.providers.contains_key("gemini"));
     }
@@ -589,6 +1393,98 @@ mod tests {
         assert!(path.to_string_lossy().ends_with("config.json"));
     }
 
+    #[test]
+    fn test_config_path_uses_xdg_config_home() {
+        let home = TempDir::new().unwrap();
+        let xdg = TempDir::new().unwrap();
+
+        let config_dir = xdg_config_dir(Some(xdg.path().to_path_buf()), home.path());
+        let path = resolve_config_path(&config_dir, home.path());
+        assert_eq!(path, xdg.path().join("terminalai").join("config.json"));
+    }
+
+    #[test]
+    fn test_config_path_without_xdg_config_home() {
+        let home = TempDir::new().unwrap();
+
+        let config_dir = xdg_config_dir(None, home.path());
+        let path = resolve_config_path(&config_dir, home.path());
+        assert_eq!(
+            path,
+            home.path()
+                .join(".config")
+                .join("terminalai")
+                .join("config.json")
+        );
+
+        // Relative values are invalid per the XDG spec and are ignored
+        let config_dir = xdg_config_dir(Some(PathBuf::from("relative/dir")), home.path());
+        assert_eq!(config_dir, home.path().join(".config"));
+    }
+
+    #[test]
+    fn test_config_path_prefers_existing_legacy_file() {
+        let home = TempDir::new().unwrap();
+        let xdg = TempDir::new().unwrap();
+        let legacy_dir = home.path().join(".terminalai");
+        std::fs::create_dir_all(&legacy_dir).unwrap();
+        std::fs::write(legacy_dir.join("config.json"), "{}").unwrap();
+
+        let config_dir = xdg_config_dir(Some(xdg.path().to_path_buf()), home.path());
+        let path = resolve_config_path(&config_dir, home.path());
+        assert_eq!(path, legacy_dir.join("config.json"));
+    }
+
+    #[test]
+    fn test_config_from_env_value() {
+        let mut config = TerminalAIConfig::default();
+        config.set_active_provider("claude").unwrap();
+        let value = serde_json::to_string(&config).unwrap();
+
+        let loaded = config_from_env(&value).unwrap();
+        assert_eq!(loaded.active_provider, "claude");
+        assert_ne!(
+            loaded.active_provider,
+            TerminalAIConfig::default().active_provider
+        );
+
+        let legacy = r#"{"provider":{"provider_type":"OpenAI","timeout_seconds":10,"settings":{"model":"gpt-4o"}}}"#;
+        assert_eq!(config_from_env(legacy).unwrap().active_provider, "openai");
+
+        let err = config_from_env("{\"api_key\": \"sk-secret\"").unwrap_err();
+        assert!(!format!("{err:#}").contains("sk-secret"));
+    }
+
+    #[test]
+    fn test_env_config_takes_precedence_over_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let local_config_path = dir.path().join("terminalai.conf");
+        let config_path = dir.path().join("config.json");
+        std::fs::write(&local_config_path, "active_provider = \"gemini\"\n").unwrap();
+        let mut user_config = TerminalAIConfig::default();
+        user_config.set_active_provider("claude").unwrap();
+        std::fs::write(&config_path, serde_json::to_string(&user_config).unwrap()).unwrap();
+        let mut env_config = TerminalAIConfig::default();
+        env_config.set_active_provider("openai").unwrap();
+        let env_value = serde_json::to_string(&env_config).unwrap();
+
+        let load = |env_value: Option<&str>| {
+            load_layered_config(env_value, Some(&local_config_path), &config_path)
+                .unwrap()
+                .active_provider
+        };
+        assert_eq!(load(Some(&env_value)), "openai");
+        // Unset or blank falls back to the files, the local .conf first
+        assert_eq!(load(None), "gemini");
+        assert_eq!(load(Some("  ")), "gemini");
+        std::fs::remove_file(&local_config_path).unwrap();
+        assert_eq!(load(None), "claude");
+        assert_eq!(load(Some(&env_value)), "openai");
+
+        // A broken value is an error rather than a silent fall back to the files
+        assert!(load_layered_config(Some("{"), Some(&local_config_path), &config_path).is_err());
+    }
+
     #[test]
     fn test_load_config_nonexistent_returns_default() {
         // Test the default configuration directly
@@ -679,6 +1575,34 @@ These commands will help you accomplish the task.
         assert!(commands.contains(&"find . -name \"*.txt\"".to_string()));
     }
 
+    #[test]
+    fn test_extract_commands_drops_consecutive_repeats() {
+        let response = "mkdir -p out\nfind . -name '*.log'\nfind . -name '*.log'\n\
+                        find . -name '*.log'\ncp a.log out/\nmkdir -p out\n";
+        let commands = extract_commands_from_response(response);
+        assert_eq!(
+            commands,
+            vec![
+                "mkdir -p out",
+                "find . -name '*.log'",
+                "cp a.log out/",
+                "mkdir -p out"
+            ]
+        );
+
+        assert_eq!(
+            dedup_commands(commands, Dedup::All),
+            vec!["mkdir -p out", "find . -name '*.log'", "cp a.log out/"]
+        );
+
+        let mut config = TerminalAIConfig::default();
+        assert_eq!(config.command_dedup(), Dedup::Consecutive);
+        config
+            .settings
+            .insert("dedup_commands".to_string(), "all".to_string());
+        assert_eq!(config.command_dedup(), Dedup::All);
+    }
+
     #[test]
     fn test_extract_commands_from_ai_response() {
         let ai_response = r#"
@@ -836,6 +1760,132 @@ cp actual_command.txt destination.txt
         assert!(found_commands.contains(&"cp actual_command.txt destination.txt"));
     }
 
+    #[test]
+    fn test_conf_top_level_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let conf_path = temp_dir.path().join("terminalai.conf");
+        std::fs::write(
+            &conf_path,
+            "active_provider = \"ollama\"\nbranding = false\n\n[ollama]\nmodel = \"llama3\"\n",
+        )
+        .unwrap();
+
+        let config = load_config_from_conf(&conf_path).unwrap();
+        assert!(!config.branding_enabled());
+        assert_eq!(
+            config.get_active_provider().unwrap().get_setting("model"),
+            Some(&"llama3".to_string())
+        );
+        assert!(TerminalAIConfig::default().branding_enabled());
+
+        save_config_to_conf(&config, &conf_path).unwrap();
+        let reloaded = load_config_from_conf(&conf_path).unwrap();
+        assert_eq!(reloaded.get_setting("branding"), Some(&"false".to_string()));
+    }
+
+    #[test]
+    fn test_conf_execution_section() {
+        let temp_dir = TempDir::new().unwrap();
+        let conf_path = temp_dir.path().join("terminalai.conf");
+        std::fs::write(&conf_path, "[execution]\nshell = \"fish\"\n").unwrap();
+
+        let config = load_config_from_conf(&conf_path).unwrap();
+        assert_eq!(
+            config.get_setting(shell::SHELL_SETTING),
+            Some(&"fish".to_string())
+        );
+
+        save_config_to_conf(&config, &conf_path).unwrap();
+        let reloaded = load_config_from_conf(&conf_path).unwrap();
+        assert_eq!(reloaded.settings, config.settings);
+    }
+
+    #[test]
+    fn test_conf_system_prompt_overrides() {
+        let temp_dir = TempDir::new().unwrap();
+        let conf_path = temp_dir.path().join("terminalai.conf");
+        std::fs::write(
+            &conf_path,
+            "[ollama]\nmodel = \"qwen2.5:1.5b\"\n\n[system_prompt_overrides]\n\
+             \"qwen2.5:1.5b\" = \"Commands only.\\nOne per line.\"\n",
+        )
+        .unwrap();
+
+        let config = load_config_from_conf(&conf_path).unwrap();
+        assert_eq!(
+            config.system_prompt_override().unwrap().as_deref(),
+            Some("Commands only.\nOne per line.")
+        );
+
+        save_config_to_conf(&config, &conf_path).unwrap();
+        let reloaded = load_config_from_conf(&conf_path).unwrap();
+        assert_eq!(
+            reloaded.system_prompt_overrides,
+            config.system_prompt_overrides
+        );
+    }
+
+    #[test]
+    fn test_conf_policy_section() {
+        let temp_dir = TempDir::new().unwrap();
+        let conf_path = temp_dir.path().join("terminalai.conf");
+        std::fs::write(
+            &conf_path,
+            "[policy]\nallow = \"^git\\b\"\nallow = \"^kubectl\\b\"\ndeny = \"docker system prune\"\n",
+        )
+        .unwrap();
+
+        let config = load_config_from_conf(&conf_path).unwrap();
+        assert_eq!(config.policy.allow, vec![r"^git\b", r"^kubectl\b"]);
+        assert_eq!(config.policy.deny, vec!["docker system prune"]);
+
+        save_config_to_conf(&config, &conf_path).unwrap();
+        let reloaded = load_config_from_conf(&conf_path).unwrap();
+        assert_eq!(reloaded.policy, config.policy);
+    }
+
+    #[tokio::test]
+    async fn test_url_override_reaches_provider_requests() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/generate")
+            .with_status(200)
+            .with_body(r#"{"response": "ls -la"}"#)
+            .create_async()
+            .await;
+
+        let mut config = TerminalAIConfig::default();
+        config.override_url(&format!("{}/", server.url())).unwrap();
+        let provider = query_provider::QueryProvider::new(config).unwrap();
+        assert_eq!(
+            provider.send_query("system", "user").await.unwrap(),
+            "ls -la"
+        );
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_url_override_key_and_validation() {
+        let mut config = TerminalAIConfig::default();
+        config.set_active_provider("openai").unwrap();
+        config.override_url("http://gpu-box:8000/v1").unwrap();
+        assert_eq!(
+            config
+                .get_active_provider()
+                .unwrap()
+                .get_setting("base_url"),
+            Some(&"http://gpu-box:8000/v1".to_string())
+        );
+
+        assert!(config.override_url("gpu-box:11434").is_err());
+        config.set_active_provider("local").unwrap();
+        assert!(config
+            .override_url("http://localhost:8080")
+            .unwrap_err()
+            .to_string()
+            .contains("has no URL"));
+    }
+
     #[test]
     fn test_config_serialization() {
         let mut config = TerminalAIConfig {
@@ -895,6 +1945,30 @@ cp actual_command.txt destination.txt
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_install_commands_are_confirmed_again() {
+        let mut asked = Vec::new();
+        let mut ask = |question: &str| {
+            asked.push(question.to_string());
+            false
+        };
+        assert!(!confirm_install_with("npm install react", false, &mut ask));
+        assert!(confirm_install_with("ls -la", false, &mut ask));
+        assert_eq!(asked, vec![ui::messages().confirm_install.clone()]);
+
+        // With auto_execute_installs even installs run as confirmed
+        assert!(confirm_install_with("npm install react", true, |_| panic!(
+            "asked about an install with auto_execute_installs"
+        )));
+
+        let mut config = TerminalAIConfig::default();
+        assert!(!config.auto_execute_installs());
+        config
+            .settings
+            .insert("auto_execute_installs".to_string(), "true".to_string());
+        assert!(config.auto_execute_installs());
+    }
+
     #[test]
     fn test_is_install_update_remove_command() {
         // Test install commands
@@ -925,4 +1999,52 @@ cp actual_command.txt destination.txt
         assert!(!is_install_update_remove_command("find . -name '*.txt'"));
         assert!(!is_install_update_remove_command("cp source dest"));
     }
+
+    #[test]
+    fn test_edited_commands_are_checked_before_running() {
+        let commands = vec!["cp notes.txt /backup".to_string()];
+        let unused = |_: &[String]| -> Result<Vec<String>> { panic!("not edited") };
+        assert_eq!(
+            commands_after_choice(commands.clone(), ui::Choice::Accept, false, unused).unwrap(),
+            Some(commands.clone())
+        );
+        assert_eq!(
+            commands_after_choice(commands.clone(), ui::Choice::Decline, false, unused).unwrap(),
+            None
+        );
+
+        let edited = commands_after_choice(commands.clone(), ui::Choice::Edit, false, |cmds| {
+            assert_eq!(cmds, ["cp notes.txt /backup"]);
+            Ok(vec!["cp -n notes.txt /mnt/backup".to_string()])
+        })
+        .unwrap();
+        assert_eq!(
+            edited,
+            Some(vec!["cp -n notes.txt /mnt/backup".to_string()])
+        );
+
+        // Deleting every command runs nothing
+        let emptied =
+            commands_after_choice(
+                commands.clone(),
+                ui::Choice::Edit,
+                false,
+                |_| Ok(Vec::new()),
+            )
+            .unwrap();
+        assert_eq!(emptied, None);
+
+        // An edit that introduces an injected command is refused under --strict
+        let err = commands_after_choice(commands.clone(), ui::Choice::Edit, true, |_| {
+            Ok(vec!["cp notes.txt /backup; rm -rf ~".to_string()])
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("--strict"), "{err}");
+
+        let err = commands_after_choice(commands, ui::Choice::Edit, false, |_| {
+            Err(anyhow::anyhow!("Editor 'vi' exited with status 1"))
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("exited with status 1"), "{err}");
+    }
 }
@@ -1,6 +1,7 @@
 use anyhow::Result;
 use clap::{Arg, Command};
-use terminalai::{config, orchestrator};
+use std::path::PathBuf;
+use terminalai::{batch, chat, cli, config, doctor, history, orchestrator, scaffold};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -15,27 +16,450 @@ async fn main() -> Result<()> {
                 .help("Convert natural language query into terminal commands and execute them sequentially")
                 .value_name("PROMPT")
         )
+        .arg(
+            Arg::new("emit-script")
+                .long("emit-script")
+                .help("Write the executed commands to a runnable shell script")
+                .value_name("PATH")
+                .requires("prompt")
+        )
+        .arg(
+            Arg::new("edit-plan")
+                .long("edit-plan")
+                .help("Open the generated plan in $EDITOR to edit, reorder or delete commands before running")
+                .action(clap::ArgAction::SetTrue)
+                .requires("prompt")
+        )
+        .args(cli::common_args().into_iter().map(|arg| match arg.get_id().as_str() {
+            "shell" | "no-cache" | "trace-http" | "simulate-error" => arg.global(true),
+            _ => arg.requires("prompt"),
+        }))
+        .mut_arg("yes", |arg| {
+            arg.help("Run the plan without asking (also TERMINALAI_ASSUME_YES=1); with Ollama, the only provider that streams, each command starts as soon as the model has generated it")
+                .conflicts_with("edit-plan")
+        })
+        .arg(
+            Arg::new("interactive-refine")
+                .long("interactive-refine")
+                .help("After showing the plan, offer to clarify the prompt or regenerate the plan until you accept it")
+                .action(clap::ArgAction::SetTrue)
+                .requires("prompt")
+                .conflicts_with("yes")
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Show the plan with the reason for each command, without running it")
+                .action(clap::ArgAction::SetTrue)
+                .requires("prompt")
+                .conflicts_with_all(["yes", "edit-plan"])
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .help("With --dry-run, print the plan as JSON: each command with its category and reason")
+                .action(clap::ArgAction::SetTrue)
+                .requires("dry-run")
+        )
+        .arg(
+            Arg::new("show-reasoning")
+                .long("show-reasoning")
+                .help("Print the model's reasoning (<think> blocks) before the plan")
+                .action(clap::ArgAction::SetTrue)
+                .requires("prompt")
+        )
+        .arg(
+            Arg::new("git-summary")
+                .long("git-summary")
+                .help("Inside a git work tree, list the files the commands created, modified or deleted")
+                .action(clap::ArgAction::SetTrue)
+                .requires("prompt")
+        )
+        .arg(
+            Arg::new("url")
+                .long("url")
+                .help("Send the query to this endpoint instead of the active provider's configured URL (e.g. http://gpu-box:11434)")
+                .value_name("URL")
+                .requires("prompt")
+        )
+        .arg(
+            Arg::new("max-cost")
+                .long("max-cost")
+                .help("Stop querying a cloud provider once the estimated spend reaches USD")
+                .value_name("USD")
+                .value_parser(clap::value_parser!(f64))
+                .requires("prompt")
+        )
+        .arg(
+            Arg::new("attach")
+                .long("attach")
+                .value_name("FILE")
+                .help("Send an image (e.g. a screenshot of an error) with the prompt; needs OpenAI, Claude or Gemini. Repeat for more images")
+                .action(clap::ArgAction::Append)
+                .requires("prompt")
+        )
+        .arg(
+            Arg::new("record")
+                .long("record")
+                .help("Save a timestamped transcript of the executed commands and their output to FILE")
+                .value_name("FILE")
+                .requires("prompt")
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .help("Refuse commands the risk check flags (sudo, rm -rf, $(...), device writes, ...) instead of asking")
+                .action(clap::ArgAction::SetTrue)
+                .requires("prompt")
+        )
+        .arg(
+            Arg::new("directory")
+                .short('C')
+                .long("directory")
+                .help("Run the commands in DIR instead of a directory named in the prompt")
+                .value_name("DIR")
+                .requires("prompt")
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .help("Print how long config loading, the provider, extraction and execution took")
+                .action(clap::ArgAction::SetTrue)
+                .requires("prompt")
+        )
         .subcommand(
             Command::new("init")
                 .about("Initialize Terminal AI configuration")
+                .arg(
+                    Arg::new("defer-setup")
+                        .long("defer-setup")
+                        .help("Configure the Local provider without installing llama.cpp or downloading the model until first use")
+                        .action(clap::ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("portable")
+                        .long("portable")
+                        .help("Save the configuration as terminalai.conf next to the executable, for portable installs")
+                        .action(clap::ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Inspect the Terminal AI configuration")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("validate")
+                        .about("Check the configuration and exit non-zero if it has problems")
+                        .arg(
+                            Arg::new("file")
+                                .long("file")
+                                .help("Validate this config file instead of the one Terminal AI loads")
+                                .value_name("PATH")
+                        )
+                )
+                .subcommand(
+                    Command::new("show")
+                        .about("Print the configuration in use, with API keys and tokens masked")
+                        .arg(
+                            Arg::new("json")
+                                .long("json")
+                                .help("Print it as JSON")
+                                .action(clap::ArgAction::SetTrue)
+                        )
+                )
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Check the configuration, provider and shell, and exit non-zero if any check fails")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the checks as JSON")
+                        .action(clap::ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("history")
+                .about("Show the prompts and commands run before, most recent last")
+                .args(history_list_args())
+                .subcommand(
+                    Command::new("list")
+                        .about("Show the prompts and commands run before, most recent last (the default)")
+                        .args(history_list_args())
+                )
+                .subcommand(
+                    Command::new("show")
+                        .about("Show one entry with each command's exit code and the model's response")
+                        .arg(history_id_arg())
+                )
+                .subcommand(
+                    Command::new("replay")
+                        .about("Run an entry's commands again in the directory they ran in, without asking the model")
+                        .arg(history_id_arg())
+                )
+        )
+        .subcommand(
+            Command::new("batch")
+                .about("Plan every prompt in a file, one per line; runs nothing without --yes")
+                .arg(
+                    Arg::new("file")
+                        .help("File with one prompt per line; blank lines and # comments are skipped")
+                        .required(true)
+                        .index(1)
+                )
+                .arg(
+                    Arg::new("jobs")
+                        .long("jobs")
+                        .short('j')
+                        .help("Number of prompts to process at the same time")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("1")
+                )
+                .arg(
+                    Arg::new("yes")
+                        .long("yes")
+                        .help("Execute each plan without asking, instead of only showing it")
+                        .action(clap::ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("no-branding")
+                        .long("no-branding")
+                        .help("Don't print the [Terminal AI] banners around package management commands")
+                        .action(clap::ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("url")
+                        .long("url")
+                        .help("Send the queries to this endpoint instead of the active provider's configured URL")
+                        .value_name("URL")
+                )
+                .arg(
+                    Arg::new("max-cost")
+                        .long("max-cost")
+                        .help("Stop querying a cloud provider once the batch's estimated spend reaches USD")
+                        .value_name("USD")
+                        .value_parser(clap::value_parser!(f64))
+                )
+                .arg(
+                    Arg::new("record")
+                        .long("record")
+                        .help("Save a timestamped transcript of the executed commands and their output to FILE")
+                        .value_name("FILE")
+                )
+        )
+        .subcommand(
+            Command::new("chat")
+                .about("Plan and run tasks in a conversation; follow-ups can refer to earlier commands and their output")
+                .arg(
+                    Arg::new("url")
+                        .long("url")
+                        .help("Send the queries to this endpoint instead of the active provider's configured URL")
+                        .value_name("URL")
+                )
+                .arg(
+                    Arg::new("max-cost")
+                        .long("max-cost")
+                        .help("Stop querying a cloud provider once the session's estimated spend reaches USD")
+                        .value_name("USD")
+                        .value_parser(clap::value_parser!(f64))
+                )
+        )
+        .subcommand(
+            Command::new("resume")
+                .about("Continue a tai -p plan from the step that failed")
+                .arg(
+                    Arg::new("id")
+                        .help("Plan id printed when the step failed; the most recent plan when left out")
+                        .index(1)
+                )
+                .arg(
+                    Arg::new("fix")
+                        .long("fix")
+                        .help("Ask the model to replace the failed step first, showing it what the step printed to stderr")
+                        .action(clap::ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("explain")
+                .about("Explain what a command does, flag by flag, and which files it would touch, without running it")
+                .arg(
+                    Arg::new("command")
+                        .help("The command to explain, best quoted so the shell passes it as it is")
+                        .required(true)
+                        .num_args(1..)
+                        .trailing_var_arg(true)
+                        .allow_hyphen_values(true)
+                        .index(1)
+                )
+                .arg(
+                    Arg::new("url")
+                        .long("url")
+                        .help("Send the query to this endpoint instead of the active provider's configured URL")
+                        .value_name("URL")
+                )
+                .arg(
+                    Arg::new("no-context")
+                        .long("no-context")
+                        .help("Leave the OS, shell, directory listing and git branch out of the prompt")
+                        .action(clap::ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("scaffold")
+                .about("Generate a new *_ai command binary and its command definition")
+                .arg(
+                    Arg::new("name")
+                        .help("Command name without the _ai suffix (e.g. 'format')")
+                        .required(true)
+                        .index(1)
+                )
+                .arg(
+                    Arg::new("keywords")
+                        .long("keywords")
+                        .help("Comma-separated keywords that indicate a valid request")
+                        .value_name("WORDS")
+                        .value_delimiter(',')
+                        .required(true)
+                )
+                .arg(
+                    Arg::new("invalid")
+                        .long("invalid")
+                        .help("Comma-separated keywords that indicate an out-of-scope request")
+                        .value_name("WORDS")
+                        .value_delimiter(',')
+                )
+                .arg(
+                    Arg::new("description")
+                        .long("description")
+                        .help("Short description of the operations (defaults to the name)")
+                        .value_name("TEXT")
+                )
+                .arg(
+                    Arg::new("root")
+                        .long("root")
+                        .help("Repository root to generate the files in")
+                        .value_name("DIR")
+                        .default_value(".")
+                )
         )
         .get_matches();
 
+    cli::configure_requests(&matches);
+
     // Handle -p/--prompt flag for orchestration
     if let Some(prompt) = matches.get_one::<String>("prompt") {
-        orchestrator::orchestrate_query(prompt).await?;
+        let options = orchestrator::OrchestrationOptions {
+            emit_script: matches.get_one::<String>("emit-script").map(PathBuf::from),
+            show_reasoning: matches.get_flag("show-reasoning"),
+            edit_plan: matches.get_flag("edit-plan"),
+            profile: matches.get_flag("profile"),
+            no_branding: matches.get_flag("no-branding"),
+            git_summary: matches.get_flag("git-summary"),
+            url: matches.get_one::<String>("url").cloned(),
+            record: matches.get_one::<String>("record").map(PathBuf::from),
+            strict: matches.get_flag("strict"),
+            max_cost: matches.get_one::<f64>("max-cost").copied(),
+            directory: matches.get_one::<String>("directory").map(PathBuf::from),
+            no_context: matches.get_flag("no-context"),
+            // TERMINALAI_ASSUME_YES acts as --yes unless the plan is to be looked at first
+            yes: matches.get_flag("yes")
+                || (terminalai::ui::assume_yes_from_env()
+                    && !["edit-plan", "interactive-refine", "dry-run"]
+                        .iter()
+                        .any(|flag| matches.get_flag(flag))),
+            interactive_refine: matches.get_flag("interactive-refine"),
+            dry_run: matches.get_flag("dry-run"),
+            json: matches.get_flag("json"),
+            shell: matches.get_one::<String>("shell").cloned(),
+            attach: matches
+                .get_many::<String>("attach")
+                .map(|files| files.map(PathBuf::from).collect())
+                .unwrap_or_default(),
+        };
+        orchestrator::orchestrate_query_with_options(prompt, &options).await?;
         return Ok(());
     }
 
     match matches.subcommand() {
-        Some(("init", _)) => {
-            config::init_config().await?;
+        Some(("init", sub_matches)) => {
+            let options = config::InitOptions {
+                defer_setup: sub_matches.get_flag("defer-setup"),
+                portable: sub_matches.get_flag("portable"),
+            };
+            config::init_config_with_options(&options).await?;
+        }
+        Some(("config", sub_matches)) => match sub_matches.subcommand() {
+            Some(("validate", validate_matches)) => run_config_validate(validate_matches),
+            Some(("show", show_matches)) => run_config_show(show_matches)?,
+            _ => {}
+        },
+        Some(("doctor", sub_matches)) => {
+            run_doctor(sub_matches)?;
+        }
+        Some(("batch", sub_matches)) => {
+            run_batch(sub_matches).await?;
+        }
+        Some(("history", sub_matches)) => match sub_matches.subcommand() {
+            Some(("list", list_matches)) => run_history(list_matches)?,
+            Some(("show", show_matches)) => run_history_show(show_matches)?,
+            Some(("replay", replay_matches)) => run_history_replay(replay_matches)?,
+            _ => run_history(sub_matches)?,
+        },
+        Some(("chat", sub_matches)) => {
+            let options = chat::ChatOptions {
+                url: sub_matches.get_one::<String>("url").cloned(),
+                max_cost: sub_matches.get_one::<f64>("max-cost").copied(),
+                shell: sub_matches.get_one::<String>("shell").cloned(),
+            };
+            chat::run_chat(&options).await?;
+        }
+        Some(("resume", sub_matches)) => {
+            let options = orchestrator::ResumeOptions {
+                fix: sub_matches.get_flag("fix"),
+                shell: sub_matches.get_one::<String>("shell").cloned(),
+            };
+            orchestrator::resume_plan(
+                sub_matches.get_one::<String>("id").map(String::as_str),
+                &options,
+            )
+            .await?;
+        }
+        Some(("explain", sub_matches)) => {
+            let command = sub_matches
+                .get_many::<String>("command")
+                .map(|words| words.cloned().collect::<Vec<_>>().join(" "))
+                .unwrap_or_default();
+            let options = terminalai::explain::ExplainOptions {
+                url: sub_matches.get_one::<String>("url").cloned(),
+                no_context: sub_matches.get_flag("no-context"),
+                shell: sub_matches.get_one::<String>("shell").cloned(),
+            };
+            terminalai::explain::explain_command(&command, &options).await?;
+        }
+        Some(("scaffold", sub_matches)) => {
+            run_scaffold(sub_matches)?;
         }
         _ => {
             println!("🤖 Terminal AI v0.1.0");
             println!();
             println!("Available commands:");
             println!("  tai init         - Initialize configuration");
+            println!("  tai config validate - Check the configuration for problems");
+            println!("  tai config show --json - Print the configuration, secrets masked");
+            println!("  tai doctor --json - Check the setup Terminal AI runs with");
+            println!("  tai batch [file] --yes - Plan (and with --yes run) one prompt per line");
+            println!("  tai history --here - Show the commands run from this directory tree");
+            println!(
+                "  tai history show|replay [id] - Inspect or re-run an entry without the model"
+            );
+            println!(
+                "  tai resume [id] [--fix] - Continue a tai -p plan from the step that failed"
+            );
+            println!(
+                "  tai explain \"[command]\" - Explain a command flag by flag without running it"
+            );
+            println!("  tai scaffold [name] --keywords a,b - Generate a new *_ai command");
             println!("  tai -p \"[query]\" - Convert query to commands and execute sequentially");
             println!("  cp_ai [prompt]           - AI-powered copy operations");
             println!("  grep_ai [prompt]         - AI-powered text search");
@@ -59,3 +483,255 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// `--here` and `--limit`, taken by `tai history` and `tai history list`
+fn history_list_args() -> [Arg; 2] {
+    [
+        Arg::new("here")
+            .long("here")
+            .help("Only show entries recorded in the current directory or below it")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("limit")
+            .short('n')
+            .long("limit")
+            .help("Number of entries to show")
+            .value_name("N")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("20"),
+    ]
+}
+
+fn history_id_arg() -> Arg {
+    Arg::new("id")
+        .help("Entry number, as shown by tai history")
+        .required(true)
+        .value_parser(clap::value_parser!(usize))
+}
+
+fn read_history() -> Result<Vec<history::HistoryEntry>> {
+    let Some(path) = history::history_path() else {
+        anyhow::bail!("Failed to find home directory");
+    };
+    history::read_from(&path)
+}
+
+/// The history entry named by the `id` argument
+fn history_entry(
+    entries: &[history::HistoryEntry],
+    matches: &clap::ArgMatches,
+) -> Result<(usize, history::HistoryEntry)> {
+    let id = *matches.get_one::<usize>("id").unwrap();
+    match history::find(entries, id) {
+        Some(entry) => Ok((id, entry.clone())),
+        None => anyhow::bail!("No history entry #{id}; run 'tai history' to list them"),
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn run_history(matches: &clap::ArgMatches) -> Result<()> {
+    let entries = read_history()?;
+    let mut shown = if matches.get_flag("here") {
+        let here = std::env::current_dir()?;
+        history::recorded_under(&entries, &here.canonicalize().unwrap_or(here))
+    } else {
+        history::numbered(&entries)
+    };
+    let limit = *matches.get_one::<usize>("limit").unwrap();
+    shown.drain(..shown.len().saturating_sub(limit));
+
+    if shown.is_empty() {
+        println!("No history yet.");
+        return Ok(());
+    }
+    let now = unix_now();
+    for (id, entry) in shown {
+        println!("{}", history::render_entry(id, entry, now));
+    }
+    Ok(())
+}
+
+fn run_history_show(matches: &clap::ArgMatches) -> Result<()> {
+    let (id, entry) = history_entry(&read_history()?, matches)?;
+    print!("{}", history::render_details(id, &entry, unix_now()));
+    Ok(())
+}
+
+/// Run a history entry's commands again, asked about like freshly suggested ones
+fn run_history_replay(matches: &clap::ArgMatches) -> Result<()> {
+    let (id, entry) = history_entry(&read_history()?, matches)?;
+    if entry.commands.is_empty() {
+        anyhow::bail!("History entry #{id} has no commands to replay");
+    }
+    if !entry.workdir.is_dir() {
+        anyhow::bail!(
+            "Directory '{}' of history entry #{id} no longer exists",
+            entry.workdir.display()
+        );
+    }
+
+    let config = terminalai::load_config()?;
+    terminalai::branding::configure(&config, false);
+    terminalai::ui::configure(&config);
+    // Replays have no --yes, but TERMINALAI_ASSUME_YES still answers for them
+    terminalai::ui::configure_assume_yes(false);
+    terminalai::configure_extraction(&config)?;
+    terminalai::shell::configure(
+        &config,
+        matches.get_one::<String>("shell").map(String::as_str),
+    )?;
+    let options = terminalai::ExecutionOptions {
+        working_dir: Some(entry.workdir.clone()),
+        error_signatures: config.error_signatures(),
+        dedup: config.command_dedup(),
+        history: config.history_origin("tai history replay", &entry.prompt),
+        auto_execute_installs: config.auto_execute_installs(),
+        confirm_each: config.confirm_each_command(),
+        ..Default::default()
+    };
+
+    println!(
+        "🔁 Replaying #{id} ({}) in {}: {}\n",
+        entry.tool,
+        entry.workdir.display(),
+        entry.prompt
+    );
+    terminalai::confirm_and_execute_commands(
+        entry.commands.clone(),
+        entry.response.as_deref(),
+        &options,
+        &mut terminalai::timing::PhaseTimer::new(),
+    )
+}
+
+/// Where `load_config` reads the configuration from, for display
+fn config_source_label() -> String {
+    if std::env::var(terminalai::CONFIG_ENV_VAR).is_ok_and(|value| !value.trim().is_empty()) {
+        return format!("${}", terminalai::CONFIG_ENV_VAR);
+    }
+    config::config_source()
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| "built-in defaults".to_string())
+}
+
+fn run_config_show(matches: &clap::ArgMatches) -> Result<()> {
+    let config = config::sanitized_config(&terminalai::load_config()?);
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&config)?);
+    } else {
+        println!("# Loaded from {}", config_source_label());
+        print!("{}", config::render_config(&config));
+    }
+    Ok(())
+}
+
+fn run_doctor(matches: &clap::ArgMatches) -> Result<()> {
+    let report = doctor::diagnose(
+        &terminalai::load_config(),
+        &config_source_label(),
+        matches.get_one::<String>("shell").map(String::as_str),
+    );
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report.render());
+    }
+    if !report.is_ok() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_config_validate(matches: &clap::ArgMatches) {
+    let path = matches.get_one::<String>("file").map(PathBuf::from);
+    let from_env = std::env::var(terminalai::CONFIG_ENV_VAR)
+        .ok()
+        .filter(|value| path.is_none() && !value.trim().is_empty());
+    let path = path.or_else(config::config_source);
+
+    let report = match (&from_env, &path) {
+        (Some(value), _) => {
+            println!("🔍 Validating ${}", terminalai::CONFIG_ENV_VAR);
+            config::validate_config_json(value)
+        }
+        (None, Some(path)) => {
+            println!("🔍 Validating {}", path.display());
+            config::validate_config_file(path)
+        }
+        (None, None) => {
+            println!("🔍 No config file found; validating the built-in defaults");
+            let mut report = config::ValidationReport::default();
+            config::validate_config(&terminalai::TerminalAIConfig::default(), &mut report);
+            report
+        }
+    };
+
+    report.print();
+    if !report.is_valid() {
+        std::process::exit(1);
+    }
+}
+
+async fn run_batch(matches: &clap::ArgMatches) -> Result<()> {
+    let options = batch::BatchOptions {
+        jobs: *matches.get_one::<usize>("jobs").unwrap(),
+        yes: matches.get_flag("yes"),
+        no_branding: matches.get_flag("no-branding"),
+        url: matches.get_one::<String>("url").cloned(),
+        record: matches.get_one::<String>("record").map(PathBuf::from),
+        max_cost: matches.get_one::<f64>("max-cost").copied(),
+        shell: matches.get_one::<String>("shell").cloned(),
+    };
+    let path = PathBuf::from(matches.get_one::<String>("file").unwrap());
+
+    let summary = batch::run_batch_file(&path, &options).await?;
+    println!("\n{}", summary.render());
+    if !summary.is_ok() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_scaffold(matches: &clap::ArgMatches) -> Result<()> {
+    let name = matches.get_one::<String>("name").unwrap();
+    let collect = |id: &str| -> Vec<String> {
+        matches
+            .get_many::<String>(id)
+            .map(|values| {
+                values
+                    .map(|v| v.trim().to_string())
+                    .filter(|v| !v.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let options = scaffold::ScaffoldOptions {
+        name: name.clone(),
+        description: matches
+            .get_one::<String>("description")
+            .cloned()
+            .unwrap_or_else(|| name.clone()),
+        keywords: collect("keywords"),
+        invalid_keywords: collect("invalid"),
+    };
+
+    let root = PathBuf::from(matches.get_one::<String>("root").unwrap());
+    let output = scaffold::scaffold(&options, &root)?;
+
+    println!("✅ Generated {}", output.binary_path.display());
+    println!("✅ Generated {}", output.definition_path.display());
+    println!("\nAdd this to Cargo.toml:\n");
+    println!("{}", scaffold::cargo_bin_stanza(name));
+    println!(
+        "Then edit the system prompt in {} and run:",
+        output.definition_path.display()
+    );
+    println!("  cargo build --bin {name}_ai");
+    Ok(())
+}
@@ -1,15 +1,690 @@
-use crate::{load_config, query_provider::QueryProvider};
+use crate::{
+    dedup_commands,
+    executor::{execute_command, CommandFailed},
+    load_config,
+    plans::SavedPlan,
+    query_provider::QueryProvider,
+    script::ScriptRecorder,
+    timing::PhaseTimer,
+    transcript::SharedTranscript,
+    Dedup, ExecutionOptions, TerminalAIConfig,
+};
 use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Options controlling an orchestration run
+#[derive(Debug, Clone, Default)]
+pub struct OrchestrationOptions {
+    /// Save the executed commands to this path as a runnable shell script
+    pub emit_script: Option<PathBuf>,
+    /// Print the model's reasoning (`<think>` blocks) before the plan
+    pub show_reasoning: bool,
+    /// Open the parsed plan in `$EDITOR` before confirming it
+    pub edit_plan: bool,
+    /// Print how long each phase took once the run finishes
+    pub profile: bool,
+    /// Suppress the `[Terminal AI]` banners regardless of the `branding` setting
+    pub no_branding: bool,
+    /// Report the files the plan created, modified and deleted in the git work tree
+    pub git_summary: bool,
+    /// Send the query to this endpoint instead of the active provider's configured URL
+    pub url: Option<String>,
+    /// Save a timestamped transcript of the executed commands and their output here
+    pub record: Option<PathBuf>,
+    /// Refuse commands flagged by the risk check instead of asking
+    pub strict: bool,
+    /// Stop querying the provider once the estimated spend reaches this many USD
+    pub max_cost: Option<f64>,
+    /// Run every command in this directory instead of one implied by the prompt
+    pub directory: Option<PathBuf>,
+    /// Leave the OS, shell, directory listing and git branch out of the prompt
+    pub no_context: bool,
+    /// Skip the confirmation; with a streaming provider (Ollama) each command runs
+    /// as soon as the model has generated it
+    pub yes: bool,
+    /// After showing the plan, offer to clarify the prompt or regenerate the plan
+    /// until the user accepts it
+    pub interactive_refine: bool,
+    /// Show the plan with each command's rationale and stop without running it
+    pub dry_run: bool,
+    /// Print the `dry_run` plan as JSON [`PlanRecord`]s instead
+    pub json: bool,
+    /// Images sent with the prompt to a multimodal provider
+    pub attach: Vec<PathBuf>,
+    /// Shell the commands run in (`--shell`), instead of the `shell` setting
+    pub shell: Option<String>,
+}
 
 pub async fn orchestrate_query(prompt: &str) -> Result<()> {
-    println!("🧠 Analyzing your request: {prompt}\n");
+    orchestrate_query_with_options(prompt, &OrchestrationOptions::default()).await
+}
+
+pub async fn orchestrate_query_with_options(
+    prompt: &str,
+    options: &OrchestrationOptions,
+) -> Result<()> {
+    let mut timer = PhaseTimer::new();
+    let result = run_orchestration(prompt, options, &mut timer).await;
+    if options.profile {
+        println!("\n{}", timer.summary());
+    }
+    result
+}
+
+async fn run_orchestration(
+    prompt: &str,
+    options: &OrchestrationOptions,
+    timer: &mut PhaseTimer,
+) -> Result<()> {
+    if options.json {
+        // Keep stdout for the JSON plan
+        crate::ui::use_pipeline_mode();
+    }
+    crate::ui::show(&format!("🧠 Analyzing your request: {prompt}\n\n"));
 
     // Load configuration
+    let mut config = timer.time("config load", load_config)?;
+    if let Some(url) = &options.url {
+        config.override_url(url)?;
+    }
+    crate::branding::configure(&config, options.no_branding);
+    crate::ui::configure(&config);
+    crate::ui::configure_assume_yes(options.yes);
+    crate::configure_extraction(&config)?;
+    crate::shell::configure(&config, options.shell.as_deref())?;
+    crate::context::configure(&config, options.no_context);
+    let mut execution_options = plan_execution_options(&config, options.record.as_deref())?;
+    execution_options.strict = options.strict;
+    let history_origin = config.history_origin("tai", prompt);
+    let plans_dir = crate::plans::plans_dir_for(&config);
+    let prompt_storage = config.history_prompt();
+    let replan_attempts = config.replan_attempts();
+    let directory = match &options.directory {
+        Some(dir) if !dir.is_dir() => anyhow::bail!(
+            "Directory '{}' does not exist or is not a directory",
+            dir.display()
+        ),
+        Some(dir) => Some(dir.clone()),
+        None if config.infer_directory() => implied_directory(prompt, Path::new(".")),
+        None => None,
+    };
+    let mut provider = timer
+        .time("provider setup", || QueryProvider::new(config))
+        .context("Failed to create query provider")?;
+    if let Some(dir) = &directory {
+        provider = provider.with_context_in(dir);
+    }
+    if let Some(max_cost) = options.max_cost {
+        provider = provider.with_max_cost(max_cost)?;
+    }
+    if !options.attach.is_empty() {
+        let attachments = options
+            .attach
+            .iter()
+            .map(|path| crate::attachment::Attachment::load(path))
+            .collect::<Result<Vec<_>>>()?;
+        provider = provider.with_attachments(attachments)?;
+    }
+
+    let query = match &directory {
+        Some(dir) => {
+            println!("📁 Running commands in {}\n", dir.display());
+            format!(
+                "{prompt}\n\nThe commands run with {} as the working directory, so don't cd into it.",
+                dir.display()
+            )
+        }
+        None => prompt.to_string(),
+    };
+
+    let planned = if options.yes {
+        None
+    } else {
+        let plan = confirmed_plan(
+            &provider,
+            &query,
+            directory.as_deref(),
+            options,
+            &execution_options,
+            timer,
+        )
+        .await?;
+        match plan {
+            Some(plan) => Some(plan),
+            None => return Ok(()),
+        }
+    };
+    execution_options.working_dir = directory;
+
+    // Execute commands in sequence
+    let mut recorder = ScriptRecorder::new();
+    let git_tracker = if options.git_summary {
+        crate::git_summary::GitTracker::start(execution_options.working_dir.as_deref())
+    } else {
+        None
+    };
+    let execution_started = Instant::now();
+    // A streamed plan runs before its rationales could be matched up, so they are
+    // only kept for a confirmed one
+    let (commands, rationales, response, result) = match planned {
+        Some(plan) => {
+            let mut saved = SavedPlan::new(
+                prompt,
+                &plan.commands,
+                &plan.rationales,
+                &plan.outputs,
+                execution_options.working_dir.as_deref(),
+            );
+            saved.prompt_storage = prompt_storage;
+            let mut replanner = Replanner::new(&provider, replan_attempts, options.strict);
+            let result = execute_saved_plan(
+                &mut saved,
+                plans_dir.as_deref(),
+                &mut execution_options,
+                &mut recorder,
+                Some(&mut replanner),
+            )
+            .await;
+            // Replanned steps ran in place of the ones the model first suggested;
+            // skipped ones never ran
+            let steps = saved
+                .steps
+                .iter()
+                .filter(|step| step.state != crate::plans::StepState::Skipped);
+            let commands = steps.clone().map(|step| step.command.clone()).collect();
+            let rationales = steps.map(|step| step.rationale.clone()).collect();
+            (commands, rationales, Some(plan.response), result)
+        }
+        None => {
+            let (commands, response, result) =
+                stream_plan(&provider, &query, &mut execution_options, &mut recorder).await;
+            (commands, Vec::new(), response, result)
+        }
+    };
+    timer.record("execution", execution_started);
+    if let Some(tracker) = git_tracker {
+        tracker.finish();
+    }
+    if commands.is_empty() && result.is_ok() {
+        println!("⚠️  No specific commands could be generated from your request.");
+        println!("💡 Try being more specific about what operations you want to perform.");
+        return Ok(());
+    }
+    if let Some(origin) = &history_origin {
+        let entry = crate::history::HistoryEntry::new(
+            origin,
+            &commands,
+            execution_options.working_dir.as_deref(),
+            result.is_ok(),
+        )
+        .with_rationales(rationales)
+        .with_exit_codes(&result);
+        crate::history::record(&match &response {
+            Some(response) => entry.with_response(origin, response),
+            None => entry,
+        });
+    }
+
+    if let Some(script_path) = &options.emit_script {
+        recorder.write_to(script_path)?;
+        println!("📝 Executed commands saved to {}", script_path.display());
+    }
+
+    result?;
+    println!("🎉 Orchestration complete!");
+    Ok(())
+}
+
+/// Options controlling `tai resume`
+#[derive(Debug, Clone, Default)]
+pub struct ResumeOptions {
+    /// Ask the model to replace the failed step first, showing it the step's stderr
+    pub fix: bool,
+    /// Shell the commands run in (`--shell`), instead of the `shell` setting
+    pub shell: Option<String>,
+}
+
+/// Continue the saved plan `id`, or the most recent one, from its first unfinished
+/// step once the user confirms the steps left
+pub async fn resume_plan(id: Option<&str>, options: &ResumeOptions) -> Result<()> {
+    let dir = crate::plans::plans_dir().context("Failed to find home directory")?;
+    let mut plan = match id {
+        Some(id) => SavedPlan::load_from(&dir, id)?,
+        None => crate::plans::latest_in(&dir)?.context(
+            "No saved plan to resume; plans are saved while tai -p runs them and kept when a step fails",
+        )?,
+    };
+    let Some(next) = plan.next_step() else {
+        anyhow::bail!("Plan {} has already finished", plan.id);
+    };
+    if !plan.workdir.is_dir() {
+        anyhow::bail!(
+            "Directory '{}' of plan {} no longer exists",
+            plan.workdir.display(),
+            plan.id
+        );
+    }
+
     let config = load_config()?;
-    let provider = QueryProvider::new(config).context("Failed to create query provider")?;
+    crate::branding::configure(&config, false);
+    crate::ui::configure(&config);
+    // Resumes have no --yes, but TERMINALAI_ASSUME_YES still answers for them
+    crate::ui::configure_assume_yes(false);
+    crate::configure_extraction(&config)?;
+    crate::shell::configure(&config, options.shell.as_deref())?;
+    crate::context::configure(&config, false);
+    let mut execution_options = plan_execution_options(&config, None)?;
+    execution_options.working_dir = Some(plan.workdir.clone());
+    let history_origin = config.history_origin("tai resume", &plan.prompt);
+    let plans_dir = crate::plans::plans_dir_for(&config);
+    plan.prompt_storage = config.history_prompt();
+
+    println!(
+        "🔁 Resuming plan {} in {}: {}\n",
+        plan.id,
+        plan.workdir.display(),
+        plan.prompt
+    );
+    println!("{}", render_progress(&plan));
+
+    if options.fix {
+        if plan.steps[next].state != crate::plans::StepState::Failed {
+            anyhow::bail!(
+                "Step {} of plan {} has not failed; there is nothing to fix",
+                next + 1,
+                plan.id
+            );
+        }
+        let provider = QueryProvider::new(config)
+            .context("Failed to create query provider")?
+            .with_context_in(&plan.workdir);
+        let fix = request_plan(
+            &provider,
+            &fix_prompt(&plan, next, &[]),
+            false,
+            execution_options.dedup,
+            &mut PhaseTimer::new(),
+            true,
+        )
+        .await?;
+        if fix.commands.is_empty() {
+            anyhow::bail!(
+                "The model suggested no commands to replace step {}",
+                next + 1
+            );
+        }
+        plan.replace_step(
+            next,
+            &fix.commands,
+            &fix.rationales_for(&fix.commands),
+            &fix.outputs_for(&fix.commands),
+        );
+    }
+
+    let commands = plan.commands_from(next);
+    crate::policy::check(&commands)?;
+    let messages = crate::ui::messages();
+    crate::ui::preview(&messages.plan_commands, &commands);
+    if !crate::risk::confirm_commands(&commands, false, &messages.confirm_execute_sequence)? {
+        println!("{}", messages.not_executed);
+        return Ok(());
+    }
+
+    let mut recorder = ScriptRecorder::new();
+    let result = execute_saved_plan(
+        &mut plan,
+        plans_dir.as_deref(),
+        &mut execution_options,
+        &mut recorder,
+        None,
+    )
+    .await;
+    if let Some(origin) = &history_origin {
+        crate::history::record(
+            &crate::history::HistoryEntry::new(
+                origin,
+                &commands,
+                execution_options.working_dir.as_deref(),
+                result.is_ok(),
+            )
+            .with_exit_codes(&result),
+        );
+    }
+    result?;
+    println!("🎉 Orchestration complete!");
+    Ok(())
+}
+
+/// Every step of `plan` with whether it ran, and what a failed one printed to stderr
+fn render_progress(plan: &SavedPlan) -> String {
+    use crate::plans::StepState;
+
+    let mut lines = Vec::new();
+    for (i, step) in plan.steps.iter().enumerate() {
+        match step.state {
+            StepState::Succeeded => lines.push(format!("  {}. ✅ {}", i + 1, step.command)),
+            StepState::Pending => lines.push(format!("  {}. ⏳ {}", i + 1, step.command)),
+            StepState::Skipped => lines.push(format!("  {}. ⏭️  {}", i + 1, step.command)),
+            StepState::Failed => {
+                let code = step
+                    .exit_code
+                    .map(|code| format!(" (exit code {code})"))
+                    .unwrap_or_default();
+                lines.push(format!("  {}. ❌ {}{code}", i + 1, step.command));
+                lines.extend(step.stderr.iter().map(|line| format!("       {line}")));
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+/// The request asking the model for commands to replace the failed step `index`,
+/// with the `earlier` replacements for it that failed too
+fn fix_prompt(plan: &SavedPlan, index: usize, earlier: &[String]) -> String {
+    use crate::plans::StepState;
+
+    let mut prompt = format!(
+        "This plan was made for the request: {}\n\nSteps:\n",
+        plan.prompt
+    );
+    for (i, step) in plan.steps.iter().enumerate() {
+        let state = match step.state {
+            StepState::Succeeded => "done".to_string(),
+            StepState::Pending => "not run yet".to_string(),
+            StepState::Skipped => "skipped".to_string(),
+            StepState::Failed => match step.exit_code {
+                Some(code) => format!("failed with exit code {code}"),
+                None => "failed".to_string(),
+            },
+        };
+        prompt.push_str(&format!("{}. {} ({state})\n", i + 1, step.command));
+    }
+    let step = &plan.steps[index];
+    if step.stderr.is_empty() {
+        prompt.push_str(&format!(
+            "\nStep {} printed nothing to stderr.\n",
+            index + 1
+        ));
+    } else {
+        prompt.push_str(&format!(
+            "\nStep {} printed this to stderr:\n{}\n",
+            index + 1,
+            step.stderr.join("\n")
+        ));
+    }
+    if !plan.variables.is_empty() {
+        prompt.push_str("\nOutput captured so far, filled in for {{name}}:\n");
+        for (name, value) in plan.variables.iter() {
+            prompt.push_str(&format!("- {name}: {value}\n"));
+        }
+    }
+    if !earlier.is_empty() {
+        prompt.push_str("\nEarlier attempts that also failed:\n");
+        for error in earlier {
+            prompt.push_str(&format!("- {error}\n"));
+        }
+    }
+    prompt.push_str(&format!(
+        "\nGive only the commands that replace step {} so the steps after it can run. Don't repeat the steps that are done or the ones after it.",
+        index + 1
+    ));
+    prompt
+}
+
+/// Request the plan, let the user refine it with `--interactive-refine`, edit it with
+/// `--edit-plan` and confirm it. Returns the commands with the model's rationale for
+/// each, or `None` when there is nothing to run, the user declined or `--dry-run`
+/// only showed the plan.
+pub(crate) async fn confirmed_plan(
+    provider: &QueryProvider,
+    query: &str,
+    directory: Option<&Path>,
+    options: &OrchestrationOptions,
+    execution: &ExecutionOptions,
+    timer: &mut PhaseTimer,
+) -> Result<Option<ConfirmedPlan>> {
+    let dedup = execution.dedup;
+    let messages = crate::ui::messages();
+    let mut refinement = Refinement::new(query);
+    let plan = loop {
+        let mut plan = request_plan(
+            provider,
+            &refinement.query(),
+            options.show_reasoning,
+            dedup,
+            timer,
+            true,
+        )
+        .await?;
+        if let Some(dir) = directory {
+            plan.commands = plan
+                .commands
+                .iter()
+                .filter_map(|cmd| {
+                    let stripped = strip_redundant_cd(cmd, dir)?;
+                    if let Some(why) = plan.rationales.get(cmd).cloned() {
+                        plan.rationales.entry(stripped.clone()).or_insert(why);
+                    }
+                    if let Some(name) = plan.outputs.get(cmd).cloned() {
+                        plan.outputs.entry(stripped.clone()).or_insert(name);
+                    }
+                    Some(stripped)
+                })
+                .collect();
+        }
+        if !options.interactive_refine {
+            break plan;
+        }
+
+        if !plan.commands.is_empty() {
+            preview_plan(&plan, &plan.commands);
+        }
+        let choice = read_refine_choice(&mut std::io::stdin().lock(), &mut std::io::stdout());
+        if choice == RefineChoice::Regenerate {
+            provider.refresh_next();
+        }
+        match refinement.next(choice, &plan.commands) {
+            RefineStep::Requery => println!("🔄 Regenerating the plan...\n"),
+            RefineStep::Accept => break plan,
+            RefineStep::Quit => {
+                println!("{}", messages.not_executed);
+                return Ok(None);
+            }
+        }
+    };
+    let mut commands = plan.commands.clone();
+
+    if commands.is_empty() {
+        println!("⚠️  No specific commands could be generated from your request.");
+        println!("💡 Try being more specific about what operations you want to perform.");
+        return Ok(None);
+    }
+
+    if options.dry_run && options.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&plan.records(&commands))?
+        );
+        return Ok(None);
+    }
+    if options.dry_run {
+        crate::explain::show_dry_run(
+            &messages.plan_commands,
+            &crate::category::label_commands(&commands),
+            &plan.rationales_for(&commands),
+        );
+        return Ok(None);
+    }
+
+    if options.edit_plan {
+        commands = edit_plan(&commands)?;
+        if commands.is_empty() {
+            println!("❌ The edited plan is empty; nothing to execute.");
+            return Ok(None);
+        }
+    }
+    crate::policy::check(&commands)?;
+
+    // An accepted refinement only needs flagged commands confirmed again, and with
+    // confirm_each the rest are asked about one at a time as they run
+    let confirmed = if options.interactive_refine && !options.edit_plan {
+        crate::risk::confirm_flagged(&commands, options.strict)?
+    } else if execution.confirm_each {
+        preview_plan(&plan, &commands);
+        crate::risk::confirm_flagged(&commands, options.strict)?
+    } else {
+        preview_plan(&plan, &commands);
+        crate::risk::confirm_commands(
+            &commands,
+            options.strict,
+            &messages.confirm_execute_sequence,
+        )?
+    };
+    if !confirmed {
+        println!("{}", messages.not_executed);
+        return Ok(None);
+    }
+    let rationales = plan.rationales_for(&commands);
+    let outputs = plan.outputs_for(&commands);
+    Ok(Some(ConfirmedPlan {
+        commands,
+        rationales,
+        outputs,
+        response: plan.response,
+    }))
+}
+
+/// List `commands` from `plan` with their category and rationale
+fn preview_plan(plan: &Plan, commands: &[String]) {
+    crate::ui::preview_explained(
+        &crate::ui::messages().plan_commands,
+        &crate::category::label_commands(commands),
+        &plan.rationales_for(commands),
+    );
+}
+
+/// An answer to the `--interactive-refine` menu
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefineChoice {
+    /// Re-query with this clarification and the plan so far
+    EditPrompt(String),
+    /// Ask again with the same prompt
+    Regenerate,
+    Accept,
+    Quit,
+}
+
+/// What the refine loop does after an answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefineStep {
+    Requery,
+    Accept,
+    Quit,
+}
+
+/// The prompt as refined so far: the original query, the clarifications the user
+/// appended and the plan they were given for
+#[derive(Debug, Clone)]
+pub struct Refinement {
+    query: String,
+    clarifications: Vec<String>,
+    previous_plan: Vec<String>,
+}
+
+impl Refinement {
+    pub fn new(query: &str) -> Self {
+        Self {
+            query: query.to_string(),
+            clarifications: Vec::new(),
+            previous_plan: Vec::new(),
+        }
+    }
+
+    /// The prompt to send: the original one until the user clarifies it, then with
+    /// the previous plan and every clarification appended
+    pub fn query(&self) -> String {
+        if self.clarifications.is_empty() {
+            return self.query.clone();
+        }
+        let mut query = format!("{}\n\nYour previous plan was:\n", self.query);
+        if self.previous_plan.is_empty() {
+            query.push_str("(no commands)\n");
+        }
+        for cmd in &self.previous_plan {
+            query.push_str(&format!("COMMAND: {cmd}\n"));
+        }
+        query.push_str("\nRevise it with these clarifications:\n");
+        for clarification in &self.clarifications {
+            query.push_str(&format!("- {clarification}\n"));
+        }
+        query
+    }
+
+    /// Apply `choice` to the plan `commands` the user was shown
+    pub fn next(&mut self, choice: RefineChoice, commands: &[String]) -> RefineStep {
+        match choice {
+            RefineChoice::EditPrompt(clarification) => {
+                self.clarifications.push(clarification);
+                self.previous_plan = commands.to_vec();
+                RefineStep::Requery
+            }
+            RefineChoice::Regenerate => RefineStep::Requery,
+            RefineChoice::Accept => RefineStep::Accept,
+            RefineChoice::Quit => RefineStep::Quit,
+        }
+    }
+}
+
+/// The menu answer `answer` names, if any
+fn parse_refine_choice(answer: &str) -> Option<RefineChoice> {
+    match answer.trim().to_lowercase().as_str() {
+        "e" | "edit" => Some(RefineChoice::EditPrompt(String::new())),
+        "r" | "regenerate" => Some(RefineChoice::Regenerate),
+        "a" | "accept" => Some(RefineChoice::Accept),
+        "q" | "quit" => Some(RefineChoice::Quit),
+        _ => None,
+    }
+}
 
-    // System prompt for query orchestration
-    let orchestration_prompt = r#"
+/// Ask the refine menu until `input` gives a valid answer; the end of input quits
+fn read_refine_choice(
+    input: &mut (impl BufRead + ?Sized),
+    output: &mut (impl Write + ?Sized),
+) -> RefineChoice {
+    loop {
+        let _ = write!(
+            output,
+            "\n❓ [e]dit prompt / [r]egenerate / [a]ccept / [q]uit: "
+        );
+        let _ = output.flush();
+        let mut answer = String::new();
+        if input.read_line(&mut answer).unwrap_or(0) == 0 {
+            return RefineChoice::Quit;
+        }
+        match parse_refine_choice(&answer) {
+            Some(RefineChoice::EditPrompt(_)) => {
+                let _ = write!(output, "✏️  Add to your prompt: ");
+                let _ = output.flush();
+                let mut clarification = String::new();
+                if input.read_line(&mut clarification).unwrap_or(0) == 0 {
+                    return RefineChoice::Quit;
+                }
+                let clarification = clarification.trim();
+                if !clarification.is_empty() {
+                    return RefineChoice::EditPrompt(clarification.to_string());
+                }
+            }
+            Some(choice) => return choice,
+            None => {}
+        }
+    }
+}
+
+/// System prompt asking for a plan of `COMMAND: ` lines
+const ORCHESTRATION_PROMPT: &str = r#"
 You are a terminal command orchestrator. Your job is to analyze user requests and break them down into specific terminal commands that can be executed safely.
 
 Convert user requests into actual shell commands that accomplish the task. Focus on common, safe operations like:
@@ -20,190 +695,1029 @@ Convert user requests into actual shell commands that accomplish the task. Focus
 - Network: curl, wget (for safe downloads)
 
 Respond with a list of specific shell commands to execute, one per line, starting each line with "COMMAND: " followed by the command.
+After each command, add a line starting with "WHY: " that says in one short sentence why the command is needed.
+When a later command needs what an earlier one prints, such as a temporary directory, start the earlier line with "COMMAND[OUT=name]: " instead, and write {{name}} in the later commands. It is replaced by the printed text, without surrounding whitespace and quoted for the shell, before they run.
 
 Example:
 User: "backup all python files to a new folder and then find all TODO comments in them"
 Response:
 COMMAND: mkdir -p backup_python
+WHY: Create the folder the backups go to
 COMMAND: find . -name "*.py" -exec cp {} backup_python/ \;
+WHY: Copy every Python file into it
 COMMAND: grep -r "TODO" backup_python/
+WHY: List the TODO comments in the copies
 
 Be specific, safe, and use standard UNIX commands. Avoid destructive operations without explicit confirmation.
+Example with a captured value:
+User: "copy the config folder into a new temporary directory, archive it and print where the archive is"
+Response:
+COMMAND[OUT=backup_dir]: mktemp -d
+WHY: Create the temporary directory
+COMMAND: cp -r config "{{backup_dir}}/"
+WHY: Copy the folder into it
+COMMAND: tar czf "{{backup_dir}}.tar.gz" -C "{{backup_dir}}" config
+WHY: Archive the copy
+COMMAND: echo "{{backup_dir}}.tar.gz"
+WHY: Print where the archive is
+
 Do not include the example commands in your response - only provide commands for the specific user request.
 "#;
 
-    // Get orchestration plan from AI
-    let orchestration_response = provider
-        .send_query(orchestration_prompt, prompt)
-        .await
-        .context("Failed to get orchestration plan from AI")?;
+/// The model's plan for a prompt
+pub(crate) struct Plan {
+    pub commands: Vec<String>,
+    /// The model's `WHY:` line for each command that has one
+    pub rationales: HashMap<String, String>,
+    /// The variable each `COMMAND[OUT=name]:` command's output is captured into
+    pub outputs: HashMap<String, String>,
+    /// The model's answer the plan was parsed from, reasoning stripped
+    pub response: String,
+}
 
-    println!("📋 Execution Plan:\n{orchestration_response}\n");
+/// A plan the user accepted, as it will run
+pub(crate) struct ConfirmedPlan {
+    pub commands: Vec<String>,
+    /// The rationale of each command, in the same order
+    pub rationales: Vec<Option<String>>,
+    /// The variable each command's output is captured into, in the same order
+    pub outputs: Vec<Option<String>>,
+    pub response: String,
+}
 
-    // Parse the orchestration response for commands
-    let commands = parse_orchestration_response(&orchestration_response)?;
+impl Plan {
+    /// The rationale of each of `commands`, which may have been edited since
+    pub fn rationales_for(&self, commands: &[String]) -> Vec<Option<String>> {
+        commands
+            .iter()
+            .map(|cmd| self.rationales.get(cmd).cloned())
+            .collect()
+    }
 
-    if commands.is_empty() {
-        println!("⚠️  No specific commands could be generated from your request.");
-        println!("💡 Try being more specific about what operations you want to perform.");
-        return Ok(());
+    /// The output variable of each of `commands`
+    pub fn outputs_for(&self, commands: &[String]) -> Vec<Option<String>> {
+        commands
+            .iter()
+            .map(|cmd| self.outputs.get(cmd).cloned())
+            .collect()
     }
 
-    // Show commands and ask for confirmation
-    println!("🤖 Commands to execute:");
-    for (i, cmd) in commands.iter().enumerate() {
-        println!("  {}. {}", i + 1, cmd);
+    /// `commands` as `--dry-run --json` prints them
+    pub fn records<'a>(&'a self, commands: &'a [String]) -> Vec<PlanRecord<'a>> {
+        commands
+            .iter()
+            .map(|cmd| PlanRecord {
+                command: cmd,
+                category: crate::category::categorize_command(cmd),
+                rationale: self.rationales.get(cmd).map(String::as_str),
+                output: self.outputs.get(cmd).map(String::as_str),
+            })
+            .collect()
     }
+}
 
-    print!("\n❓ Execute these commands in sequence? [Y/n]: ");
-    std::io::Write::flush(&mut std::io::stdout()).unwrap();
+/// A command of a plan as `tai -p --dry-run --json` prints it
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct PlanRecord<'a> {
+    pub command: &'a str,
+    pub category: crate::category::Category,
+    /// The model's `WHY:` line, when it gave one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rationale: Option<&'a str>,
+    /// The variable the command's output is captured into
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<&'a str>,
+}
 
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input).unwrap();
+/// Ask the model to break `prompt` down into commands. When `live`, the plan is shown
+/// as it is generated, or after a spinner for providers that can't stream.
+pub(crate) async fn request_plan(
+    provider: &QueryProvider,
+    prompt: &str,
+    show_reasoning: bool,
+    dedup: Dedup,
+    timer: &mut PhaseTimer,
+    live: bool,
+) -> Result<Plan> {
+    let extract = |response: &str| {
+        parse_orchestration_response(&crate::strip_reasoning(response)).unwrap_or_default()
+    };
+    // Streamed answers leave out the reasoning, so --show-reasoning waits for all of it
+    let streamed = live && !show_reasoning && provider.streams();
+    let query_started = Instant::now();
+    let response = if streamed {
+        crate::ui::show("📋 Execution Plan:\n");
+        let mut spinner = Some(crate::ui::Spinner::start("Waiting for the plan..."));
+        let mut on_chunk = |chunk: &str| {
+            spinner.take();
+            crate::ui::show_streamed(chunk);
+        };
+        provider
+            .send_command_query_streaming(ORCHESTRATION_PROMPT, prompt, extract, &mut on_chunk)
+            .await
+    } else {
+        let _spinner = live.then(|| crate::ui::Spinner::start("Generating the plan..."));
+        provider
+            .send_command_query(ORCHESTRATION_PROMPT, prompt, extract)
+            .await
+    };
+    timer.record("provider query", query_started);
+    let response = response.context("Failed to get orchestration plan from AI")?;
+
+    if show_reasoning {
+        crate::print_reasoning(&response);
+    }
 
-    if input.trim().to_lowercase() == "n" || input.trim().to_lowercase() == "no" {
-        println!("❌ Commands not executed.");
-        return Ok(());
+    let extraction_started = Instant::now();
+    let response = crate::strip_reasoning(&response);
+    let commands = parse_orchestration_response(&response);
+    timer.record("extraction", extraction_started);
+    if streamed {
+        crate::ui::show(if response.ends_with('\n') {
+            "\n"
+        } else {
+            "\n\n"
+        });
+    } else if live {
+        crate::ui::show(&format!("📋 Execution Plan:\n{response}\n\n"));
     }
+    let commands = dedup_commands(commands?, dedup);
+    crate::check_plan_size(&commands)?;
+
+    Ok(Plan {
+        commands,
+        rationales: parse_rationales(&response),
+        outputs: parse_outputs(&response),
+        response: response.to_string(),
+    })
+}
 
-    // Execute commands in sequence
+/// How plan steps are executed; steps print their own progress, so only branding
+/// and failures come from the executor
+pub(crate) fn plan_execution_options(
+    config: &TerminalAIConfig,
+    record: Option<&Path>,
+) -> Result<ExecutionOptions> {
+    let mut execution_options = ExecutionOptions {
+        quiet: true,
+        error_signatures: config.error_signatures(),
+        dedup: config.command_dedup(),
+        auto_execute_installs: config.auto_execute_installs(),
+        confirm_each: config.confirm_each_command(),
+        ..Default::default()
+    };
+    if let Some(path) = record {
+        execution_options.transcript = Some(crate::transcript::Transcript::open_shared(path)?);
+    }
+    Ok(execution_options)
+}
+
+pub(crate) async fn execute_plan(
+    commands: &[String],
+    options: &ExecutionOptions,
+    recorder: &mut ScriptRecorder,
+) -> Result<()> {
+    let mut confirm_each = options.confirm_each;
     for (i, cmd) in commands.iter().enumerate() {
-        println!("\n🔄 Step {}: Executing: {}", i + 1, cmd);
-        println!("{}", "=".repeat(60));
+        match confirm_plan_step(i + 1, cmd, &mut confirm_each) {
+            Some(true) => execute_step(i + 1, cmd, options, recorder).await?,
+            Some(false) => {}
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+/// Whether to run plan step number `step`, asking first while `confirm_each` holds
+/// (`confirm_each_command`): `Some(false)` skips it and `None` stops the plan. An
+/// answer of all runs it and stops asking.
+fn confirm_plan_step(step: usize, cmd: &str, confirm_each: &mut bool) -> Option<bool> {
+    if !*confirm_each {
+        return Some(true);
+    }
+    match crate::ui::confirm_step(step, cmd) {
+        crate::ui::Step::Run => Some(true),
+        crate::ui::Step::Skip => {
+            println!("⏭️  Skipped: {cmd}");
+            Some(false)
+        }
+        crate::ui::Step::All => {
+            *confirm_each = false;
+            Some(true)
+        }
+        crate::ui::Step::Quit => {
+            println!("🛑 Stopping; the remaining steps were not executed.");
+            None
+        }
+    }
+}
+
+/// Asks the model to replace failed steps of a running plan, like `resolve_ai` does
+/// for failed installs, up to `replan_attempts` times per plan
+pub(crate) struct Replanner<'a> {
+    provider: &'a QueryProvider,
+    strict: bool,
+    attempts_left: u32,
+    /// The failures so far, shown with every later request for a fix
+    errors: Vec<String>,
+}
+
+impl<'a> Replanner<'a> {
+    pub(crate) fn new(provider: &'a QueryProvider, attempts: u32, strict: bool) -> Self {
+        Self {
+            provider,
+            strict,
+            attempts_left: attempts,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Commands the user confirmed to run in place of the failed step `index`, or
+    /// `None` when attempts ran out, the model had none or the user declined
+    async fn replace(&mut self, plan: &SavedPlan, index: usize, dedup: Dedup) -> Option<Plan> {
+        if self.attempts_left == 0 {
+            return None;
+        }
+        self.attempts_left -= 1;
+        println!(
+            "\n🔄 Step {} failed; asking for commands to replace it...",
+            index + 1
+        );
+        let prompt = fix_prompt(plan, index, &self.errors);
+        self.errors.push(describe_failure(&plan.steps[index]));
+        let fix = match request_plan(
+            self.provider,
+            &prompt,
+            false,
+            dedup,
+            &mut PhaseTimer::new(),
+            true,
+        )
+        .await
+        {
+            Ok(fix) if !fix.commands.is_empty() => fix,
+            Ok(_) => {
+                println!("⚠️  The model suggested no commands to replace the step.");
+                return None;
+            }
+            Err(e) => {
+                eprintln!("⚠️  Failed to ask for replacement commands: {e:#}");
+                return None;
+            }
+        };
+        let messages = crate::ui::messages();
+        let confirmed = crate::policy::check(&fix.commands)
+            .and_then(|()| {
+                preview_plan(&fix, &fix.commands);
+                crate::risk::confirm_commands(
+                    &fix.commands,
+                    self.strict,
+                    &messages.confirm_resolution,
+                )
+            })
+            .unwrap_or_else(|e| {
+                eprintln!("⚠️  {e:#}");
+                false
+            });
+        if !confirmed {
+            println!("{}", messages.resolution_not_executed);
+            return None;
+        }
+        Some(fix)
+    }
+}
 
-        let result = execute_shell_command(cmd).await;
+/// One line about a failed step for [`fix_prompt`]'s list of earlier attempts
+fn describe_failure(step: &crate::plans::PlanStep) -> String {
+    let mut line = format!("`{}` failed", step.command);
+    if let Some(code) = step.exit_code {
+        line.push_str(&format!(" with exit code {code}"));
+    }
+    if let Some(last) = step.stderr.last() {
+        line.push_str(&format!(": {last}"));
+    }
+    line
+}
 
+/// Run `plan` from its first unfinished step, saving it to `dir` (not at all when
+/// `None`) before and after every step. A failed step is replaced by the `replanner`'s
+/// confirmed fix while it has attempts left; otherwise it is saved with its stderr
+/// so `tai resume` can continue from it. A finished plan is removed.
+pub(crate) async fn execute_saved_plan(
+    plan: &mut SavedPlan,
+    dir: Option<&Path>,
+    options: &mut ExecutionOptions,
+    recorder: &mut ScriptRecorder,
+    mut replanner: Option<&mut Replanner<'_>>,
+) -> Result<()> {
+    let save = |plan: &SavedPlan| {
+        if let Some(dir) = dir {
+            if let Err(e) = plan.save_in(dir) {
+                eprintln!("⚠️  Failed to save the plan: {e:#}");
+            }
+        }
+    };
+    let transcript = capture_output(options);
+    save(plan);
+    let mut confirm_each = options.confirm_each;
+    while let Some(index) = plan.next_step() {
+        match confirm_plan_step(index + 1, &plan.steps[index].command, &mut confirm_each) {
+            Some(true) => {}
+            Some(false) => {
+                plan.skipped(index);
+                save(plan);
+                continue;
+            }
+            None => {
+                if dir.is_some() {
+                    eprintln!(
+                        "💾 Plan saved; run `tai resume {}` to continue from step {}.",
+                        plan.id,
+                        index + 1
+                    );
+                }
+                return Ok(());
+            }
+        }
+        take_output(&transcript);
+        let result = async {
+            let command = plan.command_to_run(index)?;
+            check_substituted(&plan.steps[index].command, &command, options.strict)?;
+            execute_step(index + 1, &command, options, recorder).await
+        }
+        .await;
+        let (stdout, mut stderr) = take_output(&transcript);
+        let result = result.and_then(|()| {
+            if let Some((name, value)) = plan.capture(index, &stdout)? {
+                println!("📦 {{{{{name}}}}} = {value}\n");
+            }
+            Ok(())
+        });
         match result {
-            Ok(_) => println!("✅ Step {} completed successfully (exit code: 0)\n", i + 1),
+            Ok(()) => {
+                plan.succeeded(index);
+                // The file of a finished plan is removed below, not written again
+                if plan.next_step().is_some() {
+                    save(plan);
+                }
+            }
             Err(e) => {
-                eprintln!("❌ Step {} failed: {}\n", i + 1, e);
-                eprintln!("🛑 Stopping execution due to non-zero exit code.");
+                let exit_code = e.downcast_ref::<CommandFailed>().map(|f| f.exit_code);
+                // Why a step that never ran or printed nothing to capture failed
+                if exit_code.is_none() {
+                    stderr.push(format!("{e:#}"));
+                }
+                plan.failed(index, exit_code, stderr);
+                save(plan);
+                if let Some(replanner) = replanner.as_deref_mut() {
+                    if let Some(fix) = replanner.replace(plan, index, options.dedup).await {
+                        plan.replace_step(
+                            index,
+                            &fix.commands,
+                            &fix.rationales_for(&fix.commands),
+                            &fix.outputs_for(&fix.commands),
+                        );
+                        save(plan);
+                        continue;
+                    }
+                }
+                if dir.is_some() {
+                    eprintln!(
+                        "💾 Plan saved; run `tai resume {}` to continue from step {}, or `tai resume {} --fix` to have the step fixed first.",
+                        plan.id,
+                        index + 1,
+                        plan.id
+                    );
+                }
                 return Err(e);
             }
         }
     }
+    if let Some(dir) = dir {
+        if let Err(e) = plan.remove_from(dir) {
+            eprintln!("⚠️  Failed to remove the finished plan: {e:#}");
+        }
+    }
+    Ok(())
+}
 
-    println!("🎉 Orchestration complete!");
+/// Keep what commands print in memory as well, so a failed step's stderr can be
+/// saved with its plan; a `--record` transcript goes on being written
+fn capture_output(options: &mut ExecutionOptions) -> SharedTranscript {
+    let transcript = options
+        .transcript
+        .get_or_insert_with(crate::transcript::Transcript::in_memory_shared);
+    transcript
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .keep_lines();
+    transcript.clone()
+}
+
+/// The stdout and stderr lines recorded since the last call
+fn take_output(transcript: &SharedTranscript) -> (Vec<String>, Vec<String>) {
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let lines = transcript
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .take_lines();
+    for line in lines {
+        if let Some(text) = line.strip_prefix("  ") {
+            stdout.push(text.to_string());
+        } else if let Some(text) = line.strip_prefix("! ") {
+            stderr.push(text.to_string());
+        }
+    }
+    (stdout, stderr)
+}
+
+/// Check `command`, plan step `template` with captured output filled in, like the
+/// plan was checked: the command policy applies to it, and it is confirmed again
+/// when the values made it riskier
+fn check_substituted(template: &str, command: &str, strict: bool) -> Result<()> {
+    if command == template {
+        return Ok(());
+    }
+    if let Some(reason) = crate::policy::violation(command) {
+        anyhow::bail!(
+            "Refusing to run a command the command policy does not allow: {command} ({reason})"
+        );
+    }
+    let riskier = crate::risk::assess(command).tier() > crate::risk::assess(template).tier();
+    if riskier && !crate::risk::confirm_flagged(&[command.to_string()], strict)? {
+        anyhow::bail!("Command with captured output filled in not confirmed: {command}");
+    }
     Ok(())
 }
 
-fn parse_orchestration_response(response: &str) -> Result<Vec<String>> {
-    let mut commands = Vec::new();
+/// Run plan step number `step`
+async fn execute_step(
+    step: usize,
+    cmd: &str,
+    options: &ExecutionOptions,
+    recorder: &mut ScriptRecorder,
+) -> Result<()> {
+    println!("\n🔄 Step {step}: Executing: {cmd}");
+    println!("{}", "=".repeat(60));
+
+    if !crate::copy_verify::confirm_overwrites(cmd, options.working_dir.as_deref())? {
+        eprintln!("🛑 Stopping execution before overwriting existing files.");
+        anyhow::bail!("Not overwriting existing files: {cmd}");
+    }
+    if !crate::confirm_install(cmd, options.auto_execute_installs) {
+        eprintln!("🛑 Stopping execution before the package command.");
+        anyhow::bail!("Package command not confirmed: {cmd}");
+    }
 
-    for line in response.lines() {
-        let line = line.trim();
-        if line.starts_with("COMMAND:") {
-            let command = line.strip_prefix("COMMAND:").unwrap().trim();
+    let result = execute_command(cmd, options).await;
 
-            // Basic validation - ensure command is not empty and doesn't contain potentially dangerous patterns
-            if !command.is_empty() && is_safe_command(command) {
-                commands.push(command.to_string());
-            }
+    match result {
+        Ok(_) => {
+            recorder.record(cmd);
+            println!("✅ Step {step} completed successfully (exit code: 0)\n");
+            Ok(())
+        }
+        Err(e) => {
+            recorder.record_failed(cmd);
+            eprintln!("❌ Step {step} failed: {e}\n");
+            eprintln!("🛑 Stopping execution due to non-zero exit code.");
+            Err(e)
         }
     }
+}
 
-    Ok(commands)
+/// Splits streamed text into complete lines
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    pending: String,
 }
 
-fn is_safe_command(command: &str) -> bool {
-    // Basic safety checks - reject obviously dangerous patterns
-    let dangerous_patterns = [
-        "rm -rf /",
-        "dd if=",
-        "mkfs.",
-        "fdisk",
-        "chmod 777",
-        "sudo rm",
-        ">/dev/",
-    ];
+impl LineBuffer {
+    /// Add `chunk`, returning the lines it completed
+    pub fn push(&mut self, chunk: &str) -> Vec<String> {
+        self.pending.push_str(chunk);
+        let Some(end) = self.pending.rfind('\n') else {
+            return Vec::new();
+        };
+        let complete: String = self.pending.drain(..=end).collect();
+        complete.lines().map(str::to_string).collect()
+    }
 
-    for pattern in &dangerous_patterns {
-        if command.contains(pattern) {
-            return false;
+    /// The unterminated last line, once the stream has ended
+    pub fn finish(self) -> Option<String> {
+        (!self.pending.is_empty()).then_some(self.pending)
+    }
+}
+
+/// The plan's commands from a streamed response, each as soon as its `COMMAND:`
+/// line is complete. Lines inside reasoning blocks are skipped.
+#[derive(Debug, Default)]
+pub struct StreamedCommands {
+    lines: LineBuffer,
+    dedup: Dedup,
+    /// The closing tag of the reasoning block the stream is in
+    reasoning_close: Option<&'static str>,
+    seen: Vec<String>,
+    /// The variable each `COMMAND[OUT=name]:` command's output is captured into
+    outputs: HashMap<String, String>,
+}
+
+impl StreamedCommands {
+    pub fn new(dedup: Dedup) -> Self {
+        Self {
+            dedup,
+            ..Default::default()
         }
     }
 
-    // Additional check for common safe command prefixes
-    let safe_prefixes = [
-        "ls", "find", "grep", "cat", "echo", "pwd", "whoami", "mkdir", "cp", "mv", "tar", "gzip",
-        "gunzip", "zip", "unzip", "sort", "uniq", "wc", "head", "tail", "ps", "df", "du", "curl",
-        "wget", "git",
-    ];
+    /// Add `chunk` of the response, returning the commands it completed
+    pub fn push(&mut self, chunk: &str) -> Vec<String> {
+        self.lines
+            .push(chunk)
+            .iter()
+            .filter_map(|line| self.command(line))
+            .collect()
+    }
+
+    /// The command on the unterminated last line, once the stream has ended
+    pub fn finish(&mut self) -> Option<String> {
+        std::mem::take(&mut self.lines)
+            .finish()
+            .and_then(|line| self.command(&line))
+    }
+
+    /// The variable `command`'s output is captured into, if its line asked for one
+    pub fn output_of(&self, command: &str) -> Option<String> {
+        self.outputs.get(command).cloned()
+    }
+
+    fn command(&mut self, line: &str) -> Option<String> {
+        let line = self.outside_reasoning(line);
+        self.outputs.extend(parse_outputs(&line));
+        let command = parse_orchestration_response(&line).ok()?.pop()?;
+        let repeated = match self.dedup {
+            Dedup::Consecutive => self.seen.last() == Some(&command),
+            Dedup::All => self.seen.contains(&command),
+        };
+        self.seen.push(command.clone());
+        (!repeated).then_some(command)
+    }
 
-    for prefix in &safe_prefixes {
-        if command.starts_with(prefix) {
-            return true;
+    /// The parts of `line` outside reasoning blocks, which may span lines
+    fn outside_reasoning(&mut self, line: &str) -> String {
+        let mut kept = String::new();
+        let mut rest = line;
+        loop {
+            if let Some(close) = self.reasoning_close {
+                let Some(end) = rest.find(close) else {
+                    return kept;
+                };
+                rest = &rest[end + close.len()..];
+                self.reasoning_close = None;
+            }
+            let next_tag = crate::REASONING_TAGS
+                .iter()
+                .filter_map(|(open, close)| rest.find(open).map(|pos| (pos, *open, *close)))
+                .min_by_key(|(pos, _, _)| *pos);
+            let Some((start, open, close)) = next_tag else {
+                kept.push_str(rest);
+                return kept;
+            };
+            kept.push_str(&rest[..start]);
+            rest = &rest[start + open.len()..];
+            self.reasoning_close = Some(close);
         }
     }
+}
 
-    // Allow other commands but log them for review
-    println!("⚠️  Allowing command that may need review: {command}");
-    true
+/// Generate the plan and run each command as soon as its line arrives (`--yes`), so
+/// execution overlaps generation. Commands the injection check flags are refused,
+/// since nobody is asked. Returns the commands run and the outcome.
+async fn stream_plan(
+    provider: &QueryProvider,
+    query: &str,
+    options: &mut ExecutionOptions,
+    recorder: &mut ScriptRecorder,
+) -> (Vec<String>, Option<String>, Result<()>) {
+    let transcript = capture_output(options);
+    let options = &*options;
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let mut streamed = StreamedCommands::new(options.dedup);
+
+    let generation = async move {
+        let mut on_chunk = |chunk: &str| {
+            for command in streamed.push(chunk) {
+                let output = streamed.output_of(&command);
+                let _ = sender.send((command, output));
+            }
+        };
+        let response = provider
+            .send_commands_streaming(ORCHESTRATION_PROMPT, query, &mut on_chunk)
+            .await;
+        if let Some(command) = streamed.finish() {
+            let output = streamed.output_of(&command);
+            let _ = sender.send((command, output));
+        }
+        response
+    };
+
+    let execution = async {
+        let mut executed: Vec<String> = Vec::new();
+        let mut variables = crate::variables::Variables::default();
+        let mut result = Ok(());
+        while let Some((command, output)) = receiver.recv().await {
+            // After a failure the rest of the plan is drained but not run
+            if result.is_err() {
+                continue;
+            }
+            let command = match &options.working_dir {
+                Some(dir) => match strip_redundant_cd(&command, dir) {
+                    Some(command) => command,
+                    None => continue,
+                },
+                None => command,
+            };
+            // Filled in before the checks, which then see what actually runs
+            let command = match crate::shell::shell()
+                .and_then(|shell| variables.substitute(&command, shell.kind))
+            {
+                Ok(command) => command,
+                Err(e) => {
+                    result = Err(e);
+                    continue;
+                }
+            };
+            if let Some(reason) = crate::policy::violation(&command) {
+                result = Err(anyhow::anyhow!(
+                    "Refusing to run a command the command policy does not allow: {command} ({reason})"
+                ));
+                continue;
+            }
+            let findings = crate::risk::assess_all(std::slice::from_ref(&command));
+            if !findings.is_empty() {
+                crate::ui::show(&format!("\n{}", crate::risk::render_findings(&findings)));
+                result = Err(anyhow::anyhow!(
+                    "Refusing to run a command flagged by the risk check (--yes): {command}"
+                ));
+                continue;
+            }
+            executed.push(command.clone());
+            if let Err(e) = crate::check_plan_size(&executed) {
+                executed.pop();
+                result = Err(e);
+                continue;
+            }
+            take_output(&transcript);
+            result = execute_step(executed.len(), &command, options, recorder).await;
+            if let (Ok(()), Some(name)) = (&result, output) {
+                let (stdout, _) = take_output(&transcript);
+                result = match variables.capture(&name, &stdout) {
+                    Ok(value) => {
+                        println!("📦 {{{{{name}}}}} = {value}\n");
+                        Ok(())
+                    }
+                    Err(e) => Err(e.context(format!("Step {} failed", executed.len()))),
+                };
+            }
+        }
+        (executed, result)
+    };
+
+    let (response, (executed, result)) = tokio::join!(generation, execution);
+    match response {
+        Ok(response) => (executed, Some(response), result),
+        Err(e) => (
+            executed,
+            None,
+            result.and(Err(e.context("Failed to get orchestration plan from AI"))),
+        ),
+    }
 }
 
-async fn execute_shell_command(cmd: &str) -> Result<()> {
-    use colored::*;
-    use std::process::Stdio;
-    use tokio::process::Command;
+/// Words before a directory name in prompts like "in the src folder, find ..."
+const DIRECTORY_PREPOSITIONS: [&str; 4] = ["in", "inside", "under", "within"];
+
+/// The existing directory below `base` a prompt refers to, e.g. `src` for
+/// "in the src folder, find TODOs"; `None` when it names none. Absolute names,
+/// `..` and symlinks leading outside `base` are never picked.
+pub fn implied_directory(prompt: &str, base: &Path) -> Option<PathBuf> {
+    let words: Vec<String> = prompt
+        .split_whitespace()
+        .map(|word| {
+            word.trim_matches(|c: char| matches!(c, ',' | ';' | ':' | '"' | '\'' | '`'))
+                .to_string()
+        })
+        .collect();
+    (0..words.len()).find_map(|i| {
+        if !DIRECTORY_PREPOSITIONS.contains(&words[i].to_lowercase().as_str()) {
+            return None;
+        }
+        let mut name = words.get(i + 1)?.as_str();
+        if matches!(name.to_lowercase().as_str(), "the" | "my") {
+            name = words.get(i + 2)?;
+        }
+        let name = name.trim_end_matches(['?', '!']);
+        let below_base = !name.is_empty()
+            && Path::new(name)
+                .components()
+                .all(|part| matches!(part, std::path::Component::Normal(_)));
+        let dir = base.join(name);
+        (below_base && dir.is_dir() && is_inside(&dir, base)).then_some(dir)
+    })
+}
 
-    let is_install_cmd = crate::is_install_update_remove_command(cmd);
+/// Whether `dir` resolves to a directory inside `base`
+fn is_inside(dir: &Path, base: &Path) -> bool {
+    match (dir.canonicalize(), base.canonicalize()) {
+        (Ok(dir), Ok(base)) => dir.starts_with(base),
+        _ => false,
+    }
+}
 
-    if is_install_cmd {
-        println!(
-            "{}",
-            "[Terminal AI] - Executing package management command"
-                .green()
-                .bold()
-        );
-        println!("{}", format!("[Terminal AI] - Command: {cmd}").green());
-        println!("{}", "[Terminal AI] - Live output:".green());
+/// `cmd` without a leading `cd` into `dir`, which commands already run in; `None` for
+/// a bare `cd` there
+pub fn strip_redundant_cd(cmd: &str, dir: &Path) -> Option<String> {
+    let Some(rest) = cmd.trim_start().strip_prefix("cd ") else {
+        return Some(cmd.to_string());
+    };
+    let (target, remainder) = match rest.find(['&', ';']) {
+        Some(end) => (&rest[..end], &rest[end..]),
+        None => (rest, ""),
+    };
+    if !same_directory(target.trim().trim_matches(['"', '\'']), dir) {
+        return Some(cmd.to_string());
     }
+    let remainder = remainder
+        .strip_prefix("&&")
+        .or_else(|| remainder.strip_prefix(';'))
+        .unwrap_or(remainder)
+        .trim();
+    (!remainder.is_empty()).then(|| remainder.to_string())
+}
 
-    // Use shell to execute the command for proper handling of pipes, redirects, etc.
-    let mut command = Command::new("sh");
-    command.arg("-c");
-    command.arg(cmd);
-    command.stdin(Stdio::piped());
-    command.stdout(Stdio::inherit());
-    command.stderr(Stdio::inherit());
+/// Whether `target`, a `cd` argument, names `dir`
+fn same_directory(target: &str, dir: &Path) -> bool {
+    let normalize = |path: &str| {
+        let path = path.trim_end_matches('/');
+        path.strip_prefix("./").unwrap_or(path).to_string()
+    };
+    let target = normalize(target);
+    if target == "." || target == normalize(&dir.to_string_lossy()) {
+        return true;
+    }
+    match (Path::new(&target).canonicalize(), dir.canonicalize()) {
+        (Ok(target), Ok(dir)) => target == dir,
+        _ => false,
+    }
+}
 
-    let output = command
-        .output()
-        .await
-        .context(format!("Failed to execute command: {cmd}"))?;
-
-    // Check exit code - must be 0 to continue
-    if !output.status.success() {
-        let exit_code = output.status.code().unwrap_or(-1);
-        if is_install_cmd {
-            eprintln!(
-                "{}",
-                format!("[Terminal AI] - Command failed with exit code: {exit_code}")
-                    .red()
-                    .bold()
-            );
+/// Header written above the commands in the plan file opened by `--edit-plan` and
+/// by an edit answer to the confirmation
+const PLAN_FILE_HEADER: &str = "\
+# Terminal AI execution plan
+# Edit, reorder or delete commands below; one command per line.
+# Lines starting with '#' are ignored. Save and close the editor to continue.
+# An empty plan cancels execution.
+";
+
+/// Let the user edit the plan in `$VISUAL`/`$EDITOR` (falling back to `vi`)
+pub(crate) fn edit_plan(commands: &[String]) -> Result<Vec<String>> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    edit_plan_with(commands, &editor)
+}
+
+/// Round-trip the plan through a temp file edited by `editor`
+fn edit_plan_with(commands: &[String], editor: &str) -> Result<Vec<String>> {
+    let stamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let plan_path =
+        std::env::temp_dir().join(format!("terminalai-plan-{}-{stamp}.sh", std::process::id()));
+
+    std::fs::write(&plan_path, render_plan_file(commands))
+        .context("Failed to write plan file for editing")?;
+
+    // Spawned directly rather than through the configured shell, whose syntax for
+    // passing the path differs between sh, fish, PowerShell and cmd
+    let words = editor_words(editor);
+    let status = match words.split_first() {
+        Some((program, args)) => std::process::Command::new(program)
+            .args(args)
+            .arg(&plan_path)
+            .status(),
+        None => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "no editor command",
+        )),
+    };
+
+    let edited = match status {
+        Ok(status) if status.success() => {
+            std::fs::read_to_string(&plan_path).context("Failed to read edited plan file")
         }
-        return Err(anyhow::anyhow!(
-            "Command '{}' failed with exit code: {}",
-            cmd,
-            exit_code
-        ));
-    } else if is_install_cmd {
-        println!(
-            "{}",
-            "[Terminal AI] - Command completed successfully"
-                .green()
-                .bold()
-        );
+        Ok(status) => Err(anyhow::anyhow!(
+            "Editor '{}' exited with status {}",
+            editor,
+            status.code().unwrap_or(-1)
+        )),
+        Err(e) => Err(anyhow::anyhow!(
+            "Failed to launch editor '{}': {}",
+            editor,
+            e
+        )),
+    };
+    let _ = std::fs::remove_file(&plan_path);
+
+    Ok(parse_plan_file(&edited?))
+}
+
+/// `$EDITOR` split into the program and its arguments, e.g. `code --wait`. Quotes
+/// group words such as a path with spaces; backslashes are kept as written, so
+/// Windows paths work.
+fn editor_words(editor: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    for c in editor.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.push(c),
+            None if c == '\'' || c == '"' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            None if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            None => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(current);
     }
+    words
+}
 
-    Ok(())
+fn render_plan_file(commands: &[String]) -> String {
+    let mut content = String::from(PLAN_FILE_HEADER);
+    content.push('\n');
+    for cmd in commands {
+        content.push_str(cmd);
+        content.push('\n');
+    }
+    content
+}
+
+fn parse_plan_file(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+fn parse_orchestration_response(response: &str) -> Result<Vec<String>> {
+    let mut commands = Vec::new();
+
+    for line in response.lines() {
+        if let Some((command, _)) = command_line(line) {
+            // Risky commands are kept; the risk check decides how they are confirmed
+            if !command.is_empty() {
+                commands.push(command.to_string());
+            }
+        }
+    }
+
+    Ok(dedup_commands(commands, Dedup::Consecutive))
+}
+
+/// The command on a `COMMAND:` line, and the variable its output is captured into
+/// when the line starts with `COMMAND[OUT=name]:`. An invalid name is ignored.
+fn command_line(line: &str) -> Option<(&str, Option<&str>)> {
+    let rest = line.trim().strip_prefix("COMMAND")?;
+    if let Some(command) = rest.strip_prefix(':') {
+        return Some((command.trim(), None));
+    }
+    let (name, command) = rest.strip_prefix("[OUT=")?.split_once("]:")?;
+    let name = name.trim();
+    Some((
+        command.trim(),
+        crate::variables::is_valid_name(name).then_some(name),
+    ))
+}
+
+/// The output variable of each `COMMAND[OUT=name]:` command
+fn parse_outputs(response: &str) -> HashMap<String, String> {
+    let mut outputs = HashMap::new();
+    for (command, name) in response.lines().filter_map(command_line) {
+        if let Some(name) = name.filter(|_| !command.is_empty()) {
+            outputs
+                .entry(command.to_string())
+                .or_insert_with(|| name.to_string());
+        }
+    }
+    outputs
+}
+
+/// Each command's `WHY:` line, the first one following its `COMMAND:` line
+fn parse_rationales(response: &str) -> HashMap<String, String> {
+    let mut rationales = HashMap::new();
+    let mut last_command = None;
+    for line in response.lines() {
+        let line = line.trim();
+        if let Some((command, _)) = command_line(line) {
+            last_command = Some(command.to_string());
+        } else if let Some(why) = line.strip_prefix("WHY:") {
+            let why = why.trim();
+            if let Some(command) = last_command.take().filter(|_| !why.is_empty()) {
+                rationales.entry(command).or_insert_with(|| why.to_string());
+            }
+        }
+    }
+    rationales
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_plan_file_round_trip() {
+        let commands = vec!["mkdir -p out".to_string(), "ls out".to_string()];
+        let content = render_plan_file(&commands);
+        assert!(content.starts_with("# Terminal AI execution plan"));
+        assert_eq!(parse_plan_file(&content), commands);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_edit_plan_with_mock_editor_deleting_a_line() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let editor = temp_dir.path().join("mock-editor");
+        std::fs::write(
+            &editor,
+            "#!/bin/sh\ngrep -v 'rm -rf build' \"$1\" > \"$1.new\" && mv \"$1.new\" \"$1\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&editor, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let commands = vec![
+            "mkdir -p out".to_string(),
+            "rm -rf build".to_string(),
+            "ls out".to_string(),
+        ];
+        let edited = edit_plan_with(&commands, &editor.display().to_string()).unwrap();
+        assert_eq!(edited, vec!["mkdir -p out", "ls out"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_edit_plan_with_editor_arguments_and_spaces() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let editor = temp_dir.path().join("my editor.sh");
+        std::fs::write(&editor, "echo \"$1\" > \"$2\"\n").unwrap();
+
+        let commands = vec!["ls".to_string()];
+        let edited =
+            edit_plan_with(&commands, &format!("sh '{}' 'echo hi'", editor.display())).unwrap();
+        assert_eq!(edited, vec!["echo hi"]);
+    }
+
+    #[test]
+    fn test_editor_words() {
+        assert_eq!(editor_words("code --wait"), vec!["code", "--wait"]);
+        assert_eq!(
+            editor_words(r#""C:\Program Files\Notepad++\notepad++.exe" -multiInst"#),
+            vec![r"C:\Program Files\Notepad++\notepad++.exe", "-multiInst"]
+        );
+        assert_eq!(
+            editor_words("vim -c 'set tw=0' ''"),
+            vec!["vim", "-c", "set tw=0", ""]
+        );
+        assert!(editor_words("  ").is_empty());
+    }
+
+    #[test]
+    fn test_edit_plan_with_failing_editor() {
+        let commands = vec!["ls".to_string()];
+        let err = edit_plan_with(&commands, "false").unwrap_err();
+        assert!(err.to_string().contains("exited with status"));
+    }
+
     #[test]
     fn test_parse_orchestration_response_valid() {
         let response = r#"
@@ -281,73 +1795,6 @@ COMMAND: ls -la
         assert!(commands.contains(&"ls -la".to_string()));
     }
 
-    #[test]
-    fn test_is_safe_command_safe_commands() {
-        // Test safe commands that should be allowed
-        assert!(is_safe_command("ls -la"));
-        assert!(is_safe_command("find . -name '*.txt'"));
-        assert!(is_safe_command("grep -r pattern ."));
-        assert!(is_safe_command("cat file.txt"));
-        assert!(is_safe_command("echo 'hello world'"));
-        assert!(is_safe_command("pwd"));
-        assert!(is_safe_command("whoami"));
-        assert!(is_safe_command("mkdir -p new_dir"));
-        assert!(is_safe_command("cp source.txt dest.txt"));
-        assert!(is_safe_command("mv old.txt new.txt"));
-        assert!(is_safe_command("tar -czf archive.tar.gz files/"));
-        assert!(is_safe_command("gzip file.txt"));
-        assert!(is_safe_command("gunzip file.gz"));
-        assert!(is_safe_command("zip archive.zip files/"));
-        assert!(is_safe_command("unzip archive.zip"));
-        assert!(is_safe_command("sort file.txt"));
-        assert!(is_safe_command("uniq file.txt"));
-        assert!(is_safe_command("wc -l file.txt"));
-        assert!(is_safe_command("head -10 file.txt"));
-        assert!(is_safe_command("tail -f logfile.txt"));
-        assert!(is_safe_command("ps aux"));
-        assert!(is_safe_command("df -h"));
-        assert!(is_safe_command("du -sh folder/"));
-        assert!(is_safe_command("curl -s https://example.com"));
-        assert!(is_safe_command("wget https://example.com/file.txt"));
-        assert!(is_safe_command("git status"));
-    }
-
-    #[test]
-    fn test_is_safe_command_dangerous_commands() {
-        // Test dangerous commands that should be rejected
-        assert!(!is_safe_command("rm -rf /"));
-        assert!(!is_safe_command("dd if=/dev/zero of=/dev/sda"));
-        assert!(!is_safe_command("mkfs.ext4 /dev/sda1"));
-        assert!(!is_safe_command("fdisk /dev/sda"));
-        assert!(!is_safe_command("chmod 777 /etc/passwd"));
-        assert!(!is_safe_command("sudo rm -rf /home"));
-        assert!(!is_safe_command("echo 'data' >/dev/sda")); // Fixed spacing
-    }
-
-    #[test]
-    fn test_is_safe_command_edge_cases() {
-        // Test commands that contain dangerous patterns but might be legitimate
-        assert!(!is_safe_command("rm -rf / # this is a comment"));
-        assert!(!is_safe_command("backup_script.sh && rm -rf /tmp"));
-        assert!(!is_safe_command("ls -la && dd if=/dev/random"));
-
-        // Test empty command
-        assert!(is_safe_command(""));
-
-        // Test commands that don't match safe prefixes (should be allowed with warning)
-        assert!(is_safe_command("custom_script.sh"));
-        assert!(is_safe_command("python script.py"));
-        assert!(is_safe_command("node server.js"));
-    }
-
-    #[test]
-    fn test_is_safe_command_whitespace_handling() {
-        // Test commands with various whitespace patterns
-        assert!(is_safe_command("  ls -la  "));
-        assert!(is_safe_command("\tgrep pattern file\t"));
-        assert!(is_safe_command("find . -name '*.txt'"));
-    }
-
     #[test]
     fn test_parse_orchestration_response_whitespace_in_commands() {
         let response = r#"
@@ -386,7 +1833,7 @@ COMMAND: echo "this should be parsed"
     }
 
     #[test]
-    fn test_parse_orchestration_response_dangerous_commands_filtered() {
+    fn test_parse_orchestration_response_keeps_dangerous_commands_for_the_risk_check() {
         let response = r#"
 COMMAND: ls -la
 COMMAND: rm -rf /
@@ -395,27 +1842,21 @@ COMMAND: dd if=/dev/zero of=/dev/sda
 COMMAND: echo "safe command"
 "#;
 
-        let result = parse_orchestration_response(response);
-        assert!(result.is_ok());
-
-        let commands = result.unwrap();
-        // Should only contain safe commands
-        assert_eq!(commands.len(), 3);
-        assert!(commands.contains(&"ls -la".to_string()));
-        assert!(commands.contains(&"find . -name \"*.txt\"".to_string()));
-        assert!(commands.contains(&"echo \"safe command\"".to_string()));
-        // Dangerous commands should be filtered out
-        assert!(!commands.contains(&"rm -rf /".to_string()));
-        assert!(!commands.contains(&"dd if=/dev/zero of=/dev/sda".to_string()));
-    }
-
-    #[test]
-    fn test_is_safe_command_partial_dangerous_patterns() {
-        // Test commands that contain partial dangerous patterns
-        assert!(is_safe_command("grep 'rm -rf' logfile.txt")); // Should be allowed - searching for pattern
-        assert!(!is_safe_command("echo 'dont run: dd if=/dev/zero'")); // Should be rejected - contains dangerous dd pattern
-        assert!(!is_safe_command("rm -rf /tmp && ls")); // Should be rejected - contains dangerous rm pattern
-        assert!(!is_safe_command("backup && dd if=/dev/sda1")); // Should be rejected - contains dangerous dd pattern
+        let commands = parse_orchestration_response(response).unwrap();
+        assert_eq!(commands.len(), 5);
+        // Instead of being dropped, they need their confirmation phrase typed out
+        let findings = crate::risk::assess_all(&commands);
+        let flagged: Vec<(usize, crate::risk::Tier)> = findings
+            .iter()
+            .map(|(number, assessment)| (*number, assessment.tier()))
+            .collect();
+        assert_eq!(
+            flagged,
+            vec![
+                (2, crate::risk::Tier::Dangerous),
+                (4, crate::risk::Tier::Dangerous)
+            ]
+        );
     }
 
     #[test]
@@ -445,12 +1886,326 @@ This will create a backup directory, copy all Python files, list them, and find
         assert!(commands.contains(&"ls -la python_backup/".to_string()));
         assert!(commands.contains(&"grep -r \"TODO\" python_backup/".to_string()));
 
-        // Verify all commands are safe
-        for command in &commands {
-            assert!(
-                is_safe_command(command),
-                "Command should be safe: {command}"
+        // None of them needs more than the usual confirmation
+        assert!(crate::risk::assess_all(&commands).is_empty());
+    }
+
+    #[test]
+    fn test_strip_redundant_cd() {
+        let dir = Path::new("src");
+        for (cmd, stripped) in [
+            ("cd src && grep -rn TODO .", Some("grep -rn TODO .")),
+            ("cd ./src/; ls", Some("ls")),
+            (
+                "cd 'src' &&find . -name '*.rs'",
+                Some("find . -name '*.rs'"),
+            ),
+            ("cd .", None),
+            ("cd src", None),
+            ("cd tests && ls", Some("cd tests && ls")),
+            ("cd src || exit 1", Some("cd src || exit 1")),
+            ("ls src", Some("ls src")),
+        ] {
+            assert_eq!(strip_redundant_cd(cmd, dir).as_deref(), stripped, "{cmd}");
+        }
+    }
+
+    #[test]
+    fn test_fix_prompt_shows_the_plan_and_the_failed_step() {
+        let commands = ["mkdir -p out", "make", "cp app out/"].map(String::from);
+        let mut plan = SavedPlan::new("build the app", &commands, &[], &[], None);
+        plan.succeeded(0);
+        plan.failed(
+            1,
+            Some(2),
+            vec!["make: *** No rule to make target 'all'.".to_string()],
+        );
+
+        assert_eq!(
+            render_progress(&plan),
+            "  1. ✅ mkdir -p out\n  2. ❌ make (exit code 2)\n       make: *** No rule to make target 'all'.\n  3. ⏳ cp app out/"
+        );
+        assert_eq!(
+            fix_prompt(&plan, 1, &[]),
+            "This plan was made for the request: build the app\n\n\
+             Steps:\n\
+             1. mkdir -p out (done)\n\
+             2. make (failed with exit code 2)\n\
+             3. cp app out/ (not run yet)\n\n\
+             Step 2 printed this to stderr:\n\
+             make: *** No rule to make target 'all'.\n\n\
+             Give only the commands that replace step 2 so the steps after it can run. \
+             Don't repeat the steps that are done or the ones after it."
+        );
+
+        let earlier = [describe_failure(&plan.steps[1])];
+        assert_eq!(
+            earlier[0],
+            "`make` failed with exit code 2: make: *** No rule to make target 'all'."
+        );
+        assert!(fix_prompt(&plan, 1, &earlier).contains(
+            "\n\nEarlier attempts that also failed:\n\
+             - `make` failed with exit code 2: make: *** No rule to make target 'all'.\n\n\
+             Give only"
+        ));
+    }
+
+    #[test]
+    fn test_implied_directory() {
+        let base = tempfile::tempdir().unwrap();
+        std::fs::create_dir(base.path().join("src")).unwrap();
+
+        assert_eq!(
+            implied_directory("in the src folder, find all TODOs", base.path()),
+            Some(base.path().join("src"))
+        );
+        assert_eq!(
+            implied_directory("find large files inside src", base.path()),
+            Some(base.path().join("src"))
+        );
+        assert_eq!(
+            implied_directory("in the docs folder, list files", base.path()),
+            None
+        );
+        assert_eq!(implied_directory("find TODOs in .", base.path()), None);
+
+        // Nothing outside the base directory is picked
+        let outside = tempfile::tempdir().unwrap();
+        let absolute = format!("find TODOs in {}", outside.path().display());
+        assert_eq!(implied_directory(&absolute, base.path()), None);
+        assert_eq!(implied_directory("find TODOs in ../..", base.path()), None);
+        assert_eq!(implied_directory("find TODOs in src/..", base.path()), None);
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(outside.path(), base.path().join("elsewhere")).unwrap();
+            assert_eq!(
+                implied_directory("find TODOs in elsewhere", base.path()),
+                None
             );
         }
     }
+
+    #[test]
+    fn test_line_buffer_completes_lines_across_chunks() {
+        let mut buffer = LineBuffer::default();
+        assert!(buffer.push("COMMAND: mk").is_empty());
+        assert_eq!(
+            buffer.push("dir out\nCOMMAND: l"),
+            vec!["COMMAND: mkdir out"]
+        );
+        assert_eq!(buffer.push("s\n\nCOMMAND: p"), vec!["COMMAND: ls", ""]);
+        assert_eq!(buffer.finish().as_deref(), Some("COMMAND: p"));
+        assert_eq!(LineBuffer::default().finish(), None);
+    }
+
+    #[test]
+    fn test_streamed_commands_are_parsed_per_line() {
+        let mut streamed = StreamedCommands::new(Dedup::Consecutive);
+        let mut commands = Vec::new();
+        for chunk in [
+            "<think>\nCOMMAND: rm -r",
+            "f build\n</think>Here is the plan:\nCOMMAND: mkdir -p ",
+            "backup\nCOMMAND: mkdir -p backup\nCOMMAND: <thinking>x</thinking>ls\n",
+            "Explanation: done\nCOMMAND: dd if=/dev/zero of=x\nCOMMAND: pwd",
+        ] {
+            commands.extend(streamed.push(chunk));
+        }
+        assert_eq!(
+            commands,
+            vec!["mkdir -p backup", "ls", "dd if=/dev/zero of=x"]
+        );
+        assert_eq!(streamed.finish().as_deref(), Some("pwd"));
+
+        let mut streamed = StreamedCommands::new(Dedup::All);
+        assert_eq!(
+            streamed.push("COMMAND: ls\nCOMMAND: pwd\nCOMMAND: ls\n"),
+            vec!["ls", "pwd"]
+        );
+        assert_eq!(
+            streamed.push("COMMAND[OUT=dir]: mktemp -d\nCOMMAND: ls {{dir}}"),
+            vec!["mktemp -d"]
+        );
+        assert_eq!(streamed.output_of("mktemp -d").as_deref(), Some("dir"));
+        assert_eq!(streamed.finish().as_deref(), Some("ls {{dir}}"));
+        assert_eq!(streamed.output_of("ls {{dir}}"), None);
+    }
+
+    /// Streams one command, then waits for it to have run before sending the next
+    struct WaitingProvider {
+        first_done: std::path::PathBuf,
+        overlapped: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    #[async_trait::async_trait]
+    impl crate::providers::AIProvider for WaitingProvider {
+        async fn send_query(&self, _system_prompt: &str, _user_prompt: &str) -> Result<String> {
+            unreachable!("the plan is streamed")
+        }
+
+        async fn send_query_streaming(
+            &self,
+            _system_prompt: &str,
+            _user_prompt: &str,
+            on_chunk: &mut crate::providers::ChunkHandler<'_>,
+        ) -> Result<String> {
+            on_chunk("COMMAND: touch first\nCOMMAND: touch ");
+            for _ in 0..250 {
+                if self.first_done.exists() {
+                    self.overlapped
+                        .store(true, std::sync::atomic::Ordering::SeqCst);
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            on_chunk("second");
+            Ok("COMMAND: touch first\nCOMMAND: touch second".to_string())
+        }
+
+        fn supports_streaming(&self) -> bool {
+            true
+        }
+
+        fn provider_name(&self) -> &'static str {
+            "Waiting"
+        }
+
+        fn validate_config(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_plan_runs_commands_while_generating() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let overlapped = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let provider = QueryProvider::from_provider(Box::new(WaitingProvider {
+            first_done: temp_dir.path().join("first"),
+            overlapped: overlapped.clone(),
+        }));
+        let mut options = ExecutionOptions {
+            working_dir: Some(temp_dir.path().to_path_buf()),
+            quiet: true,
+            ..Default::default()
+        };
+
+        let mut recorder = ScriptRecorder::new();
+        let (executed, response, result) =
+            stream_plan(&provider, "make files", &mut options, &mut recorder).await;
+        result.unwrap();
+        assert_eq!(executed, vec!["touch first", "touch second"]);
+        assert!(response.unwrap().contains("touch second"));
+        assert!(overlapped.load(std::sync::atomic::Ordering::SeqCst));
+        assert!(temp_dir.path().join("second").exists());
+    }
+
+    #[test]
+    fn test_refine_choice_parsing_and_prompting() {
+        assert_eq!(parse_refine_choice(" A\n"), Some(RefineChoice::Accept));
+        assert_eq!(
+            parse_refine_choice("regenerate"),
+            Some(RefineChoice::Regenerate)
+        );
+        assert_eq!(parse_refine_choice("q"), Some(RefineChoice::Quit));
+        assert_eq!(parse_refine_choice("y"), None);
+
+        let mut output = Vec::new();
+        let choice = read_refine_choice(
+            &mut std::io::Cursor::new("x\ne\n\ne\nonly the src folder\n"),
+            &mut output,
+        );
+        assert_eq!(
+            choice,
+            RefineChoice::EditPrompt("only the src folder".to_string())
+        );
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches("[e]dit prompt / [r]egenerate").count(), 3);
+
+        let choice = read_refine_choice(&mut std::io::Cursor::new(""), &mut Vec::new());
+        assert_eq!(choice, RefineChoice::Quit);
+    }
+
+    #[test]
+    fn test_refinement_state_machine() {
+        let first = vec!["find . -name '*.log'".to_string()];
+        let second = vec!["find src -name '*.log'".to_string()];
+        let mut refinement = Refinement::new("find log files");
+        assert_eq!(refinement.query(), "find log files");
+
+        assert_eq!(
+            refinement.next(RefineChoice::Regenerate, &first),
+            RefineStep::Requery
+        );
+        assert_eq!(refinement.query(), "find log files");
+
+        assert_eq!(
+            refinement.next(RefineChoice::EditPrompt("only in src".to_string()), &first),
+            RefineStep::Requery
+        );
+        assert_eq!(
+            refinement.query(),
+            "find log files\n\nYour previous plan was:\nCOMMAND: find . -name '*.log'\n\nRevise it with these clarifications:\n- only in src\n"
+        );
+
+        refinement.next(
+            RefineChoice::EditPrompt("skip archives".to_string()),
+            &second,
+        );
+        let query = refinement.query();
+        assert!(query.contains("COMMAND: find src -name '*.log'\n"));
+        assert!(!query.contains("COMMAND: find . -name"));
+        assert!(query.ends_with("- only in src\n- skip archives\n"));
+
+        assert_eq!(
+            refinement.next(RefineChoice::Accept, &second),
+            RefineStep::Accept
+        );
+        assert_eq!(
+            refinement.next(RefineChoice::Quit, &second),
+            RefineStep::Quit
+        );
+    }
+
+    #[test]
+    fn test_rationales_follow_their_commands() {
+        let response = "COMMAND: mkdir -p backup\nWHY: Create the backup folder\nCOMMAND: cp *.conf backup/\nCOMMAND: ls backup\nWHY: Check the copies\nWHY: ignored second reason";
+        let rationales = parse_rationales(response);
+        assert_eq!(rationales.len(), 2);
+        assert_eq!(rationales["mkdir -p backup"], "Create the backup folder");
+        assert_eq!(rationales["ls backup"], "Check the copies");
+
+        let plan = Plan {
+            commands: parse_orchestration_response(response).unwrap(),
+            rationales,
+            outputs: HashMap::new(),
+            response: response.to_string(),
+        };
+        let edited = vec!["ls backup".to_string(), "cp *.conf backup/".to_string()];
+        assert_eq!(
+            plan.rationales_for(&edited),
+            vec![Some("Check the copies".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn test_output_variables_are_parsed_with_their_commands() {
+        let response = "COMMAND[OUT=backup_dir]: mktemp -d\nWHY: Make a place for the copy\nCOMMAND: cp -r src \"{{backup_dir}}\"\nCOMMAND[OUT=bad-name]: date +%s\nCOMMAND[OUT=x]:   \n  COMMAND[OUT=archive]: echo out.tar";
+        assert_eq!(
+            parse_orchestration_response(response).unwrap(),
+            vec![
+                "mktemp -d",
+                "cp -r src \"{{backup_dir}}\"",
+                "date +%s",
+                "echo out.tar"
+            ]
+        );
+        let outputs = parse_outputs(response);
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs["mktemp -d"], "backup_dir");
+        assert_eq!(outputs["echo out.tar"], "archive");
+        assert_eq!(
+            parse_rationales(response)["mktemp -d"],
+            "Make a place for the copy"
+        );
+        assert_eq!(command_line("COMMANDS: ls"), None);
+    }
 }
@@ -0,0 +1,417 @@
+use crate::history::PromptStorage;
+use crate::variables::Variables;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Lines of a failed step's stderr saved with the plan, from the end
+pub const MAX_STDERR_LINES: usize = 40;
+
+/// Where a step of a [`SavedPlan`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepState {
+    Pending,
+    Succeeded,
+    Failed,
+    /// Declined when asked about on its own (`confirm_each_command`)
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub command: String,
+    /// The model's `WHY:` line for the command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rationale: Option<String>,
+    /// Variable the step's stdout is captured into (`COMMAND[OUT=name]:`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    pub state: StepState,
+    /// Exit code of a failed step; `None` when it failed without running
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// The last [`MAX_STDERR_LINES`] lines a failed step printed to stderr
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stderr: Vec<String>,
+}
+
+/// A confirmed `tai -p` plan with the state of each step, saved to
+/// `~/.terminalai/plans/<id>.json` while it runs so `tai resume <id>` can continue it
+/// from a failed step
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedPlan {
+    pub id: String,
+    /// Seconds since the Unix epoch when the plan started
+    pub created: u64,
+    pub prompt: String,
+    /// Directory the commands run in
+    pub workdir: PathBuf,
+    pub steps: Vec<PlanStep>,
+    /// What the steps run so far captured, for the `{{name}}` of later ones
+    #[serde(default, skip_serializing_if = "Variables::is_empty")]
+    pub variables: Variables,
+    /// How `prompt` is written when the plan is saved (`history_prompt`); a loaded
+    /// plan's prompt was already written that way
+    #[serde(skip)]
+    pub prompt_storage: PromptStorage,
+}
+
+impl SavedPlan {
+    /// A plan of `commands` with their `rationales` and the variables their output
+    /// goes to (`outputs`), none of them run yet, that runs in `working_dir` (the
+    /// current directory when `None`)
+    pub fn new(
+        prompt: &str,
+        commands: &[String],
+        rationales: &[Option<String>],
+        outputs: &[Option<String>],
+        working_dir: Option<&Path>,
+    ) -> Self {
+        let current = std::env::current_dir().unwrap_or_default();
+        let workdir = match working_dir {
+            Some(dir) => current.join(dir),
+            None => current,
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut hasher = Sha256::new();
+        hasher.update(prompt.as_bytes());
+        hasher.update(now.as_nanos().to_le_bytes());
+        hasher.update(std::process::id().to_le_bytes());
+        let id = hasher.finalize()[..4]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        Self {
+            id,
+            created: now.as_secs(),
+            prompt: prompt.to_string(),
+            workdir: workdir.canonicalize().unwrap_or(workdir),
+            steps: steps(commands, rationales, outputs),
+            variables: Variables::default(),
+            prompt_storage: PromptStorage::default(),
+        }
+    }
+
+    /// Index of the first step that has neither succeeded nor been skipped, `None`
+    /// once every step has
+    pub fn next_step(&self) -> Option<usize> {
+        self.steps
+            .iter()
+            .position(|step| !matches!(step.state, StepState::Succeeded | StepState::Skipped))
+    }
+
+    pub fn skipped(&mut self, index: usize) {
+        self.steps[index].state = StepState::Skipped;
+    }
+
+    pub fn succeeded(&mut self, index: usize) {
+        let step = &mut self.steps[index];
+        step.state = StepState::Succeeded;
+        step.exit_code = None;
+        step.stderr.clear();
+    }
+
+    /// Record that step `index` failed with `exit_code`, keeping the end of `stderr`
+    pub fn failed(&mut self, index: usize, exit_code: Option<i32>, stderr: Vec<String>) {
+        let step = &mut self.steps[index];
+        step.state = StepState::Failed;
+        step.exit_code = exit_code;
+        step.stderr = stderr[stderr.len().saturating_sub(MAX_STDERR_LINES)..].to_vec();
+    }
+
+    /// Put `commands` in place of step `index`, e.g. the model's fix for it
+    pub fn replace_step(
+        &mut self,
+        index: usize,
+        commands: &[String],
+        rationales: &[Option<String>],
+        outputs: &[Option<String>],
+    ) {
+        self.steps
+            .splice(index..=index, steps(commands, rationales, outputs));
+    }
+
+    /// Step `index`'s command with the variables captured so far filled in. Using
+    /// one that a step of the plan captures but that has not been captured yet is
+    /// an error.
+    pub fn command_to_run(&self, index: usize) -> Result<String> {
+        let command = &self.steps[index].command;
+        for name in crate::variables::placeholders(command) {
+            let captured_later = self
+                .steps
+                .iter()
+                .any(|step| step.output.as_deref() == Some(name));
+            if captured_later && self.variables.get(name).is_none() {
+                anyhow::bail!(
+                    "Step {} uses {{{{{name}}}}} before a step has captured it",
+                    index + 1
+                );
+            }
+        }
+        self.variables
+            .substitute(command, crate::shell::shell()?.kind)
+    }
+
+    /// Keep what the succeeded step `index` printed to `stdout` when it has an
+    /// output variable, returning the variable and its value
+    pub fn capture(&mut self, index: usize, stdout: &[String]) -> Result<Option<(String, String)>> {
+        let Some(name) = self.steps[index].output.clone() else {
+            return Ok(None);
+        };
+        let value = self.variables.capture(&name, stdout)?.to_string();
+        Ok(Some((name, value)))
+    }
+
+    /// The commands from step `index` on
+    pub fn commands_from(&self, index: usize) -> Vec<String> {
+        self.steps[index..]
+            .iter()
+            .map(|step| step.command.clone())
+            .collect()
+    }
+
+    /// Write the plan to `dir`, readable by the user only: it holds the prompt, as
+    /// `prompt_storage` says, and what failed steps printed
+    pub fn save_in(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+                .with_context(|| format!("Failed to restrict {}", dir.display()))?;
+            options.mode(0o600);
+        }
+
+        let saved = Self {
+            prompt: self.prompt_storage.store(&self.prompt),
+            ..self.clone()
+        };
+        let path = plan_path(dir, &self.id);
+        let mut file = options
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        std::io::Write::write_all(&mut file, serde_json::to_string_pretty(&saved)?.as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn load_from(dir: &Path, id: &str) -> Result<Self> {
+        let path = plan_path(dir, id);
+        if !path.exists() {
+            anyhow::bail!("No saved plan '{id}' (looked for {})", path.display());
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid plan {}", path.display()))
+    }
+
+    /// Remove the saved plan, if it is still there
+    pub fn remove_from(&self, dir: &Path) -> Result<()> {
+        let path = plan_path(dir, &self.id);
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn steps(
+    commands: &[String],
+    rationales: &[Option<String>],
+    outputs: &[Option<String>],
+) -> Vec<PlanStep> {
+    commands
+        .iter()
+        .enumerate()
+        .map(|(i, command)| PlanStep {
+            command: command.clone(),
+            rationale: rationales.get(i).cloned().flatten(),
+            output: outputs.get(i).cloned().flatten(),
+            state: StepState::Pending,
+            exit_code: None,
+            stderr: Vec::new(),
+        })
+        .collect()
+}
+
+pub fn plans_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".terminalai").join("plans"))
+}
+
+/// Where `config` has plans saved: nowhere when the history is off, since a plan
+/// keeps its prompt and what its failed steps printed
+pub fn plans_dir_for(config: &crate::TerminalAIConfig) -> Option<PathBuf> {
+    config.history_enabled().then(plans_dir).flatten()
+}
+
+fn plan_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{id}.json"))
+}
+
+/// The most recently started plan saved in `dir`, if any; unreadable files are skipped
+pub fn latest_in(dir: &Path) -> Result<Option<SavedPlan>> {
+    if !dir.is_dir() {
+        return Ok(None);
+    }
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| std::fs::read_to_string(entry.path()).ok())
+        .filter_map(|content| serde_json::from_str::<SavedPlan>(&content).ok())
+        .max_by_key(|plan| plan.created))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan() -> SavedPlan {
+        let commands = ["mkdir -p out", "make", "cp build/app out/"].map(String::from);
+        SavedPlan::new(
+            "build and copy the app",
+            &commands,
+            &[Some("Create the output folder".to_string())],
+            &[],
+            Some(Path::new("/tmp/project")),
+        )
+    }
+
+    #[test]
+    fn test_steps_track_where_the_plan_stopped() {
+        let mut plan = plan();
+        assert_eq!(plan.id.len(), 8);
+        assert_eq!(plan.next_step(), Some(0));
+        assert_eq!(
+            plan.steps[0].rationale.as_deref(),
+            Some("Create the output folder")
+        );
+
+        plan.succeeded(0);
+        let stderr: Vec<String> = (0..MAX_STDERR_LINES + 5).map(|i| format!("e{i}")).collect();
+        plan.failed(1, Some(2), stderr);
+        assert_eq!(plan.next_step(), Some(1));
+        assert_eq!(plan.steps[1].state, StepState::Failed);
+        assert_eq!(plan.steps[1].stderr.len(), MAX_STDERR_LINES);
+        assert_eq!(plan.steps[1].stderr[0], "e5");
+
+        plan.replace_step(
+            1,
+            &["make clean".to_string(), "make".to_string()],
+            &[Some("Drop stale objects".to_string())],
+            &[],
+        );
+        assert_eq!(
+            plan.commands_from(1),
+            vec!["make clean", "make", "cp build/app out/"]
+        );
+        assert_eq!(plan.steps[1].state, StepState::Pending);
+        plan.skipped(1);
+        assert_eq!(plan.next_step(), Some(2));
+        for i in 2..4 {
+            plan.succeeded(i);
+        }
+        assert_eq!(plan.next_step(), None);
+    }
+
+    #[test]
+    fn test_plans_are_saved_loaded_and_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(latest_in(dir.path()).unwrap(), None);
+
+        let mut older = plan();
+        older.created -= 60;
+        older.save_in(dir.path()).unwrap();
+        let mut newer = plan();
+        newer.failed(
+            0,
+            Some(1),
+            vec!["mkdir: cannot create directory".to_string()],
+        );
+        newer.save_in(dir.path()).unwrap();
+
+        assert_eq!(SavedPlan::load_from(dir.path(), &older.id).unwrap(), older);
+        assert_eq!(latest_in(dir.path()).unwrap(), Some(newer.clone()));
+        let saved = std::fs::read_to_string(dir.path().join(format!("{}.json", newer.id))).unwrap();
+        assert!(saved.contains("\"state\": \"failed\""), "{saved}");
+
+        newer.remove_from(dir.path()).unwrap();
+        assert_eq!(latest_in(dir.path()).unwrap(), Some(older));
+        assert!(SavedPlan::load_from(dir.path(), "missing").is_err());
+    }
+
+    #[test]
+    fn test_saved_plans_are_private_and_follow_history_prompt() {
+        let dir = tempfile::tempdir().unwrap();
+        let plans = dir.path().join("plans");
+        let mut plan = plan();
+        plan.prompt_storage = PromptStorage::Hash;
+        plan.save_in(&plans).unwrap();
+
+        let loaded = SavedPlan::load_from(&plans, &plan.id).unwrap();
+        assert_eq!(
+            loaded.prompt,
+            PromptStorage::Hash.store("build and copy the app")
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&plans), 0o700);
+            assert_eq!(mode(&plans.join(format!("{}.json", plan.id))), 0o600);
+        }
+
+        let mut config = crate::TerminalAIConfig::default();
+        config
+            .settings
+            .insert("history".to_string(), "false".to_string());
+        assert_eq!(plans_dir_for(&config), None);
+    }
+
+    #[test]
+    fn test_captured_output_fills_in_later_steps() {
+        let commands = [
+            "mktemp -d",
+            "cp -r src \"{{backup_dir}}\"",
+            "echo {{backup_dir}}",
+        ]
+        .map(String::from);
+        let mut plan = SavedPlan::new(
+            "back up src",
+            &commands,
+            &[],
+            &[Some("backup_dir".to_string())],
+            None,
+        );
+        assert_eq!(plan.capture(1, &[]).unwrap(), None);
+        assert_eq!(
+            plan.command_to_run(1).unwrap_err().to_string(),
+            "Step 2 uses {{backup_dir}} before a step has captured it"
+        );
+        assert!(plan.capture(0, &[]).is_err());
+
+        assert_eq!(
+            plan.capture(0, &["/tmp/tmp.Ab12".to_string()]).unwrap(),
+            Some(("backup_dir".to_string(), "/tmp/tmp.Ab12".to_string()))
+        );
+        assert_eq!(
+            plan.command_to_run(1).unwrap(),
+            "cp -r src \"/tmp/tmp.Ab12\""
+        );
+
+        // Captured values are saved with the plan, so a resumed plan has them
+        let dir = tempfile::tempdir().unwrap();
+        plan.save_in(dir.path()).unwrap();
+        let loaded = SavedPlan::load_from(dir.path(), &plan.id).unwrap();
+        assert_eq!(loaded.command_to_run(2).unwrap(), "echo '/tmp/tmp.Ab12'");
+    }
+}
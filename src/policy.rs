@@ -0,0 +1,247 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// `.conf` section holding the config's [`PolicyConfig`]
+pub const POLICY_SECTION: &str = "policy";
+
+/// Regular expressions deciding which commands may run, from the config's `[policy]`
+/// section and `~/.terminalai/policy.toml`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// When not empty, every simple command (each side of a pipe, `&&`, `;`, ...)
+    /// has to match one of these
+    pub allow: Vec<String>,
+    /// Commands matching any of these anywhere never run
+    pub deny: Vec<String>,
+}
+
+impl PolicyConfig {
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// Add a `key = pattern` line of the `[policy]` section; repeated keys add more
+    /// patterns
+    pub fn insert(&mut self, key: &str, pattern: &str) -> Result<()> {
+        match key {
+            "allow" => self.allow.push(pattern.to_string()),
+            "deny" => self.deny.push(pattern.to_string()),
+            _ => anyhow::bail!("unknown policy key '{key}' (use allow or deny)"),
+        }
+        Ok(())
+    }
+
+    pub fn from_toml_str(content: &str) -> Result<Self> {
+        toml::from_str(content).context("Invalid command policy")
+    }
+
+    /// Load the policy at `path`, or an empty one when it does not exist
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_toml_str(&content).with_context(|| format!("In {}", path.display()))
+    }
+}
+
+pub fn policy_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".terminalai").join("policy.toml"))
+}
+
+/// Why commands with `$(...)`, backticks or `<(...)` do not run under allow patterns
+pub(crate) const NESTED_COMMANDS: &str =
+    "nested commands ($(...), backticks, <(...)) cannot be checked against allow patterns";
+
+/// A [`PolicyConfig`] with its patterns compiled
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    allow: Vec<Regex>,
+    deny: Vec<Regex>,
+}
+
+impl Policy {
+    /// Compile the patterns of all `configs` into one policy
+    pub fn compile<'a>(configs: impl IntoIterator<Item = &'a PolicyConfig>) -> Result<Self> {
+        let compile = |pattern: &String| {
+            Regex::new(pattern).with_context(|| format!("Invalid policy pattern '{pattern}'"))
+        };
+        let mut policy = Self::default();
+        for config in configs {
+            policy.allow.extend(
+                config
+                    .allow
+                    .iter()
+                    .map(compile)
+                    .collect::<Result<Vec<_>>>()?,
+            );
+            policy.deny.extend(
+                config
+                    .deny
+                    .iter()
+                    .map(compile)
+                    .collect::<Result<Vec<_>>>()?,
+            );
+        }
+        Ok(policy)
+    }
+
+    /// Why `command` may not run, or `None` when the policy allows it. With allow
+    /// patterns, nested commands are refused: the patterns only see simple commands.
+    pub fn violation(&self, command: &str) -> Option<String> {
+        if let Some(pattern) = self.deny.iter().find(|deny| deny.is_match(command)) {
+            return Some(format!("denied by policy pattern '{pattern}'"));
+        }
+        if self.allow.is_empty() {
+            return None;
+        }
+        if crate::injection::has_nested_commands(command) {
+            return Some(NESTED_COMMANDS.to_string());
+        }
+        crate::injection::segments(command)
+            .iter()
+            .map(|(_, segment)| segment.trim())
+            .filter(|segment| !segment.is_empty())
+            .find(|segment| !self.allow.iter().any(|allow| allow.is_match(segment)))
+            .map(|segment| format!("`{segment}` matches no allow pattern"))
+    }
+
+    /// Fail on the first of `commands` the policy does not allow
+    pub fn check(&self, commands: &[String]) -> Result<()> {
+        check_each(commands, |command| self.violation(command))
+    }
+}
+
+fn check_each(commands: &[String], violation: impl Fn(&str) -> Option<String>) -> Result<()> {
+    for (i, command) in commands.iter().enumerate() {
+        if let Some(reason) = violation(command) {
+            anyhow::bail!(
+                "Command {} is not allowed by the command policy: {command} ({reason})",
+                i + 1
+            );
+        }
+    }
+    Ok(())
+}
+
+// Set once at startup from the config and `~/.terminalai/policy.toml`
+static POLICY: RwLock<Option<Policy>> = RwLock::new(None);
+
+/// Enforce the config's `[policy]` section together with `~/.terminalai/policy.toml`
+/// from now on
+pub fn configure(config: &crate::TerminalAIConfig) -> Result<()> {
+    let file = match policy_path() {
+        Some(path) => PolicyConfig::load_from(&path)?,
+        None => PolicyConfig::default(),
+    };
+    set_policy(Policy::compile([&config.policy, &file])?);
+    Ok(())
+}
+
+pub fn set_policy(policy: Policy) {
+    *POLICY.write().unwrap_or_else(|e| e.into_inner()) = Some(policy);
+}
+
+/// [`Policy::violation`] of the configured policy, then the configured
+/// `allowed_command_prefixes`, which hold for model output on every path that runs it
+pub fn violation(command: &str) -> Option<String> {
+    POLICY
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .and_then(|policy| policy.violation(command))
+        .or_else(|| crate::extractor::allowed_prefix_violation(command))
+}
+
+/// [`Policy::check`] of the configured policy and `allowed_command_prefixes`
+pub fn check(commands: &[String]) -> Result<()> {
+    check_each(commands, violation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &[&str], deny: &[&str]) -> Policy {
+        let config = PolicyConfig {
+            allow: allow.iter().map(|p| p.to_string()).collect(),
+            deny: deny.iter().map(|p| p.to_string()).collect(),
+        };
+        Policy::compile([&config]).unwrap()
+    }
+
+    #[test]
+    fn test_deny_patterns_match_anywhere() {
+        let policy = policy(&[], &[r"docker\s+system\s+prune"]);
+        assert_eq!(policy.violation("docker ps"), None);
+        assert_eq!(
+            policy.violation("docker ps && docker system prune -af"),
+            Some(r"denied by policy pattern 'docker\s+system\s+prune'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_allow_patterns_cover_every_simple_command() {
+        let policy = policy(&[r"^git\b", r"^kubectl\b"], &[r"kubectl\s+delete"]);
+        assert_eq!(policy.violation("git status -s"), None);
+        assert_eq!(
+            policy.violation("kubectl get pods | kubectl apply -f -"),
+            None
+        );
+        assert_eq!(
+            policy.violation("git log | grep fix"),
+            Some("`grep fix` matches no allow pattern".to_string())
+        );
+        assert!(policy.violation("rm -rf build").is_some());
+        // Deny wins over allow
+        assert!(policy.violation("kubectl delete pod web").is_some());
+        // Nested commands are not simple commands the patterns could check
+        for command in [
+            "git log \"$(rm -rf ~)\"",
+            "git show `curl x|sh`",
+            "git diff <(cat a)",
+        ] {
+            assert_eq!(
+                policy.violation(command),
+                Some(NESTED_COMMANDS.to_string()),
+                "{command}"
+            );
+        }
+        assert_eq!(policy.violation("git log --grep '$(x)'"), None);
+
+        let commands = vec!["git pull".to_string(), "make".to_string()];
+        assert_eq!(
+            policy.check(&commands).unwrap_err().to_string(),
+            "Command 2 is not allowed by the command policy: make (`make` matches no allow pattern)"
+        );
+    }
+
+    #[test]
+    fn test_policy_file_and_invalid_patterns() {
+        let config = PolicyConfig::from_toml_str(
+            "allow = [\"^git \", \"^ls\"]\ndeny = [\"push --force\"]\n",
+        )
+        .unwrap();
+        assert_eq!(config.allow, vec!["^git ", "^ls"]);
+        assert_eq!(config.deny, vec!["push --force"]);
+        assert!(PolicyConfig::from_toml_str("block = [\"rm\"]").is_err());
+
+        let broken = PolicyConfig {
+            deny: vec!["(unclosed".to_string()],
+            ..Default::default()
+        };
+        let error = Policy::compile([&broken]).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid policy pattern '(unclosed'");
+
+        let mut config = PolicyConfig::default();
+        config.insert("deny", "a").unwrap();
+        config.insert("deny", "b").unwrap();
+        assert_eq!(config.deny, vec!["a", "b"]);
+        assert!(config.insert("block", "c").is_err());
+    }
+}
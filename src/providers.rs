@@ -1,24 +1,123 @@
+use crate::attachment::Attachment;
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+/// Receives a streamed answer piece by piece, see [`AIProvider::send_query_streaming`]
+pub type ChunkHandler<'a> = dyn for<'c> FnMut(&'c str) + Send + 'a;
 
 /// Trait for AI providers that can generate responses from prompts
 #[async_trait]
 pub trait AIProvider: Send + Sync {
     async fn send_query(&self, system_prompt: &str, user_prompt: &str) -> Result<String>;
+
+    /// Like `send_query`, with the tokens used when the provider reports them
+    async fn send_query_with_usage(&self, system_prompt: &str, user_prompt: &str) -> Result<Reply> {
+        Ok(Reply {
+            text: self.send_query(system_prompt, user_prompt).await?,
+            usage: None,
+        })
+    }
+
+    /// Like `send_query`, handing the response to `on_chunk` piece by piece as it is
+    /// generated. Providers that can't stream hand over the whole answer at the end.
+    async fn send_query_streaming(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        on_chunk: &mut ChunkHandler<'_>,
+    ) -> Result<String> {
+        let text = self.send_query(system_prompt, user_prompt).await?;
+        on_chunk(&text);
+        Ok(text)
+    }
+
+    /// Whether [`Self::send_query_streaming`] hands the answer over as it is generated
+    /// rather than all at once
+    fn supports_streaming(&self) -> bool {
+        false
+    }
+
+    /// Whether [`Self::send_query_with_attachments`] accepts images
+    fn supports_attachments(&self) -> bool {
+        false
+    }
+
+    /// Like `send_query_with_usage`, sending `attachments` with the user prompt.
+    /// Providers that can't read images refuse any.
+    async fn send_query_with_attachments(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        attachments: &[Attachment],
+    ) -> Result<Reply> {
+        if !attachments.is_empty() {
+            anyhow::bail!(
+                "{} can't read attachments; --attach needs OpenAI, Claude or Gemini",
+                self.provider_name()
+            );
+        }
+        self.send_query_with_usage(system_prompt, user_prompt).await
+    }
+
+    /// Like `send_query_with_attachments` for a request whose answer must be
+    /// commands, so a provider can offer its own way of returning them (OpenAI's
+    /// `run_commands` tool). Other providers answer it like any query.
+    async fn send_command_request(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        attachments: &[Attachment],
+    ) -> Result<Reply> {
+        self.send_query_with_attachments(system_prompt, user_prompt, attachments)
+            .await
+    }
+
     fn provider_name(&self) -> &'static str;
     fn validate_config(&self) -> Result<()>;
 }
 
+/// Tokens consumed by one query
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl Usage {
+    /// Rough count for providers that don't report usage: about four characters per token
+    pub fn estimate(prompt_chars: usize, response_chars: usize) -> Self {
+        Self {
+            input_tokens: prompt_chars.div_ceil(4) as u64,
+            output_tokens: response_chars.div_ceil(4) as u64,
+        }
+    }
+}
+
+/// A provider's answer with the tokens it used, if reported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub text: String,
+    pub usage: Option<Usage>,
+}
+
 /// Enum for different AI provider types
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum ProviderType {
     Ollama,
     OpenAI,
+    /// Any server speaking the OpenAI chat API (LM Studio, vLLM, llama.cpp server,
+    /// LiteLLM, OpenRouter, ...); `base_url` and `model` are required, `api_key` is not
+    OpenAICompatible,
     Claude,
     Gemini,
     Local,
+    /// A provider registered at runtime with [`register_provider`]
+    Custom(String),
 }
 
 impl std::fmt::Display for ProviderType {
@@ -26,9 +125,11 @@ impl std::fmt::Display for ProviderType {
         match self {
             ProviderType::Ollama => write!(f, "Ollama"),
             ProviderType::OpenAI => write!(f, "OpenAI"),
+            ProviderType::OpenAICompatible => write!(f, "OpenAI-compatible"),
             ProviderType::Claude => write!(f, "Claude (Anthropic)"),
             ProviderType::Gemini => write!(f, "Gemini (Google)"),
             ProviderType::Local => write!(f, "Local (llamacpp)"),
+            ProviderType::Custom(name) => write!(f, "{name}"),
         }
     }
 }
@@ -41,10 +142,16 @@ pub struct ProviderConfig {
     pub settings: HashMap<String, String>,
 }
 
+/// Where Ollama listens unless configured otherwise
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+
+/// The variable Ollama itself reads for its server address, e.g. `gpu-box:11434`
+pub const OLLAMA_HOST_ENV_VAR: &str = "OLLAMA_HOST";
+
 impl Default for ProviderConfig {
     fn default() -> Self {
         let mut settings = HashMap::new();
-        settings.insert("url".to_string(), "http://localhost:11434".to_string());
+        settings.insert("url".to_string(), DEFAULT_OLLAMA_URL.to_string());
         settings.insert("model".to_string(), "llama2".to_string());
 
         Self {
@@ -84,6 +191,28 @@ impl ProviderConfig {
         }
     }
 
+    /// A server speaking the OpenAI chat API at `base_url`, e.g.
+    /// `http://localhost:1234/v1`; `api_key` is only sent when given
+    pub fn new_openai_compatible(
+        base_url: String,
+        api_key: Option<String>,
+        model: String,
+        timeout_seconds: u64,
+    ) -> Self {
+        let mut settings = HashMap::new();
+        settings.insert("base_url".to_string(), base_url);
+        settings.insert("model".to_string(), model);
+        if let Some(api_key) = api_key {
+            settings.insert("api_key".to_string(), api_key);
+        }
+
+        Self {
+            provider_type: ProviderType::OpenAICompatible,
+            timeout_seconds,
+            settings,
+        }
+    }
+
     pub fn new_claude(api_key: String, model: String, timeout_seconds: u64) -> Self {
         let mut settings = HashMap::new();
         settings.insert("api_key".to_string(), api_key);
@@ -139,12 +268,223 @@ impl ProviderConfig {
             .cloned()
             .unwrap_or_else(|| default.to_string())
     }
+
+    /// The setting holding the endpoint: `url` for Ollama, `base_url` for the remote
+    /// APIs, `None` for the local provider, which runs llama.cpp directly
+    pub fn url_key(&self) -> Option<&'static str> {
+        match self.provider_type {
+            ProviderType::Ollama => Some("url"),
+            ProviderType::Local => None,
+            _ => Some("base_url"),
+        }
+    }
+
+    /// Ollama's endpoint: the `url` setting, unless it is unset, empty or the default,
+    /// in which case `OLLAMA_HOST` applies when set
+    pub fn ollama_url(&self) -> String {
+        self.ollama_url_with_host(std::env::var(OLLAMA_HOST_ENV_VAR).ok().as_deref())
+    }
+
+    fn ollama_url_with_host(&self, ollama_host: Option<&str>) -> String {
+        let configured = self
+            .get_setting("url")
+            .map(|url| url.trim())
+            .filter(|url| !url.is_empty());
+        match configured {
+            Some(url) if url != DEFAULT_OLLAMA_URL => url.to_string(),
+            _ => ollama_host
+                .and_then(ollama_url_from_host)
+                .unwrap_or_else(|| DEFAULT_OLLAMA_URL.to_string()),
+        }
+    }
+
+    /// Whether the model runs on this machine (Ollama or llama.cpp); these are
+    /// usually small and miss the expected answer format more often
+    pub fn is_local(&self) -> bool {
+        matches!(
+            self.provider_type,
+            ProviderType::Ollama | ProviderType::Local
+        )
+    }
+
+    /// How often to ask again when an answer contains no commands (`retry_on_empty`);
+    /// 2 for local models, 0 for cloud models unless set
+    pub fn retry_on_empty(&self) -> u32 {
+        self.get_setting("retry_on_empty")
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(if self.is_local() { 2 } else { 0 })
+    }
+
+    /// `min_response_chars`: answers shorter than this, once trimmed, are treated as
+    /// empty; 0 (the default) disables the check
+    pub fn min_response_chars(&self) -> usize {
+        self.get_setting("min_response_chars")
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// `structured_output`: ask for commands as a validated `{"commands": [...]}` JSON
+    /// object instead of `COMMAND:` lines. Off unless explicitly enabled.
+    pub fn structured_output(&self) -> bool {
+        self.get_setting("structured_output")
+            .is_some_and(|value| matches!(value.trim(), "true" | "yes" | "on" | "1"))
+    }
+
+    /// `self_heal`: reinstall llama.cpp once when its binary disappears between the
+    /// install check and the run. On unless explicitly disabled.
+    pub fn self_heal(&self) -> bool {
+        !self
+            .get_setting("self_heal")
+            .is_some_and(|value| matches!(value.trim(), "false" | "no" | "off" | "0"))
+    }
+
+    /// `danger_accept_invalid_certs`: skip TLS certificate checks, e.g. for a dev
+    /// gateway with a self-signed certificate. Off unless explicitly enabled.
+    pub fn accepts_invalid_certs(&self) -> bool {
+        self.get_setting("danger_accept_invalid_certs")
+            .is_some_and(|value| matches!(value.trim(), "true" | "yes" | "on" | "1"))
+    }
+
+    /// Stop sequences from the comma-separated `stop_sequences` setting
+    pub fn get_stop_sequences(&self) -> Vec<String> {
+        self.get_setting("stop_sequences")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Completion limit from the `max_tokens` setting for a prompt of `prompt_chars`;
+    /// `auto` sizes it to the model's context window. `None` when unset.
+    pub fn max_tokens(&self, prompt_chars: usize) -> Option<u32> {
+        let value = self.get_setting("max_tokens")?.trim().to_lowercase();
+        if value != "auto" {
+            return value.parse().ok();
+        }
+        let window = self
+            .get_setting("context_window")
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or_else(|| context_window(&self.get_setting_or_default("model", "")));
+        Some(auto_max_tokens(window, prompt_chars))
+    }
+}
+
+/// Completion limit when `max_tokens` is not set
+pub const DEFAULT_MAX_TOKENS: u32 = 1000;
+
+/// Most tokens `max_tokens = "auto"` asks for; command answers are short
+const AUTO_MAX_TOKENS_CAP: u32 = 4096;
+
+/// Context window assumed for models missing from [`MODEL_CONTEXT_WINDOWS`]
+const DEFAULT_CONTEXT_WINDOW: u32 = 4096;
+
+/// Context window in tokens, matched by model name prefix; more specific names come first
+const MODEL_CONTEXT_WINDOWS: [(&str, u32); 14] = [
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4-32k", 32_768),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("claude", 200_000),
+    ("gemini-1.5", 1_048_576),
+    ("gemini-2.0", 1_048_576),
+    ("gemini-pro", 32_760),
+    ("gemini-1.0-pro", 32_760),
+    ("llama3", 8_192),
+    ("llama2", 4_096),
+    ("qwen2.5", 32_768),
+    ("mistral", 32_768),
+];
+
+/// Context window of `model`, assuming a small one for unknown models
+pub fn context_window(model: &str) -> u32 {
+    MODEL_CONTEXT_WINDOWS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map_or(DEFAULT_CONTEXT_WINDOW, |&(_, window)| window)
+}
+
+/// Completion limit that leaves room in `window` for a prompt of `prompt_chars`
+/// (about four characters per token), capped at [`AUTO_MAX_TOKENS_CAP`]
+pub fn auto_max_tokens(window: u32, prompt_chars: usize) -> u32 {
+    let prompt_tokens =
+        u32::try_from(Usage::estimate(prompt_chars, 0).input_tokens).unwrap_or(u32::MAX);
+    window
+        .saturating_sub(prompt_tokens)
+        .clamp(1, AUTO_MAX_TOKENS_CAP)
+}
+
+/// URL for an `OLLAMA_HOST` value, which like Ollama may leave out the scheme
+/// (`http`) and the port (11434 without a scheme)
+pub fn ollama_url_from_host(host: &str) -> Option<String> {
+    let host = host.trim().trim_end_matches('/');
+    if host.is_empty() {
+        return None;
+    }
+    if host.contains("://") {
+        return Some(host.to_string());
+    }
+    let has_port = host
+        .rsplit_once(':')
+        .is_some_and(|(_, port)| !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()));
+    Some(if has_port {
+        format!("http://{host}")
+    } else {
+        format!("http://{host}:11434")
+    })
+}
+
+/// Constructor for a provider registered with [`register_provider`]
+pub type ProviderFactory =
+    Box<dyn Fn(&ProviderConfig) -> Result<Box<dyn AIProvider>> + Send + Sync>;
+
+fn provider_registry() -> &'static RwLock<HashMap<String, ProviderFactory>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, ProviderFactory>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Register a provider factory for `ProviderType::Custom(name)` configurations.
+///
+/// Registering the same name again replaces the previous factory.
+pub fn register_provider<F>(name: &str, factory: F)
+where
+    F: Fn(&ProviderConfig) -> Result<Box<dyn AIProvider>> + Send + Sync + 'static,
+{
+    provider_registry()
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert(name.to_string(), Box::new(factory));
+}
+
+/// Names of all providers registered with [`register_provider`]
+pub fn registered_providers() -> Vec<String> {
+    let registry = provider_registry()
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut names: Vec<String> = registry.keys().cloned().collect();
+    names.sort();
+    names
 }
 
 /// Factory function to create the appropriate provider based on configuration
 pub fn create_provider(config: &ProviderConfig) -> Result<Box<dyn AIProvider>> {
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(config.timeout_seconds))
+    if let ProviderType::Custom(name) = &config.provider_type {
+        let registry = provider_registry()
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let factory = registry.get(name).with_context(|| {
+            format!("Unknown provider type '{name}'. Register it with register_provider first")
+        })?;
+        return factory(config);
+    }
+
+    let client = ClientOptions::for_provider(config)
+        .apply(reqwest::Client::builder())
         .build()
         .context("Failed to create HTTP client")?;
 
@@ -153,7 +493,7 @@ pub fn create_provider(config: &ProviderConfig) -> Result<Box<dyn AIProvider>> {
             let provider = OllamaProvider::new(config.clone(), client)?;
             Ok(Box::new(provider))
         }
-        ProviderType::OpenAI => {
+        ProviderType::OpenAI | ProviderType::OpenAICompatible => {
             let provider = OpenAIProvider::new(config.clone(), client)?;
             Ok(Box::new(provider))
         }
@@ -169,9 +509,381 @@ pub fn create_provider(config: &ProviderConfig) -> Result<Box<dyn AIProvider>> {
             let provider = LocalProvider::new(config.clone())?;
             Ok(Box::new(provider))
         }
+        ProviderType::Custom(_) => unreachable!("custom providers are handled above"),
     }
 }
 
+/// How the HTTP client for a provider is set up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ClientOptions {
+    timeout: Duration,
+    accept_invalid_certs: bool,
+}
+
+impl ClientOptions {
+    fn for_provider(config: &ProviderConfig) -> Self {
+        Self {
+            timeout: Duration::from_secs(config.timeout_seconds),
+            accept_invalid_certs: config.accepts_invalid_certs(),
+        }
+    }
+
+    fn apply(self, builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        if self.accept_invalid_certs {
+            eprintln!("⚠️  WARNING: TLS certificate verification is DISABLED (danger_accept_invalid_certs).");
+            eprintln!("   Anyone on the network path can read or alter these requests, API keys included.");
+        }
+        builder
+            .timeout(self.timeout)
+            .danger_accept_invalid_certs(self.accept_invalid_certs)
+    }
+}
+
+// Set once at startup from `--trace-http`
+static TRACE_HTTP: AtomicBool = AtomicBool::new(false);
+
+/// Print every provider request and response body to stderr, credentials masked
+pub fn set_http_trace(enabled: bool) {
+    TRACE_HTTP.store(enabled, Ordering::Relaxed);
+}
+
+/// A provider failure `--simulate-error` injects, to try out error handling without a
+/// real outage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulatedError {
+    Timeout,
+    RateLimited,
+    ServerError,
+    Dns,
+}
+
+/// Values `--simulate-error` accepts
+pub const SIMULATED_ERRORS: [&str; 4] = ["timeout", "429", "500", "dns"];
+
+impl SimulatedError {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "timeout" => Some(Self::Timeout),
+            "429" => Some(Self::RateLimited),
+            "500" => Some(Self::ServerError),
+            "dns" => Some(Self::Dns),
+            _ => None,
+        }
+    }
+
+    /// The error a real failure of this kind produces for `provider`
+    pub fn error(self, provider: &str) -> anyhow::Error {
+        let cause = match self {
+            Self::Timeout => "operation timed out (simulated)",
+            Self::Dns => "dns error: failed to lookup address information (simulated)",
+            Self::RateLimited => {
+                return request_failed_error(
+                    provider,
+                    reqwest::StatusCode::TOO_MANY_REQUESTS,
+                    "Rate limit reached (simulated)",
+                )
+            }
+            Self::ServerError => {
+                return request_failed_error(
+                    provider,
+                    reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+                    "Internal server error (simulated)",
+                )
+            }
+        };
+        anyhow::anyhow!(cause).context(format!("Failed to send request to {provider}"))
+    }
+}
+
+// Set once at startup from `--simulate-error`, taken by the next query provider created
+static SIMULATED_ERROR: Mutex<Option<SimulatedError>> = Mutex::new(None);
+
+/// Make the next provider call fail with `error` instead of reaching the provider
+pub fn simulate_error(error: Option<SimulatedError>) {
+    *SIMULATED_ERROR
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = error;
+}
+
+/// The pending `--simulate-error`, if any; only the first caller gets it
+pub(crate) fn take_simulated_error() -> Option<SimulatedError> {
+    SIMULATED_ERROR
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .take()
+}
+
+/// Send `request` and read the whole response body, tracing both under `--trace-http`
+async fn send_request(
+    request: reqwest::RequestBuilder,
+    provider: &str,
+) -> Result<(reqwest::StatusCode, String)> {
+    let trace = TRACE_HTTP.load(Ordering::Relaxed);
+    let (client, request) = request.build_split();
+    let request = request.with_context(|| format!("Failed to send request to {provider}"))?;
+    if trace {
+        eprintln!("{}", render_request_trace(&request));
+    }
+
+    let response = client
+        .execute(request)
+        .await
+        .with_context(|| format!("Failed to send request to {provider}"))?;
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .with_context(|| format!("Failed to read {provider} response"))?;
+    if trace {
+        eprintln!("{}", render_response_trace(status, &body));
+    }
+    Ok((status, body))
+}
+
+/// Send `request` for a streamed answer; the body is left for the caller to read as
+/// it arrives. Under `--trace-http` the request is traced here.
+async fn send_streaming_request(
+    request: reqwest::RequestBuilder,
+    provider: &str,
+) -> Result<reqwest::Response> {
+    let (client, request) = request.build_split();
+    let request = request.with_context(|| format!("Failed to send request to {provider}"))?;
+    if TRACE_HTTP.load(Ordering::Relaxed) {
+        eprintln!("{}", render_request_trace(&request));
+    }
+    client
+        .execute(request)
+        .await
+        .with_context(|| format!("Failed to send request to {provider}"))
+}
+
+/// Whether a header or query parameter named `name` carries a credential
+fn is_secret_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ["auth", "key", "token", "secret"]
+        .iter()
+        .any(|part| name.contains(part))
+}
+
+fn render_request_trace(request: &reqwest::Request) -> String {
+    let mut url = request.url().clone();
+    let query: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| {
+            let value = if is_secret_name(&name) {
+                "***".into()
+            } else {
+                value
+            };
+            (name.into_owned(), value.into_owned())
+        })
+        .collect();
+    if !query.is_empty() {
+        url.query_pairs_mut().clear().extend_pairs(query);
+    }
+
+    let mut trace = format!("➡️  {} {url}\n", request.method());
+    for (name, value) in request.headers() {
+        let value = if is_secret_name(name.as_str()) {
+            "***".to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+        trace.push_str(&format!("   {name}: {value}\n"));
+    }
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .unwrap_or_default();
+    trace.push_str(&pretty_body(&String::from_utf8_lossy(body)));
+    trace
+}
+
+fn render_response_trace(status: reqwest::StatusCode, body: &str) -> String {
+    format!("⬅️  {status}\n{}", pretty_body(body))
+}
+
+/// `body` pretty-printed when it is JSON, as is otherwise
+fn pretty_body(body: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(body)
+        .and_then(|value| serde_json::to_string_pretty(&value))
+        .unwrap_or_else(|_| body.to_string())
+}
+
+/// Build the error for a failed HTTP request, with an actionable message for quota/billing errors
+fn request_failed_error(provider: &str, status: reqwest::StatusCode, body: &str) -> anyhow::Error {
+    match billing_error_message(provider, body) {
+        Some(message) => anyhow::anyhow!("{message}"),
+        None => anyhow::anyhow!(
+            "{} request failed with status: {} - {}",
+            provider,
+            status,
+            body
+        ),
+    }
+}
+
+/// Recognize quota and billing failures in a provider's JSON error body. Gemini's
+/// `RESOURCE_EXHAUSTED` is usually a rate limit, so it gets a wait-and-retry message
+/// unless it mentions billing.
+pub fn billing_error_message(provider: &str, body: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    let error = value.get("error")?;
+    let field = |key: &str| {
+        error
+            .get(key)
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_lowercase()
+    };
+    let (error_type, code, status, message) = (
+        field("type"),
+        field("code"),
+        field("status"),
+        field("message"),
+    );
+
+    let is_quota = matches!(
+        error_type.as_str(),
+        "insufficient_quota" | "billing_error" | "billing_hard_limit_reached"
+    ) || matches!(
+        code.as_str(),
+        "insufficient_quota" | "billing_hard_limit_reached" | "billing_not_active"
+    ) || message.contains("credit balance is too low")
+        || (status == "resource_exhausted" && message.contains("billing"));
+    if !is_quota && status == "resource_exhausted" {
+        return Some(format!(
+            "{provider} is rate limiting your requests — wait a minute and try again, or check your plan's rate limits"
+        ));
+    }
+
+    is_quota.then(|| {
+        format!(
+            "Your {provider} account has insufficient quota or credits — add credits or switch providers with 'tai init'"
+        )
+    })
+}
+
+/// How long a token from `token_command` is reused when `token_ttl_seconds` is not set
+const DEFAULT_TOKEN_TTL_SECONDS: u64 = 300;
+
+/// Credentials for an HTTP provider request
+#[derive(Debug, Clone, PartialEq)]
+enum Credential {
+    /// The static `api_key` setting, sent the provider's usual way
+    ApiKey(String),
+    /// A gateway token from `token_command`, sent as `Authorization: Bearer`
+    Bearer(String),
+}
+
+/// Caches the token printed by the `token_command` setting for `token_ttl_seconds`
+#[derive(Debug, Default)]
+struct TokenCache {
+    cached: Mutex<Option<(String, Instant)>>,
+}
+
+impl TokenCache {
+    /// Credentials for the next request: a fresh or cached gateway token when
+    /// `token_command` is configured, otherwise the key `api_key_command` prints or
+    /// the static `api_key`
+    async fn credential(&self, config: &ProviderConfig, provider: &str) -> Result<Credential> {
+        let Some(command) = config
+            .get_setting("token_command")
+            .filter(|c| !c.is_empty())
+        else {
+            if let Some(command) = config
+                .get_setting("api_key_command")
+                .filter(|c| !c.is_empty())
+            {
+                return Ok(Credential::ApiKey(api_key_from_command(command).await?));
+            }
+            let api_key = config
+                .get_setting("api_key")
+                .with_context(|| format!("{provider} API key not found in configuration"))?;
+            return Ok(Credential::ApiKey(api_key.clone()));
+        };
+
+        let ttl = config
+            .get_setting("token_ttl_seconds")
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(DEFAULT_TOKEN_TTL_SECONDS);
+        let ttl = Duration::from_secs(ttl);
+
+        if let Some((token, fetched_at)) = &*self.lock() {
+            if fetched_at.elapsed() < ttl {
+                return Ok(Credential::Bearer(token.clone()));
+            }
+        }
+
+        let token = run_credential_command("token_command", command).await?;
+        *self.lock() = Some((token.clone(), Instant::now()));
+        Ok(Credential::Bearer(token))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<(String, Instant)>> {
+        self.cached
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Keys printed by `api_key_command`, by command; a secret manager is asked once per
+/// process
+static API_KEYS: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+/// The API key `command` prints (`api_key_command`), run on first use and cached for
+/// the rest of the process
+async fn api_key_from_command(command: &str) -> Result<String> {
+    let cached = API_KEYS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .as_ref()
+        .and_then(|keys| keys.get(command).cloned());
+    if let Some(key) = cached {
+        return Ok(key);
+    }
+    let key = run_credential_command("api_key_command", command).await?;
+    API_KEYS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get_or_insert_with(HashMap::new)
+        .insert(command.to_string(), key.clone());
+    Ok(key)
+}
+
+/// Run the `setting` command through the shell and return the secret it prints
+async fn run_credential_command(setting: &str, command: &str) -> Result<String> {
+    let output = crate::shell::shell()?
+        .async_command(command)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {setting} '{command}'"))?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "{} '{}' failed with exit code {}: {}",
+            setting,
+            command,
+            output.status.code().unwrap_or(-1),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let secret = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if secret.is_empty() {
+        return Err(anyhow::anyhow!("{setting} '{command}' printed nothing"));
+    }
+    Ok(secret)
+}
+
+/// HTTP providers accept a static `api_key`, an `api_key_command` or a `token_command`
+fn has_credentials(config: &ProviderConfig) -> bool {
+    config.get_setting("api_key").is_some()
+        || ["api_key_command", "token_command"]
+            .iter()
+            .any(|setting| config.get_setting(setting).is_some_and(|c| !c.is_empty()))
+}
+
 // Ollama Provider Implementation
 pub struct OllamaProvider {
     config: ProviderConfig,
@@ -183,11 +895,25 @@ struct OllamaRequest {
     model: String,
     prompt: String,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<OllamaOptions>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
 }
 
+/// The answer, or with `stream` one line of it per generated piece
 #[derive(Debug, Deserialize)]
 struct OllamaResponse {
     response: String,
+    /// Reasoning returned separately by thinking models
+    #[serde(default)]
+    thinking: Option<String>,
 }
 
 impl OllamaProvider {
@@ -196,47 +922,204 @@ impl OllamaProvider {
         provider.validate_config()?;
         Ok(provider)
     }
+
+    fn request(&self, system_prompt: &str, user_prompt: &str, stream: bool) -> OllamaRequest {
+        OllamaRequest {
+            model: self.config.get_setting_or_default("model", "llama2"),
+            prompt: format!("{system_prompt}\n\nUser Request: {user_prompt}"),
+            stream,
+            options: Some(OllamaOptions {
+                stop: self.config.get_stop_sequences(),
+                num_predict: self
+                    .config
+                    .max_tokens(system_prompt.len() + user_prompt.len()),
+            })
+            .filter(|options| !options.stop.is_empty() || options.num_predict.is_some()),
+        }
+    }
 }
 
-#[async_trait]
-impl AIProvider for OllamaProvider {
-    async fn send_query(&self, system_prompt: &str, user_prompt: &str) -> Result<String> {
-        let combined_prompt = format!("{system_prompt}\n\nUser Request: {user_prompt}");
+/// Collects a streamed Ollama answer from the JSON lines of its body, which may be
+/// split anywhere across network chunks
+#[derive(Debug, Default)]
+struct OllamaStream {
+    pending: Vec<u8>,
+    text: String,
+}
 
-        let request = OllamaRequest {
-            model: self.config.get_setting_or_default("model", "llama2"),
-            prompt: combined_prompt,
-            stream: false,
-        };
+impl OllamaStream {
+    /// Add `bytes` of the body, handing each newly generated piece to `on_chunk`
+    fn push(&mut self, bytes: &[u8], on_chunk: &mut ChunkHandler<'_>) -> Result<()> {
+        self.pending.extend_from_slice(bytes);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            self.parse_line(&line, on_chunk)?;
+        }
+        Ok(())
+    }
 
-        let url = format!(
-            "{}/api/generate",
-            self.config
-                .get_setting_or_default("url", "http://localhost:11434")
+    /// The whole answer, once the body has ended
+    fn finish(&mut self, on_chunk: &mut ChunkHandler<'_>) -> Result<String> {
+        let rest = std::mem::take(&mut self.pending);
+        self.parse_line(&rest, on_chunk)?;
+        Ok(std::mem::take(&mut self.text))
+    }
+
+    fn parse_line(&mut self, line: &[u8], on_chunk: &mut ChunkHandler<'_>) -> Result<()> {
+        if line.trim_ascii().is_empty() {
+            return Ok(());
+        }
+        let piece: OllamaResponse =
+            serde_json::from_slice(line).context("Failed to parse Ollama response")?;
+        if !piece.response.is_empty() {
+            on_chunk(&piece.response);
+            self.text.push_str(&piece.response);
+        }
+        Ok(())
+    }
+}
+
+/// Finish a streamed answer that broke off after `received` was handed on, from
+/// `retried`, the whole answer asked for again without streaming. A new answer is
+/// rarely word for word the same, so only its commands must continue the ones
+/// received, which the caller may already have acted on; `on_chunk` then gets the
+/// lines after them. The unfinished last line received must still begin the next
+/// line, unless it holds no command yet. Returns the text handed on in all.
+fn resume_stream(
+    provider: &str,
+    received: &str,
+    retried: String,
+    on_chunk: &mut ChunkHandler<'_>,
+) -> Result<String> {
+    let commands = |text: &str| -> Vec<String> {
+        crate::extractor::extract(text, None)
+            .into_iter()
+            .map(|extracted| extracted.command)
+            .collect()
+    };
+    let (complete, partial) = received.split_at(received.rfind('\n').map_or(0, |i| i + 1));
+    let done = commands(complete);
+    if !commands(&retried).starts_with(&done) {
+        anyhow::bail!(
+            "{provider}'s stream broke off, and the answer asked for again has other commands than the part already received"
         );
+    }
 
-        let response = self
-            .client
-            .post(&url)
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to send request to Ollama")?;
+    // The lines of the new answer after the commands already received
+    let mut start = 0;
+    let mut found = 0;
+    for line in retried.split_inclusive('\n') {
+        if found >= done.len() {
+            break;
+        }
+        found += commands(line).len();
+        start += line.len();
+    }
+    let rest = &retried[start..];
+    let rest = match rest.strip_prefix(partial) {
+        Some(rest) => rest.to_string(),
+        None if commands(partial).is_empty() => format!("\n{rest}"),
+        None => anyhow::bail!(
+            "{provider}'s stream broke off in the middle of a command the answer asked for again doesn't repeat"
+        ),
+    };
+    eprintln!("⚠️  The rest of the answer comes from {provider} asked again without streaming");
+    if !rest.is_empty() {
+        on_chunk(&rest);
+    }
+    Ok(format!("{received}{rest}"))
+}
 
-        if !response.status().is_success() {
+impl OllamaProvider {
+    /// One answer, requested without streaming
+    async fn generate(&self, system_prompt: &str, user_prompt: &str) -> Result<OllamaResponse> {
+        let request = self.request(system_prompt, user_prompt, false);
+        let url = format!("{}/api/generate", self.config.ollama_url());
+
+        let (status, body) = send_request(self.client.post(&url).json(&request), "Ollama").await?;
+
+        if !status.is_success() {
             return Err(anyhow::anyhow!(
                 "Ollama request failed with status: {} - {}",
-                response.status(),
-                response.text().await.unwrap_or_default()
+                status,
+                body
             ));
         }
 
-        let ollama_response: OllamaResponse = response
-            .json()
-            .await
-            .context("Failed to parse Ollama response")?;
+        serde_json::from_str(&body).context("Failed to parse Ollama response")
+    }
+}
+
+#[async_trait]
+impl AIProvider for OllamaProvider {
+    async fn send_query(&self, system_prompt: &str, user_prompt: &str) -> Result<String> {
+        let ollama_response = self.generate(system_prompt, user_prompt).await?;
+
+        // Keep separately returned reasoning in the same shape as inline <think> blocks
+        match ollama_response.thinking.filter(|t| !t.trim().is_empty()) {
+            Some(thinking) => Ok(format!(
+                "<think>\n{thinking}\n</think>\n{}",
+                ollama_response.response
+            )),
+            None => Ok(ollama_response.response),
+        }
+    }
+
+    async fn send_query_streaming(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        on_chunk: &mut ChunkHandler<'_>,
+    ) -> Result<String> {
+        let request = self.request(system_prompt, user_prompt, true);
+        let url = format!("{}/api/generate", self.config.ollama_url());
+
+        let mut response =
+            send_streaming_request(self.client.post(&url).json(&request), "Ollama").await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "Ollama request failed with status: {} - {}",
+                status,
+                body
+            ));
+        }
+
+        // Thinking pieces are not handed on, so reasoning never reaches the caller
+        let mut stream = OllamaStream::default();
+        let parsed = loop {
+            let bytes = response
+                .chunk()
+                .await
+                .context("Failed to read Ollama response")?;
+            match bytes {
+                Some(bytes) => {
+                    if let Err(e) = stream.push(&bytes, on_chunk) {
+                        break Err(e);
+                    }
+                }
+                None => break stream.finish(on_chunk),
+            }
+        };
+        let text = match parsed {
+            Ok(text) => text,
+            // A line that doesn't parse leaves the answer half read; ask once more
+            // without streaming for the rest of it
+            Err(e) => {
+                eprintln!("⚠️  {e:#} while streaming; asking again without streaming");
+                let retried = self.generate(system_prompt, user_prompt).await?;
+                return resume_stream("Ollama", &stream.text, retried.response, on_chunk);
+            }
+        };
+        if TRACE_HTTP.load(Ordering::Relaxed) {
+            eprintln!("{}", render_response_trace(status, &text));
+        }
+        Ok(text)
+    }
 
-        Ok(ollama_response.response)
+    fn supports_streaming(&self) -> bool {
+        true
     }
 
     fn provider_name(&self) -> &'static str {
@@ -244,8 +1127,12 @@ impl AIProvider for OllamaProvider {
     }
 
     fn validate_config(&self) -> Result<()> {
-        if self.config.get_setting("url").is_none() {
-            return Err(anyhow::anyhow!("Ollama URL is required"));
+        if self.config.get_setting("url").is_none()
+            && std::env::var_os(OLLAMA_HOST_ENV_VAR).is_none()
+        {
+            return Err(anyhow::anyhow!(
+                "Ollama URL is required (set url, or OLLAMA_HOST)"
+            ));
         }
         if self.config.get_setting("model").is_none() {
             return Err(anyhow::anyhow!("Ollama model is required"));
@@ -258,6 +1145,7 @@ impl AIProvider for OllamaProvider {
 pub struct OpenAIProvider {
     config: ProviderConfig,
     client: reqwest::Client,
+    tokens: TokenCache,
 }
 
 #[derive(Debug, Serialize)]
@@ -266,17 +1154,32 @@ struct OpenAIRequest {
     messages: Vec<OpenAIMessage>,
     max_tokens: u32,
     temperature: f32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<serde_json::Value>,
 }
 
 #[derive(Debug, Serialize)]
 struct OpenAIMessage {
     role: String,
-    content: String,
+    /// Plain text, or an array of text and image parts when there are attachments
+    content: serde_json::Value,
 }
 
 #[derive(Debug, Deserialize)]
 struct OpenAIResponse {
     choices: Vec<OpenAIChoice>,
+    #[serde(default)]
+    usage: Option<OpenAIUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -286,86 +1189,308 @@ struct OpenAIChoice {
 
 #[derive(Debug, Deserialize)]
 struct OpenAIResponseMessage {
-    content: String,
+    /// `null` when the model answers with a tool call
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<OpenAIToolCall>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIToolCall {
+    function: OpenAIFunctionCall,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIFunctionCall {
+    name: String,
+    /// JSON-encoded arguments, e.g. `{"commands": ["ls -la"]}`
+    arguments: String,
+}
+
+/// The single tool offered when `tool_calls = true`
+const RUN_COMMANDS_TOOL: &str = "run_commands";
+
+fn run_commands_tool() -> serde_json::Value {
+    serde_json::json!({
+        "type": "function",
+        "function": {
+            "name": RUN_COMMANDS_TOOL,
+            "description": "Run shell commands in order to fulfil the user's request",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "commands": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "Shell commands, one per item, in execution order"
+                    }
+                },
+                "required": ["commands"]
+            }
+        }
+    })
+}
+
+/// Commands from the `run_commands` tool calls, or `None` when the model did not call it
+fn commands_from_tool_calls(tool_calls: &[OpenAIToolCall]) -> Option<Vec<String>> {
+    #[derive(Deserialize)]
+    struct RunCommandsArguments {
+        commands: Vec<String>,
+    }
+
+    let mut commands = Vec::new();
+    let mut called = false;
+    for call in tool_calls
+        .iter()
+        .filter(|call| call.function.name == RUN_COMMANDS_TOOL)
+    {
+        let Ok(arguments) = serde_json::from_str::<RunCommandsArguments>(&call.function.arguments)
+        else {
+            continue;
+        };
+        called = true;
+        commands.extend(
+            arguments
+                .commands
+                .into_iter()
+                .map(|cmd| cmd.trim().to_string())
+                .filter(|cmd| !cmd.is_empty()),
+        );
+    }
+    called.then_some(commands)
+}
+
+/// Render tool-call commands as `COMMAND:` lines, which every command extractor accepts
+fn render_tool_commands(commands: &[String]) -> String {
+    commands
+        .iter()
+        .map(|cmd| format!("COMMAND: {cmd}"))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 impl OpenAIProvider {
     pub fn new(config: ProviderConfig, client: reqwest::Client) -> Result<Self> {
-        let provider = Self { config, client };
+        let provider = Self {
+            config,
+            client,
+            tokens: TokenCache::default(),
+        };
         provider.validate_config()?;
         Ok(provider)
     }
-}
 
-#[async_trait]
-impl AIProvider for OpenAIProvider {
-    async fn send_query(&self, system_prompt: &str, user_prompt: &str) -> Result<String> {
+    /// Whether this talks to an OpenAI-compatible server rather than OpenAI itself
+    fn is_compatible(&self) -> bool {
+        self.config.provider_type == ProviderType::OpenAICompatible
+    }
+
+    /// Name used in errors and by [`AIProvider::provider_name`]
+    fn label(&self) -> &'static str {
+        if self.is_compatible() {
+            "OpenAI-compatible"
+        } else {
+            "OpenAI"
+        }
+    }
+
+    /// Whether to send an `Authorization` header: always for OpenAI, and for
+    /// OpenAI-compatible servers only when a key or key command is configured
+    fn sends_credentials(&self) -> bool {
+        !self.is_compatible()
+            || ["api_key", "api_key_command", "token_command"]
+                .iter()
+                .any(|setting| {
+                    self.config
+                        .get_setting(setting)
+                        .is_some_and(|value| !value.trim().is_empty())
+                })
+    }
+
+    /// Chat completions endpoint; only OpenAI itself has a default `base_url`
+    fn completions_url(&self) -> Result<String> {
+        let base_url = if self.is_compatible() {
+            self.config
+                .get_setting("base_url")
+                .filter(|url| !url.trim().is_empty())
+                .context("OpenAI-compatible base_url is required")?
+                .trim()
+                .to_string()
+        } else {
+            self.config
+                .get_setting_or_default("base_url", "https://api.openai.com/v1")
+        };
+        Ok(format!(
+            "{}/chat/completions",
+            base_url.trim_end_matches('/')
+        ))
+    }
+
+    /// Whether to offer the `run_commands` tool (the `tool_calls` setting)
+    fn uses_tool_calls(&self) -> bool {
+        self.config
+            .get_setting("tool_calls")
+            .is_some_and(|value| matches!(value.trim(), "true" | "yes" | "on" | "1"))
+    }
+
+    /// Send one chat completion request, offering the `run_commands` tool when
+    /// `commands` are asked for and `tool_calls` is on
+    async fn request(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        attachments: &[Attachment],
+        commands: bool,
+    ) -> Result<Reply> {
+        let user_content = if attachments.is_empty() {
+            serde_json::Value::from(user_prompt)
+        } else {
+            let mut parts = vec![serde_json::json!({"type": "text", "text": user_prompt})];
+            parts.extend(attachments.iter().map(|attachment| {
+                serde_json::json!({
+                    "type": "image_url",
+                    "image_url": {"url": attachment.data_url()},
+                })
+            }));
+            serde_json::Value::Array(parts)
+        };
         let messages = vec![
             OpenAIMessage {
                 role: "system".to_string(),
-                content: system_prompt.to_string(),
+                content: serde_json::Value::from(system_prompt),
             },
             OpenAIMessage {
                 role: "user".to_string(),
-                content: user_prompt.to_string(),
+                content: user_content,
             },
         ];
 
         let request = OpenAIRequest {
             model: self.config.get_setting_or_default("model", "gpt-3.5-turbo"),
             messages,
-            max_tokens: 1000,
+            max_tokens: self
+                .config
+                .max_tokens(system_prompt.len() + user_prompt.len())
+                .unwrap_or(DEFAULT_MAX_TOKENS),
             temperature: 0.1,
+            // OpenAI accepts at most four stop sequences
+            stop: self
+                .config
+                .get_stop_sequences()
+                .into_iter()
+                .take(4)
+                .collect(),
+            tools: if commands && self.uses_tool_calls() {
+                vec![run_commands_tool()]
+            } else {
+                Vec::new()
+            },
         };
 
-        let api_key = self
-            .config
-            .get_setting("api_key")
-            .context("OpenAI API key not found in configuration")?;
-
-        let url = format!(
-            "{}/chat/completions",
-            self.config
-                .get_setting_or_default("base_url", "https://api.openai.com/v1")
-        );
-
-        let response = self
+        let label = self.label();
+        let url = self.completions_url()?;
+        let mut request_builder = self
             .client
             .post(&url)
-            .header("Authorization", format!("Bearer {api_key}"))
             .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to send request to OpenAI")?;
+            .json(&request);
+        if self.sends_credentials() {
+            let credential = self.tokens.credential(&self.config, label).await?;
+            let token = match credential {
+                Credential::ApiKey(token) | Credential::Bearer(token) => token,
+            };
+            request_builder = request_builder.header("Authorization", format!("Bearer {token}"));
+        }
+        let (status, body) = send_request(request_builder, label).await?;
 
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "OpenAI request failed with status: {} - {}",
-                response.status(),
-                response.text().await.unwrap_or_default()
-            ));
+        if !status.is_success() {
+            return Err(request_failed_error(label, status, &body));
         }
 
-        let openai_response: OpenAIResponse = response
-            .json()
-            .await
-            .context("Failed to parse OpenAI response")?;
+        let openai_response: OpenAIResponse = serde_json::from_str(&body)
+            .with_context(|| format!("Failed to parse {label} response"))?;
 
-        openai_response
+        let usage = openai_response.usage.map(|usage| Usage {
+            input_tokens: usage.prompt_tokens,
+            output_tokens: usage.completion_tokens,
+        });
+        let message = openai_response
             .choices
-            .first()
-            .map(|choice| choice.message.content.clone())
-            .context("No response from OpenAI")
+            .into_iter()
+            .next()
+            .map(|choice| choice.message)
+            .with_context(|| format!("No response from {label}"))?;
+
+        // Fall back to the text answer when the model didn't call the tool
+        let text = match commands_from_tool_calls(&message.tool_calls) {
+            Some(commands) => render_tool_commands(&commands),
+            None => message
+                .content
+                .with_context(|| format!("No response from {label}"))?,
+        };
+        Ok(Reply { text, usage })
+    }
+}
+
+#[async_trait]
+impl AIProvider for OpenAIProvider {
+    async fn send_query(&self, system_prompt: &str, user_prompt: &str) -> Result<String> {
+        Ok(self
+            .send_query_with_usage(system_prompt, user_prompt)
+            .await?
+            .text)
+    }
+
+    async fn send_query_with_usage(&self, system_prompt: &str, user_prompt: &str) -> Result<Reply> {
+        self.send_query_with_attachments(system_prompt, user_prompt, &[])
+            .await
+    }
+
+    fn supports_attachments(&self) -> bool {
+        true
+    }
+
+    async fn send_query_with_attachments(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        attachments: &[Attachment],
+    ) -> Result<Reply> {
+        self.request(system_prompt, user_prompt, attachments, false)
+            .await
+    }
+
+    async fn send_command_request(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        attachments: &[Attachment],
+    ) -> Result<Reply> {
+        self.request(system_prompt, user_prompt, attachments, true)
+            .await
     }
 
     fn provider_name(&self) -> &'static str {
-        "OpenAI"
+        self.label()
     }
 
     fn validate_config(&self) -> Result<()> {
-        if self.config.get_setting("api_key").is_none() {
-            return Err(anyhow::anyhow!("OpenAI API key is required"));
+        if self.is_compatible() {
+            self.completions_url()?;
+            if self
+                .config
+                .get_setting("model")
+                .is_none_or(|model| model.trim().is_empty())
+            {
+                return Err(anyhow::anyhow!("OpenAI-compatible model is required"));
+            }
+            return Ok(());
+        }
+        if !has_credentials(&self.config) {
+            return Err(anyhow::anyhow!(
+                "OpenAI API key is required (or set api_key_command or token_command)"
+            ));
         }
         if self.config.get_setting("model").is_none() {
             return Err(anyhow::anyhow!("OpenAI model is required"));
@@ -378,6 +1503,7 @@ impl AIProvider for OpenAIProvider {
 pub struct ClaudeProvider {
     config: ProviderConfig,
     client: reqwest::Client,
+    tokens: TokenCache,
 }
 
 #[derive(Debug, Serialize)]
@@ -386,17 +1512,30 @@ struct ClaudeRequest {
     max_tokens: u32,
     messages: Vec<ClaudeMessage>,
     system: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
 struct ClaudeMessage {
     role: String,
-    content: String,
+    /// Plain text, or an array of image and text blocks when there are attachments
+    content: serde_json::Value,
 }
 
 #[derive(Debug, Deserialize)]
 struct ClaudeResponse {
     content: Vec<ClaudeContent>,
+    #[serde(default)]
+    usage: Option<ClaudeUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -406,7 +1545,11 @@ struct ClaudeContent {
 
 impl ClaudeProvider {
     pub fn new(config: ProviderConfig, client: reqwest::Client) -> Result<Self> {
-        let provider = Self { config, client };
+        let provider = Self {
+            config,
+            client,
+            tokens: TokenCache::default(),
+        };
         provider.validate_config()?;
         Ok(provider)
     }
@@ -415,24 +1558,66 @@ impl ClaudeProvider {
 #[async_trait]
 impl AIProvider for ClaudeProvider {
     async fn send_query(&self, system_prompt: &str, user_prompt: &str) -> Result<String> {
+        Ok(self
+            .send_query_with_usage(system_prompt, user_prompt)
+            .await?
+            .text)
+    }
+
+    async fn send_query_with_usage(&self, system_prompt: &str, user_prompt: &str) -> Result<Reply> {
+        self.send_query_with_attachments(system_prompt, user_prompt, &[])
+            .await
+    }
+
+    fn supports_attachments(&self) -> bool {
+        true
+    }
+
+    async fn send_query_with_attachments(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        attachments: &[Attachment],
+    ) -> Result<Reply> {
+        // Claude reads images best when they come before the question
+        let content = if attachments.is_empty() {
+            serde_json::Value::from(user_prompt)
+        } else {
+            let mut blocks: Vec<serde_json::Value> = attachments
+                .iter()
+                .map(|attachment| {
+                    serde_json::json!({
+                        "type": "image",
+                        "source": {
+                            "type": "base64",
+                            "media_type": attachment.media_type,
+                            "data": attachment.data,
+                        },
+                    })
+                })
+                .collect();
+            blocks.push(serde_json::json!({"type": "text", "text": user_prompt}));
+            serde_json::Value::Array(blocks)
+        };
         let messages = vec![ClaudeMessage {
             role: "user".to_string(),
-            content: user_prompt.to_string(),
+            content,
         }];
 
         let request = ClaudeRequest {
             model: self
                 .config
                 .get_setting_or_default("model", "claude-3-sonnet-20240229"),
-            max_tokens: 1000,
+            max_tokens: self
+                .config
+                .max_tokens(system_prompt.len() + user_prompt.len())
+                .unwrap_or(DEFAULT_MAX_TOKENS),
             messages,
             system: system_prompt.to_string(),
+            stop_sequences: self.config.get_stop_sequences(),
         };
 
-        let api_key = self
-            .config
-            .get_setting("api_key")
-            .context("Claude API key not found in configuration")?;
+        let credential = self.tokens.credential(&self.config, "Claude").await?;
 
         let url = format!(
             "{}/v1/messages",
@@ -440,35 +1625,35 @@ impl AIProvider for ClaudeProvider {
                 .get_setting_or_default("base_url", "https://api.anthropic.com")
         );
 
-        let response = self
-            .client
-            .post(&url)
-            .header("x-api-key", api_key)
+        let request_builder = match credential {
+            Credential::ApiKey(api_key) => self.client.post(&url).header("x-api-key", api_key),
+            Credential::Bearer(token) => self.client.post(&url).bearer_auth(token),
+        };
+        let request_builder = request_builder
             .header("anthropic-version", "2023-06-01")
             .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to send request to Claude")?;
+            .json(&request);
+        let (status, body) = send_request(request_builder, "Claude").await?;
 
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "Claude request failed with status: {} - {}",
-                response.status(),
-                response.text().await.unwrap_or_default()
-            ));
+        if !status.is_success() {
+            return Err(request_failed_error("Claude", status, &body));
         }
 
-        let claude_response: ClaudeResponse = response
-            .json()
-            .await
-            .context("Failed to parse Claude response")?;
+        let claude_response: ClaudeResponse =
+            serde_json::from_str(&body).context("Failed to parse Claude response")?;
 
-        claude_response
+        let text = claude_response
             .content
             .first()
             .map(|content| content.text.clone())
-            .context("No response from Claude")
+            .context("No response from Claude")?;
+        Ok(Reply {
+            text,
+            usage: claude_response.usage.map(|usage| Usage {
+                input_tokens: usage.input_tokens,
+                output_tokens: usage.output_tokens,
+            }),
+        })
     }
 
     fn provider_name(&self) -> &'static str {
@@ -476,8 +1661,10 @@ impl AIProvider for ClaudeProvider {
     }
 
     fn validate_config(&self) -> Result<()> {
-        if self.config.get_setting("api_key").is_none() {
-            return Err(anyhow::anyhow!("Claude API key is required"));
+        if !has_credentials(&self.config) {
+            return Err(anyhow::anyhow!(
+                "Claude API key is required (or set api_key_command or token_command)"
+            ));
         }
         if self.config.get_setting("model").is_none() {
             return Err(anyhow::anyhow!("Claude model is required"));
@@ -490,6 +1677,7 @@ impl AIProvider for ClaudeProvider {
 pub struct GeminiProvider {
     config: ProviderConfig,
     client: reqwest::Client,
+    tokens: TokenCache,
 }
 
 #[derive(Debug, Serialize)]
@@ -506,8 +1694,22 @@ struct GeminiContent {
 }
 
 #[derive(Debug, Serialize)]
-struct GeminiPart {
-    text: String,
+#[serde(untagged)]
+enum GeminiPart {
+    Text {
+        text: String,
+    },
+    InlineData {
+        #[serde(rename = "inlineData")]
+        inline_data: GeminiInlineData,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct GeminiInlineData {
+    #[serde(rename = "mimeType")]
+    mime_type: String,
+    data: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -515,31 +1717,101 @@ struct GeminiGenerationConfig {
     temperature: f32,
     #[serde(rename = "maxOutputTokens")]
     max_output_tokens: u32,
+    #[serde(rename = "stopSequences", skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+}
+
+/// API versions Gemini serves `generateContent` under; newer models need `v1beta`
+const GEMINI_API_VERSIONS: [&str; 2] = ["v1", "v1beta"];
+
+/// Finish reasons meaning a filter withheld the candidate's content
+const GEMINI_BLOCK_REASONS: [&str; 5] = [
+    "SAFETY",
+    "RECITATION",
+    "BLOCKLIST",
+    "PROHIBITED_CONTENT",
+    "SPII",
+];
+
+#[derive(Debug, Deserialize)]
+struct GeminiResponse {
+    #[serde(default)]
+    candidates: Vec<GeminiCandidate>,
+    #[serde(rename = "promptFeedback")]
+    prompt_feedback: Option<GeminiPromptFeedback>,
+    #[serde(rename = "usageMetadata")]
+    usage_metadata: Option<GeminiUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiUsage {
+    #[serde(rename = "promptTokenCount", default)]
+    prompt_token_count: u64,
+    #[serde(rename = "candidatesTokenCount", default)]
+    candidates_token_count: u64,
 }
 
 #[derive(Debug, Deserialize)]
-struct GeminiResponse {
-    candidates: Vec<GeminiCandidate>,
+struct GeminiPromptFeedback {
+    #[serde(rename = "blockReason")]
+    block_reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct GeminiCandidate {
-    content: GeminiResponseContent,
+    content: Option<GeminiResponseContent>,
+    #[serde(rename = "finishReason")]
+    finish_reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct GeminiResponseContent {
+    #[serde(default)]
     parts: Vec<GeminiResponsePart>,
 }
 
 #[derive(Debug, Deserialize)]
 struct GeminiResponsePart {
+    #[serde(default)]
     text: String,
 }
 
+/// The text of the first candidate, or why there is none.
+///
+/// A blocked prompt has no candidates and a `blockReason`; a blocked answer has a
+/// candidate without content whose `finishReason` names the filter.
+fn gemini_response_text(response: GeminiResponse) -> Result<String> {
+    if let Some(reason) = response.prompt_feedback.and_then(|f| f.block_reason) {
+        return Err(anyhow::anyhow!(
+            "Gemini blocked the response (safety): the prompt was blocked ({reason})"
+        ));
+    }
+    let candidate = response
+        .candidates
+        .into_iter()
+        .next()
+        .context("No response from Gemini")?;
+    let text: String = candidate
+        .content
+        .map(|content| content.parts.into_iter().map(|part| part.text).collect())
+        .unwrap_or_default();
+
+    match candidate.finish_reason.as_deref() {
+        Some(reason) if text.is_empty() && GEMINI_BLOCK_REASONS.contains(&reason) => Err(
+            anyhow::anyhow!("Gemini blocked the response (safety): finishReason {reason}"),
+        ),
+        _ if text.is_empty() => Err(anyhow::anyhow!("No response from Gemini")),
+        _ => Ok(text),
+    }
+}
+
 impl GeminiProvider {
     pub fn new(config: ProviderConfig, client: reqwest::Client) -> Result<Self> {
-        let provider = Self { config, client };
+        let provider = Self {
+            config,
+            client,
+            tokens: TokenCache::default(),
+        };
         provider.validate_config()?;
         Ok(provider)
     }
@@ -548,12 +1820,40 @@ impl GeminiProvider {
 #[async_trait]
 impl AIProvider for GeminiProvider {
     async fn send_query(&self, system_prompt: &str, user_prompt: &str) -> Result<String> {
+        Ok(self
+            .send_query_with_usage(system_prompt, user_prompt)
+            .await?
+            .text)
+    }
+
+    async fn send_query_with_usage(&self, system_prompt: &str, user_prompt: &str) -> Result<Reply> {
+        self.send_query_with_attachments(system_prompt, user_prompt, &[])
+            .await
+    }
+
+    fn supports_attachments(&self) -> bool {
+        true
+    }
+
+    async fn send_query_with_attachments(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        attachments: &[Attachment],
+    ) -> Result<Reply> {
         let combined_prompt = format!("{system_prompt}\n\nUser Request: {user_prompt}");
 
+        let mut parts = vec![GeminiPart::Text {
+            text: combined_prompt,
+        }];
+        parts.extend(attachments.iter().map(|attachment| GeminiPart::InlineData {
+            inline_data: GeminiInlineData {
+                mime_type: attachment.media_type.to_string(),
+                data: attachment.data.clone(),
+            },
+        }));
         let contents = vec![GeminiContent {
-            parts: vec![GeminiPart {
-                text: combined_prompt,
-            }],
+            parts,
             role: "user".to_string(),
         }];
 
@@ -561,49 +1861,47 @@ impl AIProvider for GeminiProvider {
             contents,
             generation_config: GeminiGenerationConfig {
                 temperature: 0.1,
-                max_output_tokens: 1000,
+                max_output_tokens: self
+                    .config
+                    .max_tokens(system_prompt.len() + user_prompt.len())
+                    .unwrap_or(DEFAULT_MAX_TOKENS),
+                stop_sequences: self.config.get_stop_sequences(),
             },
         };
 
-        let api_key = self
-            .config
-            .get_setting("api_key")
-            .context("Gemini API key not found in configuration")?;
+        let credential = self.tokens.credential(&self.config, "Gemini").await?;
 
         let model = self.config.get_setting_or_default("model", "gemini-pro");
         let base_url = self
             .config
             .get_setting_or_default("base_url", "https://generativelanguage.googleapis.com");
-        let url = format!("{base_url}/v1/models/{model}:generateContent?key={api_key}");
+        let api_version = self.config.get_setting_or_default("api_version", "v1");
+        let url = format!("{base_url}/{api_version}/models/{model}:generateContent");
 
-        let response = self
-            .client
-            .post(&url)
+        let request_builder = match credential {
+            Credential::ApiKey(api_key) => self.client.post(&url).query(&[("key", api_key)]),
+            Credential::Bearer(token) => self.client.post(&url).bearer_auth(token),
+        };
+        let request_builder = request_builder
             .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to send request to Gemini")?;
+            .json(&request);
+        let (status, body) = send_request(request_builder, "Gemini").await?;
 
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "Gemini request failed with status: {} - {}",
-                response.status(),
-                response.text().await.unwrap_or_default()
-            ));
+        if !status.is_success() {
+            return Err(request_failed_error("Gemini", status, &body));
         }
 
-        let gemini_response: GeminiResponse = response
-            .json()
-            .await
-            .context("Failed to parse Gemini response")?;
-
-        gemini_response
-            .candidates
-            .first()
-            .and_then(|candidate| candidate.content.parts.first())
-            .map(|part| part.text.clone())
-            .context("No response from Gemini")
+        let gemini_response: GeminiResponse =
+            serde_json::from_str(&body).context("Failed to parse Gemini response")?;
+
+        let usage = gemini_response.usage_metadata.as_ref().map(|usage| Usage {
+            input_tokens: usage.prompt_token_count,
+            output_tokens: usage.candidates_token_count,
+        });
+        Ok(Reply {
+            text: gemini_response_text(gemini_response)?,
+            usage,
+        })
     }
 
     fn provider_name(&self) -> &'static str {
@@ -611,16 +1909,88 @@ impl AIProvider for GeminiProvider {
     }
 
     fn validate_config(&self) -> Result<()> {
-        if self.config.get_setting("api_key").is_none() {
-            return Err(anyhow::anyhow!("Gemini API key is required"));
+        if !has_credentials(&self.config) {
+            return Err(anyhow::anyhow!(
+                "Gemini API key is required (or set api_key_command or token_command)"
+            ));
         }
         if self.config.get_setting("model").is_none() {
             return Err(anyhow::anyhow!("Gemini model is required"));
         }
+        if let Some(version) = self.config.get_setting("api_version") {
+            if !GEMINI_API_VERSIONS.contains(&version.as_str()) {
+                return Err(anyhow::anyhow!(
+                    "Gemini api_version must be one of {}, found '{version}'",
+                    GEMINI_API_VERSIONS.join(", ")
+                ));
+            }
+        }
         Ok(())
     }
 }
 
+/// The first llama.cpp binary in the `PATH`-style list `path_var`, preferring
+/// `llama-cli`. The legacy `main` name is too generic to trust outside our own install.
+fn find_llama_cpp_on_path(path_var: &std::ffi::OsStr, windows: bool) -> Option<std::path::PathBuf> {
+    let names: &[&str] = if windows {
+        &["llama-cli.exe", "llama-server.exe"]
+    } else {
+        &["llama-cli", "llama-server"]
+    };
+    names.iter().find_map(|name| {
+        std::env::split_paths(path_var)
+            .map(|dir| dir.join(name))
+            .find(|path| crate::shell::is_executable(path))
+    })
+}
+
+/// The command `get_model_path` would run to install git-lfs on `os`, if it knows one
+fn git_lfs_installer(os: &str) -> Option<&'static str> {
+    match os {
+        "macos" => Some("brew install git-lfs"),
+        "linux" => Some("sudo apt-get update && sudo apt-get install -y git-lfs"),
+        "windows" => Some("winget install Git.GitLFS"),
+        _ => None,
+    }
+}
+
+/// Git LFS pointer files are small text stubs that start with this header
+const LFS_POINTER_HEADER: &[u8] = b"version https://git-lfs.github.com/spec/";
+
+/// Pointer stubs are well under a kilobyte; real models are hundreds of megabytes
+const LFS_POINTER_MAX_SIZE: u64 = 1024;
+
+/// Whether `path` is an LFS pointer stub rather than the actual file contents
+fn is_lfs_pointer_file(path: &std::path::Path) -> Result<bool> {
+    use std::io::Read;
+
+    let metadata = std::fs::metadata(path)
+        .with_context(|| format!("Failed to read metadata for {}", path.display()))?;
+    if metadata.len() > LFS_POINTER_MAX_SIZE {
+        return Ok(false);
+    }
+
+    let mut header = vec![0u8; LFS_POINTER_HEADER.len()];
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    match file.read_exact(&mut header) {
+        Ok(()) => Ok(header == LFS_POINTER_HEADER),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// Fail when a downloaded model is only an LFS pointer stub
+fn validate_downloaded_model(path: &std::path::Path) -> Result<()> {
+    if is_lfs_pointer_file(path)? {
+        return Err(anyhow::anyhow!(
+            "Model download incomplete — LFS pull failed ({} is a Git LFS pointer, not the model)",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
 // Local Provider Implementation
 pub struct LocalProvider {
     config: ProviderConfig,
@@ -786,6 +2156,14 @@ impl LocalProvider {
             }
         }
 
+        // A system-wide install (brew, apt, nix...) saves a multi-hundred-MB download
+        if let Some(path) = std::env::var_os("PATH")
+            .and_then(|path_var| find_llama_cpp_on_path(&path_var, os == "windows"))
+        {
+            println!("✅ Using llama.cpp from PATH: {}", path.display());
+            return Ok(path.to_string_lossy().to_string());
+        }
+
         println!("📥 Installing llama.cpp...");
 
         // Create installation directory
@@ -947,6 +2325,7 @@ impl LocalProvider {
         let model_path = model_dir.join(model_filename);
 
         if model_path.exists() {
+            validate_downloaded_model(&model_path)?;
             return Ok(model_path.to_string_lossy().to_string());
         }
 
@@ -1009,9 +2388,18 @@ impl LocalProvider {
             .output();
 
         if lfs_check.is_err() {
-            println!("❌ Git LFS is not installed. Attempting to install automatically...");
+            println!("❌ Git LFS is not installed.");
 
             let os = Self::detect_os();
+            // Installing system packages (with sudo on Linux) needs the user's consent
+            if let Some(installer) = git_lfs_installer(os) {
+                let question = format!("❓ Install git-lfs now with `{installer}`?");
+                if !crate::ui::confirm_risky(&question) {
+                    return Err(anyhow::anyhow!(
+                        "Git LFS is required to download the model. Install it with `{installer}` and try again."
+                    ));
+                }
+            }
             let install_result = match os {
                 "macos" => {
                     println!("📥 Installing git-lfs using Homebrew...");
@@ -1247,6 +2635,13 @@ impl LocalProvider {
         // Clean up temporary directory
         std::fs::remove_dir_all(&temp_dir).context("Failed to remove temp directory")?;
 
+        // A failed LFS pull leaves the pointer stub in place of the model
+        if let Err(e) = validate_downloaded_model(&model_path) {
+            println!("❌ {e}");
+            let _ = std::fs::remove_file(&model_path);
+            return Err(e);
+        }
+
         println!("✅ Model downloaded successfully using git clone!");
         Ok(model_path.to_string_lossy().to_string())
     }
@@ -1289,8 +2684,13 @@ impl LocalProvider {
         let model_path = model_dir.join(model_filename);
 
         if model_path.exists() {
-            println!("✅ Model already exists at: {}", model_path.display());
-            return Ok(model_path.to_string_lossy().to_string());
+            if validate_downloaded_model(&model_path).is_ok() {
+                println!("✅ Model already exists at: {}", model_path.display());
+                return Ok(model_path.to_string_lossy().to_string());
+            }
+            // A previous download left an LFS pointer behind; fetch the real file
+            println!("⚠️  Found an incomplete model download, downloading again...");
+            std::fs::remove_file(&model_path).context("Failed to remove incomplete model file")?;
         }
 
         // Since Hugging Face requires authentication, try git clone as fallback
@@ -1458,62 +2858,112 @@ impl LocalProvider {
     }
 }
 
+/// Run llama.cpp from `path` with `run`. When that binary has been deleted since the
+/// install check (a partial cleanup of ~/.terminalai, say) and `self_heal` is set,
+/// `reinstall` is tried once and the run repeated with the path it returns.
+async fn run_with_reinstall<F, Fut>(
+    path: String,
+    self_heal: bool,
+    mut run: F,
+    reinstall: impl FnOnce() -> Result<String>,
+) -> Result<String>
+where
+    F: FnMut(String) -> Fut,
+    Fut: std::future::Future<Output = Result<String>>,
+{
+    match run(path.clone()).await {
+        Err(e) if self_heal && crate::executor::is_missing_program(&e) => {
+            crate::ui::show(&format!(
+                "⚠️  llama.cpp is missing from {path}; reinstalling it...\n"
+            ));
+            let path = reinstall()
+                .with_context(|| format!("{path} is missing and reinstalling llama.cpp failed"))?;
+            run(path).await
+        }
+        result => result,
+    }
+}
+
 #[async_trait]
 impl AIProvider for LocalProvider {
     async fn send_query(&self, system_prompt: &str, user_prompt: &str) -> Result<String> {
-        println!("🔧 Setting up local AI provider...");
+        crate::ui::show("🔧 Setting up local AI provider...\n");
 
         // Ensure llama.cpp is installed
         let llama_cpp_path = self.ensure_llama_cpp_installed()?;
+        let self_heal = self.config.self_heal();
 
         // Check for existing model first, only download if absolutely necessary
         let model_path = match self.get_existing_model_path() {
             Ok(path) => {
-                println!("✅ Using existing model at: {path}");
+                crate::ui::show(&format!("✅ Using existing model at: {path}\n"));
                 path
             }
+            // The download was agreed to at `tai init --defer-setup`
+            Err(_)
+                if self
+                    .config
+                    .get_setting(crate::config::LOCAL_SETUP_SETTING)
+                    .is_some_and(|s| s == crate::config::LOCAL_SETUP_DEFERRED) =>
+            {
+                crate::ui::show("📥 Finishing the local provider setup deferred at init...\n");
+                self.ensure_model_downloaded().await?
+            }
             Err(_) => {
-                println!("⚠️  No existing model found. This will require downloading a large model file.");
-                println!("💡 Consider using Ollama instead for easier model management:");
-                println!("   tai init");
-                println!("   # Select Ollama provider");
-                println!("   ollama pull qwen2.5-coder:1.5b");
+                crate::ui::show(concat!(
+                    "⚠️  No existing model found. This will require downloading a large model file.\n",
+                    "💡 Consider using Ollama instead for easier model management:\n",
+                    "   tai init\n",
+                    "   # Select Ollama provider\n",
+                    "   ollama pull qwen2.5-coder:1.5b\n",
+                ));
                 return Err(anyhow::anyhow!(
                     "No model found. Please set up a model or use a different provider."
                 ));
             }
         };
 
-        println!("🤖 Running local AI model...");
+        crate::ui::show("🤖 Running local AI model...\n");
 
         // Prepare the prompt
         let combined_prompt = format!("{system_prompt}\n\nUser Request: {user_prompt}");
 
-        // Run llama.cpp with optimized parameters
-        let output = std::process::Command::new(&llama_cpp_path)
-            .arg("-m")
-            .arg(&model_path)
-            .arg("-p")
-            .arg(&combined_prompt)
-            .arg("-n")
-            .arg("512") // Max tokens
-            .arg("-c")
-            .arg("2048") // Context size
-            .arg("-t")
-            .arg("4") // Threads
-            .arg("--temp")
-            .arg("0.1") // Temperature
-            .arg("--repeat-penalty")
-            .arg("1.1") // Repeat penalty
-            .output()
-            .context("Failed to run llama.cpp")?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!("llama.cpp failed: {}", stderr));
-        }
+        let run = |llama_cpp_path: String| {
+            let model_path = model_path.clone();
+            let combined_prompt = combined_prompt.clone();
+            async move {
+                // Run llama.cpp with optimized parameters
+                let mut command = tokio::process::Command::new(&llama_cpp_path);
+                command
+                    .arg("-m")
+                    .arg(&model_path)
+                    .arg("-p")
+                    .arg(&combined_prompt)
+                    .arg("-n")
+                    .arg("512") // Max tokens
+                    .arg("-c")
+                    .arg("2048") // Context size
+                    .arg("-t")
+                    .arg("4") // Threads
+                    .arg("--temp")
+                    .arg("0.1") // Temperature
+                    .arg("--repeat-penalty")
+                    .arg("1.1"); // Repeat penalty
+
+                // Show the generation as it is produced instead of waiting for all of it,
+                // on stderr when stdout is piped on
+                crate::executor::stream_command_output(&mut command, |line| {
+                    crate::ui::show(&format!("{line}\n"));
+                })
+                .await
+            }
+        };
+        let response = run_with_reinstall(llama_cpp_path, self_heal, run, || {
+            self.ensure_llama_cpp_installed()
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("llama.cpp failed: {e:#}"))?;
 
-        let response = String::from_utf8_lossy(&output.stdout);
         Ok(response.trim().to_string())
     }
 
@@ -1526,3 +2976,1097 @@ impl AIProvider for LocalProvider {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+
+    fn with_stop_sequences(mut config: ProviderConfig, base_url: &str) -> ProviderConfig {
+        let url_key = match config.provider_type {
+            ProviderType::Ollama => "url",
+            _ => "base_url",
+        };
+        config
+            .settings
+            .insert(url_key.to_string(), base_url.to_string());
+        config.settings.insert(
+            "stop_sequences".to_string(),
+            "```, Explanation:".to_string(),
+        );
+        config
+    }
+
+    #[tokio::test]
+    async fn test_registered_provider_is_created_via_registry() {
+        register_provider("dummy", |config| {
+            let greeting = config.get_setting_or_default("greeting", "hello");
+            Ok(Box::new(crate::test_support::MockProvider::from_fn(
+                move |user_prompt| Ok(format!("{greeting} {user_prompt}")),
+            )))
+        });
+        assert!(registered_providers().contains(&"dummy".to_string()));
+
+        let mut config = ProviderConfig {
+            provider_type: ProviderType::Custom("dummy".to_string()),
+            timeout_seconds: 30,
+            settings: HashMap::new(),
+        };
+        config
+            .settings
+            .insert("greeting".to_string(), "hi".to_string());
+
+        let provider = create_provider(&config).unwrap();
+        assert_eq!(provider.provider_name(), "Mock");
+        assert_eq!(
+            provider.send_query("system", "there").await.unwrap(),
+            "hi there"
+        );
+        assert_eq!(config.provider_type.to_string(), "dummy");
+    }
+
+    #[test]
+    fn test_unregistered_custom_provider_fails() {
+        let config = ProviderConfig {
+            provider_type: ProviderType::Custom("missing-provider".to_string()),
+            timeout_seconds: 30,
+            settings: HashMap::new(),
+        };
+        let err = create_provider(&config).err().unwrap();
+        assert!(err
+            .to_string()
+            .contains("Unknown provider type 'missing-provider'"));
+    }
+
+    #[test]
+    fn test_custom_provider_type_serialization_round_trip() {
+        let provider_type = ProviderType::Custom("dummy".to_string());
+        let json = serde_json::to_string(&provider_type).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ProviderType>(&json).unwrap(),
+            provider_type
+        );
+        assert_eq!(
+            serde_json::to_string(&ProviderType::Ollama).unwrap(),
+            "\"Ollama\""
+        );
+    }
+
+    #[test]
+    fn test_lfs_pointer_stub_is_invalid_model() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+
+        let stub = temp_dir.path().join("model.gguf");
+        std::fs::write(
+            &stub,
+            "version https://git-lfs.github.com/spec/v1\n\
+             oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393\n\
+             size 1117320736\n",
+        )
+        .unwrap();
+        assert!(is_lfs_pointer_file(&stub).unwrap());
+        let err = validate_downloaded_model(&stub).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Model download incomplete — LFS pull failed"));
+
+        let model = temp_dir.path().join("real.gguf");
+        std::fs::write(&model, b"GGUF\x03\x00\x00\x00").unwrap();
+        assert!(!is_lfs_pointer_file(&model).unwrap());
+        assert!(validate_downloaded_model(&model).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_llama_cpp_on_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let install = |dir: &str, name: &str, mode: u32| {
+            let dir = temp_dir.path().join(dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(name);
+            std::fs::write(&path, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+            path
+        };
+        install("usr/bin", "main", 0o755);
+        install("opt/bin", "llama-server", 0o755);
+        install("home/bin", "llama-cli", 0o644);
+        let path_var = std::env::join_paths(
+            ["home/bin", "usr/bin", "opt/bin"].map(|dir| temp_dir.path().join(dir)),
+        )
+        .unwrap();
+
+        // A non-executable llama-cli and a generic `main` are both passed over
+        assert_eq!(
+            find_llama_cpp_on_path(&path_var, false),
+            Some(temp_dir.path().join("opt/bin/llama-server"))
+        );
+
+        let cli = install("usr/bin", "llama-cli", 0o755);
+        assert_eq!(find_llama_cpp_on_path(&path_var, false), Some(cli));
+        assert_eq!(
+            find_llama_cpp_on_path(std::ffi::OsStr::new(""), false),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_token_command_output_is_used_as_bearer_token() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let counter = temp_dir.path().join("invocations");
+        let token_command = format!("echo run >> '{}'; echo gateway-token", counter.display());
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .match_header("authorization", "Bearer gateway-token")
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"ls"}}]}"#)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let mut config = ProviderConfig::new_openai(String::new(), "gpt-4".to_string(), 30);
+        config.settings.remove("api_key");
+        config.settings.insert("base_url".to_string(), server.url());
+        config
+            .settings
+            .insert("token_command".to_string(), token_command);
+
+        let provider = create_provider(&config).unwrap();
+        assert_eq!(provider.send_query("system", "user").await.unwrap(), "ls");
+        assert_eq!(provider.send_query("system", "user").await.unwrap(), "ls");
+        mock.assert_async().await;
+
+        // The token is cached for its TTL, so the command only ran once
+        let invocations = std::fs::read_to_string(&counter).unwrap();
+        assert_eq!(invocations.lines().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_token_command_refreshes_after_ttl() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let counter = temp_dir.path().join("invocations");
+        let mut config = ProviderConfig::new_claude(String::new(), "claude-3".to_string(), 30);
+        config.settings.insert(
+            "token_command".to_string(),
+            format!("echo run >> '{}'; echo fresh", counter.display()),
+        );
+        config
+            .settings
+            .insert("token_ttl_seconds".to_string(), "0".to_string());
+
+        let cache = TokenCache::default();
+        for _ in 0..2 {
+            assert_eq!(
+                cache.credential(&config, "Claude").await.unwrap(),
+                Credential::Bearer("fresh".to_string())
+            );
+        }
+        let invocations = std::fs::read_to_string(&counter).unwrap();
+        assert_eq!(invocations.lines().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failing_token_command_is_reported() {
+        let mut config = ProviderConfig::new_gemini(String::new(), "gemini-pro".to_string(), 30);
+        config
+            .settings
+            .insert("token_command".to_string(), "exit 7".to_string());
+
+        let err = TokenCache::default()
+            .credential(&config, "Gemini")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("failed with exit code 7"));
+
+        config.settings.remove("token_command");
+        assert_eq!(
+            TokenCache::default()
+                .credential(&config, "Gemini")
+                .await
+                .unwrap(),
+            Credential::ApiKey(String::new())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_key_command_output_is_used_as_key() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let counter = temp_dir.path().join("invocations");
+        let api_key_command = format!("echo run >> '{}'; echo sk-from-vault", counter.display());
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .match_header("authorization", "Bearer sk-from-vault")
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"ls"}}]}"#)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let mut config = ProviderConfig::new_openai(String::new(), "gpt-4".to_string(), 30);
+        config.settings.remove("api_key");
+        config.settings.insert("base_url".to_string(), server.url());
+        config
+            .settings
+            .insert("api_key_command".to_string(), api_key_command);
+
+        // Cached for the process, even across providers
+        for _ in 0..2 {
+            let provider = create_provider(&config).unwrap();
+            assert_eq!(provider.send_query("system", "user").await.unwrap(), "ls");
+        }
+        mock.assert_async().await;
+        let invocations = std::fs::read_to_string(&counter).unwrap();
+        assert_eq!(invocations.lines().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failing_api_key_command_is_reported() {
+        let mut config = ProviderConfig::new_claude(String::new(), "claude-3".to_string(), 30);
+        config.settings.insert(
+            "api_key_command".to_string(),
+            "echo 'vault is sealed' >&2; exit 3".to_string(),
+        );
+        let err = TokenCache::default()
+            .credential(&config, "Claude")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "api_key_command 'echo 'vault is sealed' >&2; exit 3' failed with exit code 3: vault is sealed"
+        );
+
+        config
+            .settings
+            .insert("api_key_command".to_string(), "true".to_string());
+        let err = TokenCache::default()
+            .credential(&config, "Claude")
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "api_key_command 'true' printed nothing");
+    }
+
+    #[test]
+    fn test_invalid_certs_only_accepted_when_enabled() {
+        let mut config = ProviderConfig::new_openai("key".to_string(), "gpt-4o".to_string(), 45);
+        assert_eq!(
+            ClientOptions::for_provider(&config),
+            ClientOptions {
+                timeout: Duration::from_secs(45),
+                accept_invalid_certs: false,
+            }
+        );
+
+        for (value, accepted) in [("true", true), ("1", true), ("false", false), ("", false)] {
+            config
+                .settings
+                .insert("danger_accept_invalid_certs".to_string(), value.to_string());
+            assert_eq!(
+                ClientOptions::for_provider(&config).accept_invalid_certs,
+                accepted,
+                "{value}"
+            );
+        }
+        // The flag reaches a client that builds
+        config.settings.insert(
+            "danger_accept_invalid_certs".to_string(),
+            "true".to_string(),
+        );
+        assert!(create_provider(&config).is_ok());
+    }
+
+    #[test]
+    fn test_get_stop_sequences() {
+        let mut config = ProviderConfig::default();
+        assert!(config.get_stop_sequences().is_empty());
+
+        config.settings.insert(
+            "stop_sequences".to_string(),
+            " ```, Explanation: ,,".to_string(),
+        );
+        assert_eq!(config.get_stop_sequences(), vec!["```", "Explanation:"]);
+    }
+
+    #[tokio::test]
+    async fn test_ollama_request_includes_stop_sequences() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/generate")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"options":{"stop":["```","Explanation:"]}}"#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"response": "ls"}"#)
+            .create_async()
+            .await;
+
+        let config = with_stop_sequences(ProviderConfig::default(), &server.url());
+        let provider = create_provider(&config).unwrap();
+        assert!(provider.send_query("system", "user").await.is_ok());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_openai_request_includes_stop_sequences() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"stop":["```","Explanation:"]}"#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"ls"}}]}"#)
+            .create_async()
+            .await;
+
+        let config = with_stop_sequences(
+            ProviderConfig::new_openai("key".to_string(), "gpt-4".to_string(), 30),
+            &server.url(),
+        );
+        let provider = create_provider(&config).unwrap();
+        assert!(provider.send_query("system", "user").await.is_ok());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_openai_request_includes_image_attachment() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"messages":[{"role":"system","content":"system"},{"role":"user","content":[{"type":"text","text":"what fixes this error?"},{"type":"image_url","image_url":{"url":"data:image/png;base64,iVBORw=="}}]}]}"#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: chmod +x run.sh"}}]}"#)
+            .create_async()
+            .await;
+
+        let mut config = ProviderConfig::new_openai("key".to_string(), "gpt-4o".to_string(), 30);
+        config.settings.insert("base_url".to_string(), server.url());
+        let provider = create_provider(&config).unwrap();
+        assert!(provider.supports_attachments());
+        let attachment = Attachment::new("image/png", b"\x89PNG");
+        let reply = provider
+            .send_query_with_attachments("system", "what fixes this error?", &[attachment])
+            .await
+            .unwrap();
+        assert_eq!(reply.text, "COMMAND: chmod +x run.sh");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_claude_request_includes_image_attachment() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/messages")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"messages":[{"role":"user","content":[{"type":"image","source":{"type":"base64","media_type":"image/jpeg","data":"/9j/"}},{"type":"text","text":"what fixes this error?"}]}]}"#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"content":[{"text":"ls"}]}"#)
+            .create_async()
+            .await;
+
+        let mut config = ProviderConfig::new_claude("key".to_string(), "claude-3".to_string(), 30);
+        config.settings.insert("base_url".to_string(), server.url());
+        let provider = create_provider(&config).unwrap();
+        let attachment = Attachment::new("image/jpeg", b"\xff\xd8\xff");
+        assert!(provider
+            .send_query_with_attachments("system", "what fixes this error?", &[attachment])
+            .await
+            .is_ok());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_text_only_providers_refuse_attachments() {
+        let provider = create_provider(&ProviderConfig::default()).unwrap();
+        assert!(!provider.supports_attachments());
+        let attachment = Attachment::new("image/png", b"\x89PNG");
+        let err = provider
+            .send_query_with_attachments("system", "user", std::slice::from_ref(&attachment))
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("Ollama can't read attachments"),
+            "{err}"
+        );
+
+        let err = crate::query_provider::QueryProvider::from_provider(provider)
+            .with_attachments(vec![attachment])
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("--attach needs OpenAI, Claude or Gemini"));
+    }
+
+    #[tokio::test]
+    async fn test_claude_request_includes_stop_sequences() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/messages")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"stop_sequences":["```","Explanation:"]}"#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"content":[{"text":"ls"}]}"#)
+            .create_async()
+            .await;
+
+        let config = with_stop_sequences(
+            ProviderConfig::new_claude("key".to_string(), "claude-3".to_string(), 30),
+            &server.url(),
+        );
+        let provider = create_provider(&config).unwrap();
+        assert!(provider.send_query("system", "user").await.is_ok());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_gemini_request_includes_stop_sequences() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock(
+                "POST",
+                Matcher::Regex(r"^/v1/models/.*:generateContent".to_string()),
+            )
+            .match_body(Matcher::PartialJsonString(
+                r#"{"generationConfig":{"stopSequences":["```","Explanation:"]}}"#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"candidates":[{"content":{"parts":[{"text":"ls"}]}}]}"#)
+            .create_async()
+            .await;
+
+        let config = with_stop_sequences(
+            ProviderConfig::new_gemini("key".to_string(), "gemini-pro".to_string(), 30),
+            &server.url(),
+        );
+        let provider = create_provider(&config).unwrap();
+        assert!(provider.send_query("system", "user").await.is_ok());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_gemini_v1beta_endpoint() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1beta/models/gemini-2.0-flash:generateContent")
+            .match_query(Matcher::UrlEncoded("key".to_string(), "key".to_string()))
+            .with_status(200)
+            .with_body(
+                r#"{"candidates":[{"content":{"parts":[{"text":"ls "},{"text":"-la"}],"role":"model"},"finishReason":"STOP"}],
+                    "usageMetadata":{"totalTokenCount":12},"modelVersion":"gemini-2.0-flash"}"#,
+            )
+            .create_async()
+            .await;
+
+        let mut config =
+            ProviderConfig::new_gemini("key".to_string(), "gemini-2.0-flash".to_string(), 30);
+        config.settings.insert("base_url".to_string(), server.url());
+        config
+            .settings
+            .insert("api_version".to_string(), "v1beta".to_string());
+        let provider = create_provider(&config).unwrap();
+        assert_eq!(
+            provider.send_query("system", "user").await.unwrap(),
+            "ls -la"
+        );
+        mock.assert_async().await;
+
+        config
+            .settings
+            .insert("api_version".to_string(), "v2".to_string());
+        assert!(create_provider(&config).is_err());
+    }
+
+    #[test]
+    fn test_gemini_safety_blocks() {
+        let parse = |body: &str| {
+            gemini_response_text(serde_json::from_str(body).unwrap()).map_err(|e| e.to_string())
+        };
+
+        assert_eq!(
+            parse(
+                r#"{"candidates":[{"finishReason":"SAFETY","safetyRatings":[{"category":"HARM_CATEGORY_DANGEROUS_CONTENT","probability":"HIGH"}]}]}"#
+            ),
+            Err("Gemini blocked the response (safety): finishReason SAFETY".to_string())
+        );
+        assert_eq!(
+            parse(r#"{"promptFeedback":{"blockReason":"SAFETY","safetyRatings":[]}}"#),
+            Err(
+                "Gemini blocked the response (safety): the prompt was blocked (SAFETY)".to_string()
+            )
+        );
+        assert_eq!(
+            parse(r#"{"candidates":[]}"#),
+            Err("No response from Gemini".to_string())
+        );
+        assert_eq!(
+            parse(
+                r#"{"candidates":[{"content":{"parts":[{"text":"pwd"}]},"finishReason":"STOP"}]}"#
+            ),
+            Ok("pwd".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ollama_streams_pieces() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/generate")
+            .match_body(Matcher::PartialJsonString(r#"{"stream":true}"#.to_string()))
+            .with_status(200)
+            .with_body(concat!(
+                "{\"response\":\"\",\"thinking\":\"plan first\"}\n",
+                "{\"response\":\"COMMAND: mk\"}\n",
+                "{\"response\":\"dir out\\n\"}\n",
+                "{\"response\":\"\",\"done\":true}\n",
+            ))
+            .create_async()
+            .await;
+
+        let mut config = ProviderConfig::default();
+        config.settings.insert("url".to_string(), server.url());
+        let provider = create_provider(&config).unwrap();
+        let mut pieces = Vec::new();
+        let text = provider
+            .send_query_streaming("system", "user", &mut |piece| {
+                pieces.push(piece.to_string())
+            })
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        assert_eq!(pieces, vec!["COMMAND: mk", "dir out\n"]);
+        assert_eq!(text, "COMMAND: mkdir out\n");
+    }
+
+    #[tokio::test]
+    async fn test_broken_ollama_stream_falls_back_to_one_plain_request() {
+        let mut server = mockito::Server::new_async().await;
+        let streamed = server
+            .mock("POST", "/api/generate")
+            .match_body(Matcher::PartialJsonString(r#"{"stream":true}"#.to_string()))
+            .with_status(200)
+            .with_body(concat!(
+                "{\"response\":\"COMMAND: mkdir out\\n\"}\n",
+                "{\"response\": COMMAND: ls out\n",
+            ))
+            .create_async()
+            .await;
+        let plain = server
+            .mock("POST", "/api/generate")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"stream":false}"#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"response":"COMMAND: mkdir out\nCOMMAND: ls out"}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut config = ProviderConfig::default();
+        config.settings.insert("url".to_string(), server.url());
+        let provider = create_provider(&config).unwrap();
+        let mut pieces = Vec::new();
+        let text = provider
+            .send_query_streaming("system", "user", &mut |piece| {
+                pieces.push(piece.to_string())
+            })
+            .await
+            .unwrap();
+        streamed.assert_async().await;
+        plain.assert_async().await;
+        assert_eq!(text, "COMMAND: mkdir out\nCOMMAND: ls out");
+        assert_eq!(pieces, vec!["COMMAND: mkdir out\n", "COMMAND: ls out"]);
+        assert_eq!(pieces.concat(), text);
+    }
+
+    #[test]
+    fn test_resumed_stream_must_continue_what_was_received() {
+        let mut pieces = Vec::new();
+        let text = resume_stream("Ollama", "", "ls".to_string(), &mut |piece| {
+            pieces.push(piece.to_string())
+        })
+        .unwrap();
+        assert_eq!((text.as_str(), pieces), ("ls", vec!["ls".to_string()]));
+
+        // Worded differently, with the same commands so far
+        let mut pieces = Vec::new();
+        let text = resume_stream(
+            "Ollama",
+            "Okay.\nCOMMAND: mkdir out\nCOMMAND: ls o",
+            "Here is the plan:\nCOMMAND: mkdir out\nCOMMAND: ls out\nCOMMAND: touch out/a"
+                .to_string(),
+            &mut |piece| pieces.push(piece.to_string()),
+        )
+        .unwrap();
+        assert_eq!(pieces, vec!["ut\nCOMMAND: touch out/a"]);
+        assert_eq!(
+            text,
+            "Okay.\nCOMMAND: mkdir out\nCOMMAND: ls out\nCOMMAND: touch out/a"
+        );
+
+        // An unfinished line without a command is ended and the rest follows
+        let mut pieces = Vec::new();
+        resume_stream(
+            "Ollama",
+            "COMMAND: mkdir out\nThen",
+            "COMMAND: mkdir out\nCOMMAND: ls -la out".to_string(),
+            &mut |piece| pieces.push(piece.to_string()),
+        )
+        .unwrap();
+        assert_eq!(pieces, vec!["\nCOMMAND: ls -la out"]);
+
+        let err = resume_stream(
+            "Ollama",
+            "COMMAND: rm -r build\n",
+            "COMMAND: ls".to_string(),
+            &mut |_| panic!("handed on a differing answer"),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("has other commands"));
+
+        let err = resume_stream(
+            "Ollama",
+            "COMMAND: rm -r bu",
+            "COMMAND: rm -r dist".to_string(),
+            &mut |_| panic!("handed on a differing answer"),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("in the middle of a command"));
+    }
+
+    #[test]
+    fn test_ollama_stream_lines_split_across_chunks() {
+        let mut stream = OllamaStream::default();
+        let mut pieces = Vec::new();
+        let mut on_chunk = |piece: &str| pieces.push(piece.to_string());
+        let body = "{\"response\":\"héllo\"}\n{\"response\":\" wörld\"}".as_bytes();
+        for byte in body.chunks(3) {
+            stream.push(byte, &mut on_chunk).unwrap();
+        }
+        assert_eq!(stream.finish(&mut on_chunk).unwrap(), "héllo wörld");
+        assert_eq!(pieces, vec!["héllo", " wörld"]);
+
+        let mut stream = OllamaStream::default();
+        assert!(stream.push(b"not json\n", &mut |_| {}).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ollama_thinking_field_is_wrapped() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/generate")
+            .with_status(200)
+            .with_body(r#"{"response": "ls -la", "thinking": "list files with ls"}"#)
+            .create_async()
+            .await;
+
+        let mut config = ProviderConfig::default();
+        config.settings.insert("url".to_string(), server.url());
+        let provider = create_provider(&config).unwrap();
+        let response = provider.send_query("system", "user").await.unwrap();
+        mock.assert_async().await;
+
+        let (answer, reasoning) = crate::split_reasoning(&response);
+        assert_eq!(answer, "ls -la");
+        assert_eq!(reasoning.unwrap(), "list files with ls");
+    }
+
+    #[test]
+    fn test_billing_error_message_openai() {
+        let body = r#"{"error": {"message": "You exceeded your current quota, please check your plan and billing details.", "type": "insufficient_quota", "param": null, "code": "insufficient_quota"}}"#;
+        let message = billing_error_message("OpenAI", body).unwrap();
+        assert!(message.contains("Your OpenAI account has insufficient quota"));
+        assert!(message.contains("switch providers"));
+    }
+
+    #[test]
+    fn test_billing_error_message_claude() {
+        let body = r#"{"type": "error", "error": {"type": "invalid_request_error", "message": "Your credit balance is too low to access the Anthropic API. Please go to Plans & Billing to upgrade or purchase credits."}}"#;
+        assert!(billing_error_message("Claude", body)
+            .unwrap()
+            .contains("Your Claude account"));
+
+        let body =
+            r#"{"type": "error", "error": {"type": "billing_error", "message": "Billing issue"}}"#;
+        assert!(billing_error_message("Claude", body).is_some());
+    }
+
+    #[test]
+    fn test_billing_error_message_gemini() {
+        let body = r#"{"error": {"code": 429, "message": "Resource has been exhausted (e.g. check quota).", "status": "RESOURCE_EXHAUSTED"}}"#;
+        let message = billing_error_message("Gemini", body).unwrap();
+        assert!(message.contains("Gemini is rate limiting your requests"));
+        assert!(!message.contains("credits"));
+
+        let body = r#"{"error": {"code": 429, "message": "Quota exceeded; enable billing to continue.", "status": "RESOURCE_EXHAUSTED"}}"#;
+        assert!(billing_error_message("Gemini", body)
+            .unwrap()
+            .contains("Your Gemini account"));
+    }
+
+    #[test]
+    fn test_billing_error_message_ignores_other_errors() {
+        let rate_limit = r#"{"error": {"message": "Rate limit reached", "type": "requests", "code": "rate_limit_exceeded"}}"#;
+        assert!(billing_error_message("OpenAI", rate_limit).is_none());
+        assert!(billing_error_message("OpenAI", "Internal Server Error").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_quota_error_surfaces_actionable_message() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/chat/completions")
+            .with_status(429)
+            .with_body(r#"{"error": {"type": "insufficient_quota", "code": "insufficient_quota", "message": "quota"}}"#)
+            .create_async()
+            .await;
+
+        let mut config = ProviderConfig::new_openai("key".to_string(), "gpt-4".to_string(), 30);
+        config.settings.insert("base_url".to_string(), server.url());
+        let provider = create_provider(&config).unwrap();
+        let err = provider.send_query("system", "user").await.unwrap_err();
+        assert!(err.to_string().contains("insufficient quota"));
+    }
+
+    #[test]
+    fn test_requests_omit_stop_when_not_configured() {
+        let config = ProviderConfig::default();
+        assert!(config.get_stop_sequences().is_empty());
+
+        let claude_body = serde_json::to_value(ClaudeRequest {
+            model: "claude-3".to_string(),
+            max_tokens: 1000,
+            messages: vec![],
+            system: String::new(),
+            stop_sequences: config.get_stop_sequences(),
+        })
+        .unwrap();
+        assert!(claude_body.get("stop_sequences").is_none());
+
+        let ollama_body = serde_json::to_value(OllamaRequest {
+            model: "llama2".to_string(),
+            prompt: String::new(),
+            stream: false,
+            options: None,
+        })
+        .unwrap();
+        assert!(ollama_body.get("options").is_none());
+
+        let ollama_body = serde_json::to_value(OllamaOptions {
+            stop: config.get_stop_sequences(),
+            num_predict: Some(512),
+        })
+        .unwrap();
+        assert_eq!(ollama_body, serde_json::json!({"num_predict": 512}));
+    }
+
+    fn openai_with_tool_calls(base_url: &str) -> ProviderConfig {
+        let mut config = ProviderConfig::new_openai("sk-test".to_string(), "gpt-4".to_string(), 30);
+        config
+            .settings
+            .insert("base_url".to_string(), base_url.to_string());
+        config
+            .settings
+            .insert("tool_calls".to_string(), "true".to_string());
+        config
+    }
+
+    #[tokio::test]
+    async fn test_openai_tool_calls_are_parsed_into_commands() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "tools": [{"type": "function", "function": {"name": "run_commands"}}]
+            })))
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":null,"tool_calls":[{"id":"call_1","type":"function","function":{"name":"run_commands","arguments":"{\"commands\":[\"mkdir -p backup\",\"rsync -av src/ backup/\"]}"}}]}}]}"#,
+            )
+            .create_async()
+            .await;
+
+        // Answers that aren't commands, like explanations, are asked for without the tool
+        let _plain = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"Lists the files"}}]}"#)
+            .create_async()
+            .await;
+
+        let provider = create_provider(&openai_with_tool_calls(&server.url())).unwrap();
+        assert_eq!(
+            provider.send_query("system", "explain ls").await.unwrap(),
+            "Lists the files"
+        );
+        let response = provider
+            .send_command_request("system", "user", &[])
+            .await
+            .unwrap()
+            .text;
+        mock.assert_async().await;
+
+        assert_eq!(
+            crate::extract_commands_from_response(&response),
+            vec!["mkdir -p backup", "rsync -av src/ backup/"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_openai_tool_calls_fall_back_to_text() {
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"ls -la"}}]}"#)
+            .create_async()
+            .await;
+
+        let provider = create_provider(&openai_with_tool_calls(&server.url())).unwrap();
+        assert_eq!(
+            provider
+                .send_command_request("system", "user", &[])
+                .await
+                .unwrap()
+                .text,
+            "ls -la"
+        );
+    }
+
+    #[test]
+    fn test_http_trace_prints_body_and_masks_credentials() {
+        let request = reqwest::Client::new()
+            .post("https://gateway.example/v1/models/m:generateContent?key=sk-secret-1&alt=json")
+            .header("x-api-key", "sk-secret-2")
+            .bearer_auth("sk-secret-3")
+            .header("Content-Type", "application/json")
+            .json(&serde_json::json!({"model": "gpt-4o", "temperature": 0}))
+            .build()
+            .unwrap();
+
+        let trace = render_request_trace(&request);
+        assert!(
+            trace.starts_with(
+                "➡️  POST https://gateway.example/v1/models/m:generateContent?key=***&alt=json\n"
+            ),
+            "{trace}"
+        );
+        assert!(trace.contains("   x-api-key: ***\n"), "{trace}");
+        assert!(trace.contains("   authorization: ***\n"), "{trace}");
+        assert!(
+            trace.contains("   content-type: application/json\n"),
+            "{trace}"
+        );
+        assert!(trace.contains("  \"model\": \"gpt-4o\""), "{trace}");
+        assert!(!trace.contains("sk-secret"), "{trace}");
+
+        assert_eq!(
+            render_response_trace(reqwest::StatusCode::OK, r#"{"response":"ls"}"#),
+            "⬅️  200 OK\n{\n  \"response\": \"ls\"\n}"
+        );
+        assert_eq!(
+            render_response_trace(reqwest::StatusCode::BAD_GATEWAY, "upstream down"),
+            "⬅️  502 Bad Gateway\nupstream down"
+        );
+    }
+
+    #[test]
+    fn test_auto_max_tokens_fits_context_window() {
+        // Plenty of room: the completion cap applies
+        assert_eq!(auto_max_tokens(128_000, 4_000), AUTO_MAX_TOKENS_CAP);
+
+        // A long prompt in a small window leaves the rest for the completion
+        for (window, prompt_chars) in [(4_096u32, 12_000usize), (8_192, 30_000), (4_096, 100)] {
+            let prompt_tokens = prompt_chars.div_ceil(4) as u32;
+            let max_tokens = auto_max_tokens(window, prompt_chars);
+            assert!(
+                max_tokens <= window - prompt_tokens,
+                "{window} {prompt_chars}"
+            );
+        }
+        assert_eq!(auto_max_tokens(4_096, 12_000), 1_096);
+        assert_eq!(auto_max_tokens(4_096, 1_000_000), 1);
+
+        let mut config = ProviderConfig::new_openai("key".to_string(), "gpt-4".to_string(), 30);
+        assert_eq!(config.max_tokens(100), None);
+        config
+            .settings
+            .insert("max_tokens".to_string(), "auto".to_string());
+        assert_eq!(config.max_tokens(30_000), Some(8_192 - 7_500));
+        config
+            .settings
+            .insert("context_window".to_string(), "2000".to_string());
+        assert_eq!(config.max_tokens(4_000), Some(1_000));
+        config
+            .settings
+            .insert("max_tokens".to_string(), "256".to_string());
+        assert_eq!(config.max_tokens(4_000), Some(256));
+    }
+
+    #[test]
+    fn test_ollama_host_fills_in_unset_url() {
+        let mut config = ProviderConfig::default();
+        config.settings.remove("url");
+        assert_eq!(config.ollama_url_with_host(None), DEFAULT_OLLAMA_URL);
+        assert_eq!(
+            config.ollama_url_with_host(Some("gpu-box")),
+            "http://gpu-box:11434"
+        );
+        assert_eq!(
+            config.ollama_url_with_host(Some("0.0.0.0:8080")),
+            "http://0.0.0.0:8080"
+        );
+        assert_eq!(
+            config.ollama_url_with_host(Some("https://ollama.example/")),
+            "https://ollama.example"
+        );
+
+        // The default url counts as unset; a configured one wins
+        config
+            .settings
+            .insert("url".to_string(), DEFAULT_OLLAMA_URL.to_string());
+        assert_eq!(
+            config.ollama_url_with_host(Some("gpu-box:11434")),
+            "http://gpu-box:11434"
+        );
+        config
+            .settings
+            .insert("url".to_string(), "http://lab:11434".to_string());
+        assert_eq!(
+            config.ollama_url_with_host(Some("gpu-box:11434")),
+            "http://lab:11434"
+        );
+    }
+
+    async fn echo_run(path: String) -> Result<String> {
+        let mut command = tokio::process::Command::new(&path);
+        command.arg("answer");
+        crate::executor::stream_command_output(&mut command, |_| {}).await
+    }
+
+    #[tokio::test]
+    async fn test_missing_llama_cpp_binary_is_reinstalled_once() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let deleted = temp_dir.path().join("llama-cli");
+
+        let reinstalls = std::cell::Cell::new(0);
+        let output = run_with_reinstall(deleted.display().to_string(), true, echo_run, || {
+            reinstalls.set(reinstalls.get() + 1);
+            Ok("echo".to_string())
+        })
+        .await
+        .unwrap();
+        assert_eq!(output, "answer\n");
+        assert_eq!(reinstalls.get(), 1);
+
+        // Still missing after the reinstall: the spawn error is returned, not retried
+        let reinstalls = std::cell::Cell::new(0);
+        let err = run_with_reinstall(deleted.display().to_string(), true, echo_run, || {
+            reinstalls.set(reinstalls.get() + 1);
+            Ok(deleted.display().to_string())
+        })
+        .await
+        .unwrap_err();
+        assert!(crate::executor::is_missing_program(&err));
+        assert_eq!(reinstalls.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_llama_cpp_reinstall_only_for_missing_binary() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let deleted = temp_dir.path().join("llama-cli").display().to_string();
+        let no_reinstall = || -> Result<String> { panic!("reinstall attempted") };
+
+        // Disabled with self_heal = false
+        let err = run_with_reinstall(deleted, false, echo_run, no_reinstall)
+            .await
+            .unwrap_err();
+        assert!(crate::executor::is_missing_program(&err));
+
+        // A binary that starts but fails is not a missing install
+        let err = run_with_reinstall("false".to_string(), true, echo_run, no_reinstall)
+            .await
+            .unwrap_err();
+        assert!(!crate::executor::is_missing_program(&err));
+
+        let mut config = ProviderConfig::new_local(30);
+        assert!(config.self_heal());
+        config
+            .settings
+            .insert("self_heal".to_string(), "false".to_string());
+        assert!(!config.self_heal());
+    }
+
+    #[tokio::test]
+    async fn test_openai_compatible_sends_no_key_unless_configured() {
+        let mut server = mockito::Server::new_async().await;
+        let anonymous = server
+            .mock("POST", "/v1/chat/completions")
+            .match_header("authorization", Matcher::Missing)
+            .match_body(Matcher::PartialJsonString(
+                r#"{"model":"qwen2.5-coder-7b-instruct"}"#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: ls"}}]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let config = ProviderConfig::new_openai_compatible(
+            format!("{}/v1/", server.url()),
+            None,
+            "qwen2.5-coder-7b-instruct".to_string(),
+            30,
+        );
+        let provider = create_provider(&config).unwrap();
+        assert_eq!(provider.provider_name(), "OpenAI-compatible");
+        assert_eq!(
+            provider.send_query("system", "user").await.unwrap(),
+            "COMMAND: ls"
+        );
+        anonymous.assert_async().await;
+
+        let keyed = server
+            .mock("POST", "/v1/chat/completions")
+            .match_header("authorization", "Bearer sk-or-123")
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: pwd"}}]}"#)
+            .create_async()
+            .await;
+        let config = ProviderConfig::new_openai_compatible(
+            format!("{}/v1", server.url()),
+            Some("sk-or-123".to_string()),
+            "qwen2.5-coder-7b-instruct".to_string(),
+            30,
+        );
+        let provider = create_provider(&config).unwrap();
+        assert_eq!(
+            provider.send_query("system", "user").await.unwrap(),
+            "COMMAND: pwd"
+        );
+        keyed.assert_async().await;
+    }
+
+    #[test]
+    fn test_openai_compatible_requires_base_url_and_model() {
+        let config = |base_url: &str, model: &str| {
+            ProviderConfig::new_openai_compatible(base_url.to_string(), None, model.to_string(), 30)
+        };
+        let err = create_provider(&config("", "llama3")).err().unwrap();
+        assert_eq!(err.to_string(), "OpenAI-compatible base_url is required");
+        let err = create_provider(&config("http://localhost:8000/v1", " "))
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "OpenAI-compatible model is required");
+        assert!(create_provider(&config("http://localhost:8000/v1", "llama3")).is_ok());
+    }
+}
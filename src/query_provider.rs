@@ -1,9 +1,51 @@
-use crate::providers::{create_provider, AIProvider};
+use crate::attachment::Attachment;
+use crate::cache::ResponseCache;
+use crate::cost::{Budget, Pricing};
+use crate::providers::{create_provider, AIProvider, ChunkHandler, SimulatedError, Usage};
 use crate::TerminalAIConfig;
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Appended to the system prompt when commands are requested as structured JSON
+const STRUCTURED_OUTPUT_INSTRUCTIONS: &str = r#"
+Respond ONLY with a JSON object of the form {"commands": ["<command>", ...]}.
+"commands" must be a non-empty array of shell command strings, in execution order.
+Do not include explanations, markdown, or any text outside the JSON object."#;
+
+/// Appended to the request when a local model is asked again after answering without commands
+const EMPTY_RETRY_INSTRUCTION: &str =
+    "Answer again with only the shell commands, one per line, each starting with \"COMMAND: \". No explanations.";
 
 pub struct QueryProvider {
     provider: Box<dyn AIProvider>,
+    /// Appended to every system prompt so commands suit the shell they run in
+    /// ([`crate::shell::Shell::prompt_note`])
+    shell_note: Option<&'static str>,
+    /// Put before every system prompt: the OS, shell, working directory and its
+    /// listing ([`crate::context::PromptContext`]; `prompt_context`, `--no-context`)
+    context: Option<String>,
+    /// Token prices of the active model, when known
+    pricing: Option<Pricing>,
+    /// Cap on the estimated spend of this session (`--max-cost`)
+    budget: Option<Budget>,
+    /// Extra attempts when an answer contains no commands
+    retry_on_empty: u32,
+    /// Retries add [`EMPTY_RETRY_INSTRUCTION`]; set for local models
+    strict_retry: bool,
+    /// Answers with fewer characters count as empty (`min_response_chars`)
+    min_response_chars: usize,
+    /// Ask for commands as validated `{"commands": [...]}` JSON (`structured_output`)
+    structured: bool,
+    /// Images sent with every query (`--attach`)
+    attachments: Vec<Attachment>,
+    /// Failure the next query returns instead of reaching the provider (`--simulate-error`)
+    simulated_error: Mutex<Option<SimulatedError>>,
+    /// Answers to earlier identical requests (`cache_ttl_seconds`, `--no-cache`)
+    cache: Option<ResponseCache>,
+    /// Ask the provider for the next query even when the cache has an answer
+    /// ([`QueryProvider::refresh_next`])
+    refresh_next: AtomicBool,
 }
 
 impl QueryProvider {
@@ -16,11 +58,466 @@ impl QueryProvider {
         })?;
 
         let provider = create_provider(active_provider_config)?;
-        Ok(Self { provider })
+        Ok(Self {
+            provider,
+            shell_note: crate::shell::shell()
+                .ok()
+                .and_then(|shell| shell.prompt_note()),
+            context: crate::context::for_directory(std::path::Path::new(".")),
+            pricing: Pricing::for_provider(active_provider_config),
+            budget: None,
+            retry_on_empty: active_provider_config.retry_on_empty(),
+            strict_retry: active_provider_config.is_local(),
+            min_response_chars: active_provider_config.min_response_chars(),
+            structured: active_provider_config.structured_output(),
+            attachments: Vec::new(),
+            simulated_error: Mutex::new(crate::providers::take_simulated_error()),
+            cache: ResponseCache::for_config(&config),
+            refresh_next: AtomicBool::new(false),
+        })
+    }
+
+    /// Wrap an already constructed provider, bypassing configuration lookup
+    pub fn from_provider(provider: Box<dyn AIProvider>) -> Self {
+        Self {
+            provider,
+            shell_note: None,
+            context: None,
+            pricing: None,
+            budget: None,
+            retry_on_empty: 0,
+            strict_retry: false,
+            min_response_chars: 0,
+            structured: false,
+            attachments: Vec::new(),
+            simulated_error: Mutex::new(None),
+            cache: None,
+            refresh_next: AtomicBool::new(false),
+        }
+    }
+
+    /// Ask up to `retries` more times when an answer has no commands, adding a
+    /// stricter instruction to the retries when `strict`
+    pub fn with_retry_on_empty(mut self, retries: u32, strict: bool) -> Self {
+        self.retry_on_empty = retries;
+        self.strict_retry = strict;
+        self
+    }
+
+    /// Ask for commands as `{"commands": [...]}` JSON ([`Self::send_command_query`])
+    pub fn with_structured_output(mut self, structured: bool) -> Self {
+        self.structured = structured;
+        self
+    }
+
+    /// Treat answers shorter than `chars` characters as empty
+    pub fn with_min_response_chars(mut self, chars: usize) -> Self {
+        self.min_response_chars = chars;
+        self
+    }
+
+    /// Send `attachments` with every query; fails for providers that can't read images
+    pub fn with_attachments(mut self, attachments: Vec<Attachment>) -> Result<Self> {
+        if !attachments.is_empty() && !self.provider.supports_attachments() {
+            bail!(
+                "{} can't read attachments; --attach needs OpenAI, Claude or Gemini",
+                self.provider.provider_name()
+            );
+        }
+        self.attachments = attachments;
+        Ok(self)
+    }
+
+    /// Describe `dir` instead of the current directory to the model, for commands
+    /// that will run there; nothing changes when the context is turned off
+    pub fn with_context_in(mut self, dir: &std::path::Path) -> Self {
+        if self.context.is_some() {
+            self.context = crate::context::for_directory(dir);
+        }
+        self
+    }
+
+    /// Fail the next query with `error` instead of sending it; the ones after go through
+    pub fn with_simulated_error(self, error: SimulatedError) -> Self {
+        *self
+            .simulated_error
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(error);
+        self
+    }
+
+    /// Answer identical requests from `cache` instead of asking the provider again
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Send the next query to the provider even when the cache has an answer, as
+    /// regenerating a plan has to; the new answer replaces the cached one
+    pub fn refresh_next(&self) {
+        self.refresh_next.store(true, Ordering::Relaxed);
+    }
+
+    /// The cached answer to this request, if there is one. A pending simulated
+    /// failure still comes first, and requests with attachments are never cached.
+    fn cached(&self, system_prompt: &str, user_prompt: &str) -> Result<Option<String>> {
+        self.simulated_failure()?;
+        if self.refresh_next.swap(false, Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let Some(cache) = self.cache.as_ref().filter(|_| self.attachments.is_empty()) else {
+            return Ok(None);
+        };
+        let response = cache.get(system_prompt, user_prompt);
+        if response.is_some() {
+            crate::ui::show("⚡ Using the cached answer (--no-cache to ask again)\n");
+        }
+        Ok(response)
+    }
+
+    fn save_to_cache(&self, system_prompt: &str, user_prompt: &str, response: &str) {
+        if let Some(cache) = self.cache.as_ref().filter(|_| self.attachments.is_empty()) {
+            cache.put(system_prompt, user_prompt, response);
+        }
+    }
+
+    /// The simulated failure for this query, if one is still pending
+    fn simulated_failure(&self) -> Result<()> {
+        let pending = self
+            .simulated_error
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take();
+        match pending {
+            Some(error) => Err(error.error(self.provider.provider_name())),
+            None => Ok(()),
+        }
+    }
+
+    pub fn with_pricing(mut self, pricing: Pricing) -> Self {
+        self.pricing = Some(pricing);
+        self
+    }
+
+    /// Refuse further queries once their estimated cost reaches `max_usd`
+    pub fn with_max_cost(mut self, max_usd: f64) -> Result<Self> {
+        if self.pricing.is_none() {
+            bail!(
+                "--max-cost needs the price of the active model; set {} and {} (USD per million tokens) for its provider",
+                crate::cost::INPUT_COST_SETTING,
+                crate::cost::OUTPUT_COST_SETTING
+            );
+        }
+        self.budget = Some(Budget::new(max_usd)?);
+        Ok(self)
+    }
+
+    /// Estimated USD spent so far, when a budget is set
+    pub fn spent(&self) -> Option<f64> {
+        self.budget.as_ref().map(Budget::spent)
+    }
+
+    /// Every provider call goes through here so the budget sees it
+    async fn query(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        commands: bool,
+    ) -> Result<String> {
+        self.simulated_failure()?;
+        if !commands && self.budget.is_none() && self.attachments.is_empty() {
+            return self.provider.send_query(system_prompt, user_prompt).await;
+        }
+        if let Some(budget) = &self.budget {
+            budget.check()?;
+        }
+        let reply = if commands {
+            self.provider
+                .send_command_request(system_prompt, user_prompt, &self.attachments)
+                .await?
+        } else {
+            self.provider
+                .send_query_with_attachments(system_prompt, user_prompt, &self.attachments)
+                .await?
+        };
+        let usage = reply.usage.unwrap_or_else(|| {
+            Usage::estimate(system_prompt.len() + user_prompt.len(), reply.text.len())
+        });
+        if let (Some(budget), Some(pricing)) = (&self.budget, &self.pricing) {
+            budget.charge(pricing.cost(usage));
+        }
+        Ok(reply.text)
+    }
+
+    /// The prompt actually sent in place of `system_prompt`
+    fn system_prompt(&self, system_prompt: &str) -> String {
+        let system_prompt = match self.shell_note {
+            Some(note) => format!("{}\n{note}\n", system_prompt.trim_end()),
+            None => system_prompt.to_string(),
+        };
+        match &self.context {
+            Some(context) => format!("{context}\n\n{system_prompt}"),
+            None => system_prompt,
+        }
     }
 
     pub async fn send_query(&self, system_prompt: &str, user_prompt: &str) -> Result<String> {
-        self.provider.send_query(system_prompt, user_prompt).await
+        let system_prompt = self.system_prompt(system_prompt);
+        if let Some(response) = self.cached(&system_prompt, user_prompt)? {
+            return Ok(response);
+        }
+        let response = self.query(&system_prompt, user_prompt, false).await?;
+        self.save_to_cache(&system_prompt, user_prompt, &response);
+        Ok(response)
+    }
+
+    /// Like [`Self::send_query`], handing the answer to `on_chunk` as it is generated.
+    /// Usage is estimated from the text for the budget.
+    pub async fn send_query_streaming(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        on_chunk: &mut ChunkHandler<'_>,
+    ) -> Result<String> {
+        self.cached_stream(system_prompt, user_prompt, on_chunk, false)
+            .await
+    }
+
+    /// Like [`Self::send_query_streaming`] for a request that must produce commands,
+    /// asked for the way the provider prefers ([`AIProvider::send_command_request`]).
+    /// Unlike [`Self::send_command_query_streaming`] an answer without commands is
+    /// not asked again, since the commands may already be running.
+    pub async fn send_commands_streaming(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        on_chunk: &mut ChunkHandler<'_>,
+    ) -> Result<String> {
+        self.cached_stream(system_prompt, user_prompt, on_chunk, true)
+            .await
+    }
+
+    async fn cached_stream(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        on_chunk: &mut ChunkHandler<'_>,
+        commands: bool,
+    ) -> Result<String> {
+        let system_prompt = self.system_prompt(system_prompt);
+        if let Some(text) = self.cached(&system_prompt, user_prompt)? {
+            on_chunk(&text);
+            return Ok(text);
+        }
+        let text = self
+            .stream(&system_prompt, user_prompt, on_chunk, commands)
+            .await?;
+        self.save_to_cache(&system_prompt, user_prompt, &text);
+        Ok(text)
+    }
+
+    /// [`Self::query`] streaming to `on_chunk`
+    async fn stream(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        on_chunk: &mut ChunkHandler<'_>,
+        commands: bool,
+    ) -> Result<String> {
+        // Only the plain requests stream; one with images is handed over whole, as
+        // is a request for commands a provider that can't stream answers its own way
+        if !self.attachments.is_empty() || (commands && !self.provider.supports_streaming()) {
+            let text = self.query(system_prompt, user_prompt, commands).await?;
+            on_chunk(&text);
+            return Ok(text);
+        }
+        self.simulated_failure()?;
+        if let Some(budget) = &self.budget {
+            budget.check()?;
+        }
+        let text = self
+            .provider
+            .send_query_streaming(system_prompt, user_prompt, on_chunk)
+            .await?;
+        if let (Some(budget), Some(pricing)) = (&self.budget, &self.pricing) {
+            let usage = Usage::estimate(system_prompt.len() + user_prompt.len(), text.len());
+            budget.charge(pricing.cost(usage));
+        }
+        Ok(text)
+    }
+
+    /// Whether answers reach [`Self::send_query_streaming`]'s handler as they are
+    /// generated; otherwise it gets the whole answer at the end
+    pub fn streams(&self) -> bool {
+        self.attachments.is_empty() && !self.structured && self.provider.supports_streaming()
+    }
+
+    /// Like [`Self::send_query`], calling `on_chunk` with each piece of the answer as
+    /// it streams in, e.g. to render it in a TUI. The pieces add up to the returned
+    /// text; providers that can't stream call it once with the whole answer.
+    pub async fn send_query_with_callback(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        mut on_chunk: impl FnMut(&str) + Send,
+    ) -> Result<String> {
+        self.send_query_streaming(system_prompt, user_prompt, &mut on_chunk)
+            .await
+    }
+
+    /// Like [`Self::send_query`] for a request that must produce commands: while
+    /// `extract` finds none or the answer is shorter than `min_response_chars`, ask
+    /// again up to `retry_on_empty` times. The last answer is returned unless it is
+    /// still too short.
+    ///
+    /// With `structured_output` the commands are requested as JSON instead and
+    /// returned as `COMMAND:` lines.
+    pub async fn send_command_query(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        extract: impl Fn(&str) -> Vec<String>,
+    ) -> Result<String> {
+        self.command_query(system_prompt, user_prompt, extract, None)
+            .await
+    }
+
+    /// Like [`Self::send_command_query`], streaming every attempt to `on_chunk`
+    pub async fn send_command_query_streaming(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        extract: impl Fn(&str) -> Vec<String>,
+        on_chunk: &mut ChunkHandler<'_>,
+    ) -> Result<String> {
+        self.command_query(system_prompt, user_prompt, extract, Some(on_chunk))
+            .await
+    }
+
+    async fn command_query(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        extract: impl Fn(&str) -> Vec<String>,
+        mut on_chunk: Option<&mut ChunkHandler<'_>>,
+    ) -> Result<String> {
+        if self.structured {
+            let response = self
+                .structured_query(system_prompt, user_prompt)
+                .await?
+                .iter()
+                .map(|command| format!("COMMAND: {command}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            if let Some(on_chunk) = on_chunk {
+                on_chunk(&response);
+            }
+            return Ok(response);
+        }
+        let system_prompt = &self.system_prompt(system_prompt);
+        if let Some(response) = self.cached(system_prompt, user_prompt)? {
+            if let Some(on_chunk) = on_chunk {
+                on_chunk(&response);
+            }
+            return Ok(response);
+        }
+        let mut response = self
+            .attempt(system_prompt, user_prompt, on_chunk.as_deref_mut())
+            .await?;
+        let retry_prompt = if self.strict_retry {
+            format!("{user_prompt}\n\n{EMPTY_RETRY_INSTRUCTION}")
+        } else {
+            user_prompt.to_string()
+        };
+        for attempt in 1..=self.retry_on_empty {
+            let reason = if self.too_short(&response) {
+                "The answer is too short"
+            } else if extract(&response).is_empty() {
+                "No commands in the answer"
+            } else {
+                break;
+            };
+            crate::ui::show(&format!(
+                "🔁 {reason}; asking again ({attempt}/{})\n",
+                self.retry_on_empty
+            ));
+            response = self
+                .attempt(system_prompt, &retry_prompt, on_chunk.as_deref_mut())
+                .await?;
+        }
+        if self.too_short(&response) {
+            bail!(
+                "The provider's answer was too short ({} characters, min_response_chars is {}): {:?}",
+                response.trim().chars().count(),
+                self.min_response_chars,
+                response.trim()
+            );
+        }
+        // Only answers worth repeating are saved, so a retry is never answered from
+        // the cache
+        if !extract(&response).is_empty() {
+            self.save_to_cache(system_prompt, user_prompt, &response);
+        }
+        Ok(response)
+    }
+
+    /// One uncached query, streamed when there is a handler for the pieces
+    async fn attempt(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        on_chunk: Option<&mut ChunkHandler<'_>>,
+    ) -> Result<String> {
+        match on_chunk {
+            Some(on_chunk) => {
+                self.stream(system_prompt, user_prompt, on_chunk, true)
+                    .await
+            }
+            None => self.query(system_prompt, user_prompt, true).await,
+        }
+    }
+
+    fn too_short(&self, response: &str) -> bool {
+        response.trim().chars().count() < self.min_response_chars
+    }
+
+    /// Ask for commands as `{"commands": [...]}` JSON and validate the result.
+    ///
+    /// A response that fails validation is re-prompted once with the validation error.
+    async fn structured_query(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+    ) -> Result<Vec<String>> {
+        // The JSON instructions are kept even when the system prompt is overridden
+        let structured_prompt = format!(
+            "{}\n{STRUCTURED_OUTPUT_INSTRUCTIONS}",
+            self.system_prompt(system_prompt)
+        );
+
+        if let Some(response) = self.cached(&structured_prompt, user_prompt)? {
+            if let Ok(commands) = parse_structured_response(&response) {
+                return Ok(commands);
+            }
+        }
+        let response = self.query(&structured_prompt, user_prompt, false).await?;
+        let error = match parse_structured_response(&response) {
+            Ok(commands) => {
+                self.save_to_cache(&structured_prompt, user_prompt, &response);
+                return Ok(commands);
+            }
+            Err(e) => e,
+        };
+
+        let retry_prompt = format!(
+            "{user_prompt}\n\nYour previous response was invalid: {error}. \
+             Respond again with only the JSON object described in the instructions."
+        );
+        let response = self.query(&structured_prompt, &retry_prompt, false).await?;
+        let commands = parse_structured_response(&response)
+            .context("Structured response failed validation after one retry")?;
+        self.save_to_cache(&structured_prompt, user_prompt, &response);
+        Ok(commands)
     }
 
     pub fn provider_name(&self) -> &str {
@@ -28,11 +525,136 @@ impl QueryProvider {
     }
 }
 
+/// Parse and validate a structured `{"commands": [...]}` response.
+///
+/// `commands` must be a non-empty array of non-empty strings. Markdown code
+/// fences or text around the JSON object are tolerated.
+fn parse_structured_response(response: &str) -> Result<Vec<String>> {
+    let start = response.find('{');
+    let end = response.rfind('}');
+    let json = match (start, end) {
+        (Some(start), Some(end)) if start < end => &response[start..=end],
+        _ => bail!("Structured response does not contain a JSON object"),
+    };
+
+    let value: serde_json::Value =
+        serde_json::from_str(json).context("Structured response is not valid JSON")?;
+    let commands = value
+        .get("commands")
+        .context("Structured response is missing the \"commands\" field")?
+        .as_array()
+        .context("\"commands\" must be an array of strings")?;
+
+    if commands.is_empty() {
+        bail!("\"commands\" must not be empty");
+    }
+
+    commands
+        .iter()
+        .enumerate()
+        .map(|(i, command)| match command.as_str().map(str::trim) {
+            Some(command) if !command.is_empty() => Ok(command.to_string()),
+            Some(_) => bail!("\"commands\"[{i}] must not be an empty string"),
+            None => bail!("\"commands\"[{i}] must be a string"),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::{MockProvider, PromptLog};
     use mockito::Matcher;
 
+    fn sequence_provider(responses: &[&str]) -> (QueryProvider, PromptLog) {
+        MockProvider::sequence(responses).into_query_provider()
+    }
+
+    #[test]
+    fn test_parse_structured_response_valid() {
+        let commands = parse_structured_response(
+            "```json\n{\"commands\": [\"mkdir -p out\", \"ls out\"]}\n```",
+        )
+        .unwrap();
+        assert_eq!(commands, vec!["mkdir -p out", "ls out"]);
+    }
+
+    #[test]
+    fn test_parse_structured_response_missing_field() {
+        let err = parse_structured_response(r#"{"cmds": ["ls"]}"#).unwrap_err();
+        assert!(err.to_string().contains("missing the \"commands\" field"));
+    }
+
+    #[test]
+    fn test_parse_structured_response_wrong_type() {
+        let err = parse_structured_response(r#"{"commands": "ls"}"#).unwrap_err();
+        assert!(err.to_string().contains("must be an array"));
+
+        let err = parse_structured_response(r#"{"commands": ["ls", 42]}"#).unwrap_err();
+        assert!(err.to_string().contains("[1] must be a string"));
+
+        let err = parse_structured_response(r#"{"commands": []}"#).unwrap_err();
+        assert!(err.to_string().contains("must not be empty"));
+    }
+
+    #[tokio::test]
+    async fn test_send_structured_query_valid_first_try() {
+        let (provider, prompts) = sequence_provider(&[r#"{"commands": ["pwd"]}"#]);
+        let commands = provider.structured_query("system", "where am I").await;
+        assert_eq!(commands.unwrap(), vec!["pwd"]);
+        assert_eq!(prompts.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_structured_query_reprompts_once() {
+        let (provider, prompts) =
+            sequence_provider(&[r#"{"command": "pwd"}"#, r#"{"commands": ["pwd"]}"#]);
+        let commands = provider.structured_query("system", "where am I").await;
+        assert_eq!(commands.unwrap(), vec!["pwd"]);
+
+        let prompts = prompts.user_prompts();
+        assert_eq!(prompts.len(), 2);
+        assert!(prompts[1].contains("previous response was invalid"));
+        assert!(prompts[1].contains("\"commands\" field"));
+    }
+
+    #[tokio::test]
+    async fn test_structured_output_answers_command_queries() {
+        let (provider, prompts) =
+            sequence_provider(&[r#"{"commands": ["mkdir -p out", "ls out"]}"#]);
+        let provider = provider.with_structured_output(true);
+        let response = provider
+            .send_command_query(
+                "system",
+                "make a folder",
+                crate::extract_commands_from_response,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response, "COMMAND: mkdir -p out\nCOMMAND: ls out");
+        assert!(prompts.all()[0]
+            .0
+            .contains(r#"{"commands": ["<command>", ...]}"#));
+
+        let mut config = crate::providers::ProviderConfig::default();
+        assert!(!config.structured_output());
+        config
+            .settings
+            .insert("structured_output".to_string(), "true".to_string());
+        assert!(config.structured_output());
+    }
+
+    #[tokio::test]
+    async fn test_send_structured_query_fails_after_retry() {
+        let (provider, prompts) = sequence_provider(&["not json", r#"{"commands": [1]}"#]);
+        let err = provider
+            .structured_query("system", "where am I")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("after one retry"));
+        assert_eq!(prompts.len(), 2);
+    }
+
     #[test]
     fn test_query_provider_new() {
         let mut config = TerminalAIConfig {
@@ -349,4 +971,421 @@ mod tests {
         assert_eq!(claude_provider.provider_name(), "Claude");
         assert_eq!(gemini_provider.provider_name(), "Gemini");
     }
+
+    fn config_with_prompt_overrides(url: String, model: &str) -> TerminalAIConfig {
+        let mut config = TerminalAIConfig {
+            active_provider: "ollama".to_string(),
+            ..Default::default()
+        };
+        config.update_provider(
+            "ollama",
+            crate::providers::ProviderConfig::new_ollama(url, model.to_string(), 30),
+        );
+        config.system_prompt_overrides.insert(
+            "qwen2.5:1.5b".to_string(),
+            "Reply with one shell command.\\nNo prose.".to_string(),
+        );
+        config
+    }
+
+    #[tokio::test]
+    async fn test_system_prompt_override_leaves_protocol_prompts_alone() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/generate")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"model":"qwen2.5:1.5b","prompt":"Reply with COMMAND: lines\n\nUser Request: list files"}"#
+                    .to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"response": "ls", "done": true}"#)
+            .create_async()
+            .await;
+
+        // The override replaces command definitions' prompts where they are loaded,
+        // never the prompts sent here
+        let config = config_with_prompt_overrides(server.url(), "qwen2.5:1.5b");
+        let provider = QueryProvider::new(config).unwrap();
+        let result = provider
+            .send_query("Reply with COMMAND: lines", "list files")
+            .await;
+
+        mock.assert_async().await;
+        assert_eq!(result.unwrap(), "ls");
+    }
+
+    #[tokio::test]
+    async fn test_shell_note_follows_the_system_prompt() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/generate")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"prompt":"Long default prompt\nCommands run in fish.\n\n\nUser Request: list files"}"#
+                    .to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"response": "ls", "done": true}"#)
+            .create_async()
+            .await;
+
+        let config = config_with_prompt_overrides(server.url(), "qwen2.5:1.5b");
+        let provider = QueryProvider {
+            shell_note: Some("Commands run in fish."),
+            ..QueryProvider::new(config).unwrap()
+        };
+        provider
+            .send_query("Long default prompt", "list files")
+            .await
+            .unwrap();
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_system_prompt_override_replaces_command_definitions() {
+        let (definition, _) = crate::command_parser::load_command_definition("cp").unwrap();
+        let config = config_with_prompt_overrides("http://localhost:11434".into(), "qwen2.5:1.5b");
+        assert_eq!(
+            crate::command_parser::system_prompt_for(&config, definition.clone()).unwrap(),
+            "Reply with one shell command.\nNo prose."
+        );
+        let other_model = config_with_prompt_overrides("http://localhost:11434".into(), "gpt-4o");
+        assert_eq!(
+            crate::command_parser::system_prompt_for(&other_model, definition.clone()).unwrap(),
+            definition
+        );
+    }
+
+    #[test]
+    fn test_system_prompt_override_from_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("small.txt");
+        std::fs::write(&path, "Only output commands.\n").unwrap();
+
+        let mut config = config_with_prompt_overrides("http://localhost:11434".into(), "tiny");
+        config
+            .system_prompt_overrides
+            .insert("tiny".to_string(), format!("@{}", path.display()));
+        assert_eq!(
+            config.system_prompt_override().unwrap().as_deref(),
+            Some("Only output commands.\n")
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(crate::command_parser::system_prompt_for(&config, String::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_max_cost_aborts_calls_once_reached() {
+        // $0.20 per query against a $0.50 budget
+        let (provider, prompts) = MockProvider::repeating("COMMAND: pip install requests")
+            .with_usage(Usage {
+                input_tokens: 1_000,
+                output_tokens: 1_000,
+            })
+            .into_query_provider();
+        let provider = provider
+            .with_pricing(Pricing {
+                input_per_million: 100.0,
+                output_per_million: 100.0,
+            })
+            .with_max_cost(0.5)
+            .unwrap();
+
+        for _ in 0..3 {
+            provider.send_query("system", "fix it").await.unwrap();
+        }
+        assert!((provider.spent().unwrap() - 0.6).abs() < 1e-9);
+
+        let err = provider.send_query("system", "fix it").await.unwrap_err();
+        assert!(err.to_string().contains("--max-cost"), "{err}");
+        assert_eq!(prompts.len(), 3);
+    }
+
+    #[test]
+    fn test_max_cost_needs_pricing() {
+        let (provider, _) = sequence_provider(&[]);
+        let err = provider.with_max_cost(1.0).err().unwrap();
+        assert!(err.to_string().contains("input_cost_per_million"));
+    }
+
+    #[test]
+    fn test_retry_on_empty_defaults_per_provider() {
+        use crate::providers::ProviderConfig;
+
+        assert_eq!(ProviderConfig::new_local(30).retry_on_empty(), 2);
+        assert_eq!(ProviderConfig::default().retry_on_empty(), 2);
+        let mut openai = ProviderConfig::new_openai("key".to_string(), "gpt-4o".to_string(), 30);
+        assert_eq!(openai.retry_on_empty(), 0);
+        openai
+            .settings
+            .insert("retry_on_empty".to_string(), "1".to_string());
+        assert_eq!(openai.retry_on_empty(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_command_query_honors_retry_count() {
+        let extract = crate::extract_commands_from_response;
+
+        // A cloud provider with the default of no retries gives up after one answer
+        let (provider, prompts) = sequence_provider(&["Sure!", "COMMAND: ls"]);
+        let response = provider
+            .with_retry_on_empty(0, false)
+            .send_command_query("system", "list files", extract)
+            .await
+            .unwrap();
+        assert_eq!(response, "Sure!");
+        assert_eq!(prompts.len(), 1);
+
+        // Two retries for a local model, each with the stricter instruction
+        let (provider, prompts) =
+            sequence_provider(&["Sure!", "Here you go.", "COMMAND: ls", "unused"]);
+        let response = provider
+            .with_retry_on_empty(2, true)
+            .send_command_query("system", "list files", extract)
+            .await
+            .unwrap();
+        assert_eq!(response, "COMMAND: ls");
+        let prompts = prompts.user_prompts();
+        assert_eq!(prompts.len(), 3);
+        assert_eq!(prompts[0], "list files");
+        assert_eq!(
+            prompts[2],
+            format!("list files\n\n{EMPTY_RETRY_INSTRUCTION}")
+        );
+
+        // Retries stop at the configured count even if every answer is empty
+        let (provider, prompts) = sequence_provider(&["no", "still no", "nope"]);
+        let response = provider
+            .with_retry_on_empty(1, false)
+            .send_command_query("system", "list files", extract)
+            .await
+            .unwrap();
+        assert_eq!(response, "still no");
+        assert_eq!(prompts.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cached_answers_skip_the_provider() {
+        let extract = crate::extract_commands_from_response;
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::new(dir.path().to_path_buf(), "test", "", "model", 60);
+
+        // The empty first answer is retried and only the answer with commands is kept
+        let (provider, prompts) = sequence_provider(&["Sure!", "COMMAND: grep -rn TODO ."]);
+        let provider = provider
+            .with_retry_on_empty(1, false)
+            .with_cache(cache.clone());
+        let response = provider
+            .send_command_query("system", "find all TODOs", extract)
+            .await
+            .unwrap();
+        assert_eq!(response, "COMMAND: grep -rn TODO .");
+        assert_eq!(prompts.len(), 2);
+
+        let (provider, prompts) = sequence_provider(&["COMMAND: ls"]);
+        let provider = provider.with_cache(cache);
+        let response = provider
+            .send_command_query("system", "find all TODOs", extract)
+            .await
+            .unwrap();
+        assert_eq!(response, "COMMAND: grep -rn TODO .");
+        assert!(prompts.is_empty());
+
+        // A different prompt still reaches the provider
+        let response = provider
+            .send_command_query("system", "list files", extract)
+            .await
+            .unwrap();
+        assert_eq!(response, "COMMAND: ls");
+        assert_eq!(prompts.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_refreshed_queries_skip_the_cache_once() {
+        let extract = crate::extract_commands_from_response;
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::new(dir.path().to_path_buf(), "test", "", "model", 60);
+        let (provider, prompts) = sequence_provider(&["COMMAND: ls", "COMMAND: ls -la"]);
+        let provider = provider.with_cache(cache);
+        let ask = || provider.send_command_query("system", "list files", extract);
+
+        assert_eq!(ask().await.unwrap(), "COMMAND: ls");
+        provider.refresh_next();
+        assert_eq!(ask().await.unwrap(), "COMMAND: ls -la");
+        // The new answer is the one cached, and the cache is used again
+        assert_eq!(ask().await.unwrap(), "COMMAND: ls -la");
+        assert_eq!(prompts.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_send_command_query_retries_too_short_answers() {
+        let extract = crate::extract_commands_from_response;
+
+        // "ls" is a command, but shorter than the threshold, so it is asked again
+        let (provider, prompts) = sequence_provider(&["ls", "COMMAND: ls -la", "unused"]);
+        let response = provider
+            .with_retry_on_empty(2, false)
+            .with_min_response_chars(8)
+            .send_command_query("system", "list files", extract)
+            .await
+            .unwrap();
+        assert_eq!(response, "COMMAND: ls -la");
+        assert_eq!(prompts.len(), 2);
+
+        // An adequate answer is used as is
+        let (provider, prompts) = sequence_provider(&["COMMAND: ls -la", "unused"]);
+        let response = provider
+            .with_retry_on_empty(2, false)
+            .with_min_response_chars(8)
+            .send_command_query("system", "list files", extract)
+            .await
+            .unwrap();
+        assert_eq!(response, "COMMAND: ls -la");
+        assert_eq!(prompts.len(), 1);
+
+        // Still too short after the retries: the request fails
+        let (provider, prompts) = sequence_provider(&[".", "  ok \n"]);
+        let err = provider
+            .with_retry_on_empty(1, false)
+            .with_min_response_chars(8)
+            .send_command_query("system", "list files", extract)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("too short (2 characters"), "{err}");
+        assert_eq!(prompts.len(), 2);
+
+        let mut config = crate::providers::ProviderConfig::new_local(30);
+        assert_eq!(config.min_response_chars(), 0);
+        config
+            .settings
+            .insert("min_response_chars".to_string(), "12".to_string());
+        assert_eq!(config.min_response_chars(), 12);
+    }
+
+    #[tokio::test]
+    async fn test_callback_receives_chunks_of_the_full_response() {
+        let (provider, _) = MockProvider::repeating("COMMAND: mkdir -p out\nCOMMAND: ls out")
+            .streaming(3)
+            .into_query_provider();
+        let mut chunks = Vec::new();
+        let response = provider
+            .send_query_with_callback("system", "make a folder", |chunk| {
+                chunks.push(chunk.to_string())
+            })
+            .await
+            .unwrap();
+        assert_eq!(response, "COMMAND: mkdir -p out\nCOMMAND: ls out");
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks.concat(), response);
+
+        // A provider that can't stream hands over the whole answer at once
+        let (provider, _) = sequence_provider(&["COMMAND: ls"]);
+        let mut chunks = Vec::new();
+        let response = provider
+            .send_query_with_callback("system", "list", |chunk| chunks.push(chunk.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(chunks, vec![response]);
+    }
+
+    #[tokio::test]
+    async fn test_simulated_errors_fail_only_the_next_query() {
+        let cases = [
+            ("timeout", "Failed to send request to Mock: operation timed out (simulated)"),
+            ("429", "Mock request failed with status: 429 Too Many Requests - Rate limit reached (simulated)"),
+            ("500", "Mock request failed with status: 500 Internal Server Error - Internal server error (simulated)"),
+            ("dns", "Failed to send request to Mock: dns error: failed to lookup address information (simulated)"),
+        ];
+        for (name, expected) in cases {
+            let (provider, prompts) = sequence_provider(&["COMMAND: ls"]);
+            let provider = provider.with_simulated_error(SimulatedError::from_name(name).unwrap());
+            let err = provider
+                .send_query("system", "list files")
+                .await
+                .unwrap_err();
+            assert_eq!(format!("{err:#}"), expected);
+            assert!(prompts.is_empty());
+
+            // A retry reaches the provider
+            assert_eq!(
+                provider.send_query("system", "list files").await.unwrap(),
+                "COMMAND: ls"
+            );
+        }
+        assert_eq!(SimulatedError::from_name("503"), None);
+    }
+
+    #[tokio::test]
+    async fn test_simulated_http_errors_match_real_ones() {
+        let mut server = mockito::Server::new_async().await;
+        let _rate_limited = server
+            .mock("POST", "/chat/completions")
+            .with_status(429)
+            .with_body("Rate limit reached (simulated)")
+            .create_async()
+            .await;
+        let mut config = TerminalAIConfig {
+            active_provider: "openai".to_string(),
+            ..Default::default()
+        };
+        let mut openai = crate::providers::ProviderConfig::new_openai(
+            "sk-test".to_string(),
+            "gpt-4o".to_string(),
+            30,
+        );
+        openai.settings.insert("base_url".to_string(), server.url());
+        config.update_provider("openai", openai);
+
+        let provider = QueryProvider::new(config).unwrap();
+        let real = provider.send_query("system", "user").await.unwrap_err();
+        let simulated = provider
+            .with_simulated_error(SimulatedError::RateLimited)
+            .send_query("system", "user")
+            .await
+            .unwrap_err();
+        assert_eq!(format!("{simulated:#}"), format!("{real:#}"));
+    }
+
+    #[tokio::test]
+    async fn test_command_query_streams_every_attempt() {
+        let (provider, _) = MockProvider::sequence(&["Sure, I can help.", "COMMAND: ls -la"])
+            .streaming(2)
+            .into_query_provider();
+        let provider = provider.with_retry_on_empty(1, false);
+        assert!(provider.streams());
+
+        let mut streamed = String::new();
+        let mut on_chunk = |chunk: &str| streamed.push_str(chunk);
+        let response = provider
+            .send_command_query_streaming(
+                "system",
+                "list files",
+                crate::extract_commands_from_response,
+                &mut on_chunk,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response, "COMMAND: ls -la");
+        assert_eq!(streamed, "Sure, I can help.COMMAND: ls -la");
+    }
+
+    #[test]
+    fn test_only_streaming_providers_stream() {
+        let (provider, _) = sequence_provider(&[]);
+        assert!(!provider.streams());
+
+        let mut config = TerminalAIConfig {
+            active_provider: "ollama".to_string(),
+            ..Default::default()
+        };
+        config.update_provider(
+            "ollama",
+            crate::providers::ProviderConfig::new_ollama(
+                "http://localhost:11434".to_string(),
+                "llama3".to_string(),
+                30,
+            ),
+        );
+        assert!(QueryProvider::new(config).unwrap().streams());
+    }
 }
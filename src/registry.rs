@@ -0,0 +1,346 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::time::Duration;
+
+const PYPI_URL: &str = "https://pypi.org";
+const NPM_URL: &str = "https://registry.npmjs.org";
+
+/// Public package registry a package type installs from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registry {
+    PyPI,
+    Npm,
+}
+
+impl Registry {
+    /// Registry for a `resolve_ai` package type (`python` or `npm`)
+    pub fn for_package_type(package_type: &str) -> Option<Self> {
+        match package_type {
+            "python" => Some(Registry::PyPI),
+            "npm" => Some(Registry::Npm),
+            _ => None,
+        }
+    }
+
+    /// Package name without its version, e.g. `requests` for `requests==2.31.0`
+    /// or `@types/node` for `@types/node@20.1.0`
+    pub fn package_name(self, spec: &str) -> String {
+        let spec = spec.trim();
+        let name = match self {
+            Registry::PyPI => spec
+                .split(['=', '<', '>', '!', '~', '[', ';', ' '])
+                .next()
+                .unwrap_or(spec),
+            Registry::Npm => match spec.strip_prefix('@') {
+                // Scoped packages keep their leading '@'
+                Some(scoped) => &spec[..scoped.find('@').map_or(spec.len(), |i| i + 1)],
+                None => spec.split('@').next().unwrap_or(spec),
+            },
+        };
+        name.to_string()
+    }
+}
+
+impl std::fmt::Display for Registry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Registry::PyPI => write!(f, "PyPI"),
+            Registry::Npm => write!(f, "the npm registry"),
+        }
+    }
+}
+
+/// Result of looking a package up in its registry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Existence {
+    Exists,
+    /// The registry answered 404; `suggestion` is the closest existing name, if any
+    Missing {
+        suggestion: Option<String>,
+    },
+}
+
+/// Looks packages up on PyPI and npm so invented names fail before any install runs
+pub struct RegistryClient {
+    client: reqwest::Client,
+    pypi_url: String,
+    npm_url: String,
+}
+
+impl Default for RegistryClient {
+    fn default() -> Self {
+        Self::with_base_urls(PYPI_URL, NPM_URL)
+    }
+}
+
+impl RegistryClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Client for registries at other locations, e.g. mirrors or a test server
+    pub fn with_base_urls(pypi_url: &str, npm_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            pypi_url: pypi_url.trim_end_matches('/').to_string(),
+            npm_url: npm_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Whether `name` is published; errors for anything other than 200 or 404
+    pub async fn package_exists(&self, registry: Registry, name: &str) -> Result<bool> {
+        let url = match registry {
+            Registry::PyPI => format!("{}/pypi/{name}/json", self.pypi_url),
+            // Scoped names keep their '@' but the '/' must be escaped
+            Registry::Npm => format!("{}/{}", self.npm_url, name.replace('/', "%2F")),
+        };
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {registry}"))?;
+
+        match response.status() {
+            status if status.is_success() => Ok(true),
+            reqwest::StatusCode::NOT_FOUND => Ok(false),
+            status => Err(anyhow::anyhow!("{registry} returned {status} for '{name}'")),
+        }
+    }
+
+    /// Check the package named by `spec`, suggesting the closest match when it is missing
+    pub async fn check(&self, registry: Registry, spec: &str) -> Result<Existence> {
+        let name = registry.package_name(spec);
+        if self.package_exists(registry, &name).await? {
+            return Ok(Existence::Exists);
+        }
+        Ok(Existence::Missing {
+            suggestion: self.closest_match(registry, &name).await,
+        })
+    }
+
+    async fn closest_match(&self, registry: Registry, name: &str) -> Option<String> {
+        match registry {
+            // PyPI has no search API, so try common spellings of the same name
+            Registry::PyPI => {
+                for candidate in pypi_name_variants(name) {
+                    if let Ok(true) = self.package_exists(registry, &candidate).await {
+                        return Some(candidate);
+                    }
+                }
+                None
+            }
+            Registry::Npm => {
+                let url = format!("{}/-/v1/search", self.npm_url);
+                let response = self
+                    .client
+                    .get(&url)
+                    .query(&[("text", name), ("size", "10")])
+                    .send()
+                    .await
+                    .ok()?;
+                let body: serde_json::Value = response.json().await.ok()?;
+                let names = body["objects"]
+                    .as_array()?
+                    .iter()
+                    .filter_map(|object| object["package"]["name"].as_str());
+                closest_name(name, names)
+            }
+        }
+    }
+}
+
+/// Other spellings PyPI users commonly mean: `-`/`_` swapped and `python-`/`py` prefixes
+fn pypi_name_variants(name: &str) -> Vec<String> {
+    let lower = name.to_lowercase();
+    let mut variants = vec![lower.replace('_', "-"), lower.replace('-', "_")];
+    // Only the longest prefix is dropped, so `python-x` never becomes `thon-x`
+    if let Some(stripped) = ["python-", "python_", "py-", "py_", "py"]
+        .iter()
+        .find_map(|prefix| lower.strip_prefix(prefix))
+    {
+        variants.push(stripped.to_string());
+    }
+    variants.push(format!("python-{lower}"));
+    let mut seen = HashSet::new();
+    variants
+        .retain(|variant| !variant.is_empty() && variant != name && seen.insert(variant.clone()));
+    variants
+}
+
+/// The candidate with the smallest edit distance to `name`, if any is reasonably close
+fn closest_name<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<String> {
+    let max_distance = (name.chars().count() / 3).max(2);
+    candidates
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_name() {
+        assert_eq!(Registry::PyPI.package_name("requests==2.31.0"), "requests");
+        assert_eq!(
+            Registry::PyPI.package_name("uvicorn[standard]>=0.20"),
+            "uvicorn"
+        );
+        assert_eq!(Registry::Npm.package_name("react@18.2.0"), "react");
+        assert_eq!(
+            Registry::Npm.package_name("@types/node@20.1.0"),
+            "@types/node"
+        );
+        assert_eq!(Registry::Npm.package_name("@types/node"), "@types/node");
+    }
+
+    #[test]
+    fn test_pypi_name_variants() {
+        assert_eq!(
+            pypi_name_variants("python-dateutil"),
+            vec!["python_dateutil", "dateutil", "python-python-dateutil"]
+        );
+        assert_eq!(
+            pypi_name_variants("Py_Yaml"),
+            vec!["py-yaml", "py_yaml", "yaml", "python-py_yaml"]
+        );
+        assert_eq!(pypi_name_variants("requests"), vec!["python-requests"]);
+    }
+
+    #[test]
+    fn test_closest_name() {
+        let candidates = ["expresso", "express", "express-session", "koa"];
+        assert_eq!(
+            closest_name("expres", candidates.into_iter()),
+            Some("express".to_string())
+        );
+        assert_eq!(closest_name("zzzzzz", candidates.into_iter()), None);
+    }
+
+    #[tokio::test]
+    async fn test_pypi_existence_check() {
+        let mut server = mockito::Server::new_async().await;
+        let _exists = server
+            .mock("GET", "/pypi/requests/json")
+            .with_status(200)
+            .with_body(r#"{"info": {"name": "requests"}}"#)
+            .create_async()
+            .await;
+        let _missing = server
+            .mock("GET", "/pypi/python_dateutils/json")
+            .with_status(404)
+            .create_async()
+            .await;
+        let _missing_variant = server
+            .mock("GET", "/pypi/python-dateutils/json")
+            .with_status(200)
+            .create_async()
+            .await;
+        let _invented = server
+            .mock(
+                "GET",
+                mockito::Matcher::Regex("^/pypi/.*turbo.*".to_string()),
+            )
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let client = RegistryClient::with_base_urls(&server.url(), &server.url());
+        assert_eq!(
+            client
+                .check(Registry::PyPI, "requests==2.31.0")
+                .await
+                .unwrap(),
+            Existence::Exists
+        );
+        assert_eq!(
+            client
+                .check(Registry::PyPI, "python_dateutils")
+                .await
+                .unwrap(),
+            Existence::Missing {
+                suggestion: Some("python-dateutils".to_string())
+            }
+        );
+        assert_eq!(
+            client
+                .check(Registry::PyPI, "requests-turbo")
+                .await
+                .unwrap(),
+            Existence::Missing { suggestion: None }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_npm_existence_check_suggests_search_match() {
+        let mut server = mockito::Server::new_async().await;
+        let _scoped = server
+            .mock("GET", "/@types%2Fnode")
+            .with_status(200)
+            .create_async()
+            .await;
+        let _missing = server
+            .mock("GET", "/expres")
+            .with_status(404)
+            .with_body(r#"{"error": "Not found"}"#)
+            .create_async()
+            .await;
+        let _search = server
+            .mock("GET", "/-/v1/search")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "text".to_string(),
+                "expres".to_string(),
+            ))
+            .with_status(200)
+            .with_body(
+                r#"{"objects": [
+                    {"package": {"name": "express-session"}},
+                    {"package": {"name": "express"}}
+                ]}"#,
+            )
+            .create_async()
+            .await;
+        let _down = server
+            .mock("GET", "/left-pad")
+            .with_status(503)
+            .create_async()
+            .await;
+
+        let client = RegistryClient::with_base_urls(&server.url(), &server.url());
+        assert_eq!(
+            client
+                .check(Registry::Npm, "@types/node@20.1.0")
+                .await
+                .unwrap(),
+            Existence::Exists
+        );
+        assert_eq!(
+            client.check(Registry::Npm, "expres@4").await.unwrap(),
+            Existence::Missing {
+                suggestion: Some("express".to_string())
+            }
+        );
+        assert!(client.check(Registry::Npm, "left-pad").await.is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use std::ffi::OsStr;
+use std::path::Path;
+
+/// Version managers that can install language runtimes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionManager {
+    Mise,
+    Asdf,
+}
+
+impl VersionManager {
+    pub fn binary_name(&self) -> &'static str {
+        match self {
+            VersionManager::Mise => "mise",
+            VersionManager::Asdf => "asdf",
+        }
+    }
+}
+
+impl std::fmt::Display for VersionManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.binary_name())
+    }
+}
+
+/// A language runtime request such as `python==3.13` or `node@18.17.0`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeSpec {
+    /// Canonical runtime name: python, node or ruby
+    pub runtime: String,
+    pub version: String,
+}
+
+/// Parse a runtime spec, accepting `name==version` and `name@version`.
+///
+/// Returns `None` for anything that is not a known runtime (i.e. a regular package).
+pub fn parse_runtime_spec(spec: &str) -> Option<RuntimeSpec> {
+    let (name, version) = spec.split_once("==").or_else(|| spec.split_once('@'))?;
+    let runtime = match name.trim().to_lowercase().as_str() {
+        "python" | "python3" => "python",
+        "node" | "nodejs" => "node",
+        "ruby" => "ruby",
+        _ => return None,
+    };
+    let version = version.trim();
+    if version.is_empty() {
+        return None;
+    }
+    Some(RuntimeSpec {
+        runtime: runtime.to_string(),
+        version: version.to_string(),
+    })
+}
+
+/// Find an installed version manager on the current `PATH`, preferring mise
+pub fn detect_version_manager() -> Option<VersionManager> {
+    let path_var = std::env::var_os("PATH")?;
+    detect_version_manager_in(&path_var)
+}
+
+/// Find a version manager in the given `PATH`-style directory list
+pub fn detect_version_manager_in(path_var: &OsStr) -> Option<VersionManager> {
+    [VersionManager::Mise, VersionManager::Asdf]
+        .into_iter()
+        .find(|manager| {
+            std::env::split_paths(path_var)
+                .any(|dir| is_executable(&dir.join(manager.binary_name())))
+        })
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+/// Commands that install the runtime with the given manager and make it active
+pub fn runtime_install_commands(spec: &RuntimeSpec, manager: VersionManager) -> Vec<String> {
+    match manager {
+        VersionManager::Mise => vec![
+            format!("mise install {}@{}", spec.runtime, spec.version),
+            format!("mise use --global {}@{}", spec.runtime, spec.version),
+        ],
+        VersionManager::Asdf => {
+            // asdf names the Node.js plugin "nodejs"
+            let plugin = match spec.runtime.as_str() {
+                "node" => "nodejs",
+                other => other,
+            };
+            vec![
+                format!("asdf plugin add {plugin} || true"),
+                format!("asdf install {plugin} {}", spec.version),
+                format!("asdf global {plugin} {}", spec.version),
+            ]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_runtime_spec() {
+        assert_eq!(
+            parse_runtime_spec("python==3.13"),
+            Some(RuntimeSpec {
+                runtime: "python".to_string(),
+                version: "3.13".to_string()
+            })
+        );
+        assert_eq!(
+            parse_runtime_spec("nodejs@18.17.0").unwrap().runtime,
+            "node"
+        );
+        assert_eq!(
+            parse_runtime_spec("python3==3.12.1").unwrap().version,
+            "3.12.1"
+        );
+        assert!(parse_runtime_spec("requests==2.31.0").is_none());
+        assert!(parse_runtime_spec("react@18.2.0").is_none());
+        assert!(parse_runtime_spec("python").is_none());
+        assert!(parse_runtime_spec("ruby@").is_none());
+    }
+
+    #[test]
+    fn test_mise_install_commands() {
+        let spec = parse_runtime_spec("python==3.13").unwrap();
+        assert_eq!(
+            runtime_install_commands(&spec, VersionManager::Mise),
+            vec!["mise install python@3.13", "mise use --global python@3.13"]
+        );
+    }
+
+    #[test]
+    fn test_asdf_install_commands() {
+        let spec = parse_runtime_spec("node==18.17.0").unwrap();
+        assert_eq!(
+            runtime_install_commands(&spec, VersionManager::Asdf),
+            vec![
+                "asdf plugin add nodejs || true",
+                "asdf install nodejs 18.17.0",
+                "asdf global nodejs 18.17.0"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_detect_version_manager_in_path() {
+        use std::os::unix::fs::PermissionsExt;
+        use tempfile::TempDir;
+
+        let mise_dir = TempDir::new().unwrap();
+        let asdf_dir = TempDir::new().unwrap();
+        for (dir, name) in [(&mise_dir, "mise"), (&asdf_dir, "asdf")] {
+            let bin = dir.path().join(name);
+            std::fs::write(&bin, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let only_asdf = std::env::join_paths([asdf_dir.path()]).unwrap();
+        assert_eq!(
+            detect_version_manager_in(&only_asdf),
+            Some(VersionManager::Asdf)
+        );
+
+        let both = std::env::join_paths([asdf_dir.path(), mise_dir.path()]).unwrap();
+        assert_eq!(detect_version_manager_in(&both), Some(VersionManager::Mise));
+
+        let empty = TempDir::new().unwrap();
+        let none = std::env::join_paths([empty.path()]).unwrap();
+        assert_eq!(detect_version_manager_in(&none), None);
+    }
+}
@@ -0,0 +1,257 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+// The template binary and command definition that new commands are generated from
+const TEMPLATE_BINARY: &str = include_str!("bin/template_ai.rs");
+const TEMPLATE_DEFINITION: &str = include_str!("../cmd/template.conf");
+
+/// What to generate for a new `*_ai` command
+#[derive(Debug, Clone)]
+pub struct ScaffoldOptions {
+    /// Command name without the `_ai` suffix, e.g. `format`
+    pub name: String,
+    /// Short description used in help text and the system prompt
+    pub description: String,
+    /// Keywords that indicate a prompt belongs to this command
+    pub keywords: Vec<String>,
+    /// Keywords that indicate a prompt is outside this command's scope
+    pub invalid_keywords: Vec<String>,
+}
+
+/// Paths of the files written by [`scaffold`]
+#[derive(Debug, Clone)]
+pub struct ScaffoldOutput {
+    pub binary_path: PathBuf,
+    pub definition_path: PathBuf,
+}
+
+/// Command names become binary names and file names, so keep them simple
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid {
+        bail!(
+            "Invalid command name '{}'. Use lowercase letters, digits and underscores, starting with a letter",
+            name
+        );
+    }
+    if name.ends_with("_ai") {
+        bail!("Command name '{}' should not include the _ai suffix", name);
+    }
+    Ok(())
+}
+
+/// Generate `src/bin/<name>_ai.rs` and `cmd/<name>.conf` under `root`.
+///
+/// Existing files are never overwritten.
+pub fn scaffold(options: &ScaffoldOptions, root: &Path) -> Result<ScaffoldOutput> {
+    validate_name(&options.name)?;
+    if options.keywords.is_empty() {
+        bail!("At least one keyword is required (--keywords)");
+    }
+
+    let binary_path = root
+        .join("src")
+        .join("bin")
+        .join(format!("{}_ai.rs", options.name));
+    let definition_path = root.join("cmd").join(format!("{}.conf", options.name));
+
+    for path in [&binary_path, &definition_path] {
+        if path.exists() {
+            bail!(
+                "{} already exists; refusing to overwrite it",
+                path.display()
+            );
+        }
+    }
+
+    for path in [&binary_path, &definition_path] {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+    }
+
+    std::fs::write(&binary_path, render_binary(options))
+        .with_context(|| format!("Failed to write {}", binary_path.display()))?;
+    std::fs::write(&definition_path, render_definition(options))
+        .with_context(|| format!("Failed to write {}", definition_path.display()))?;
+
+    Ok(ScaffoldOutput {
+        binary_path,
+        definition_path,
+    })
+}
+
+/// The `[[bin]]` stanza to add to Cargo.toml for the new command
+pub fn cargo_bin_stanza(name: &str) -> String {
+    format!("[[bin]]\nname = \"{name}_ai\"\npath = \"src/bin/{name}_ai.rs\"\n")
+}
+
+/// Render the binary source from the template with all placeholders substituted
+pub fn render_binary(options: &ScaffoldOptions) -> String {
+    let name = &options.name;
+
+    // Drop the trailing how-to comment; the generated file is the finished result
+    let source = match TEMPLATE_BINARY.find("\n/*\nTODO") {
+        Some(end) => &TEMPLATE_BINARY[..=end],
+        None => TEMPLATE_BINARY,
+    };
+
+    let source = replace_keyword_arrays(source, options);
+    let source = source
+        .replace(
+            "command_parser::load_command_definition(\"template\")?",
+            &format!(
+                "command_parser::parse_command_definition(include_str!(\"../../cmd/{name}.conf\"))?"
+            ),
+        )
+        .replace("template_ai", &format!("{name}_ai"))
+        .replace("[COMMAND_DESCRIPTION]", &options.description)
+        .replace("[COMMAND_TYPE]", name);
+
+    strip_template_comments(&source)
+}
+
+/// Render the command definition from the template with placeholders substituted
+pub fn render_definition(options: &ScaffoldOptions) -> String {
+    let name = &options.name;
+
+    // The closing note only applies to the template itself
+    let definition = match TEMPLATE_DEFINITION.find("\n---\n") {
+        Some(end) => &TEMPLATE_DEFINITION[..=end],
+        None => TEMPLATE_DEFINITION,
+    };
+
+    definition
+        .replace("# TEMPLATE AI", &format!("# {} AI", name.to_uppercase()))
+        .replace("template_ai", &format!("{name}_ai"))
+        .replace("[COMMAND_DESCRIPTION]", &options.description)
+        .replace("[COMMAND_TYPE]", name)
+}
+
+/// Swap the placeholder keyword arrays for the configured keywords
+fn replace_keyword_arrays(source: &str, options: &ScaffoldOptions) -> String {
+    const START: &str = "    // TODO: Replace with your specific validation keywords\n";
+    const END_MARKER: &str = "\"invalid1\", \"invalid2\", \"invalid3\",\n    ];\n";
+
+    let (Some(start), Some(end)) = (source.find(START), source.find(END_MARKER)) else {
+        return source.to_string();
+    };
+
+    let arrays = format!(
+        "    let valid_keywords = [{}];\n\n    let invalid_keywords: [&str; {}] = [{}];\n",
+        quote_list(&options.keywords),
+        options.invalid_keywords.len(),
+        quote_list(&options.invalid_keywords)
+    );
+
+    format!(
+        "{}{}{}",
+        &source[..start],
+        arrays,
+        &source[end + END_MARKER.len()..]
+    )
+}
+
+fn quote_list(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| format!("{:?}", item.trim()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Remove the template's "Replace ..." guidance comments
+fn strip_template_comments(source: &str) -> String {
+    source
+        .lines()
+        .filter(|line| {
+            let trimmed = line.trim_start();
+            !(trimmed.starts_with("// Replace") || trimmed.starts_with("// TODO"))
+        })
+        .map(|line| match line.find("// Replace") {
+            Some(pos) => line[..pos].trim_end(),
+            None => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn format_options() -> ScaffoldOptions {
+        ScaffoldOptions {
+            name: "format".to_string(),
+            description: "code formatting".to_string(),
+            keywords: vec!["format".to_string(), "beautify".to_string()],
+            invalid_keywords: vec!["delete".to_string(), "install".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_render_binary_substitutes_placeholders() {
+        let source = render_binary(&format_options());
+
+        assert!(source.contains("Command::new(\"format_ai\")"));
+        assert!(source.contains("AI-powered code formatting operations"));
+        assert!(source.contains("let valid_keywords = [\"format\", \"beautify\"];"));
+        assert!(source.contains("= [\"delete\", \"install\"];"));
+        assert!(source.contains("include_str!(\"../../cmd/format.conf\")"));
+
+        assert!(!source.contains("template"));
+        assert!(!source.contains("[COMMAND_"));
+        assert!(!source.contains("keyword1"));
+        assert!(!source.contains("// Replace"));
+    }
+
+    #[test]
+    fn test_render_definition_substitutes_placeholders() {
+        let definition = render_definition(&format_options());
+
+        assert!(definition.starts_with("# FORMAT AI"));
+        assert!(definition.contains("`format_ai [prompt]`"));
+        assert!(definition.contains("code formatting operations"));
+        assert!(!definition.contains("This is a template file"));
+
+        let (system_prompt, args) =
+            crate::command_parser::parse_command_definition(&definition).unwrap();
+        assert!(system_prompt.contains("code formatting"));
+        assert!(args.contains("format_ai"));
+    }
+
+    #[test]
+    fn test_scaffold_writes_files_and_refuses_overwrite() {
+        let root = TempDir::new().unwrap();
+        let output = scaffold(&format_options(), root.path()).unwrap();
+
+        assert_eq!(output.binary_path, root.path().join("src/bin/format_ai.rs"));
+        let binary = std::fs::read_to_string(&output.binary_path).unwrap();
+        assert!(binary.contains("\"beautify\""));
+        let definition = std::fs::read_to_string(&output.definition_path).unwrap();
+        assert!(definition.contains("format_ai"));
+
+        let err = scaffold(&format_options(), root.path()).unwrap_err();
+        assert!(err.to_string().contains("refusing to overwrite"));
+    }
+
+    #[test]
+    fn test_validate_name_and_stanza() {
+        assert!(validate_name("format").is_ok());
+        assert!(validate_name("json2yaml").is_ok());
+        assert!(validate_name("Format").is_err());
+        assert!(validate_name("format_ai").is_err());
+        assert!(validate_name("../evil").is_err());
+        assert_eq!(
+            cargo_bin_stanza("format"),
+            "[[bin]]\nname = \"format_ai\"\npath = \"src/bin/format_ai.rs\"\n"
+        );
+    }
+}
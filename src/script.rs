@@ -0,0 +1,127 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Collects executed commands so they can be saved as a re-runnable shell script
+#[derive(Debug, Clone, Default)]
+pub struct ScriptRecorder {
+    /// Each command run, in execution order, with whether it failed
+    commands: Vec<(String, bool)>,
+}
+
+impl ScriptRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a command that ran successfully
+    pub fn record(&mut self, cmd: &str) {
+        self.commands.push((cmd.to_string(), false));
+    }
+
+    /// Record a command that failed; the script keeps it commented out, so running
+    /// it does not stop where the original run went on with a fix
+    pub fn record_failed(&mut self, cmd: &str) {
+        self.commands.push((cmd.to_string(), true));
+    }
+
+    /// The commands that ran successfully
+    pub fn commands(&self) -> impl Iterator<Item = &str> {
+        self.commands
+            .iter()
+            .filter(|(_, failed)| !failed)
+            .map(|(cmd, _)| cmd.as_str())
+    }
+
+    /// Render the recorded commands as a POSIX shell script that stops on the first error
+    pub fn render(&self) -> String {
+        let mut script = String::from("#!/bin/sh\n# Generated by Terminal AI\nset -e\n\n");
+        for (cmd, failed) in &self.commands {
+            if *failed {
+                script.push_str("# failed:\n");
+                for line in cmd.lines() {
+                    script.push_str(&format!("# {line}\n"));
+                }
+            } else {
+                script.push_str(cmd);
+                script.push('\n');
+            }
+        }
+        script
+    }
+
+    /// Write the script to `path` and mark it executable
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).context("Failed to create script directory")?;
+        }
+        std::fs::write(path, self.render())
+            .with_context(|| format!("Failed to write script to {}", path.display()))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+                .context("Failed to make script executable")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_render_keeps_command_order() {
+        let mut recorder = ScriptRecorder::new();
+        recorder.record("mkdir -p backup");
+        recorder.record("cp *.txt backup/");
+        recorder.record("ls backup");
+
+        let script = recorder.render();
+        assert!(script.starts_with("#!/bin/sh\n"));
+        let lines: Vec<&str> = script.lines().collect();
+        let set_e = lines.iter().position(|l| *l == "set -e").unwrap();
+        let first = lines.iter().position(|l| *l == "mkdir -p backup").unwrap();
+        let second = lines.iter().position(|l| *l == "cp *.txt backup/").unwrap();
+        let third = lines.iter().position(|l| *l == "ls backup").unwrap();
+        assert!(set_e < first && first < second && second < third);
+    }
+
+    #[test]
+    fn test_failed_commands_are_commented_out() {
+        let mut recorder = ScriptRecorder::new();
+        recorder.record_failed("pip install numpy==1.19\npip check");
+        recorder.record("pip install numpy==1.26.4");
+
+        assert!(recorder.render().ends_with(
+            "set -e\n\n# failed:\n# pip install numpy==1.19\n# pip check\npip install numpy==1.26.4\n"
+        ));
+        assert_eq!(
+            recorder.commands().collect::<Vec<_>>(),
+            vec!["pip install numpy==1.26.4"]
+        );
+    }
+
+    #[test]
+    fn test_write_to_creates_executable_script() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("scripts").join("run.sh");
+
+        let mut recorder = ScriptRecorder::new();
+        recorder.record("echo hello");
+        recorder.write_to(&path).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content, recorder.render());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o111, 0o111);
+        }
+    }
+}
@@ -0,0 +1,681 @@
+use anyhow::{bail, Result};
+use std::path::Path;
+
+/// Characters that end the simple command we rewrite (pipes, lists, redirections)
+const COMMAND_TERMINATORS: [char; 5] = ['|', ';', '&', '>', '<'];
+
+/// Depth injected into `find /` commands that have no depth limit of their own
+pub const DEFAULT_ROOT_SCAN_MAXDEPTH: u32 = 3;
+
+/// grep options that consume the following argument
+const GREP_OPTIONS_WITH_VALUE: [&str; 12] = [
+    "-e",
+    "-f",
+    "-m",
+    "-A",
+    "-B",
+    "-C",
+    "-d",
+    "-D",
+    "--regexp",
+    "--file",
+    "--max-count",
+    "--context",
+];
+
+/// Make sure the requested search root exists and is a directory.
+///
+/// Relative paths are resolved against `working_dir` when commands run elsewhere.
+pub fn validate_search_path(path: &str, working_dir: Option<&Path>) -> Result<()> {
+    let resolved = match working_dir {
+        Some(dir) => dir.join(path),
+        None => Path::new(path).to_path_buf(),
+    };
+    if !resolved.is_dir() {
+        bail!("Search path '{}' is not an existing directory", path);
+    }
+    Ok(())
+}
+
+/// Append the intended search scope to the user prompt so the model targets it
+pub fn scope_prompt(prompt: &str, path: &str) -> String {
+    format!("{prompt}\n\nSearch only within the directory '{path}' and use it as the search root.")
+}
+
+/// Phrases asking for the lines around each match
+const CONTEXT_PHRASES: [&str; 6] = [
+    "surrounding lines",
+    "lines around",
+    "lines before",
+    "lines after",
+    "with context",
+    "context lines",
+];
+
+/// Whether the prompt asks to see the lines around each match
+pub fn wants_context(prompt: &str) -> bool {
+    let prompt = prompt.to_lowercase();
+    CONTEXT_PHRASES.iter().any(|phrase| prompt.contains(phrase))
+}
+
+/// Append a hint asking for `-C` context: `lines` of it, or as many as the model
+/// sees fit when the prompt only implies it
+pub fn context_prompt(prompt: &str, lines: Option<u32>) -> String {
+    match lines {
+        Some(lines) => {
+            format!("{prompt}\n\nShow {lines} lines of context around each match with -C {lines}.")
+        }
+        None => {
+            format!("{prompt}\n\nShow the surrounding lines of each match with grep's -C option.")
+        }
+    }
+}
+
+/// Give every `grep` or `rg` in an AI response exactly `lines` lines of context
+pub fn context_in_response(ai_response: &str, lines: u32) -> String {
+    map_command_lines(ai_response, |cmd| apply_context(cmd, lines))
+}
+
+/// Replace the context options of a `grep` or `rg` command with a single `-C lines`
+pub fn apply_context(cmd: &str, lines: u32) -> String {
+    let (tokens, tail) = split_leading_command(cmd);
+    if !matches!(
+        tokens.first().map(String::as_str),
+        Some("grep" | "egrep" | "fgrep" | "rg")
+    ) {
+        return cmd.to_string();
+    }
+
+    let mut rewritten = vec![tokens[0].clone(), "-C".to_string(), lines.to_string()];
+    let mut iter = tokens[1..].iter();
+    while let Some(token) = iter.next() {
+        if token == "--" {
+            rewritten.push(token.clone());
+            rewritten.extend(iter.by_ref().cloned());
+        } else if matches!(
+            token.as_str(),
+            "-A" | "-B" | "-C" | "--context" | "--after-context" | "--before-context"
+        ) {
+            iter.next();
+        } else if GREP_OPTIONS_WITH_VALUE.contains(&token.as_str()) {
+            // The value may itself look like an option, e.g. `-e -C`
+            rewritten.push(token.clone());
+            rewritten.extend(iter.next().cloned());
+        } else if !is_attached_context_option(token) {
+            rewritten.push(token.clone());
+        }
+    }
+    join_with_tail(&rewritten, tail)
+}
+
+/// `-C3`, `-A2`, `--context=3` and the like
+fn is_attached_context_option(token: &str) -> bool {
+    ["--context=", "--after-context=", "--before-context="]
+        .iter()
+        .any(|prefix| token.starts_with(prefix))
+        || ["-A", "-B", "-C"].iter().any(|option| {
+            token
+                .strip_prefix(option)
+                .is_some_and(|value| !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()))
+        })
+}
+
+/// Rewrite every command line in an AI response so it searches under `path`
+pub fn scope_response_to_path(ai_response: &str, path: &str) -> String {
+    map_command_lines(ai_response, |cmd| scope_command_to_path(cmd, path))
+}
+
+/// Cap every whole-filesystem `find` in an AI response at `max_depth`
+pub fn limit_root_scans_in_response(ai_response: &str, max_depth: u32) -> String {
+    map_command_lines(ai_response, |cmd| limit_root_scan(cmd, max_depth))
+}
+
+/// Whether `cmd` is a `find` starting at `/` without `-maxdepth`
+pub fn is_unbounded_root_scan(cmd: &str) -> bool {
+    let (tokens, _) = split_leading_command(cmd);
+    if tokens.first().map(String::as_str) != Some("find") {
+        return false;
+    }
+    let roots = &tokens[1..find_expression_start(&tokens)];
+    let scans_root = roots
+        .iter()
+        .any(|root| matches!(root.as_str(), "/" | "'/'" | "\"/\"" | "/."));
+    scans_root && !tokens.iter().any(|t| t == "-maxdepth")
+}
+
+/// Inject `-maxdepth` after the starting points of an unbounded root scan
+pub fn limit_root_scan(cmd: &str, max_depth: u32) -> String {
+    if !is_unbounded_root_scan(cmd) {
+        return cmd.to_string();
+    }
+    let (mut tokens, tail) = split_leading_command(cmd);
+    let expression_start = find_expression_start(&tokens);
+    tokens.insert(expression_start, max_depth.to_string());
+    tokens.insert(expression_start, "-maxdepth".to_string());
+    join_with_tail(&tokens, tail)
+}
+
+/// How generated `find` and `cp` commands treat symbolic links
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Symlinks {
+    /// Act on the files links point to (`-L`)
+    Follow,
+    /// Act on the links themselves (`-P`); the safer default for backups
+    #[default]
+    NoFollow,
+}
+
+impl Symlinks {
+    fn flag(self) -> &'static str {
+        match self {
+            Symlinks::Follow => "-L",
+            Symlinks::NoFollow => "-P",
+        }
+    }
+}
+
+/// The mode for `--follow-symlinks` or, by default, `--no-follow-symlinks`
+pub fn symlink_mode(follow: bool) -> Symlinks {
+    if follow {
+        Symlinks::Follow
+    } else {
+        Symlinks::NoFollow
+    }
+}
+
+/// Force the symlink handling of every `find` and `cp` in an AI response to `mode`
+pub fn apply_symlinks_in_response(ai_response: &str, mode: Symlinks) -> String {
+    map_command_lines(ai_response, |cmd| apply_symlinks(cmd, mode))
+}
+
+/// Replace whatever symlink option a `find` or `cp` command uses with `mode`'s.
+///
+/// Like the other rewrites, only the leading simple command is changed.
+pub fn apply_symlinks(cmd: &str, mode: Symlinks) -> String {
+    let (tokens, tail) = split_leading_command(cmd);
+    let rewritten = match tokens.first().map(String::as_str) {
+        Some("find") => symlinks_find(&tokens, mode),
+        Some("cp") => symlinks_cp(&tokens, mode),
+        _ => return cmd.to_string(),
+    };
+    join_with_tail(&rewritten, tail)
+}
+
+/// `find` takes `-H`, `-L` or `-P` before the starting points; `-follow` is the
+/// old expression form of `-L`
+fn symlinks_find(tokens: &[String], mode: Symlinks) -> Vec<String> {
+    let rest = tokens[1..]
+        .iter()
+        .skip_while(|t| matches!(t.as_str(), "-H" | "-L" | "-P"))
+        .filter(|t| *t != "-follow");
+    let mut rewritten = vec![tokens[0].clone(), mode.flag().to_string()];
+    rewritten.extend(rest.cloned());
+    rewritten
+}
+
+/// Drop `cp`'s own `-H`/`-L`/`-P` choices and add `mode`'s after the other options,
+/// so that it also wins over the `-P` implied by `-a` and `-d`
+fn symlinks_cp(tokens: &[String], mode: Symlinks) -> Vec<String> {
+    let mut options = Vec::new();
+    let mut operands = Vec::new();
+    let mut options_done = false;
+    let mut iter = tokens[1..].iter();
+    while let Some(token) = iter.next() {
+        if options_done || !token.starts_with('-') || token == "-" {
+            operands.push(token.clone());
+        } else if token == "--" {
+            options_done = true;
+        } else if token.starts_with("--") {
+            if !matches!(token.as_str(), "--dereference" | "--no-dereference") {
+                options.push(token.clone());
+                if matches!(token.as_str(), "--target-directory" | "--suffix") {
+                    options.extend(iter.next().cloned());
+                }
+            }
+        } else {
+            let cluster: String = token
+                .chars()
+                .filter(|c| !matches!(c, 'H' | 'L' | 'P'))
+                .collect();
+            if cluster != "-" {
+                // -t and -S take the next word as their value
+                let takes_value = cluster.ends_with(['t', 'S']);
+                options.push(cluster);
+                if takes_value {
+                    options.extend(iter.next().cloned());
+                }
+            }
+        }
+    }
+
+    let mut rewritten = vec![tokens[0].clone()];
+    rewritten.extend(options);
+    rewritten.push(mode.flag().to_string());
+    if options_done {
+        rewritten.push("--".to_string());
+    }
+    rewritten.extend(operands);
+    rewritten
+}
+
+/// Apply `rewrite` to each line of the response that holds an executable command
+fn map_command_lines(ai_response: &str, rewrite: impl Fn(&str) -> String) -> String {
+    ai_response
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim();
+            // The allowlist is applied (and reported) when the result is extracted
+            if crate::extract_commands_allowed(trimmed, None).is_empty() {
+                line.to_string()
+            } else {
+                rewrite(trimmed)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Force the search root of a `find` or `grep` command to `path`.
+///
+/// Only the leading simple command is rewritten; anything after a pipe, list
+/// operator or redirection is kept as-is. Other commands are returned unchanged.
+pub fn scope_command_to_path(cmd: &str, path: &str) -> String {
+    let (tokens, tail) = split_leading_command(cmd);
+
+    let scoped = match tokens.first().map(String::as_str) {
+        Some("find") => scope_find(&tokens, path),
+        Some("grep") => scope_grep(&tokens, path),
+        _ => return cmd.to_string(),
+    };
+
+    join_with_tail(&scoped, tail)
+}
+
+/// Tokenize the leading simple command, returning the rest (pipes, redirections) untouched
+pub(crate) fn split_leading_command(cmd: &str) -> (Vec<String>, &str) {
+    let split_at = find_unquoted(cmd, &COMMAND_TERMINATORS).unwrap_or(cmd.len());
+    let (head, tail) = cmd.split_at(split_at);
+    (tokenize(head), tail)
+}
+
+fn join_with_tail(tokens: &[String], tail: &str) -> String {
+    let joined = tokens.join(" ");
+    if tail.is_empty() {
+        joined
+    } else {
+        format!("{} {}", joined, tail.trim_start())
+    }
+}
+
+/// Index of the first `find` expression token, i.e. the end of the starting points
+fn find_expression_start(tokens: &[String]) -> usize {
+    tokens
+        .iter()
+        .skip(1)
+        .position(|t| t.starts_with('-') || t.starts_with('(') || t.starts_with('\\') || t == "!")
+        .map(|i| i + 1)
+        .unwrap_or(tokens.len())
+}
+
+/// Replace the starting points of `find` (everything before the first expression)
+fn scope_find(tokens: &[String], path: &str) -> Vec<String> {
+    let expression_start = find_expression_start(tokens);
+
+    let mut scoped = vec![tokens[0].clone(), quote_path(path)];
+    scoped.extend_from_slice(&tokens[expression_start..]);
+    scoped
+}
+
+/// Replace the file operands of `grep`, enabling recursion when none was requested
+fn scope_grep(tokens: &[String], path: &str) -> Vec<String> {
+    let mut kept = vec![tokens[0].clone()];
+    let mut pattern_seen = false;
+    let mut recursive = false;
+    let mut options_done = false;
+    let mut iter = tokens.iter().skip(1);
+
+    while let Some(token) = iter.next() {
+        if !options_done && token == "--" {
+            options_done = true;
+            kept.push(token.clone());
+        } else if !options_done && token.starts_with('-') && token.len() > 1 {
+            if token == "--recursive"
+                || token == "--dereference-recursive"
+                || (!token.starts_with("--") && token.contains(['r', 'R']))
+            {
+                recursive = true;
+            }
+            if matches!(token.as_str(), "-e" | "--regexp" | "-f" | "--file") {
+                pattern_seen = true;
+            }
+            kept.push(token.clone());
+            if GREP_OPTIONS_WITH_VALUE.contains(&token.as_str()) {
+                if let Some(value) = iter.next() {
+                    kept.push(value.clone());
+                }
+            }
+        } else if !pattern_seen {
+            pattern_seen = true;
+            kept.push(token.clone());
+        }
+        // Remaining operands are files or directories chosen by the model; drop them
+    }
+
+    if !recursive {
+        kept.insert(1, "-r".to_string());
+    }
+    kept.push(quote_path(path));
+    kept
+}
+
+/// Split a command into whitespace separated words, keeping quotes intact
+fn tokenize(cmd: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for c in cmd.chars() {
+        if escaped {
+            current.push(c);
+            escaped = false;
+            continue;
+        }
+        match quote {
+            Some(q) => {
+                current.push(c);
+                if c == q {
+                    quote = None;
+                }
+            }
+            None => {
+                if c == '\\' {
+                    current.push(c);
+                    escaped = true;
+                } else if c == '\'' || c == '"' {
+                    current.push(c);
+                    quote = Some(c);
+                } else if c.is_whitespace() {
+                    if !current.is_empty() {
+                        tokens.push(std::mem::take(&mut current));
+                    }
+                } else {
+                    current.push(c);
+                }
+            }
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// Byte index of the first unquoted, unescaped occurrence of any of `chars`
+fn find_unquoted(cmd: &str, chars: &[char]) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (i, c) in cmd.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\\' => escaped = true,
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if chars.contains(&c) => return Some(i),
+            None => {}
+        }
+    }
+    None
+}
+
+/// Single-quote a path for the shell unless it only contains safe characters
+fn quote_path(path: &str) -> String {
+    let is_safe = !path.is_empty()
+        && path
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/._-~+,:@%".contains(c));
+    if is_safe {
+        path.to_string()
+    } else {
+        format!("'{}'", path.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_find_replaces_root() {
+        assert_eq!(
+            scope_command_to_path("find / -name \"*.pdf\" -type f", "/home/user/docs"),
+            "find /home/user/docs -name \"*.pdf\" -type f"
+        );
+        assert_eq!(
+            scope_command_to_path("find . ~/other -type d -empty", "src"),
+            "find src -type d -empty"
+        );
+    }
+
+    #[test]
+    fn test_scope_find_without_root_and_with_pipe() {
+        assert_eq!(
+            scope_command_to_path("find -name '*.log'", "/var/log"),
+            "find /var/log -name '*.log'"
+        );
+        assert_eq!(
+            scope_command_to_path("find / -type f -size +1G | head -n 5", "/data"),
+            "find /data -type f -size +1G | head -n 5"
+        );
+        assert_eq!(
+            scope_command_to_path(
+                "find . -name \"*.py\" -type f -exec grep -n \"TODO\" {} +",
+                "/repo"
+            ),
+            "find /repo -name \"*.py\" -type f -exec grep -n \"TODO\" {} +"
+        );
+    }
+
+    #[test]
+    fn test_scope_grep_replaces_file_operands() {
+        assert_eq!(
+            scope_command_to_path("grep -rn \"TODO\" /", "/repo"),
+            "grep -rn \"TODO\" /repo"
+        );
+        assert_eq!(
+            scope_command_to_path("grep -C 3 'error' app.log other.log", "logs"),
+            "grep -r -C 3 'error' logs"
+        );
+        assert_eq!(
+            scope_command_to_path("grep -e foo -e bar -R .", "/srv/app"),
+            "grep -e foo -e bar -R /srv/app"
+        );
+    }
+
+    #[test]
+    fn test_scope_quotes_unsafe_paths() {
+        assert_eq!(
+            scope_command_to_path("find . -type f", "/tmp/my dir"),
+            "find '/tmp/my dir' -type f"
+        );
+    }
+
+    #[test]
+    fn test_scope_leaves_other_commands_alone() {
+        assert_eq!(
+            scope_command_to_path("ps aux --sort=-%mem", "/tmp"),
+            "ps aux --sort=-%mem"
+        );
+    }
+
+    #[test]
+    fn test_scope_response_to_path() {
+        let response = "Here you go:\n```bash\nfind / -name \"*.rs\"\n```";
+        let scoped = scope_response_to_path(response, "/code");
+        assert_eq!(
+            crate::extract_commands_from_response(&scoped),
+            vec!["find /code -name \"*.rs\"".to_string()]
+        );
+        assert!(scoped.starts_with("Here you go:"));
+    }
+
+    #[test]
+    fn test_scope_prompt_mentions_path() {
+        let prompt = scope_prompt("find large files", "/data");
+        assert!(prompt.starts_with("find large files"));
+        assert!(prompt.contains("'/data'"));
+    }
+
+    #[test]
+    fn test_is_unbounded_root_scan() {
+        assert!(is_unbounded_root_scan("find / -name \"*.conf\""));
+        assert!(is_unbounded_root_scan("find '/' -type f | wc -l"));
+        assert!(!is_unbounded_root_scan(
+            "find / -maxdepth 2 -name \"*.conf\""
+        ));
+        assert!(!is_unbounded_root_scan("find . -name \"*.conf\""));
+        assert!(!is_unbounded_root_scan("find /etc -name \"*.conf\""));
+        assert!(!is_unbounded_root_scan("grep -r foo /"));
+    }
+
+    #[test]
+    fn test_limit_root_scan_injects_maxdepth() {
+        assert_eq!(
+            limit_root_scan("find / -name \"*.conf\" -type f", 3),
+            "find / -maxdepth 3 -name \"*.conf\" -type f"
+        );
+        assert_eq!(
+            limit_root_scan("find / | head", 2),
+            "find / -maxdepth 2 | head"
+        );
+        assert_eq!(
+            limit_root_scan("find . -name \"*.conf\"", 3),
+            "find . -name \"*.conf\""
+        );
+    }
+
+    #[test]
+    fn test_limit_root_scans_in_response() {
+        let response = "```bash\nfind / -size +1G\n```";
+        let limited = limit_root_scans_in_response(response, DEFAULT_ROOT_SCAN_MAXDEPTH);
+        assert_eq!(
+            crate::extract_commands_from_response(&limited),
+            vec!["find / -maxdepth 3 -size +1G".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_validate_search_path() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert!(validate_search_path(dir.path().to_str().unwrap(), None).is_ok());
+        assert!(validate_search_path("/definitely/not/a/real/dir", None).is_err());
+
+        std::fs::create_dir(dir.path().join("nested")).unwrap();
+        assert!(validate_search_path("nested", Some(dir.path())).is_ok());
+        assert!(validate_search_path("missing", Some(dir.path())).is_err());
+    }
+
+    #[test]
+    fn test_symlink_mode_defaults_to_no_follow() {
+        assert_eq!(symlink_mode(true), Symlinks::Follow);
+        assert_eq!(symlink_mode(false), Symlinks::NoFollow);
+        assert_eq!(symlink_mode(false), Symlinks::default());
+    }
+
+    #[test]
+    fn test_symlink_flags_for_find() {
+        assert_eq!(
+            apply_symlinks("find . -name '*.conf'", Symlinks::Follow),
+            "find -L . -name '*.conf'"
+        );
+        assert_eq!(
+            apply_symlinks("find -L /etc -follow -type f", Symlinks::NoFollow),
+            "find -P /etc -type f"
+        );
+        assert_eq!(
+            apply_symlinks("find ~ -type l | wc -l", Symlinks::NoFollow),
+            "find -P ~ -type l | wc -l"
+        );
+    }
+
+    #[test]
+    fn test_symlink_flags_for_cp() {
+        assert_eq!(
+            apply_symlinks("cp -a config backup/", Symlinks::Follow),
+            "cp -a -L config backup/"
+        );
+        assert_eq!(
+            apply_symlinks("cp -rL --dereference src dst", Symlinks::NoFollow),
+            "cp -r -P src dst"
+        );
+        assert_eq!(
+            apply_symlinks("cp -vt backup 'my link' && ls backup", Symlinks::NoFollow),
+            "cp -vt backup -P 'my link' && ls backup"
+        );
+        assert_eq!(
+            apply_symlinks("cp -L -- -odd-name dst", Symlinks::NoFollow),
+            "cp -P -- -odd-name dst"
+        );
+        assert_eq!(
+            apply_symlinks("rsync -a src dst", Symlinks::Follow),
+            "rsync -a src dst"
+        );
+        assert_eq!(
+            apply_symlinks_in_response(
+                "Copy the dotfiles:\ncp ~/.bashrc backup/",
+                Symlinks::NoFollow
+            ),
+            "Copy the dotfiles:\ncp -P ~/.bashrc backup/"
+        );
+    }
+
+    #[test]
+    fn test_apply_context_injects_option_once() {
+        assert_eq!(
+            apply_context("grep -rn TODO src", 3),
+            "grep -C 3 -rn TODO src"
+        );
+        assert_eq!(
+            apply_context("rg -i 'panic!' src | head -20", 2),
+            "rg -C 2 -i 'panic!' src | head -20"
+        );
+        // Existing context options are replaced, not duplicated
+        assert_eq!(
+            apply_context("grep -C 3 -rn TODO .", 3),
+            "grep -C 3 -rn TODO ."
+        );
+        assert_eq!(
+            apply_context("grep -A 2 -B1 --context=4 -n error log.txt", 5),
+            "grep -C 5 -n error log.txt"
+        );
+        // Operands after -- are never options
+        assert_eq!(apply_context("grep -- -C file", 1), "grep -C 1 -- -C file");
+        assert_eq!(
+            apply_context("grep -e -A2 file", 1),
+            "grep -C 1 -e -A2 file"
+        );
+        assert_eq!(
+            apply_context("find . -name '*.rs'", 3),
+            "find . -name '*.rs'"
+        );
+        assert_eq!(
+            context_in_response("Search with context:\ngrep -n fixme *.py", 1),
+            "Search with context:\ngrep -C 1 -n fixme *.py"
+        );
+    }
+
+    #[test]
+    fn test_context_prompt_hint() {
+        assert!(wants_context(
+            "find 'timeout' in logs and show the surrounding lines"
+        ));
+        assert!(!wants_context("find TODO comments in src"));
+        assert!(context_prompt("find errors", Some(4)).ends_with("with -C 4."));
+        assert!(context_prompt("find errors", None).ends_with("grep's -C option."));
+    }
+}
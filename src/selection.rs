@@ -0,0 +1,200 @@
+use anyhow::{Context, Result};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::transcript::{self, Event};
+use crate::{shell, ExecutionOptions};
+
+/// Added to the search request so its output can be offered as a list
+pub const PATHS_ONLY_INSTRUCTION: &str =
+    "Print only the matching paths, one per line, without -ls, -printf or -exec ls.";
+
+/// More paths than this are cut off before being offered for selection
+pub const MAX_CHOICES: usize = 500;
+
+/// Run the search `commands` and collect the paths they print.
+///
+/// Output is captured instead of shown, since it becomes the selection list. A
+/// non-zero exit (e.g. `find` hitting an unreadable directory) keeps the paths
+/// printed so far.
+pub async fn collect_paths(commands: &[String], options: &ExecutionOptions) -> Result<Vec<String>> {
+    let working_dir = options.working_dir.as_deref();
+    let transcript = options.transcript.as_ref();
+    let shell = shell::shell()?;
+    let mut output = String::new();
+    for cmd in commands {
+        transcript::record(transcript, Event::Command(cmd));
+        let mut command = shell.async_command(&shell.fix_command(cmd));
+        if let Some(dir) = working_dir {
+            command.current_dir(dir);
+        }
+        let result = command
+            .output()
+            .await
+            .with_context(|| format!("Failed to execute command '{cmd}'"))?;
+        let code = result.status.code().unwrap_or(-1);
+        transcript::record(transcript, Event::Exit(code));
+        if !result.status.success() {
+            eprintln!("⚠️  '{cmd}' exited with code {code}; keeping the paths it found");
+        }
+        output.push_str(&String::from_utf8_lossy(&result.stdout));
+        output.push('\n');
+    }
+    Ok(paths_from_output(&output, working_dir))
+}
+
+/// Lines of `output` that name existing paths, in order and without duplicates
+pub fn paths_from_output(output: &str, working_dir: Option<&Path>) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    for line in output.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || paths.iter().any(|p| p == line) {
+            continue;
+        }
+        if absolute(line, working_dir).exists() {
+            paths.push(line.to_string());
+        }
+    }
+    paths
+}
+
+/// Write `paths` one per line with nothing else, for `find_ai --emit-paths`
+pub fn write_paths(paths: &[String], output: &mut impl Write) -> std::io::Result<()> {
+    for path in paths {
+        writeln!(output, "{path}")?;
+    }
+    output.flush()
+}
+
+/// Paths piped in one per line, e.g. from `find_ai --emit-paths`, without blanks or repeats
+pub fn read_paths(input: impl BufRead) -> Result<Vec<String>> {
+    let mut paths: Vec<String> = Vec::new();
+    for line in input.lines() {
+        let line = line.context("Failed to read paths from stdin")?;
+        let path = line.trim_end_matches('\r');
+        if !path.trim().is_empty() && !paths.iter().any(|p| p == path) {
+            paths.push(path.to_string());
+        }
+    }
+    Ok(paths)
+}
+
+/// Let the user pick any number of `paths`; an empty result means nothing was chosen
+pub fn choose(paths: &[String]) -> Result<Vec<String>> {
+    let shown = &paths[..paths.len().min(MAX_CHOICES)];
+    if shown.len() < paths.len() {
+        println!(
+            "⚠️  Showing the first {MAX_CHOICES} of {} paths; narrow the search to see the rest",
+            paths.len()
+        );
+    }
+    let chosen = dialoguer::MultiSelect::new()
+        .with_prompt("Select paths (space to toggle, enter to confirm)")
+        .items(shown)
+        .max_length(15)
+        .interact_opt()
+        .context("--select needs an interactive terminal")?;
+    Ok(chosen
+        .unwrap_or_default()
+        .into_iter()
+        .map(|i| shown[i].clone())
+        .collect())
+}
+
+/// Ask what to do with the selected paths; `None` when the answer is empty
+pub fn ask_followup() -> Result<Option<String>> {
+    let action: String = dialoguer::Input::new()
+        .with_prompt("What should be done with the selected paths? (empty to stop)")
+        .allow_empty(true)
+        .interact_text()
+        .context("--select needs an interactive terminal")?;
+    let action = action.trim();
+    Ok((!action.is_empty()).then(|| action.to_string()))
+}
+
+/// Orchestrator prompt applying `action` to exactly the `selected` paths.
+///
+/// Paths are made absolute and shell-quoted so the generated commands work from
+/// any directory and with names containing spaces.
+pub fn followup_prompt(action: &str, selected: &[String], working_dir: Option<&Path>) -> String {
+    let paths = selected
+        .iter()
+        .map(|path| {
+            format!(
+                "- {}",
+                shell_quote(&absolute(path, working_dir).to_string_lossy())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "{}\n\nApply this only to these {} paths, using them exactly as written:\n{paths}",
+        action.trim(),
+        selected.len()
+    )
+}
+
+fn absolute(path: &str, working_dir: Option<&Path>) -> PathBuf {
+    let path = Path::new(path);
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    match working_dir {
+        Some(dir) => dir.join(path),
+        None => std::env::current_dir().unwrap_or_default().join(path),
+    }
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_paths_from_output_keeps_existing_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("logs")).unwrap();
+        std::fs::write(temp_dir.path().join("logs/app 1.log"), "").unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "").unwrap();
+
+        let output = "./logs/app 1.log\n\nfind: './private': Permission denied\n./notes.txt\n./logs/app 1.log\n";
+        assert_eq!(
+            paths_from_output(output, Some(temp_dir.path())),
+            vec!["./logs/app 1.log".to_string(), "./notes.txt".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_emitted_paths_round_trip() {
+        let paths = vec![
+            "./logs/app 1.log".to_string(),
+            "/tmp/report.pdf".to_string(),
+        ];
+        let mut output = Vec::new();
+        write_paths(&paths, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output.clone()).unwrap(),
+            "./logs/app 1.log\n/tmp/report.pdf\n"
+        );
+
+        let piped = [output.as_slice(), b"\r\n/tmp/report.pdf\r\n"].concat();
+        assert_eq!(read_paths(piped.as_slice()).unwrap(), paths);
+    }
+
+    #[test]
+    fn test_selection_to_prompt_handoff() {
+        let base = Path::new("/home/dev/project");
+        let selected = vec!["./logs/app 1.log".to_string(), "/tmp/it's.log".to_string()];
+
+        assert_eq!(
+            followup_prompt("  now compress these  ", &selected, Some(base)),
+            "now compress these\n\nApply this only to these 2 paths, using them exactly as written:\n\
+             - '/home/dev/project/./logs/app 1.log'\n\
+             - '/tmp/it'\\''s.log'"
+        );
+    }
+}
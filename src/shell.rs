@@ -0,0 +1,450 @@
+use crate::TerminalAIConfig;
+use anyhow::Result;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Setting naming the shell; an `[execution]` section's `shell` ends up here
+pub const SHELL_SETTING: &str = "execution.shell";
+
+/// The syntax a shell understands, which decides how scripts are passed to it,
+/// which fixups apply and what the model is asked to write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellKind {
+    Posix,
+    /// fish, which takes `-c` like a POSIX shell but has its own syntax
+    Fish,
+    PowerShell,
+    /// Windows `cmd.exe`
+    Cmd,
+}
+
+impl ShellKind {
+    /// The flag running the next argument as a script
+    fn script_flag(self) -> &'static str {
+        match self {
+            ShellKind::Posix | ShellKind::Fish => "-c",
+            ShellKind::PowerShell => "-Command",
+            ShellKind::Cmd => "/C",
+        }
+    }
+
+    /// `value` as one literal word for this shell; `None` for a value cmd can't
+    /// quote (one with `"`, `%` or a line break)
+    pub fn quote(self, value: &str) -> Option<String> {
+        match self {
+            ShellKind::Posix => Some(format!("'{}'", value.replace('\'', r"'\''"))),
+            ShellKind::Fish => Some(format!(
+                "'{}'",
+                value.replace('\\', r"\\").replace('\'', r"\'")
+            )),
+            // PowerShell takes the typographic single quotes as quotes too
+            ShellKind::PowerShell => Some(format!(
+                "'{}'",
+                escape_with(value, |c| matches!(c, '\'' | '‘' | '’' | '‚' | '‛')
+                    .then_some(c))
+            )),
+            ShellKind::Cmd => {
+                (!value.contains(['"', '%', '\n', '\r'])).then(|| format!("\"{value}\""))
+            }
+        }
+    }
+}
+
+/// `value` with `escape(c)` put before every character it returns one for
+pub(crate) fn escape_with(value: &str, escape: impl Fn(char) -> Option<char>) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if let Some(prefix) = escape(c) {
+            escaped.push(prefix);
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// POSIX shells tried in order, with the arguments that select a POSIX shell
+const POSIX_CANDIDATES: [Shell; 4] = [
+    Shell::posix("sh", &[]),
+    Shell::posix("bash", &[]),
+    Shell::posix("/bin/sh", &[]),
+    Shell::posix("busybox", &["sh"]),
+];
+
+/// Shells tried in order on Windows, where `sh` is usually missing
+const WINDOWS_CANDIDATES: [Shell; 3] = [
+    Shell {
+        program: "pwsh",
+        args: &["-NoProfile"],
+        kind: ShellKind::PowerShell,
+    },
+    Shell {
+        program: "powershell",
+        args: &["-NoProfile"],
+        kind: ShellKind::PowerShell,
+    },
+    Shell {
+        program: "cmd",
+        args: &[],
+        kind: ShellKind::Cmd,
+    },
+];
+
+/// Shells that can be picked by name with `--shell` or the `shell` setting
+const NAMED: [Shell; 8] = [
+    Shell::posix("sh", &[]),
+    Shell::posix("bash", &[]),
+    Shell::posix("zsh", &[]),
+    Shell::posix("dash", &[]),
+    Shell {
+        program: "fish",
+        args: &[],
+        kind: ShellKind::Fish,
+    },
+    WINDOWS_CANDIDATES[0],
+    WINDOWS_CANDIDATES[1],
+    WINDOWS_CANDIDATES[2],
+];
+
+/// The candidates for this platform
+fn candidates() -> &'static [Shell] {
+    if cfg!(windows) {
+        &WINDOWS_CANDIDATES
+    } else {
+        &POSIX_CANDIDATES
+    }
+}
+
+/// The shell used to run commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shell {
+    pub program: &'static str,
+    pub args: &'static [&'static str],
+    pub kind: ShellKind,
+}
+
+impl Shell {
+    const fn posix(program: &'static str, args: &'static [&'static str]) -> Self {
+        Self {
+            program,
+            args,
+            kind: ShellKind::Posix,
+        }
+    }
+
+    /// `<shell> -c <script>` (`-Command` for PowerShell, `/C` for cmd), ready for
+    /// further arguments (`$0`, `$1`, ...)
+    pub fn command(&self, script: &str) -> std::process::Command {
+        let mut command = std::process::Command::new(self.program);
+        command.args(self.args).arg(self.kind.script_flag());
+        #[cfg(windows)]
+        if self.kind == ShellKind::Cmd {
+            // cmd parses its command line itself and mangles Rust's argument quoting
+            use std::os::windows::process::CommandExt;
+            command.raw_arg(script);
+            return command;
+        }
+        command.arg(script);
+        command
+    }
+
+    /// Async variant of [`Shell::command`]
+    pub fn async_command(&self, script: &str) -> tokio::process::Command {
+        let mut command = tokio::process::Command::new(self.program);
+        command.args(self.args).arg(self.kind.script_flag());
+        #[cfg(windows)]
+        if self.kind == ShellKind::Cmd {
+            command.raw_arg(script);
+            return command;
+        }
+        command.arg(script);
+        command
+    }
+
+    pub fn is_posix(&self) -> bool {
+        self.kind == ShellKind::Posix
+    }
+
+    /// `cmd` adjusted for this shell before it runs: POSIX shells and fish get the
+    /// find `-exec ... +` fix, Windows shells run commands as they are
+    pub fn fix_command(&self, cmd: &str) -> String {
+        match self.kind {
+            ShellKind::Posix | ShellKind::Fish => crate::fix_find_exec_command(cmd),
+            ShellKind::PowerShell | ShellKind::Cmd => cmd.to_string(),
+        }
+    }
+
+    /// What the model needs to know to write commands for this shell; `None` for
+    /// plain `sh`, which the system prompts assume
+    pub fn prompt_note(&self) -> Option<&'static str> {
+        match self.kind {
+            ShellKind::Posix => match self.program {
+                "bash" => Some("Commands run in bash, so bash syntax such as arrays and [[ ]] is fine."),
+                "zsh" => Some(
+                    "Commands run in zsh, so zsh syntax such as recursive **/* globs is fine; quote globs that may match nothing.",
+                ),
+                "dash" => Some(
+                    "Commands run in dash: use plain POSIX sh syntax, without bash arrays, [[ ]] or brace expansion.",
+                ),
+                _ => None,
+            },
+            ShellKind::Fish => Some(
+                "Commands run in fish, which is not POSIX: use fish syntax, e.g. `set -x NAME value` instead of `export NAME=value`, `(cmd)` instead of `$(cmd)` and `; and` / `; or` to chain commands.",
+            ),
+            ShellKind::PowerShell => Some(
+                "Commands run in PowerShell on Windows: write PowerShell commands (e.g. Get-ChildItem, Copy-Item, Select-String, Remove-Item) instead of UNIX tools, and ignore any UNIX examples above.",
+            ),
+            ShellKind::Cmd => Some(
+                "Commands run in cmd.exe on Windows: write cmd commands (e.g. dir, copy, findstr, del) instead of UNIX tools, and ignore any UNIX examples above.",
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Shell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.program)?;
+        for arg in self.args {
+            write!(f, " {arg}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The first of `candidates` available according to `is_available`, checked in order
+fn discover_with(candidates: &[Shell], is_available: impl Fn(&str) -> bool) -> Option<Shell> {
+    candidates
+        .iter()
+        .find(|shell| is_available(shell.program))
+        .cloned()
+}
+
+fn no_shell_error(candidates: &[Shell]) -> anyhow::Error {
+    let tried: Vec<String> = candidates.iter().map(Shell::to_string).collect();
+    let hint = if candidates.iter().any(Shell::is_posix) {
+        "(e.g. `apk add busybox` or `apt-get install dash`)"
+    } else {
+        "(e.g. PowerShell from https://aka.ms/powershell)"
+    };
+    anyhow::anyhow!(
+        "No shell found to run commands (tried {}). Install one in this environment \
+         {hint} or add it to PATH",
+        tried.join(", ")
+    )
+}
+
+/// Whether `program` is an executable path or can be found on PATH (with `.exe`
+/// appended on Windows)
+pub fn is_available(program: &str) -> bool {
+    if program.contains(['/', '\\']) {
+        return is_executable(Path::new(program));
+    }
+    let names = if cfg!(windows) {
+        vec![program.to_string(), format!("{program}.exe")]
+    } else {
+        vec![program.to_string()]
+    };
+    std::env::var_os("PATH")
+        .map(|path| {
+            std::env::split_paths(&path)
+                .any(|dir| names.iter().any(|name| is_executable(&dir.join(name))))
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(unix)]
+pub(crate) fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path)
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// The shell picked by `name`, one of [`NAMED`]
+fn named_with(name: &str, is_available: impl Fn(&str) -> bool) -> Result<Shell> {
+    let shell = NAMED
+        .iter()
+        .find(|shell| shell.program == name)
+        .cloned()
+        .ok_or_else(|| {
+            let names: Vec<&str> = NAMED.iter().map(|shell| shell.program).collect();
+            anyhow::anyhow!(
+                "Unsupported shell '{name}' (choose from {})",
+                names.join(", ")
+            )
+        })?;
+    if !is_available(shell.program) {
+        anyhow::bail!("Shell '{name}' was not found on PATH");
+    }
+    Ok(shell)
+}
+
+/// The shell `--shell` (`flag`) or the `execution.shell` setting asks for, or the first
+/// one found when neither is set. A top-level `shell` setting is still read as before.
+pub fn from_config(config: &TerminalAIConfig, flag: Option<&str>) -> Result<Shell> {
+    let name = flag
+        .or(config.get_setting(SHELL_SETTING).map(String::as_str))
+        .or(config.get_setting("shell").map(String::as_str))
+        .map(str::trim)
+        .filter(|name| !name.is_empty());
+    match name {
+        Some(name) => named_with(name, is_available),
+        None => {
+            discover_with(candidates(), is_available).ok_or_else(|| no_shell_error(candidates()))
+        }
+    }
+}
+
+static SHELL: OnceLock<Option<Shell>> = OnceLock::new();
+
+/// Use the shell from [`from_config`] for the rest of the process; call before
+/// anything runs a command
+pub fn configure(config: &TerminalAIConfig, flag: Option<&str>) -> Result<()> {
+    let shell = from_config(config, flag)?;
+    let _ = SHELL.set(Some(shell));
+    Ok(())
+}
+
+/// The shell for running commands: the configured one, otherwise discovered once
+/// per process
+pub fn shell() -> Result<&'static Shell> {
+    SHELL
+        .get_or_init(|| discover_with(candidates(), is_available))
+        .as_ref()
+        .ok_or_else(|| no_shell_error(candidates()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discovery_prefers_sh_then_falls_back_in_order() {
+        let found = |available: &'static [&'static str]| {
+            discover_with(&POSIX_CANDIDATES, |program| available.contains(&program))
+                .map(|s| s.to_string())
+        };
+
+        assert_eq!(found(&["sh", "bash", "busybox"]).as_deref(), Some("sh"));
+        assert_eq!(found(&["busybox", "bash"]).as_deref(), Some("bash"));
+        assert_eq!(found(&["busybox", "/bin/sh"]).as_deref(), Some("/bin/sh"));
+        assert_eq!(found(&["busybox"]).as_deref(), Some("busybox sh"));
+    }
+
+    #[test]
+    fn test_no_shell_error_is_actionable() {
+        assert!(discover_with(&POSIX_CANDIDATES, |_| false).is_none());
+        let message = no_shell_error(&POSIX_CANDIDATES).to_string();
+        assert!(message.contains("tried sh, bash, /bin/sh, busybox sh"));
+        assert!(message.contains("add it to PATH"));
+    }
+
+    #[test]
+    fn test_busybox_command_line() {
+        let busybox = Shell::posix("busybox", &["sh"]);
+        let command = busybox.command("echo hi");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(command.get_program(), "busybox");
+        assert_eq!(args, ["sh", "-c", "echo hi"]);
+    }
+
+    #[test]
+    fn test_windows_prefers_powershell_then_cmd() {
+        let found = |available: &'static [&'static str]| {
+            discover_with(&WINDOWS_CANDIDATES, |program| available.contains(&program))
+        };
+
+        let pwsh = found(&["cmd", "powershell", "pwsh"]).unwrap();
+        assert_eq!(pwsh.to_string(), "pwsh -NoProfile");
+        assert_eq!(pwsh.kind, ShellKind::PowerShell);
+        assert_eq!(found(&["cmd"]).unwrap().kind, ShellKind::Cmd);
+        assert!(no_shell_error(&WINDOWS_CANDIDATES)
+            .to_string()
+            .contains("tried pwsh -NoProfile, powershell -NoProfile, cmd"));
+    }
+
+    #[test]
+    fn test_powershell_command_line_and_fixups() {
+        let powershell = &WINDOWS_CANDIDATES[1];
+        let command = powershell.command("Get-ChildItem -Recurse");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["-NoProfile", "-Command", "Get-ChildItem -Recurse"]);
+
+        // The find fix is for POSIX shells only
+        let find = "find . -name '*.log' -exec rm {} +";
+        assert_eq!(powershell.fix_command(find), find);
+        assert_eq!(
+            Shell::posix("sh", &[]).fix_command(find),
+            "find . -name '*.log' -exec rm {} \\;"
+        );
+    }
+
+    #[test]
+    fn test_prompt_note_names_the_shell_unless_plain_sh() {
+        assert_eq!(Shell::posix("sh", &[]).prompt_note(), None);
+        assert_eq!(Shell::posix("busybox", &["sh"]).prompt_note(), None);
+        assert!(WINDOWS_CANDIDATES[0]
+            .prompt_note()
+            .unwrap()
+            .starts_with("Commands run in PowerShell"));
+        assert!(WINDOWS_CANDIDATES[2]
+            .prompt_note()
+            .unwrap()
+            .contains("write cmd commands"));
+        for name in ["bash", "zsh", "dash", "fish"] {
+            let shell = named_with(name, |_| true).unwrap();
+            let note = shell.prompt_note().unwrap();
+            assert!(
+                note.starts_with(&format!("Commands run in {name}")),
+                "{note}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_shell_picked_by_name() {
+        let fish = named_with("fish", |_| true).unwrap();
+        assert_eq!(fish.kind, ShellKind::Fish);
+        let command = fish.command("set -x A 1");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["-c", "set -x A 1"]);
+        assert_eq!(named_with("zsh", |_| true).unwrap().kind, ShellKind::Posix);
+
+        let err = named_with("tcsh", |_| true).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "Unsupported shell 'tcsh' (choose from sh, bash, zsh, dash, fish, pwsh, powershell, cmd)"
+        );
+        let err = named_with("zsh", |_| false).unwrap_err().to_string();
+        assert_eq!(err, "Shell 'zsh' was not found on PATH");
+    }
+
+    #[test]
+    fn test_flag_takes_precedence_over_setting() {
+        let mut config = TerminalAIConfig::default();
+        config
+            .settings
+            .insert("shell".to_string(), "no-such-shell".to_string());
+        assert!(from_config(&config, None)
+            .unwrap_err()
+            .to_string()
+            .contains("Unsupported shell 'no-such-shell'"));
+        assert_eq!(from_config(&config, Some("sh")).unwrap().program, "sh");
+    }
+
+    #[test]
+    fn test_execution_shell_takes_precedence_over_shell() {
+        let mut config = TerminalAIConfig::default();
+        config
+            .settings
+            .insert("shell".to_string(), "no-such-shell".to_string());
+        config
+            .settings
+            .insert(SHELL_SETTING.to_string(), "sh".to_string());
+        assert_eq!(from_config(&config, None).unwrap().program, "sh");
+    }
+}
@@ -0,0 +1,147 @@
+use crate::providers::{AIProvider, ChunkHandler, Reply, Usage};
+use crate::query_provider::QueryProvider;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+type Answer = dyn Fn(&str) -> Result<String> + Send + Sync;
+
+/// Every (system prompt, user prompt) pair a [`MockProvider`] received
+#[derive(Clone, Default)]
+pub(crate) struct PromptLog(Arc<Mutex<Vec<(String, String)>>>);
+
+impl PromptLog {
+    pub(crate) fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn all(&self) -> Vec<(String, String)> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub(crate) fn user_prompts(&self) -> Vec<String> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, u)| u.clone())
+            .collect()
+    }
+}
+
+/// Provider answering from canned responses and recording every prompt it receives
+pub(crate) struct MockProvider {
+    answer: Box<Answer>,
+    prompts: PromptLog,
+    usage: Option<Usage>,
+    stream_pieces: Option<usize>,
+}
+
+impl MockProvider {
+    /// Answers each user prompt with `answer(user_prompt)`
+    pub(crate) fn from_fn(answer: impl Fn(&str) -> Result<String> + Send + Sync + 'static) -> Self {
+        Self {
+            answer: Box::new(answer),
+            prompts: PromptLog::default(),
+            usage: None,
+            stream_pieces: None,
+        }
+    }
+
+    /// Answers with `responses` in order, failing once they run out
+    pub(crate) fn sequence(responses: &[&str]) -> Self {
+        let responses: Mutex<VecDeque<String>> =
+            Mutex::new(responses.iter().map(|r| r.to_string()).collect());
+        Self::from_fn(move |_| {
+            responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .context("No more canned responses")
+        })
+    }
+
+    /// Answers every query with `response`
+    pub(crate) fn repeating(response: &str) -> Self {
+        let response = response.to_string();
+        Self::from_fn(move |_| Ok(response.clone()))
+    }
+
+    /// Fails every query
+    pub(crate) fn failing() -> Self {
+        Self::from_fn(|_| Err(anyhow::anyhow!("provider unavailable")))
+    }
+
+    /// Reports `usage` for every answer
+    pub(crate) fn with_usage(mut self, usage: Usage) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Streams every answer in `pieces` roughly equal chunks
+    pub(crate) fn streaming(mut self, pieces: usize) -> Self {
+        self.stream_pieces = Some(pieces.max(1));
+        self
+    }
+
+    pub(crate) fn prompts(&self) -> PromptLog {
+        self.prompts.clone()
+    }
+
+    /// Wraps the provider, keeping a handle on its prompt log
+    pub(crate) fn into_query_provider(self) -> (QueryProvider, PromptLog) {
+        let prompts = self.prompts();
+        (QueryProvider::from_provider(Box::new(self)), prompts)
+    }
+}
+
+#[async_trait]
+impl AIProvider for MockProvider {
+    async fn send_query(&self, system_prompt: &str, user_prompt: &str) -> Result<String> {
+        self.prompts
+            .0
+            .lock()
+            .unwrap()
+            .push((system_prompt.to_string(), user_prompt.to_string()));
+        (self.answer)(user_prompt)
+    }
+
+    async fn send_query_with_usage(&self, system_prompt: &str, user_prompt: &str) -> Result<Reply> {
+        Ok(Reply {
+            text: self.send_query(system_prompt, user_prompt).await?,
+            usage: self.usage,
+        })
+    }
+
+    async fn send_query_streaming(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        on_chunk: &mut ChunkHandler<'_>,
+    ) -> Result<String> {
+        let answer = self.send_query(system_prompt, user_prompt).await?;
+        let chars: Vec<char> = answer.chars().collect();
+        let size = chars.len().div_ceil(self.stream_pieces.unwrap_or(1)).max(1);
+        for piece in chars.chunks(size) {
+            on_chunk(&piece.iter().collect::<String>());
+        }
+        Ok(answer)
+    }
+
+    fn supports_streaming(&self) -> bool {
+        self.stream_pieces.is_some()
+    }
+
+    fn provider_name(&self) -> &'static str {
+        "Mock"
+    }
+
+    fn validate_config(&self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use std::time::{Duration, Instant};
+
+/// Records how long each phase of a request takes, for `--profile`
+#[derive(Debug, Clone)]
+pub struct PhaseTimer {
+    started: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Default for PhaseTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PhaseTimer {
+    /// Start timing; the total is measured from this point
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            phases: Vec::new(),
+        }
+    }
+
+    /// Record a phase that began at `since` and ends now
+    pub fn record(&mut self, phase: &'static str, since: Instant) {
+        self.phases.push((phase, since.elapsed()));
+    }
+
+    /// Run `f` and record its duration as `phase`
+    pub fn time<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let since = Instant::now();
+        let result = f();
+        self.record(phase, since);
+        result
+    }
+
+    pub fn phases(&self) -> &[(&'static str, Duration)] {
+        &self.phases
+    }
+
+    /// Summary of the recorded phases and the total time so far
+    pub fn summary(&self) -> String {
+        format_timing_summary(&self.phases, self.started.elapsed())
+    }
+}
+
+/// Format phase durations as an aligned table.
+///
+/// Time not covered by any phase (confirmation prompts, printing) is shown as "other".
+pub fn format_timing_summary(phases: &[(&str, Duration)], total: Duration) -> String {
+    let measured: Duration = phases.iter().map(|(_, duration)| *duration).sum();
+    let other = total.saturating_sub(measured);
+
+    let mut rows: Vec<(&str, Duration)> = phases.to_vec();
+    rows.push(("other", other));
+    let width = rows
+        .iter()
+        .map(|(name, _)| name.len())
+        .chain(std::iter::once("total".len()))
+        .max()
+        .unwrap_or(0);
+
+    let mut summary = String::from("⏱️  Timing summary:\n");
+    for (name, duration) in rows {
+        summary.push_str(&format!(
+            "  {name:<width$}  {:>10}\n",
+            format_duration(duration)
+        ));
+    }
+    summary.push_str(&format!(
+        "  {:<width$}  {:>10}",
+        "total",
+        format_duration(total)
+    ));
+    summary
+}
+
+fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_secs_f64() * 1000.0;
+    if millis < 1000.0 {
+        format!("{millis:.1} ms")
+    } else {
+        format!("{:.2} s", duration.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_timing_summary() {
+        let phases = [
+            ("config load", Duration::from_micros(1_500)),
+            ("provider query", Duration::from_millis(2_350)),
+            ("extraction", Duration::from_micros(300)),
+            ("execution", Duration::from_millis(400)),
+        ];
+        let summary = format_timing_summary(&phases, Duration::from_millis(3_000));
+
+        assert_eq!(
+            summary,
+            "⏱️  Timing summary:\n\
+             \x20 config load         1.5 ms\n\
+             \x20 provider query      2.35 s\n\
+             \x20 extraction          0.3 ms\n\
+             \x20 execution         400.0 ms\n\
+             \x20 other             248.2 ms\n\
+             \x20 total               3.00 s"
+        );
+    }
+
+    #[test]
+    fn test_other_never_negative() {
+        let phases = [("provider query", Duration::from_millis(20))];
+        let summary = format_timing_summary(&phases, Duration::from_millis(10));
+        assert!(summary.contains("other               0.0 ms"));
+    }
+
+    #[test]
+    fn test_timer_records_phases_in_order() {
+        let mut timer = PhaseTimer::new();
+        let value = timer.time("config load", || 42);
+        timer.record("provider query", Instant::now());
+
+        assert_eq!(value, 42);
+        let names: Vec<&str> = timer.phases().iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, vec!["config load", "provider query"]);
+        assert!(timer.summary().ends_with('s'));
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Something that happened while running commands under `--record`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'a> {
+    Command(&'a str),
+    Stdout(&'a str),
+    Stderr(&'a str),
+    Exit(i32),
+}
+
+/// A plain-text, timestamped record of a session: the commands run and their output
+#[derive(Debug)]
+pub struct Transcript {
+    sink: Sink,
+    started: Instant,
+}
+
+/// Where a [`Transcript`] goes
+#[derive(Debug)]
+enum Sink {
+    File {
+        path: PathBuf,
+        file: std::fs::File,
+        /// Also kept for [`Transcript::take_lines`] after [`Transcript::keep_lines`]
+        lines: Option<Vec<String>>,
+    },
+    /// Lines without timestamps, collected with [`Transcript::take_lines`]
+    Memory(Vec<String>),
+}
+
+/// A transcript shared by every command of a session
+pub type SharedTranscript = Arc<Mutex<Transcript>>;
+
+impl Transcript {
+    /// Create (or truncate) the transcript at `path` and write its header
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create transcript {}", path.display()))?;
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        writeln!(
+            file,
+            "# Terminal AI transcript, started at {started_at} (unix time)"
+        )?;
+        Ok(Self {
+            sink: Sink::File {
+                path: path.to_path_buf(),
+                file,
+                lines: None,
+            },
+            started: Instant::now(),
+        })
+    }
+
+    /// Open a transcript for use from [`crate::ExecutionOptions`]
+    pub fn open_shared(path: &Path) -> Result<SharedTranscript> {
+        Ok(Arc::new(Mutex::new(Self::create(path)?)))
+    }
+
+    /// A transcript kept in memory, e.g. to show the model what earlier commands
+    /// printed
+    pub fn in_memory_shared() -> SharedTranscript {
+        Arc::new(Mutex::new(Self {
+            sink: Sink::Memory(Vec::new()),
+            started: Instant::now(),
+        }))
+    }
+
+    /// The file the transcript is written to; `None` when it is kept in memory
+    pub fn path(&self) -> Option<&Path> {
+        match &self.sink {
+            Sink::File { path, .. } => Some(path),
+            Sink::Memory(_) => None,
+        }
+    }
+
+    /// Keep the lines in memory for [`Self::take_lines`] too when writing to a file
+    pub fn keep_lines(&mut self) {
+        if let Sink::File { lines: kept, .. } = &mut self.sink {
+            kept.get_or_insert_with(Vec::new);
+        }
+    }
+
+    /// The lines recorded in memory since the last call; always empty for a file,
+    /// unless [`Self::keep_lines`] was called
+    pub fn take_lines(&mut self) -> Vec<String> {
+        match &mut self.sink {
+            Sink::File { lines: None, .. } => Vec::new(),
+            Sink::File {
+                lines: Some(lines), ..
+            }
+            | Sink::Memory(lines) => std::mem::take(lines),
+        }
+    }
+
+    /// Append `event`; a failed write is reported on stderr rather than
+    /// interrupting the commands being recorded
+    pub fn record(&mut self, event: Event) {
+        match &mut self.sink {
+            Sink::File { path, file, lines } => {
+                let line = render_event(self.started.elapsed(), event);
+                if let Err(e) = writeln!(file, "{line}") {
+                    eprintln!("⚠️  Failed to write transcript {}: {e}", path.display());
+                }
+                if let Some(lines) = lines {
+                    let (marker, text) = marker_and_text(event);
+                    lines.push(format!("{marker} {text}"));
+                }
+            }
+            Sink::Memory(lines) => {
+                let (marker, text) = marker_and_text(event);
+                lines.push(format!("{marker} {text}"));
+            }
+        }
+    }
+}
+
+/// Record `event` in `transcript`, if there is one
+pub fn record(transcript: Option<&SharedTranscript>, event: Event) {
+    if let Some(transcript) = transcript {
+        transcript
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .record(event);
+    }
+}
+
+/// The marker for `event` (`$` command, ` ` stdout, `!` stderr, `=` exit) and its text
+fn marker_and_text(event: Event) -> (&'static str, String) {
+    match event {
+        Event::Command(cmd) => ("$", cmd.to_string()),
+        Event::Stdout(line) => (" ", line.to_string()),
+        Event::Stderr(line) => ("!", line.to_string()),
+        Event::Exit(code) => ("=", format!("exit {code}")),
+    }
+}
+
+/// One transcript line: seconds since the session started, a marker and the text
+fn render_event(elapsed: Duration, event: Event) -> String {
+    let (marker, text) = marker_and_text(event);
+    format!("[{:>9.3}s] {marker} {text}", elapsed.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_events() {
+        let at = Duration::from_millis;
+        let lines: Vec<String> = [
+            (at(0), Event::Command("npm install left-pad")),
+            (at(1_250), Event::Stdout("added 1 package")),
+            (at(1_300), Event::Stderr("npm WARN deprecated")),
+            (at(62_004), Event::Exit(0)),
+        ]
+        .into_iter()
+        .map(|(elapsed, event)| render_event(elapsed, event))
+        .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "[    0.000s] $ npm install left-pad",
+                "[    1.250s]   added 1 package",
+                "[    1.300s] ! npm WARN deprecated",
+                "[   62.004s] = exit 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transcript_file_keeps_event_order() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("session.log");
+        let transcript = Transcript::open_shared(&path).unwrap();
+
+        record(Some(&transcript), Event::Command("ls"));
+        record(Some(&transcript), Event::Stdout("Cargo.toml"));
+        record(Some(&transcript), Event::Exit(2));
+        record(None, Event::Stdout("not recorded"));
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert!(lines[0].starts_with("# Terminal AI transcript, started at "));
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].ends_with("] $ ls"));
+        assert!(lines[2].ends_with("]   Cargo.toml"));
+        assert!(lines[3].ends_with("] = exit 2"));
+    }
+
+    #[test]
+    fn test_in_memory_transcript_hands_out_lines_once() {
+        let transcript = Transcript::in_memory_shared();
+        record(Some(&transcript), Event::Command("du -sh photos"));
+        record(Some(&transcript), Event::Stdout("1.2G\tphotos"));
+        record(Some(&transcript), Event::Exit(0));
+
+        let mut transcript = transcript.lock().unwrap();
+        assert!(transcript.path().is_none());
+        assert_eq!(
+            transcript.take_lines(),
+            vec!["$ du -sh photos", "  1.2G\tphotos", "= exit 0"]
+        );
+        assert!(transcript.take_lines().is_empty());
+    }
+    #[test]
+    fn test_file_transcript_can_keep_lines_too() {
+        let dir = tempfile::tempdir().unwrap();
+        let transcript = Transcript::open_shared(&dir.path().join("session.log")).unwrap();
+        record(Some(&transcript), Event::Command("make"));
+        assert!(transcript.lock().unwrap().take_lines().is_empty());
+
+        transcript.lock().unwrap().keep_lines();
+        record(Some(&transcript), Event::Stderr("make: *** No targets."));
+        assert_eq!(
+            transcript.lock().unwrap().take_lines(),
+            vec!["! make: *** No targets."]
+        );
+        let content = std::fs::read_to_string(dir.path().join("session.log")).unwrap();
+        assert_eq!(content.lines().count(), 3);
+    }
+}
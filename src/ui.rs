@@ -0,0 +1,942 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::io::{BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// User-facing prompt text, overridable from `~/.terminalai/messages.toml`.
+///
+/// Keys missing from the file keep their built-in English defaults, so a catalog
+/// only needs the strings it changes.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Messages {
+    /// Heading above the numbered list of suggested commands
+    pub suggested_commands: String,
+    /// Heading above the numbered list of a multi-step plan
+    pub plan_commands: String,
+    pub confirm_execute: String,
+    pub confirm_execute_sequence: String,
+    pub confirm_resolution: String,
+    pub confirm_new_resolution: String,
+    /// Appended to every confirmation question
+    pub confirm_hint: String,
+    /// Answers that decline a confirmation (case-insensitive); anything else accepts
+    pub no_answers: Vec<String>,
+    /// Appended instead of `confirm_hint` when the commands can be edited first
+    pub confirm_edit_hint: String,
+    /// Answers that open the commands in `$EDITOR` before running them
+    /// (case-insensitive)
+    pub edit_answers: Vec<String>,
+    /// Asked before each command when `confirm_each_command` is set
+    pub confirm_step: String,
+    /// Appended to `confirm_step`; a plain Enter runs the command
+    pub confirm_step_hint: String,
+    /// Answers to `confirm_step` that run this and every remaining command
+    pub all_answers: Vec<String>,
+    /// Answers to `confirm_step` that stop before this command
+    pub quit_answers: Vec<String>,
+    /// Asked instead of the usual confirmation about caution commands
+    pub confirm_risky: String,
+    /// Appended to `confirm_risky`, whose default answer is no
+    pub confirm_risky_hint: String,
+    /// Answers that accept `confirm_risky` (case-insensitive); anything else declines
+    pub yes_answers: Vec<String>,
+    /// Asked about each dangerous command, followed by its number and the phrase to
+    /// type (e.g. `yes, run rm`); any other answer declines
+    pub confirm_dangerous: String,
+    /// Asked, like `confirm_risky`, before a `cp` or `mv` replaces existing files
+    pub confirm_overwrite: String,
+    /// Asked, like `confirm_risky`, before each install, update or removal unless
+    /// `auto_execute_installs` is set
+    pub confirm_install: String,
+    pub not_executed: String,
+    pub resolution_not_executed: String,
+    pub new_resolution_not_executed: String,
+    /// Printed after `--dry-run` showed the commands
+    pub dry_run: String,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self {
+            suggested_commands: "Terminal AI suggest following commands:".to_string(),
+            plan_commands: "🤖 Commands to execute:".to_string(),
+            confirm_execute: "❓ Execute these commands?".to_string(),
+            confirm_execute_sequence: "❓ Execute these commands in sequence?".to_string(),
+            confirm_resolution: "❓ Execute these resolution commands?".to_string(),
+            confirm_new_resolution: "❓ Execute these new resolution commands?".to_string(),
+            confirm_hint: "[Y/n]".to_string(),
+            no_answers: vec!["n".to_string(), "no".to_string()],
+            confirm_edit_hint: "[Y/n/e]".to_string(),
+            edit_answers: vec!["e".to_string(), "edit".to_string()],
+            confirm_step: "❓ Run this command?".to_string(),
+            confirm_step_hint: "[y]es / [n]o / [a]ll / [q]uit".to_string(),
+            all_answers: vec!["a".to_string(), "all".to_string()],
+            quit_answers: vec!["q".to_string(), "quit".to_string()],
+            confirm_risky: "❓ Run these flagged commands anyway?".to_string(),
+            confirm_risky_hint: "[y/N]".to_string(),
+            yes_answers: vec!["y".to_string(), "yes".to_string()],
+            confirm_dangerous: "❓ To run dangerous command".to_string(),
+            confirm_overwrite: "❓ Destination exists, overwrite?".to_string(),
+            confirm_install: "❓ Run this package command?".to_string(),
+            not_executed: "❌ Commands not executed.".to_string(),
+            resolution_not_executed: "❌ Resolution commands not executed.".to_string(),
+            new_resolution_not_executed: "❌ New resolution commands not executed.".to_string(),
+            dry_run: "🧪 Dry run: nothing was executed.".to_string(),
+        }
+    }
+}
+
+impl Messages {
+    pub fn from_toml_str(content: &str) -> Result<Self> {
+        toml::from_str(content).context("Invalid message catalog")
+    }
+
+    /// Load the catalog at `path`, or the defaults when it does not exist
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_toml_str(&content).with_context(|| format!("In {}", path.display()))
+    }
+
+    pub fn is_no(&self, answer: &str) -> bool {
+        is_one_of(&self.no_answers, answer)
+    }
+
+    pub fn is_yes(&self, answer: &str) -> bool {
+        is_one_of(&self.yes_answers, answer)
+    }
+
+    pub fn is_edit(&self, answer: &str) -> bool {
+        is_one_of(&self.edit_answers, answer)
+    }
+
+    /// The answer to `confirm_step`, `None` when it isn't one
+    pub fn step(&self, answer: &str) -> Option<Step> {
+        if answer.trim().is_empty() || self.is_yes(answer) {
+            Some(Step::Run)
+        } else if self.is_no(answer) {
+            Some(Step::Skip)
+        } else if is_one_of(&self.all_answers, answer) {
+            Some(Step::All)
+        } else if is_one_of(&self.quit_answers, answer) {
+            Some(Step::Quit)
+        } else {
+            None
+        }
+    }
+}
+
+fn is_one_of(answers: &[String], answer: &str) -> bool {
+    let answer = answer.trim().to_lowercase();
+    answers
+        .iter()
+        .any(|candidate| candidate.trim().to_lowercase() == answer)
+}
+
+pub fn messages_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".terminalai").join("messages.toml"))
+}
+
+/// The active catalog, loaded once; a broken catalog falls back to the defaults
+pub fn messages() -> &'static Messages {
+    static MESSAGES: OnceLock<Messages> = OnceLock::new();
+    MESSAGES.get_or_init(|| {
+        let Some(path) = messages_path() else {
+            return Messages::default();
+        };
+        Messages::load_from(&path).unwrap_or_else(|e| {
+            eprintln!("⚠️  Ignoring message catalog: {e:#}");
+            Messages::default()
+        })
+    })
+}
+
+/// Heading followed by the numbered command list
+pub fn render_preview(heading: &str, commands: &[String]) -> String {
+    render_explained_preview(heading, commands, &[])
+}
+
+/// Like [`render_preview`], with an explanation line under each command that has one
+pub fn render_explained_preview(
+    heading: &str,
+    commands: &[String],
+    explanations: &[Option<String>],
+) -> String {
+    let mut text = format!("{heading}\n");
+    for (i, cmd) in commands.iter().enumerate() {
+        text.push_str(&format!("  {}. {}\n", i + 1, cmd));
+        if let Some(Some(explanation)) = explanations.get(i) {
+            text.push_str(&format!("     ↳ {explanation}\n"));
+        }
+    }
+    text
+}
+
+/// Set once a tool's stdin or stdout belongs to a pipeline
+static PIPELINE_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Keep stdout and stdin free for a pipeline: previews and questions go to stderr
+/// and answers are read from the terminal (`/dev/tty`) instead of stdin
+pub fn use_pipeline_mode() {
+    PIPELINE_MODE.store(true, Ordering::Relaxed);
+}
+
+fn pipeline_mode() -> bool {
+    PIPELINE_MODE.load(Ordering::Relaxed)
+}
+
+/// Environment variable that turns on `--yes` for every command, e.g. in cron jobs and CI
+pub const ASSUME_YES_ENV_VAR: &str = "TERMINALAI_ASSUME_YES";
+
+/// Set once at startup from `--yes` or `TERMINALAI_ASSUME_YES`
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// Whether `TERMINALAI_ASSUME_YES` is set to 1, true or yes
+pub fn assume_yes_from_env() -> bool {
+    std::env::var(ASSUME_YES_ENV_VAR)
+        .is_ok_and(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+}
+
+/// Answer every question without reading input when `flag` (`--yes`) is given or
+/// `TERMINALAI_ASSUME_YES` is set: confirmations and package commands are accepted,
+/// while questions that default to no (flagged commands, overwrites) are declined
+pub fn configure_assume_yes(flag: bool) {
+    ASSUME_YES.store(flag || assume_yes_from_env(), Ordering::Relaxed);
+}
+
+pub fn assume_yes() -> bool {
+    ASSUME_YES.load(Ordering::Relaxed)
+}
+
+/// With `--yes`, show `question` with `answer` and return it instead of asking
+fn assumed(question: &str, answer: bool) -> Option<bool> {
+    if !assume_yes() {
+        return None;
+    }
+    let shown = if answer { "yes" } else { "no" };
+    show(&format!("\n{question} {shown} (--yes)\n"));
+    Some(answer)
+}
+
+/// Print `text` for the user: stdout normally, stderr in pipeline mode
+pub fn show(text: &str) {
+    if pipeline_mode() {
+        eprint!("{text}");
+    } else {
+        print!("{text}");
+    }
+}
+
+pub fn preview(heading: &str, commands: &[String]) {
+    show(&render_preview(heading, commands));
+}
+
+pub fn preview_explained(heading: &str, commands: &[String], explanations: &[Option<String>]) {
+    show(&render_explained_preview(heading, commands, explanations));
+}
+
+/// Ask `question` and read the answer from `input`; true unless the answer is a "no"
+pub fn confirm_with(
+    messages: &Messages,
+    question: &str,
+    input: &mut (impl BufRead + ?Sized),
+    output: &mut (impl Write + ?Sized),
+) -> bool {
+    let _ = write!(output, "\n{} {}: ", question, messages.confirm_hint);
+    let _ = output.flush();
+
+    let mut answer = String::new();
+    let _ = input.read_line(&mut answer);
+    !messages.is_no(&answer)
+}
+
+/// Ask `question` on the terminal with the active catalog
+pub fn confirm(question: &str) -> bool {
+    if let Some(answer) = assumed(question, true) {
+        return answer;
+    }
+    let default = confirm_timeout().is_some_and(|timeout| timeout.default);
+    ask_terminal(
+        |input, output| confirm_with(messages(), question, input, output),
+        default,
+    )
+}
+
+/// An answer to a confirmation that also offers to edit the commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    Accept,
+    Edit,
+    Decline,
+}
+
+/// Like [`confirm_with`], where an edit answer asks for the commands to be edited
+pub fn confirm_or_edit_with(
+    messages: &Messages,
+    question: &str,
+    input: &mut (impl BufRead + ?Sized),
+    output: &mut (impl Write + ?Sized),
+) -> Choice {
+    let _ = write!(output, "\n{} {}: ", question, messages.confirm_edit_hint);
+    let _ = output.flush();
+
+    let mut answer = String::new();
+    let _ = input.read_line(&mut answer);
+    if messages.is_edit(&answer) {
+        Choice::Edit
+    } else if messages.is_no(&answer) {
+        Choice::Decline
+    } else {
+        Choice::Accept
+    }
+}
+
+/// Ask `question` on the terminal, offering to edit the commands first
+pub fn confirm_or_edit(question: &str) -> Choice {
+    if assumed(question, true).is_some() {
+        return Choice::Accept;
+    }
+    let default = confirm_timeout().is_some_and(|timeout| timeout.default);
+    let mut choice = Choice::Decline;
+    let accepted = ask_terminal(
+        |input, output| {
+            choice = confirm_or_edit_with(messages(), question, input, output);
+            choice != Choice::Decline
+        },
+        default,
+    );
+    // A timed-out answer resolves to the default, which never edits
+    match (accepted, choice) {
+        (false, _) => Choice::Decline,
+        (true, Choice::Edit) => Choice::Edit,
+        (true, _) => Choice::Accept,
+    }
+}
+
+/// An answer to the question asked before each command (`confirm_each_command`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Run,
+    Skip,
+    /// Run this and every remaining command without asking
+    All,
+    /// Run nothing more
+    Quit,
+}
+
+/// Ask whether to run command `number`, `cmd`, until the answer is one of the step
+/// answers; no answer at all quits
+pub fn confirm_step_with(
+    messages: &Messages,
+    number: usize,
+    cmd: &str,
+    input: &mut (impl BufRead + ?Sized),
+    output: &mut (impl Write + ?Sized),
+) -> Step {
+    let _ = write!(output, "\n  {number}. {cmd}");
+    loop {
+        let _ = write!(
+            output,
+            "\n{} {}: ",
+            messages.confirm_step, messages.confirm_step_hint
+        );
+        let _ = output.flush();
+
+        let mut answer = String::new();
+        match input.read_line(&mut answer) {
+            Ok(0) | Err(_) => return Step::Quit,
+            Ok(_) => {}
+        }
+        if let Some(step) = messages.step(&answer) {
+            return step;
+        }
+    }
+}
+
+/// Ask on the terminal whether to run command `number`; an unanswered question runs
+/// it only when `confirm_default` is yes
+pub fn confirm_step(number: usize, cmd: &str) -> Step {
+    if assume_yes() {
+        show(&format!("\n  {number}. {cmd}"));
+        assumed(&messages().confirm_step, true);
+        return Step::Run;
+    }
+    let default = confirm_timeout().is_some_and(|timeout| timeout.default);
+    let mut step = Step::Quit;
+    let accepted = ask_terminal(
+        |input, output| {
+            step = confirm_step_with(messages(), number, cmd, input, output);
+            step != Step::Quit
+        },
+        default,
+    );
+    match (accepted, step) {
+        (false, _) => Step::Quit,
+        // Timed out with a yes default
+        (true, Step::Quit) => Step::Run,
+        (true, step) => step,
+    }
+}
+
+/// How long a confirmation waits for an answer before resolving on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfirmTimeout {
+    pub after: Duration,
+    /// The answer assumed for a regular confirmation; risky ones always decline
+    pub default: bool,
+}
+
+// Set once at startup from `confirm_timeout_seconds` and `confirm_default`
+static CONFIRM_TIMEOUT: Mutex<Option<ConfirmTimeout>> = Mutex::new(None);
+
+pub fn set_confirm_timeout(timeout: Option<ConfirmTimeout>) {
+    *CONFIRM_TIMEOUT
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = timeout;
+}
+
+fn confirm_timeout() -> Option<ConfirmTimeout> {
+    *CONFIRM_TIMEOUT
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Apply the `confirm_timeout_seconds` and `confirm_default` config settings
+pub fn configure(config: &crate::TerminalAIConfig) {
+    set_confirm_timeout(
+        config
+            .confirm_timeout_seconds()
+            .map(|seconds| ConfirmTimeout {
+                after: Duration::from_secs(seconds),
+                default: config.confirm_default(),
+            }),
+    );
+}
+
+/// Run `ask` against stdin/stdout, or in pipeline mode against the terminal and
+/// stderr. Without a terminal to ask on, the answer is no; without an answer before
+/// the confirmation timeout, it is `default`.
+fn ask_terminal(ask: impl FnOnce(&mut dyn BufRead, &mut dyn Write) -> bool, default: bool) -> bool {
+    let timeout = confirm_timeout().map(|timeout| timeout.after);
+    if !pipeline_mode() {
+        return match timeout {
+            Some(after) => ask_within(after, default, stdin_lines(), ask, &mut std::io::stdout()),
+            None => ask(&mut std::io::stdin().lock(), &mut std::io::stdout()),
+        };
+    }
+    let result = match timeout {
+        Some(after) => {
+            tty_lines().map(|lines| ask_within(after, default, lines, ask, &mut std::io::stderr()))
+        }
+        None => std::fs::File::open("/dev/tty")
+            .map(|tty| ask(&mut std::io::BufReader::new(tty), &mut std::io::stderr()))
+            .map_err(|e| e.to_string()),
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("❌ No terminal to confirm on ({e}); nothing will be executed.");
+        false
+    })
+}
+
+/// Answers typed on stdin, read by one thread for the whole run
+fn stdin_lines() -> &'static LineFeed {
+    static STDIN: OnceLock<LineFeed> = OnceLock::new();
+    STDIN.get_or_init(|| LineFeed::spawn(|line| std::io::stdin().read_line(line)))
+}
+
+/// Answers typed on the terminal in pipeline mode, read by one thread for the whole run
+fn tty_lines() -> Result<&'static LineFeed, String> {
+    static TTY: OnceLock<Result<LineFeed, String>> = OnceLock::new();
+    TTY.get_or_init(|| {
+        let mut tty =
+            std::io::BufReader::new(std::fs::File::open("/dev/tty").map_err(|e| e.to_string())?);
+        Ok(LineFeed::spawn(move |line| tty.read_line(line)))
+    })
+    .as_ref()
+    .map_err(Clone::clone)
+}
+
+/// Run `ask` with answers that must arrive from `lines` within `after`, else resolve
+/// to `default`
+fn ask_within(
+    after: Duration,
+    default: bool,
+    lines: &LineFeed,
+    ask: impl FnOnce(&mut dyn BufRead, &mut dyn Write) -> bool,
+    output: &mut dyn Write,
+) -> bool {
+    let mut input = TimedInput {
+        lines,
+        deadline: Instant::now() + after,
+        line: Vec::new(),
+        consumed: 0,
+        timed_out: false,
+    };
+    let accepted = ask(&mut input, output);
+    if !input.timed_out {
+        return accepted;
+    }
+    let _ = writeln!(
+        output,
+        "\n⏱️  No answer within {}s; assuming {}.",
+        after.as_millis().div_ceil(1000),
+        if default { "yes" } else { "no" }
+    );
+    default
+}
+
+/// Lines of input read on one long-lived thread, so a prompt nobody answers can't
+/// block, and a line typed after its timeout goes to the next prompt instead of a
+/// reader left behind
+struct LineFeed(Mutex<mpsc::Receiver<String>>);
+
+impl LineFeed {
+    /// Read lines with `read_line` until the input ends
+    fn spawn(
+        mut read_line: impl FnMut(&mut String) -> std::io::Result<usize> + Send + 'static,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || loop {
+            let mut line = String::new();
+            match read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) if sender.send(line).is_err() => break,
+                Ok(_) => {}
+            }
+        });
+        Self(Mutex::new(receiver))
+    }
+}
+
+/// Answers from a [`LineFeed`], all due by `deadline`; once it passes the input ends
+struct TimedInput<'a> {
+    lines: &'a LineFeed,
+    deadline: Instant,
+    line: Vec<u8>,
+    consumed: usize,
+    timed_out: bool,
+}
+
+impl Read for TimedInput<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl BufRead for TimedInput<'_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.consumed == self.line.len() && !self.timed_out {
+            let remaining = self.deadline.saturating_duration_since(Instant::now());
+            let received = self
+                .lines
+                .0
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .recv_timeout(remaining);
+            self.consumed = 0;
+            self.line = match received {
+                Ok(line) => line.into_bytes(),
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    self.timed_out = true;
+                    Vec::new()
+                }
+                // The input ended
+                Err(mpsc::RecvTimeoutError::Disconnected) => Vec::new(),
+            };
+        }
+        Ok(&self.line[self.consumed..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.consumed = (self.consumed + amount).min(self.line.len());
+    }
+}
+
+/// Like [`show`], flushed right away for text that arrives a piece at a time
+pub fn show_streamed(text: &str) {
+    show(text);
+    if pipeline_mode() {
+        let _ = std::io::stderr().flush();
+    } else {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+const SPINNER_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Animates `message` on stderr while waiting for an answer that can't be shown as
+/// it arrives, and clears the line once dropped. Nothing is drawn unless stderr is
+/// a terminal.
+pub struct Spinner {
+    running: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Spinner {
+    pub fn start(message: &str) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = std::io::stderr().is_terminal().then(|| {
+            let running = Arc::clone(&running);
+            let message = message.to_string();
+            std::thread::spawn(move || {
+                for frame in SPINNER_FRAMES.iter().cycle() {
+                    if !running.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    eprint!("\r{frame} {message}");
+                    let _ = std::io::stderr().flush();
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                eprint!("\r\x1b[2K");
+            })
+        });
+        Self { running, thread }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Like [`confirm_with`], but only an explicit yes accepts
+pub fn confirm_risky_with(
+    messages: &Messages,
+    question: &str,
+    input: &mut (impl BufRead + ?Sized),
+    output: &mut (impl Write + ?Sized),
+) -> bool {
+    let _ = write!(output, "\n{} {}: ", question, messages.confirm_risky_hint);
+    let _ = output.flush();
+
+    let mut answer = String::new();
+    let _ = input.read_line(&mut answer);
+    messages.is_yes(&answer)
+}
+
+/// Ask `question` on the terminal, declining unless the answer is a yes
+pub fn confirm_risky(question: &str) -> bool {
+    if let Some(answer) = assumed(question, false) {
+        return answer;
+    }
+    ask_terminal(
+        |input, output| confirm_risky_with(messages(), question, input, output),
+        false,
+    )
+}
+
+/// Ask to run dangerous command `number`, accepting only `phrase` typed out
+/// (ignoring case and surrounding spaces)
+pub fn confirm_typed_with(
+    messages: &Messages,
+    number: usize,
+    phrase: &str,
+    input: &mut (impl BufRead + ?Sized),
+    output: &mut (impl Write + ?Sized),
+) -> bool {
+    let _ = write!(
+        output,
+        "\n{} {number}, type '{phrase}': ",
+        messages.confirm_dangerous
+    );
+    let _ = output.flush();
+
+    let mut answer = String::new();
+    let _ = input.read_line(&mut answer);
+    answer.trim().eq_ignore_ascii_case(phrase)
+}
+
+/// [`confirm_typed_with`] on the terminal
+pub fn confirm_typed(number: usize, phrase: &str) -> bool {
+    if let Some(answer) = assumed(&format!("{} {number}", messages().confirm_dangerous), false) {
+        return answer;
+    }
+    ask_terminal(
+        |input, output| confirm_typed_with(messages(), number, phrase, input, output),
+        false,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ask(messages: &Messages, question: &str, answer: &str) -> (bool, String) {
+        let mut output = Vec::new();
+        let accepted = confirm_with(messages, question, &mut answer.as_bytes(), &mut output);
+        (accepted, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_default_prompt_text() {
+        let messages = Messages::default();
+        let (accepted, shown) = ask(&messages, &messages.confirm_execute, "\n");
+        assert!(accepted);
+        assert_eq!(shown, "\n❓ Execute these commands? [Y/n]: ");
+
+        let (accepted, _) = ask(&messages, &messages.confirm_execute, "No\n");
+        assert!(!accepted);
+    }
+
+    #[test]
+    fn test_edit_answer_asks_for_editing() {
+        let messages = Messages::default();
+        let choose = |answer: &str| {
+            let mut output = Vec::new();
+            let choice = confirm_or_edit_with(
+                &messages,
+                &messages.confirm_execute,
+                &mut answer.as_bytes(),
+                &mut output,
+            );
+            (choice, String::from_utf8(output).unwrap())
+        };
+
+        let (choice, shown) = choose("\n");
+        assert_eq!(choice, Choice::Accept);
+        assert_eq!(shown, "\n❓ Execute these commands? [Y/n/e]: ");
+        assert_eq!(choose("E\n").0, Choice::Edit);
+        assert_eq!(choose("edit\n").0, Choice::Edit);
+        assert_eq!(choose("n\n").0, Choice::Decline);
+    }
+
+    #[test]
+    fn test_step_answers() {
+        let messages = Messages::default();
+        let step = |answers: &str| {
+            let mut output = Vec::new();
+            let step = confirm_step_with(
+                &messages,
+                2,
+                "rm old.log",
+                &mut answers.as_bytes(),
+                &mut output,
+            );
+            (step, String::from_utf8(output).unwrap())
+        };
+
+        let (answer, shown) = step("\n");
+        assert_eq!(answer, Step::Run);
+        assert_eq!(
+            shown,
+            "\n  2. rm old.log\n❓ Run this command? [y]es / [n]o / [a]ll / [q]uit: "
+        );
+        assert_eq!(step("N\n").0, Step::Skip);
+        assert_eq!(step("all\n").0, Step::All);
+        assert_eq!(step("q\n").0, Step::Quit);
+        assert_eq!(step("").0, Step::Quit);
+
+        // Anything else asks again
+        let (answer, shown) = step("maybe\nn\n");
+        assert_eq!(answer, Step::Skip);
+        assert_eq!(shown.matches("Run this command?").count(), 2);
+    }
+
+    #[test]
+    fn test_risky_confirmation_needs_explicit_yes() {
+        let messages = Messages::default();
+        let ask_risky = |answer: &str| {
+            let mut output = Vec::new();
+            let accepted = confirm_risky_with(
+                &messages,
+                &messages.confirm_risky,
+                &mut answer.as_bytes(),
+                &mut output,
+            );
+            (accepted, String::from_utf8(output).unwrap())
+        };
+
+        let (accepted, shown) = ask_risky("\n");
+        assert!(!accepted);
+        assert_eq!(shown, "\n❓ Run these flagged commands anyway? [y/N]: ");
+        assert!(!ask_risky("sure\n").0);
+        assert!(ask_risky("YES\n").0);
+    }
+
+    #[test]
+    fn test_dangerous_confirmation_needs_the_typed_phrase() {
+        let messages = Messages::default();
+        let ask = |answer: &str| {
+            let mut output = Vec::new();
+            let accepted = confirm_typed_with(
+                &messages,
+                3,
+                "yes, run rm",
+                &mut answer.as_bytes(),
+                &mut output,
+            );
+            (accepted, String::from_utf8(output).unwrap())
+        };
+
+        let (accepted, shown) = ask("yes, run rm\n");
+        assert!(accepted);
+        assert_eq!(
+            shown,
+            "\n❓ To run dangerous command 3, type 'yes, run rm': "
+        );
+        assert!(ask("  Yes, Run RM \n").0);
+        assert!(!ask("yes\n").0);
+        assert!(!ask("y\n").0);
+        assert!(!ask("\n").0);
+    }
+
+    #[test]
+    fn test_override_catalog_changes_prompt_text() {
+        let messages = Messages::from_toml_str(
+            r#"
+suggested_commands = "Terminal AI schlägt folgende Befehle vor:"
+confirm_execute = "❓ Diese Befehle ausführen?"
+confirm_hint = "[J/n]"
+no_answers = ["n", "nein"]
+"#,
+        )
+        .unwrap();
+
+        let (accepted, shown) = ask(&messages, &messages.confirm_execute, "nein\n");
+        assert!(!accepted);
+        assert_eq!(shown, "\n❓ Diese Befehle ausführen? [J/n]: ");
+        assert!(ask(&messages, &messages.confirm_execute, "no\n").0);
+
+        assert_eq!(
+            render_preview(&messages.suggested_commands, &["ls -la".to_string()]),
+            "Terminal AI schlägt folgende Befehle vor:\n  1. ls -la\n"
+        );
+        // Keys the catalog leaves out keep their defaults
+        assert_eq!(
+            messages.confirm_resolution,
+            Messages::default().confirm_resolution
+        );
+    }
+
+    #[test]
+    fn test_load_catalog_from_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("messages.toml");
+        assert_eq!(Messages::load_from(&path).unwrap(), Messages::default());
+
+        std::fs::write(&path, "not_executed = \"❌ Rien n'a été exécuté.\"\n").unwrap();
+        let messages = Messages::load_from(&path).unwrap();
+        assert_eq!(messages.not_executed, "❌ Rien n'a été exécuté.");
+
+        std::fs::write(&path, "confirm_excute = \"typo\"\n").unwrap();
+        assert!(Messages::load_from(&path).is_err());
+    }
+
+    /// Answers arriving one at a time, each after its delay in milliseconds
+    fn scripted(answers: &[(u64, &str)]) -> LineFeed {
+        let mut answers: std::collections::VecDeque<(u64, String)> = answers
+            .iter()
+            .map(|(delay, answer)| (*delay, answer.to_string()))
+            .collect();
+        LineFeed::spawn(move |line| {
+            let Some((delay, answer)) = answers.pop_front() else {
+                return Ok(0);
+            };
+            std::thread::sleep(Duration::from_millis(delay));
+            line.push_str(&answer);
+            Ok(answer.len())
+        })
+    }
+
+    #[test]
+    fn test_unanswered_confirmation_resolves_to_default() {
+        let messages = Messages::default();
+        let ask = |input: &mut dyn BufRead, output: &mut dyn Write| {
+            confirm_with(&messages, &messages.confirm_execute, input, output)
+        };
+
+        let mut output = Vec::new();
+        let after = Duration::from_millis(50);
+        let slow = scripted(&[(2000, "n\n")]);
+        assert!(ask_within(after, true, &slow, ask, &mut output));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\n❓ Execute these commands? [Y/n]: \n⏱️  No answer within 1s; assuming yes.\n"
+        );
+
+        let mut output = Vec::new();
+        assert!(!ask_within(after, false, &slow, ask, &mut output));
+
+        // An answer in time is used as is
+        let quick = scripted(&[(0, "no\n")]);
+        let mut output = Vec::new();
+        let after = Duration::from_secs(5);
+        assert!(!ask_within(after, true, &quick, ask, &mut output));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\n❓ Execute these commands? [Y/n]: "
+        );
+    }
+
+    #[test]
+    fn test_answer_after_a_timeout_goes_to_the_next_prompt() {
+        let messages = Messages::default();
+        let ask = |input: &mut dyn BufRead, output: &mut dyn Write| {
+            confirm_with(&messages, &messages.confirm_execute, input, output)
+        };
+        let lines = scripted(&[(300, "n\n")]);
+
+        assert!(ask_within(
+            Duration::from_millis(20),
+            true,
+            &lines,
+            ask,
+            &mut Vec::new()
+        ));
+        assert!(!ask_within(
+            Duration::from_secs(5),
+            true,
+            &lines,
+            ask,
+            &mut Vec::new()
+        ));
+    }
+
+    #[test]
+    fn test_step_is_asked_again_within_the_timeout() {
+        let messages = Messages::default();
+        let mut step = Step::Quit;
+        let lines = scripted(&[(0, "maybe\n"), (0, "n\n")]);
+        let answered = ask_within(
+            Duration::from_secs(5),
+            false,
+            &lines,
+            |input, output| {
+                step = confirm_step_with(&messages, 1, "ls", input, output);
+                step != Step::Quit
+            },
+            &mut Vec::new(),
+        );
+        assert!(answered);
+        assert_eq!(step, Step::Skip);
+
+        // No valid answer before the timeout resolves to the default
+        let mut output = Vec::new();
+        let lines = scripted(&[(0, "maybe\n"), (2000, "n\n")]);
+        assert!(ask_within(
+            Duration::from_millis(100),
+            true,
+            &lines,
+            |input, output| confirm_step_with(&messages, 1, "ls", input, output) != Step::Quit,
+            &mut output
+        ));
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .ends_with("No answer within 1s; assuming yes.\n"));
+    }
+}
@@ -0,0 +1,281 @@
+use crate::shell::{escape_with, ShellKind};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// What plan steps printed to stdout, captured with `COMMAND[OUT=name]: ...` and put
+/// into later steps in place of `{{name}}`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Variables(BTreeMap<String, String>);
+
+impl Variables {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    /// Each variable and its value, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Set `name` to a step's `stdout`, without surrounding whitespace. Nothing
+    /// printed is an error, since an empty value could turn a later `rm -rf
+    /// "{{dir}}"/*` into one deleting `/*`.
+    pub fn capture(&mut self, name: &str, stdout: &[String]) -> Result<&str> {
+        let value = stdout.join("\n").trim().to_string();
+        if value.is_empty() {
+            anyhow::bail!("The command printed nothing to capture into {{{{{name}}}}}");
+        }
+        self.0.insert(name.to_string(), value);
+        Ok(&self.0[name])
+    }
+
+    /// `command` with the `{{name}}` of every captured variable replaced by its
+    /// value, quoted for `shell` so it stays one word whatever it contains: quoted
+    /// where the placeholder is bare, escaped where it is inside quotes already.
+    /// Other `{{...}}`, like `docker ps --format '{{.Names}}'`, stay as they are.
+    pub fn substitute(&self, command: &str, shell: ShellKind) -> Result<String> {
+        let mut substituted = String::with_capacity(command.len());
+        let mut quoting = Quoting::Bare;
+        let mut rest = command;
+        while let Some(c) = rest.chars().next() {
+            if let Some((name, value, len)) = self.placeholder_at(rest) {
+                let quoted = quote_in(shell, quoting, value).with_context(|| {
+                    format!(
+                        "{{{{{name}}}}} can't be passed to cmd safely, it contains \", % or a line break: {value}"
+                    )
+                })?;
+                substituted.push_str(&quoted);
+                rest = &rest[len..];
+                continue;
+            }
+            rest = &rest[c.len_utf8()..];
+            substituted.push(c);
+            if Some(c) == escape_char(shell, quoting) {
+                // The escaped character can't open or close quotes
+                if let Some(next) = rest.chars().next() {
+                    substituted.push(next);
+                    rest = &rest[next.len_utf8()..];
+                }
+                continue;
+            }
+            quoting = match (quoting, c) {
+                (Quoting::Bare, '\'') if shell != ShellKind::Cmd => Quoting::Single,
+                (Quoting::Single, '\'') => Quoting::Bare,
+                (Quoting::Bare, '"') => Quoting::Double,
+                (Quoting::Double, '"') => Quoting::Bare,
+                (quoting, _) => quoting,
+            };
+        }
+        Ok(substituted)
+    }
+
+    /// The captured variable whose `{{name}}` `text` starts with, its value and the
+    /// placeholder's length
+    fn placeholder_at<'a>(&'a self, text: &str) -> Option<(&'a str, &'a str, usize)> {
+        let inner = text.strip_prefix("{{")?;
+        let name = &inner[..inner.find("}}")?];
+        let (name, value) = self.0.get_key_value(name)?;
+        Some((name, value, name.len() + 4))
+    }
+}
+
+/// The quotes a placeholder is inside of
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Quoting {
+    Bare,
+    Single,
+    Double,
+}
+
+/// The character escaping the next one in `shell` within `quoting`
+fn escape_char(shell: ShellKind, quoting: Quoting) -> Option<char> {
+    match (shell, quoting) {
+        (ShellKind::Posix, Quoting::Single) => None,
+        (ShellKind::Posix | ShellKind::Fish, _) => Some('\\'),
+        (ShellKind::PowerShell, Quoting::Single) => None,
+        (ShellKind::PowerShell, _) => Some('`'),
+        (ShellKind::Cmd, Quoting::Bare) => Some('^'),
+        (ShellKind::Cmd, _) => None,
+    }
+}
+
+/// `value` written so `shell` reads it literally at a spot inside `quoting`
+fn quote_in(shell: ShellKind, quoting: Quoting, value: &str) -> Option<String> {
+    match (shell, quoting) {
+        (_, Quoting::Bare) | (ShellKind::Cmd, _) => shell.quote(value),
+        // Close the quotes, add the quoted value and open them again
+        (ShellKind::Posix | ShellKind::Fish, Quoting::Single) => {
+            Some(format!("'{}'", shell.quote(value)?))
+        }
+        (ShellKind::PowerShell, Quoting::Single) => {
+            let quoted = shell.quote(value)?;
+            Some(quoted[1..quoted.len() - 1].to_string())
+        }
+        (ShellKind::Posix, Quoting::Double) => Some(escape_with(value, |c| {
+            matches!(c, '\\' | '$' | '`' | '"').then_some('\\')
+        })),
+        (ShellKind::Fish, Quoting::Double) => Some(escape_with(value, |c| {
+            matches!(c, '\\' | '$' | '"').then_some('\\')
+        })),
+        (ShellKind::PowerShell, Quoting::Double) => Some(escape_with(value, |c| {
+            matches!(c, '`' | '$' | '"' | '“' | '”' | '„').then_some('`')
+        })),
+    }
+}
+
+/// Whether `name` can be captured into: letters, digits and `_`, not starting with
+/// a digit
+pub fn is_valid_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The names of the `{{name}}` placeholders in `command`, in order
+pub fn placeholders(command: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = command;
+    while let Some(start) = rest.find("{{") {
+        rest = &rest[start + 2..];
+        if let Some(end) = rest.find("}}") {
+            if is_valid_name(&rest[..end]) {
+                names.push(&rest[..end]);
+            }
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_captured_values_replace_their_placeholders() {
+        let mut variables = Variables::default();
+        assert_eq!(
+            variables
+                .capture("backup_dir", &["/tmp/tmp.x1Y".to_string(), String::new()])
+                .unwrap(),
+            "/tmp/tmp.x1Y"
+        );
+        assert_eq!(
+            variables
+                .substitute(
+                    "cp -r src \"{{backup_dir}}\" && docker ps --format '{{.Names}}' {{other}}",
+                    ShellKind::Posix
+                )
+                .unwrap(),
+            "cp -r src \"/tmp/tmp.x1Y\" && docker ps --format '{{.Names}}' {{other}}"
+        );
+        assert_eq!(
+            variables
+                .capture("empty", &["  ".to_string()])
+                .unwrap_err()
+                .to_string(),
+            "The command printed nothing to capture into {{empty}}"
+        );
+        assert_eq!(variables.get("empty"), None);
+    }
+
+    #[test]
+    fn test_values_are_quoted_for_the_shell() {
+        let mut variables = Variables::default();
+        variables
+            .capture(
+                "dir",
+                &["/tmp/my backups; rm -rf ~ $(id) 'x' \"y\"".to_string()],
+            )
+            .unwrap();
+        let substitute = |command: &str, shell| variables.substitute(command, shell).unwrap();
+
+        assert_eq!(
+            substitute("ls {{dir}}", ShellKind::Posix),
+            r#"ls '/tmp/my backups; rm -rf ~ $(id) '\''x'\'' "y"'"#
+        );
+        assert_eq!(
+            substitute("ls \"{{dir}}/a\"", ShellKind::Posix),
+            r#"ls "/tmp/my backups; rm -rf ~ \$(id) 'x' \"y\"/a""#
+        );
+        assert_eq!(
+            substitute("echo 'in {{dir}}'", ShellKind::Posix),
+            r#"echo 'in ''/tmp/my backups; rm -rf ~ $(id) '\''x'\'' "y"'''"#
+        );
+        // An escaped quote doesn't open quotes
+        assert_eq!(
+            substitute(r#"echo \" {{dir}}"#, ShellKind::Posix),
+            r#"echo \" '/tmp/my backups; rm -rf ~ $(id) '\''x'\'' "y"'"#
+        );
+        assert_eq!(
+            substitute("ls {{dir}}", ShellKind::Fish),
+            r#"ls '/tmp/my backups; rm -rf ~ $(id) \'x\' "y"'"#
+        );
+        assert_eq!(
+            substitute("ls {{dir}}", ShellKind::PowerShell),
+            r#"ls '/tmp/my backups; rm -rf ~ $(id) ''x'' "y"'"#
+        );
+        assert_eq!(
+            substitute("ls \"{{dir}}\"", ShellKind::PowerShell),
+            "ls \"/tmp/my backups; rm -rf ~ `$(id) 'x' `\"y`\"\""
+        );
+        let err = variables
+            .substitute("dir {{dir}}", ShellKind::Cmd)
+            .unwrap_err();
+        assert!(err.to_string().contains("can't be passed to cmd"), "{err}");
+
+        let mut variables = Variables::default();
+        variables
+            .capture("dir", &["C:\\My Backups".to_string()])
+            .unwrap();
+        assert_eq!(
+            variables.substitute("dir {{dir}}", ShellKind::Cmd).unwrap(),
+            r#"dir "C:\My Backups""#
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_a_path_with_a_space_stays_one_argument() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("my backups");
+        std::fs::create_dir(&path).unwrap();
+        std::fs::write(path.join("notes.txt"), "kept").unwrap();
+
+        let mut variables = Variables::default();
+        variables
+            .capture("backup_dir", &[path.display().to_string()])
+            .unwrap();
+        for template in [
+            "cat {{backup_dir}}/notes.txt",
+            "cat \"{{backup_dir}}/notes.txt\"",
+        ] {
+            let command = variables.substitute(template, ShellKind::Posix).unwrap();
+            let output = std::process::Command::new("sh")
+                .args(["-c", &command])
+                .output()
+                .unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), "kept", "{command}");
+        }
+    }
+
+    #[test]
+    fn test_placeholders_are_valid_names_only() {
+        assert_eq!(
+            placeholders("tar czf {{archive}} -C {{dir}} . {{.Names}} {{1x}} {{a b}}"),
+            vec!["archive", "dir"]
+        );
+        assert!(is_valid_name("_tmp2"));
+        assert!(!is_valid_name("2tmp"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("backup-dir"));
+    }
+}
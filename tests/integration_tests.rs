@@ -417,4 +417,1132 @@ mod integration_tests {
             );
         }
     }
+
+    #[test]
+    fn test_tai_config_validate_exit_codes() {
+        let temp_dir = TempDir::new().unwrap();
+        let validate = |content: &str| {
+            let path = temp_dir.path().join("terminalai.conf");
+            std::fs::write(&path, content).unwrap();
+            std::process::Command::new(env!("CARGO_BIN_EXE_tai"))
+                .args(["config", "validate", "--file"])
+                .arg(&path)
+                .output()
+                .unwrap()
+        };
+
+        let valid = validate(
+            "active_provider = \"ollama\"\n\n[ollama]\nurl = \"http://localhost:11434\"\nmodel = \"llama3\"\n",
+        );
+        assert!(valid.status.success());
+        assert!(String::from_utf8_lossy(&valid.stdout).contains("Configuration is valid"));
+
+        let invalid = validate("active_provider = \"missing\"\n\n[ollama\n");
+        assert_eq!(invalid.status.code(), Some(1));
+        let stdout = String::from_utf8_lossy(&invalid.stdout);
+        assert!(stdout.contains("line 3: unterminated section header"));
+        assert!(stdout.contains("active_provider 'missing' is not configured"));
+    }
+
+    #[test]
+    fn test_tai_json_diagnostics() {
+        let config_json = r#"{"active_provider":"openai","providers":{"openai":{"provider_type":"OpenAI","timeout_seconds":30,"settings":{"api_key":"sk-live-123","model":"gpt-4","base_url":"https://api.openai.com/v1"}}}}"#;
+        let run = |args: &[&str]| {
+            let output = std::process::Command::new(env!("CARGO_BIN_EXE_tai"))
+                .args(args)
+                .env("TERMINALAI_CONFIG", config_json)
+                .output()
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+        };
+
+        let shown = run(&["config", "show", "--json"]);
+        assert_eq!(shown["active_provider"], "openai");
+        assert_eq!(shown["providers"]["openai"]["settings"]["api_key"], "***");
+        assert_eq!(shown["providers"]["openai"]["settings"]["model"], "gpt-4");
+
+        let report = run(&["doctor", "--json"]);
+        let checks = report["checks"].as_array().unwrap();
+        assert_eq!(checks[0]["name"], "config");
+        assert_eq!(checks[0]["ok"], true);
+        assert_eq!(checks[0]["detail"], "loaded from $TERMINALAI_CONFIG");
+        assert!(checks
+            .iter()
+            .all(|check| check["ok"].is_boolean() && check["detail"].is_string()));
+    }
+
+    #[test]
+    fn test_print_outputs_only_the_commands() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"```bash\ngrep -rn TODO . > matches.txt\ncp notes.txt notes.bak\n```"}}]}"#,
+            )
+            .expect(2)
+            .create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}}}}"#,
+            server.url()
+        );
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "TODO: ship it\n").unwrap();
+
+        let run = |bin: &str, prompt: &str| {
+            std::process::Command::new(bin)
+                .args(["--print", prompt])
+                .current_dir(temp_dir.path())
+                .env("TERMINALAI_CONFIG", &config_json)
+                .stdin(std::process::Stdio::null())
+                .output()
+                .unwrap()
+        };
+        // cp_ai adds -P, as it does without --follow-symlinks
+        for (output, expected) in [
+            (
+                run(
+                    env!("CARGO_BIN_EXE_grep_ai"),
+                    "search for TODO in all files",
+                ),
+                "grep -rn TODO . > matches.txt\ncp notes.txt notes.bak\n",
+            ),
+            (
+                run(env!("CARGO_BIN_EXE_cp_ai"), "copy notes.txt to notes.bak"),
+                "grep -rn TODO . > matches.txt\ncp -P notes.txt notes.bak\n",
+            ),
+        ] {
+            assert!(output.status.success());
+            assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+        }
+        mock.assert();
+
+        // Nothing ran
+        assert!(!temp_dir.path().join("matches.txt").exists());
+        assert!(!temp_dir.path().join("notes.bak").exists());
+    }
+
+    #[test]
+    fn test_simulate_error_fails_without_reaching_the_provider() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"ls"}}]}"#)
+            .expect(0)
+            .create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}}}}"#,
+            server.url()
+        );
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_grep_ai"))
+            .args(["--simulate-error", "429", "search for TODO"])
+            .env("TERMINALAI_CONFIG", &config_json)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("429 Too Many Requests"), "{stderr}");
+        mock.assert();
+    }
+
+    #[test]
+    fn test_dry_run_explains_commands_without_running_them() {
+        // One reply serves both the command query and the explanation request
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"COMMAND: cp notes.txt notes.bak\n1. Copies notes.txt to notes.bak"}}]}"#,
+            )
+            .expect(2)
+            .create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}}}}"#,
+            server.url()
+        );
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "notes\n").unwrap();
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_cp_ai"))
+            .args(["--dry-run", "copy notes.txt to notes.bak"])
+            .current_dir(temp_dir.path())
+            .env("TERMINALAI_CONFIG", &config_json)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("1. cp notes.txt notes.bak"), "{stdout}");
+        assert!(stdout.contains("Copies notes.txt to notes.bak"), "{stdout}");
+        assert!(
+            stdout.contains("Dry run: nothing was executed."),
+            "{stdout}"
+        );
+        mock.assert();
+        assert!(!temp_dir.path().join("notes.bak").exists());
+    }
+
+    #[test]
+    fn test_chosen_shell_is_named_in_the_prompt() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                "Commands run in bash, so bash syntax".to_string(),
+            ))
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"COMMAND: ps aux\n1. Lists every process"}}]}"#,
+            )
+            .expect(2)
+            .create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","settings":{{"shell":"dash"}},"providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}}}}"#,
+            server.url()
+        );
+
+        // --shell wins over the shell setting
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ps_ai"))
+            .args(["--shell", "bash", "--dry-run", "show all processes"])
+            .env("TERMINALAI_CONFIG", &config_json)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        mock.assert();
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ps_ai"))
+            .args(["--shell", "tcsh", "show all processes"])
+            .env("TERMINALAI_CONFIG", &config_json)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Unsupported shell 'tcsh'"));
+    }
+
+    #[test]
+    fn test_skipped_commands_do_not_stop_the_rest() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"COMMAND: cp notes.txt first.bak\nCOMMAND: cp notes.txt second.bak\nCOMMAND: cp notes.txt third.bak"}}]}"#,
+            )
+            .create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"confirm_each_command":"true","history":"false"}}}}"#,
+            server.url()
+        );
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "notes\n").unwrap();
+
+        let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_cp_ai"))
+            .arg("copy notes.txt to three backups")
+            .current_dir(temp_dir.path())
+            .env("TERMINALAI_CONFIG", &config_json)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        // Skip the first, run the second, stop before the third
+        std::io::Write::write_all(child.stdin.as_mut().unwrap(), b"n\ny\nq\n").unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("Skipped: cp notes.txt first.bak"),
+            "{stdout}"
+        );
+        mock.assert();
+
+        assert!(!temp_dir.path().join("first.bak").exists());
+        assert!(temp_dir.path().join("second.bak").exists());
+        assert!(!temp_dir.path().join("third.bak").exists());
+
+        // tai -p asks about each step of its plan the same way
+        let home = tempfile::TempDir::new().unwrap();
+        let project = tempfile::TempDir::new().unwrap();
+        std::fs::write(project.path().join("notes.txt"), "notes\n").unwrap();
+        let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_tai"))
+            .args(["-p", "copy notes.txt to three backups"])
+            .current_dir(project.path())
+            .env("TERMINALAI_CONFIG", &config_json)
+            .env("HOME", home.path())
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        std::io::Write::write_all(child.stdin.as_mut().unwrap(), b"n\ny\nq\n").unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("Skipped: cp notes.txt first.bak"),
+            "{stdout}"
+        );
+        assert!(!project.path().join("first.bak").exists());
+        assert!(project.path().join("second.bak").exists());
+        assert!(!project.path().join("third.bak").exists());
+    }
+
+    #[test]
+    fn test_chat_follow_up_sees_earlier_output() {
+        let mut server = mockito::Server::new();
+        let first = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                r#""content":"print the marker""#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: echo chat-marker-41"}}]}"#)
+            .expect(1)
+            .create();
+        // The follow-up carries the earlier request, its command and what it printed
+        let follow_up = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                r"Request: print the marker\\n\$ echo chat-marker-41\\n  chat-marker-41\\n= exit 0.*Current request: now save it".to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: echo chat-marker-41 > marker.txt"}}]}"#)
+            .expect(1)
+            .create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}}}}"#,
+            server.url()
+        );
+        let home = tempfile::TempDir::new().unwrap();
+        let project = tempfile::TempDir::new().unwrap();
+
+        let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_tai"))
+            .arg("chat")
+            .current_dir(project.path())
+            .env("HOME", home.path())
+            .env("TERMINALAI_CONFIG", &config_json)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        std::io::Write::write_all(
+            child.stdin.as_mut().unwrap(),
+            b"print the marker\ny\nnow save it to a file\ny\nexit\n",
+        )
+        .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{output:?}");
+        first.assert();
+        follow_up.assert();
+        assert_eq!(
+            std::fs::read_to_string(project.path().join("marker.txt")).unwrap(),
+            "chat-marker-41\n"
+        );
+    }
+
+    #[test]
+    fn test_history_replay_runs_commands_without_the_model() {
+        // Any request to the provider would fail the mock's expectation
+        let mut server = mockito::Server::new();
+        let mock = server.mock("POST", "/chat/completions").expect(0).create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}}}}"#,
+            server.url()
+        );
+        let home = tempfile::TempDir::new().unwrap();
+        let project = tempfile::TempDir::new().unwrap();
+        let entry = serde_json::json!({
+            "timestamp": 1,
+            "workdir": project.path(),
+            "tool": "cp_ai",
+            "prompt": "make a marker file",
+            "commands": ["touch marker.txt"],
+            "exit_codes": [0],
+            "response": "COMMAND: touch marker.txt",
+            "succeeded": true
+        });
+        let history_dir = home.path().join(".terminalai");
+        std::fs::create_dir(&history_dir).unwrap();
+        std::fs::write(history_dir.join("history.jsonl"), format!("{entry}\n")).unwrap();
+
+        let tai = |args: &[&str], answers: &[u8]| {
+            let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_tai"))
+                .args(args)
+                .env("HOME", home.path())
+                .env("TERMINALAI_CONFIG", &config_json)
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped())
+                .spawn()
+                .unwrap();
+            std::io::Write::write_all(child.stdin.as_mut().unwrap(), answers).unwrap();
+            child.wait_with_output().unwrap()
+        };
+
+        let shown = tai(&["history", "show", "1"], b"");
+        assert!(shown.status.success());
+        let stdout = String::from_utf8_lossy(&shown.stdout);
+        assert!(stdout.starts_with("#1 ✅"), "{stdout}");
+        assert!(stdout.contains("exit code 0"), "{stdout}");
+        assert!(
+            stdout.contains("Model response:\n     COMMAND: touch marker.txt"),
+            "{stdout}"
+        );
+
+        let missing = tai(&["history", "replay", "2"], b"");
+        assert!(!missing.status.success());
+        assert!(String::from_utf8_lossy(&missing.stderr).contains("No history entry #2"));
+
+        let replayed = tai(&["history", "replay", "1"], b"y\n");
+        assert!(replayed.status.success(), "{replayed:?}");
+        assert!(project.path().join("marker.txt").exists());
+        mock.assert();
+
+        // The replay is saved as an entry of its own
+        let listed = tai(&["history", "list"], b"");
+        let stdout = String::from_utf8_lossy(&listed.stdout);
+        assert!(stdout.contains("#2 ✅"), "{stdout}");
+        assert!(stdout.contains("tai history replay"), "{stdout}");
+    }
+
+    #[test]
+    fn test_dangerous_command_needs_the_typed_phrase() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: rm -rf ~"}}]}"#)
+            .expect(3)
+            .create();
+        let config_json = |settings: &str| {
+            format!(
+                r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"history":"false"{settings}}}}}"#,
+                server.url()
+            )
+        };
+        let home = tempfile::TempDir::new().unwrap();
+        let run = |config: &str, answer: &[u8]| {
+            let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_tai"))
+                .args(["-p", "clean up my home directory"])
+                .env("TERMINALAI_CONFIG", config)
+                .env("HOME", home.path())
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped())
+                .spawn()
+                .unwrap();
+            std::io::Write::write_all(child.stdin.as_mut().unwrap(), answer).unwrap();
+            child.wait_with_output().unwrap()
+        };
+
+        // A plain yes is not enough
+        let output = run(&config_json(""), b"y\n");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("1. [dangerous] recursive delete of ~"),
+            "{stdout}"
+        );
+        assert!(stdout.contains("type 'yes, run rm'"), "{stdout}");
+        assert!(home.path().exists());
+
+        // The block policy refuses without asking
+        let output = run(
+            &config_json(r#","dangerous_commands":"block""#),
+            b"yes, run rm\n",
+        );
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains("Refusing to run dangerous command 1 (dangerous_commands = \"block\")"));
+        assert!(home.path().exists());
+
+        let output = run(&config_json(""), b"yes, run rm\n");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(!home.path().exists());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_command_policy_is_enforced() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"COMMAND: grep -rn TODO .\nCOMMAND: grep -rn TODO /etc"}}]}"#,
+            )
+            .expect(2)
+            .create();
+        let home = tempfile::TempDir::new().unwrap();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"history":"false"}},"policy":{{"deny":["\\s/etc\\b"]}}}}"#,
+            server.url()
+        );
+
+        // The *_ai tools skip denied commands when taking them from the answer
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_grep_ai"))
+            .args(["--print", "find the TODOs"])
+            .env("TERMINALAI_CONFIG", &config_json)
+            .env("HOME", home.path())
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            "grep -rn TODO ."
+        );
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains(r"Skipping `grep -rn TODO /etc`: denied by policy pattern '\s/etc\b'"));
+
+        // tai -p refuses the plan; ~/.terminalai/policy.toml adds to the config's policy
+        std::fs::create_dir_all(home.path().join(".terminalai")).unwrap();
+        std::fs::write(
+            home.path().join(".terminalai").join("policy.toml"),
+            "allow = [\"^git\\\\b\"]\n",
+        )
+        .unwrap();
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_tai"))
+            .args(["-p", "find the TODOs"])
+            .env("TERMINALAI_CONFIG", &config_json)
+            .env("HOME", home.path())
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("Command 1 is not allowed by the command policy: grep -rn TODO ."),
+            "{stderr}"
+        );
+        mock.assert();
+    }
+
+    #[test]
+    fn test_yes_runs_without_input_and_refuses_flagged_commands() {
+        let mut server = mockito::Server::new();
+        let listing = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::Regex("list process 1".to_string()))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: ps -p 1 -o pid="}}]}"#)
+            .create();
+        let killing = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::Regex("kill process 1".to_string()))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: sudo kill -9 1"}}]}"#)
+            .create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"history":"false"}}}}"#,
+            server.url()
+        );
+        let run = |args: &[&str], env: Option<&str>| {
+            let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_ps_ai"));
+            command
+                .args(args)
+                .env("TERMINALAI_CONFIG", &config_json)
+                .env_remove("TERMINALAI_ASSUME_YES")
+                .stdin(std::process::Stdio::piped());
+            if let Some(value) = env {
+                command.env("TERMINALAI_ASSUME_YES", value);
+            }
+            let child = command
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped())
+                .spawn()
+                .unwrap();
+            child.wait_with_output().unwrap()
+        };
+
+        let output = run(&["list process 1"], Some("1"));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{stdout}");
+        assert!(
+            stdout.contains("❓ Execute these commands? yes (--yes)"),
+            "{stdout}"
+        );
+        listing.assert();
+
+        let output = run(&["-y", "kill process 1"], None);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains("Refusing to run commands flagged by the risk check (--yes)"));
+        killing.assert();
+    }
+
+    #[test]
+    fn test_repeated_prompts_are_answered_from_the_cache() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: grep -rn TODO ."}}]}"#)
+            .expect(2)
+            .create();
+        let home = tempfile::TempDir::new().unwrap();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"history":"false","cache_ttl_seconds":"3600"}}}}"#,
+            server.url()
+        );
+        let run = |extra: &[&str]| {
+            let output = std::process::Command::new(env!("CARGO_BIN_EXE_grep_ai"))
+                .args(extra)
+                .args(["--print", "find all TODOs"])
+                .env("TERMINALAI_CONFIG", &config_json)
+                .env("HOME", home.path())
+                .stdin(std::process::Stdio::null())
+                .output()
+                .unwrap();
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            assert_eq!(
+                String::from_utf8_lossy(&output.stdout).trim(),
+                "grep -rn TODO ."
+            );
+            String::from_utf8_lossy(&output.stderr).to_string()
+        };
+
+        // The second run is answered from ~/.terminalai/cache, --no-cache asks again
+        assert!(!run(&[]).contains("Using the cached answer"));
+        assert!(run(&[]).contains("Using the cached answer"));
+        assert!(!run(&["--no-cache"]).contains("Using the cached answer"));
+        mock.assert();
+    }
+
+    #[test]
+    fn test_prompt_context_describes_the_working_directory() {
+        let mut server = mockito::Server::new();
+        let with_context = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                r"Working directory: [^\n]*\\n- Its contents: notes\.txt, src/".to_string(),
+            ))
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"COMMAND: grep -rn TODO notes.txt src"}}]}"#,
+            )
+            .expect(1)
+            .create();
+        let without_context = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                r#""content":"You are an AI assistant"#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: grep -rn TODO ."}}]}"#)
+            .expect(2)
+            .create();
+        let home = tempfile::TempDir::new().unwrap();
+        let workdir = tempfile::TempDir::new().unwrap();
+        std::fs::write(workdir.path().join("notes.txt"), "TODO: write\n").unwrap();
+        std::fs::create_dir(workdir.path().join("src")).unwrap();
+        let config_json = |context: &str| {
+            format!(
+                r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"history":"false"{context}}}}}"#,
+                server.url()
+            )
+        };
+        let run = |context: &str, extra: &[&str]| {
+            let output = std::process::Command::new(env!("CARGO_BIN_EXE_grep_ai"))
+                .args(extra)
+                .args(["--print", "find the TODOs"])
+                .current_dir(workdir.path())
+                .env("TERMINALAI_CONFIG", config_json(context))
+                .env("HOME", home.path())
+                .stdin(std::process::Stdio::null())
+                .output()
+                .unwrap();
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        };
+
+        // Only sent once prompt_context opts in
+        let opt_in = r#","prompt_context":"true""#;
+        assert_eq!(run(opt_in, &[]), "grep -rn TODO notes.txt src");
+        assert_eq!(run(opt_in, &["--no-context"]), "grep -rn TODO .");
+        assert_eq!(run("", &[]), "grep -rn TODO .");
+        with_context.assert();
+        without_context.assert();
+    }
+
+    #[test]
+    fn test_failed_plans_are_saved_and_resumed() {
+        let mut server = mockito::Server::new();
+        let plan = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                r#""content":"build the site""#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"COMMAND: echo one > one.txt\nCOMMAND: ls site\nCOMMAND: echo three > three.txt"}}]}"#,
+            )
+            .expect(1)
+            .create();
+        let fix = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                r"Step 2 printed this to stderr:\\n[^\n]*site".to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: mkdir site"}}]}"#)
+            .expect(1)
+            .create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"replan_attempts":"0"}}}}"#,
+            server.url()
+        );
+        let home = tempfile::TempDir::new().unwrap();
+        let project = tempfile::TempDir::new().unwrap();
+        let plans = home.path().join(".terminalai").join("plans");
+        let run = |args: &[&str]| {
+            let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_tai"))
+                .args(args)
+                .current_dir(project.path())
+                .env("HOME", home.path())
+                .env("TERMINALAI_CONFIG", &config_json)
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped())
+                .spawn()
+                .unwrap();
+            std::io::Write::write_all(child.stdin.as_mut().unwrap(), b"y\n").unwrap();
+            child.wait_with_output().unwrap()
+        };
+
+        // Step 2 fails and is not replanned; the plan is kept with its state and stderr
+        let output = run(&["-p", "build the site"]);
+        assert!(!output.status.success());
+        let saved: Vec<_> = std::fs::read_dir(&plans).unwrap().collect();
+        assert_eq!(saved.len(), 1);
+        let path = saved[0].as_ref().unwrap().path();
+        let id = path.file_stem().unwrap().to_str().unwrap().to_string();
+        assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("tai resume {id}")));
+        let state: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(state["steps"][0]["state"], "succeeded");
+        assert_eq!(state["steps"][1]["state"], "failed");
+        assert!(state["steps"][1]["stderr"][0]
+            .as_str()
+            .unwrap()
+            .contains("site"));
+        assert_eq!(state["steps"][2]["state"], "pending");
+
+        // --fix replaces the failed step with the model's commands, then the rest runs
+        let output = run(&["resume", &id, "--fix"]);
+        assert!(output.status.success(), "{output:?}");
+        assert!(project.path().join("site").is_dir());
+        assert_eq!(
+            std::fs::read_to_string(project.path().join("three.txt")).unwrap(),
+            "three\n"
+        );
+        assert_eq!(std::fs::read_dir(&plans).unwrap().count(), 0);
+
+        let output = run(&["resume"]);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("No saved plan to resume"));
+        plan.assert();
+        fix.assert();
+    }
+
+    #[test]
+    fn test_failed_steps_are_replanned_with_their_stderr() {
+        let mut server = mockito::Server::new();
+        let plan = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                r#""content":"publish the site""#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"COMMAND: ls site\nCOMMAND: echo done > done.txt"}}]}"#,
+            )
+            .expect(1)
+            .create();
+        let first_fix = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::Regex(r"request: publish the site".to_string()),
+                mockito::Matcher::Regex(r"Step 1 printed this to stderr:\\n[^\n]*site".to_string()),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: ls missing-site"}}]}"#)
+            .expect(1)
+            .create();
+        let second_fix = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                r"Earlier attempts that also failed:\\n- `ls site` failed".to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: mkdir site"}}]}"#)
+            .expect(1)
+            .create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"history_prompt":"hash"}}}}"#,
+            server.url()
+        );
+        let home = tempfile::TempDir::new().unwrap();
+        let project = tempfile::TempDir::new().unwrap();
+
+        let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_tai"))
+            .args(["-p", "publish the site"])
+            .current_dir(project.path())
+            .env("HOME", home.path())
+            .env("TERMINALAI_CONFIG", &config_json)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        // The plan, then each of the two fixes
+        std::io::Write::write_all(child.stdin.as_mut().unwrap(), b"y\ny\ny\n").unwrap();
+        let output = child.wait_with_output().unwrap();
+
+        assert!(output.status.success(), "{output:?}");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("Step 1 failed; asking for commands to replace it"));
+        assert!(project.path().join("site").is_dir());
+        assert_eq!(
+            std::fs::read_to_string(project.path().join("done.txt")).unwrap(),
+            "done\n"
+        );
+        // The finished plan is not kept
+        let plans = home.path().join(".terminalai").join("plans");
+        assert_eq!(std::fs::read_dir(&plans).unwrap().count(), 0);
+        plan.assert();
+        first_fix.assert();
+        second_fix.assert();
+    }
+
+    #[test]
+    fn test_captured_step_output_is_filled_into_later_steps() {
+        let mut server = mockito::Server::new();
+        let plan = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"COMMAND[OUT=work]: mktemp -d\nCOMMAND: cp notes.txt \"{{work}}/\"\nCOMMAND: tar cf \"{{work}}.tar\" -C \"{{work}}\" notes.txt\nCOMMAND: echo \"{{work}}.tar\" > archive-path.txt"}}]}"#,
+            )
+            .expect(1)
+            .create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"history":"false"}}}}"#,
+            server.url()
+        );
+        let home = tempfile::TempDir::new().unwrap();
+        let project = tempfile::TempDir::new().unwrap();
+        std::fs::write(project.path().join("notes.txt"), "remember the milk\n").unwrap();
+
+        let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_tai"))
+            .args(["-p", "archive notes.txt in a temporary directory"])
+            .current_dir(project.path())
+            .env("HOME", home.path())
+            .env("TERMINALAI_CONFIG", &config_json)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        std::io::Write::write_all(child.stdin.as_mut().unwrap(), b"y\n").unwrap();
+        let output = child.wait_with_output().unwrap();
+
+        assert!(output.status.success(), "{output:?}");
+        let archive = std::fs::read_to_string(project.path().join("archive-path.txt")).unwrap();
+        let archive = std::path::Path::new(archive.trim());
+        let work = archive.with_extension("");
+        assert!(String::from_utf8_lossy(&output.stdout)
+            .contains(&format!("📦 {{{{work}}}} = {}", work.display())));
+        assert_eq!(
+            std::fs::read_to_string(work.join("notes.txt")).unwrap(),
+            "remember the milk\n"
+        );
+        assert!(archive.is_file());
+        std::fs::remove_dir_all(&work).unwrap();
+        std::fs::remove_file(archive).unwrap();
+        plan.assert();
+    }
+
+    #[test]
+    fn test_explain_breaks_commands_down_without_running_them() {
+        let mut server = mockito::Server::new();
+        let breakdown = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                r"Explain this command:\\n\\n".to_string(),
+            ))
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"SUMMARY: Deletes the build folder.\nPART: rm => Removes files\nPART: -rf => Recursively, without asking\nPART: build => The folder to delete\nFILE: build/ => deleted"}}]}"#,
+            )
+            .expect(2)
+            .create();
+        let suggestion = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                r"search for TODO in the build folder".to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: grep -rn TODO build"}}]}"#)
+            .expect(1)
+            .create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"history":"false"}}}}"#,
+            server.url()
+        );
+        let project = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(project.path().join("build")).unwrap();
+        let run = |bin: &str, args: &[&str]| {
+            std::process::Command::new(bin)
+                .args(args)
+                .current_dir(project.path())
+                .env("TERMINALAI_CONFIG", &config_json)
+                .stdin(std::process::Stdio::null())
+                .output()
+                .unwrap()
+        };
+
+        // Unquoted words are explained as one command
+        let output = run(
+            env!("CARGO_BIN_EXE_tai"),
+            &["explain", "rm", "-rf", "build"],
+        );
+        assert!(output.status.success(), "{output:?}");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("📖 rm -rf build\n   Deletes the build folder."),
+            "{stdout}"
+        );
+        assert!(
+            stdout.contains("   -rf    Recursively, without asking"),
+            "{stdout}"
+        );
+        assert!(stdout.contains("      build/: deleted"), "{stdout}");
+        assert!(stdout.contains("[caution]"), "{stdout}");
+
+        let output = run(
+            env!("CARGO_BIN_EXE_grep_ai"),
+            &["--breakdown", "search for TODO in the build folder"],
+        );
+        assert!(output.status.success(), "{output:?}");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("📖 grep -rn TODO build"), "{stdout}");
+        assert!(stdout.contains("Commands not executed"), "{stdout}");
+        assert!(project.path().join("build").is_dir());
+        breakdown.assert();
+        suggestion.assert();
+    }
+
+    #[test]
+    fn test_allowed_prefixes_hold_for_every_command_of_a_plan() {
+        let mut server = mockito::Server::new();
+        let _plan = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"COMMAND: ls\nCOMMAND: ls && touch pwned"}}]}"#,
+            )
+            .create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"history":"false","allowed_command_prefixes":"ls"}}}}"#,
+            server.url()
+        );
+        let home = tempfile::TempDir::new().unwrap();
+        let project = tempfile::TempDir::new().unwrap();
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_tai"))
+            .args(["-p", "list the files"])
+            .current_dir(project.path())
+            .env("HOME", home.path())
+            .env("TERMINALAI_CONFIG", &config_json)
+            .env("TERMINALAI_ASSUME_YES", "1")
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success(), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("`touch pwned` is not in allowed_command_prefixes"),
+            "{stderr}"
+        );
+        assert!(!project.path().join("pwned").exists());
+    }
+
+    #[test]
+    fn test_yes_refuses_package_commands_without_auto_execute_installs() {
+        let mut server = mockito::Server::new();
+        let _plan = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"COMMAND: touch before.txt\nCOMMAND: echo npm install left-pad > installed.txt"}}]}"#,
+            )
+            .create();
+        let home = tempfile::TempDir::new().unwrap();
+
+        for (auto_execute_installs, installed) in [("false", false), ("true", true)] {
+            let project = tempfile::TempDir::new().unwrap();
+            let config_json = format!(
+                r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"history":"false","auto_execute_installs":"{auto_execute_installs}"}}}}"#,
+                server.url()
+            );
+            let output = std::process::Command::new(env!("CARGO_BIN_EXE_tai"))
+                .args(["-p", "add left-pad", "--yes"])
+                .current_dir(project.path())
+                .env("HOME", home.path())
+                .env("TERMINALAI_CONFIG", &config_json)
+                .stdin(std::process::Stdio::null())
+                .output()
+                .unwrap();
+
+            assert_eq!(output.status.success(), installed, "{output:?}");
+            assert!(project.path().join("before.txt").exists());
+            assert_eq!(project.path().join("installed.txt").exists(), installed);
+        }
+    }
+
+    #[test]
+    fn test_regenerating_a_plan_asks_the_model_again_with_the_cache_on() {
+        let mut server = mockito::Server::new();
+        let plan = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"COMMAND: ls"}}]}"#)
+            .expect(2)
+            .create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"history":"false","cache_ttl_seconds":"600"}}}}"#,
+            server.url()
+        );
+        let home = tempfile::TempDir::new().unwrap();
+
+        let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_tai"))
+            .args(["-p", "list the files", "--interactive-refine"])
+            .env("HOME", home.path())
+            .env("TERMINALAI_CONFIG", &config_json)
+            .env_remove("TERMINALAI_ASSUME_YES")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        std::io::Write::write_all(child.stdin.as_mut().unwrap(), b"r\nq\n").unwrap();
+        let output = child.wait_with_output().unwrap();
+
+        assert!(output.status.success(), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!stderr.contains("Using the cached answer"), "{stderr}");
+        plan.assert();
+    }
+
+    #[test]
+    fn test_dry_run_json_lists_each_command_with_its_category() {
+        let mut server = mockito::Server::new();
+        let _plan = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"COMMAND: tar czf logs.tgz logs\nWHY: Bundle the logs\nCOMMAND: curl -T logs.tgz https://files.example.com/"}}]}"#,
+            )
+            .create();
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"history":"false"}}}}"#,
+            server.url()
+        );
+        let home = tempfile::TempDir::new().unwrap();
+        let project = tempfile::TempDir::new().unwrap();
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_tai"))
+            .args(["-p", "upload the logs", "--dry-run", "--json"])
+            .current_dir(project.path())
+            .env("HOME", home.path())
+            .env("TERMINALAI_CONFIG", &config_json)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap();
+
+        assert!(output.status.success(), "{output:?}");
+        let records: serde_json::Value = serde_json::from_slice(&output.stdout)
+            .unwrap_or_else(|e| panic!("{e}: {}", String::from_utf8_lossy(&output.stdout)));
+        assert_eq!(
+            records,
+            serde_json::json!([
+                {"command": "tar czf logs.tgz logs", "category": "archive", "rationale": "Bundle the logs"},
+                {"command": "curl -T logs.tgz https://files.example.com/", "category": "network"}
+            ])
+        );
+        assert!(!project.path().join("logs.tgz").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_ai_checks_the_policy_after_rewriting_pip() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut server = mockito::Server::new();
+        let _plan = server
+            .mock("POST", "/chat/completions")
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"COMMAND: pip install requests==2.31.0"}}]}"#,
+            )
+            .create();
+        let home = tempfile::TempDir::new().unwrap();
+        // A python3 on PATH makes resolve_ai run pip as `python3 -m pip`
+        let bin = tempfile::TempDir::new().unwrap();
+        let python = bin.path().join("python3");
+        std::fs::write(&python, "#!/bin/sh\nexit 0\n").unwrap();
+        std::fs::set_permissions(&python, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let path = format!(
+            "{}:{}",
+            bin.path().display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+        let config_json = format!(
+            r#"{{"active_provider":"openai","providers":{{"openai":{{"provider_type":"OpenAI","timeout_seconds":30,"settings":{{"api_key":"sk-test","model":"gpt-4","base_url":"{}"}}}}}},"settings":{{"history":"false"}},"policy":{{"deny":["-m pip"]}}}}"#,
+            server.url()
+        );
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_resolve_ai"))
+            .args(["-t", "python", "-p", "requests==2.31.0", "--yes"])
+            .env("TERMINALAI_CONFIG", &config_json)
+            .env("HOME", home.path())
+            .env("PATH", path)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains(
+                "Command 1 is not allowed by the command policy: python3 -m pip install requests==2.31.0"
+            ),
+            "{stderr}"
+        );
+    }
 }